@@ -0,0 +1,332 @@
+//! Stable handles to the nodes of a tree. An `Arena` numbers every statement, declaration,
+//! parameter, field, enumerator, initializer list and expression of a program, and stores a
+//! reference to each in one flat table, so a pass can name a node by a copyable `NodeId` and
+//! keep what it learns about it, such as its type, in a `SideTable` instead of in the tree.
+//!
+//! Ids are given in source order, the order `Visit` walks the tree, so parsing the same source
+//! twice numbers it the same way.
+//!
+//! The arena only indexes the tree: it doesn't own the nodes. The parser allocates each node in a
+//! `Box` owned by its parent, and `Arena::new` numbers the finished tree, finding a node's id by
+//! its address. Storing the nodes in the arena, with the parser allocating into it and children
+//! linked by `NodeId`, is a separate piece of work that this module doesn't do.
+
+use std::collections::HashMap;
+
+use crate::ast::*;
+use crate::cst::NodeKind;
+use crate::source::Span;
+use crate::visit::{walk_decl, walk_expr, walk_init, walk_program, walk_stmt, Visit};
+
+/// A node of the `Arena` it was handed out by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A reference to a node of the tree.
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'ast> {
+    Program(&'ast Program),
+    Stmt(&'ast Stmt),
+    Decl(&'ast Decl),
+    Param(&'ast Param),
+    Field(&'ast Field),
+    Enumerator(&'ast Enumerator),
+    /// Always an `Init::List`. An initializer that is a single expression is numbered as the
+    /// expression.
+    InitList(&'ast Init),
+    Expr(&'ast Expr),
+}
+
+impl<'ast> Node<'ast> {
+    pub fn kind(self) -> NodeKind {
+        match self {
+            Node::Program(_) => NodeKind::Program,
+            Node::Stmt(_) => NodeKind::Stmt,
+            Node::Decl(_) => NodeKind::Decl,
+            Node::Param(_) => NodeKind::Param,
+            Node::Field(_) => NodeKind::Field,
+            Node::Enumerator(_) => NodeKind::Enumerator,
+            Node::InitList(_) => NodeKind::InitList,
+            Node::Expr(_) => NodeKind::Expr,
+        }
+    }
+
+    /// Identifies the node by its kind and address, since a node can share its address with
+    /// its first child.
+    fn key(self) -> (NodeKind, usize) {
+        let addr = match self {
+            Node::Program(node) => node as *const _ as usize,
+            Node::Stmt(node) => node as *const _ as usize,
+            Node::Decl(node) => node as *const _ as usize,
+            Node::Param(node) => node as *const _ as usize,
+            Node::Field(node) => node as *const _ as usize,
+            Node::Enumerator(node) => node as *const _ as usize,
+            Node::InitList(node) => node as *const _ as usize,
+            Node::Expr(node) => node as *const _ as usize,
+        };
+        (self.kind(), addr)
+    }
+}
+
+impl Spanned for Node<'_> {
+    fn span(&self) -> Span {
+        match *self {
+            Node::Program(program) => program.span,
+            Node::Stmt(stmt) => stmt.span,
+            Node::Decl(decl) => decl.span(),
+            Node::Param(param) => param.span,
+            Node::Field(field) => field.span,
+            Node::Enumerator(enumerator) => enumerator.span,
+            Node::InitList(init) => init.span(),
+            Node::Expr(expr) => expr.span,
+        }
+    }
+}
+
+/// The nodes of one program, numbered from the program itself at `NodeId` 0.
+#[derive(Debug, Clone)]
+pub struct Arena<'ast> {
+    nodes: Vec<Node<'ast>>,
+    parents: Vec<Option<NodeId>>,
+    ids: HashMap<(NodeKind, usize), NodeId>,
+}
+
+impl<'ast> Arena<'ast> {
+    pub fn new(program: &'ast Program) -> Arena<'ast> {
+        let mut arena = Arena {
+            nodes: Vec::new(),
+            parents: Vec::new(),
+            ids: HashMap::new(),
+        };
+        let mut builder = Builder {
+            arena: &mut arena,
+            open: Vec::new(),
+        };
+        builder.visit_program(program);
+        arena
+    }
+
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Panics if `id` comes from a different `Arena`.
+    pub fn get(&self, id: NodeId) -> Node<'ast> {
+        self.nodes[id.index()]
+    }
+
+    pub fn span(&self, id: NodeId) -> Span {
+        self.get(id).span()
+    }
+
+    /// The innermost node whose span contains the byte at `pos`.
+    pub fn node_at(&self, pos: usize) -> Option<NodeId> {
+        // a node comes after every node containing it, and before any that don't
+        self.iter()
+            .filter(|(_, node)| node.span().contains(pos))
+            .last()
+            .map(|(id, _)| id)
+    }
+
+    /// The innermost numbered node containing `id`, or `None` for the root.
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.parents[id.index()]
+    }
+
+    /// The id of a node of the program, or `None` if it belongs to another tree.
+    pub fn id(&self, node: Node<'ast>) -> Option<NodeId> {
+        self.ids.get(&node.key()).copied()
+    }
+
+    pub fn expr_id(&self, expr: &'ast Expr) -> Option<NodeId> {
+        self.id(Node::Expr(expr))
+    }
+
+    pub fn stmt_id(&self, stmt: &'ast Stmt) -> Option<NodeId> {
+        self.id(Node::Stmt(stmt))
+    }
+
+    /// Every node with its id, in source order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, Node<'ast>)> + '_ {
+        (0..).map(NodeId).zip(self.nodes.iter().copied())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+struct Builder<'a, 'ast> {
+    arena: &'a mut Arena<'ast>,
+    /// The nodes being walked, innermost last.
+    open: Vec<NodeId>,
+}
+
+impl<'ast> Builder<'_, 'ast> {
+    /// Numbers `node` and runs `walk` with it as the parent of the nodes found inside.
+    fn add(&mut self, node: Node<'ast>, walk: impl FnOnce(&mut Self)) {
+        let id = NodeId(self.arena.nodes.len() as u32);
+        self.arena.nodes.push(node);
+        self.arena.parents.push(self.open.last().copied());
+        self.arena.ids.insert(node.key(), id);
+        self.open.push(id);
+        walk(self);
+        self.open.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Builder<'_, 'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        self.add(Node::Program(program), |b| walk_program(b, program));
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        self.add(Node::Stmt(stmt), |b| walk_stmt(b, stmt));
+    }
+
+    fn visit_decl(&mut self, decl: &'ast Decl) {
+        self.add(Node::Decl(decl), |b| walk_decl(b, decl));
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        self.add(Node::Param(param), |_| {});
+    }
+
+    fn visit_field(&mut self, field: &'ast Field) {
+        self.add(Node::Field(field), |_| {});
+    }
+
+    fn visit_enumerator(&mut self, enumerator: &'ast Enumerator) {
+        self.add(Node::Enumerator(enumerator), |_| {});
+    }
+
+    fn visit_init(&mut self, init: &'ast Init) {
+        match init {
+            Init::Expr(_) => walk_init(self, init),
+            Init::List { .. } => self.add(Node::InitList(init), |b| walk_init(b, init)),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        self.add(Node::Expr(expr), |b| walk_expr(b, expr));
+    }
+}
+
+/// Facts about some of the nodes of an `Arena`, indexed by `NodeId`.
+#[derive(Debug, Clone)]
+pub struct SideTable<T> {
+    entries: Vec<Option<T>>,
+}
+
+impl<T> Default for SideTable<T> {
+    fn default() -> SideTable<T> {
+        SideTable {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> SideTable<T> {
+    pub fn new() -> SideTable<T> {
+        SideTable::default()
+    }
+
+    /// Records `value` for `id`, giving back what was recorded before.
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        if self.entries.len() <= id.index() {
+            self.entries.resize_with(id.index() + 1, || None);
+        }
+        self.entries[id.index()].replace(value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.entries.get(id.index())?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.entries.get_mut(id.index())?.as_mut()
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        self.entries.get_mut(id.index())?.take()
+    }
+
+    /// Every recorded id with its value, in id order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| Some((NodeId(i as u32), v.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn arena_test() {
+        let src = "int a[2] = {1, 2}; int f(int n) { return n + 1; }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let kinds: Vec<_> = arena.iter().map(|(_, node)| node.kind()).collect();
+        {
+            use NodeKind::*;
+            assert_eq!(
+                kinds,
+                [
+                    Program, Stmt, Decl, InitList, Expr, Expr, Stmt, Decl, Param, Stmt, Stmt, Expr,
+                    Expr, Expr
+                ]
+            );
+        }
+
+        let ret = match &program.stmts[1].kind {
+            StmtKind::Decl(decls) => match &decls[0] {
+                Decl::Func(func) => func.body.as_deref().unwrap(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let id = arena.stmt_id(ret).unwrap();
+        assert_eq!(
+            &src[arena.span(id).start..arena.span(id).end],
+            "{ return n + 1; }"
+        );
+        let decl = arena.parent(id).unwrap();
+        assert_eq!(arena.get(decl).kind(), NodeKind::Decl);
+        assert_eq!(
+            arena.parent(arena.parent(decl).unwrap()),
+            Some(arena.root())
+        );
+        assert_eq!(arena.parent(arena.root()), None);
+
+        let n = arena.node_at(41).unwrap();
+        assert_eq!(arena.get(n).text(src), "n");
+        assert_eq!(arena.get(arena.node_at(29).unwrap()).text(src), "int n");
+        assert_eq!(arena.node_at(src.len()), None);
+
+        let other = parse(src).unwrap();
+        assert_eq!(arena.id(Node::Program(&other)), None);
+        // the block, then the `return`, then its value
+        let n_plus_1 = NodeId(id.0 + 2);
+        assert_eq!(arena.span(n_plus_1), Span { start: 41, end: 46 });
+
+        let mut types = SideTable::new();
+        assert_eq!(types.insert(n_plus_1, Type::Int), None);
+        assert_eq!(types.get(n_plus_1), Some(&Type::Int));
+        assert_eq!(types.get(id), None);
+        assert_eq!(types.iter().count(), 1);
+    }
+}
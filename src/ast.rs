@@ -0,0 +1,881 @@
+//! The abstract syntax tree built by the parser. Every node records the span of source it
+//! was parsed from.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::source::Span;
+
+mod build;
+mod json;
+
+pub use json::{program_from_json, program_to_json, SCHEMA_VERSION};
+
+/// A node that knows the span of source it was parsed from. Types aren't nodes: the same
+/// `Type` can be spelled many ways, and the declaration holding one spans where it's written.
+pub trait Spanned {
+    fn span(&self) -> Span;
+
+    /// The source text of the node, given the `src` it was parsed from.
+    fn text<'src>(&self, src: &'src str) -> &'src str {
+        self.span().text(src)
+    }
+}
+
+/// Implements `Spanned` for nodes with a `span` field.
+macro_rules! spanned {
+    ($($node:ty),*) => {
+        $(impl Spanned for $node {
+            fn span(&self) -> Span {
+                self.span
+            }
+        })*
+    };
+}
+
+spanned!(
+    Ident, Program, StructDef, Typedef, EnumDef, Enumerator, Field, VarDecl, Function, Param, Stmt,
+    Expr
+);
+
+/// A name as written in the source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Void,
+    /// 0 or 1, in a byte. Converting any scalar to it gives 1 if the scalar is nonzero, and
+    /// comparisons and logical operators give one.
+    Bool,
+    /// A signed byte, as `char` and `signed char` are.
+    Char,
+    UChar,
+    /// 16 bits, wide enough for counters and addresses.
+    Int,
+    UInt,
+    /// 32 bits.
+    Long,
+    ULong,
+    /// The address of a value of the type, as declared by `ty *name`.
+    Pointer(Box<Type>),
+    /// `len` consecutive `elem`s, as declared by `elem name[len]`.
+    Array {
+        elem: Box<Type>,
+        len: u64,
+    },
+    /// `struct tag`, laid out by the `StructDef` with that tag.
+    Struct(String),
+    /// `union tag`, laid out by the `StructDef` with that tag and `union` set.
+    Union(String),
+    /// `enum tag`, stored in a byte.
+    Enum(String),
+    /// A function taking `params` and returning `ret`. Only pointers to functions are values.
+    Function {
+        ret: Box<Type>,
+        params: Vec<Type>,
+    },
+    /// The type with non-empty qualifiers, as declared by `const ty name` or `ty *const name`.
+    /// Built by `Type::qualified`, which never nests them or qualifies an array.
+    Qualified(Quals, Box<Type>),
+}
+
+/// The qualifiers of a type. A `constant` object can't be assigned to once initialized, and a
+/// `volatile` one can change or be read outside the program's control, as a memory-mapped I/O
+/// register is, so every access to it must happen exactly as written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Quals {
+    pub constant: bool,
+    pub volatile: bool,
+}
+
+impl Quals {
+    pub fn is_empty(self) -> bool {
+        !self.constant && !self.volatile
+    }
+
+    /// Whether these qualifiers include all of `other`.
+    pub fn contains(self, other: Quals) -> bool {
+        (self.constant || !other.constant) && (self.volatile || !other.volatile)
+    }
+}
+
+/// Both sets of qualifiers together.
+impl std::ops::BitOr for Quals {
+    type Output = Quals;
+
+    fn bitor(self, other: Quals) -> Quals {
+        Quals {
+            constant: self.constant || other.constant,
+            volatile: self.volatile || other.volatile,
+        }
+    }
+}
+
+/// Qualifiers print as C writes them, such as `const volatile`.
+impl fmt::Display for Quals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.constant, self.volatile) {
+            (true, true) => write!(f, "const volatile"),
+            (true, false) => write!(f, "const"),
+            (false, true) => write!(f, "volatile"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+/// Types print as C writes them without a name, such as `char*[4]` or `void(*)(int)`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (specifier, declarator) = self.declaration("");
+        write!(f, "{}{}", specifier, declarator)
+    }
+}
+
+impl Type {
+    /// The two halves of a C declaration of `name` with this type: the specifier, such as
+    /// `const char`, and the declarator around the name, such as `*name[4]`. An empty name
+    /// gives the abstract declarator a cast or `sizeof` takes.
+    pub fn declaration(&self, name: &str) -> (String, String) {
+        // C types read inside out, so build the declarator from the outermost step in
+        let mut ty = self;
+        let mut declarator = name.to_string();
+        loop {
+            let parenthesized = |declarator: String| {
+                if declarator.starts_with('*') {
+                    format!("({})", declarator)
+                } else {
+                    declarator
+                }
+            };
+            let specifier = match ty {
+                Type::Void => "void".to_string(),
+                Type::Bool => "bool".to_string(),
+                Type::Char => "char".to_string(),
+                Type::Int => "int".to_string(),
+                Type::UChar => "unsigned char".to_string(),
+                Type::UInt => "unsigned int".to_string(),
+                Type::Long => "long".to_string(),
+                Type::ULong => "unsigned long".to_string(),
+                Type::Struct(tag) => format!("struct {}", tag),
+                Type::Union(tag) => format!("union {}", tag),
+                Type::Enum(tag) => format!("enum {}", tag),
+                // a qualified pointer is written `*const`, anything else `const char`
+                Type::Qualified(quals, inner) if matches!(**inner, Type::Pointer(_)) => {
+                    let named = declarator.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+                    let space = if named { " " } else { "" };
+                    declarator = format!("{}{}{}", quals, space, declarator);
+                    ty = inner;
+                    continue;
+                }
+                Type::Qualified(quals, inner) => format!("{} {}", quals, inner.declaration("").0),
+                Type::Pointer(to) => {
+                    declarator.insert(0, '*');
+                    ty = to;
+                    continue;
+                }
+                Type::Array { elem, len } => {
+                    declarator = format!("{}[{}]", parenthesized(declarator), len);
+                    ty = elem;
+                    continue;
+                }
+                Type::Function { ret, params } => {
+                    let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                    let params = params.join(", ");
+                    declarator = format!("{}({})", parenthesized(declarator), params);
+                    ty = ret;
+                    continue;
+                }
+            };
+            return (specifier, declarator);
+        }
+    }
+
+    /// How many bytes a value of the type takes up in memory, looking up struct and union
+    /// types in `structs`. A `char` is one byte, an `int` two, as are pointers, and a `long`
+    /// four. `None` for `void`, functions, and structs or unions that aren't defined there.
+    pub fn size(&self, structs: &[StructDef]) -> Option<u64> {
+        match self {
+            Type::Void | Type::Function { .. } => None,
+            Type::Bool | Type::Char | Type::UChar | Type::Enum(_) => Some(1),
+            Type::Int | Type::UInt => Some(2),
+            Type::Long | Type::ULong => Some(4),
+            // addresses are 16 bits
+            Type::Pointer(_) => Some(2),
+            Type::Array { elem, len } => elem.size(structs)?.checked_mul(*len),
+            Type::Qualified(_, ty) => ty.size(structs),
+            Type::Struct(tag) | Type::Union(tag) => {
+                let union = matches!(self, Type::Union(_));
+                let def = structs
+                    .iter()
+                    .find(|s| s.tag.name == *tag && s.union == union);
+                def.map(|s| s.size)
+            }
+        }
+    }
+
+    /// The byte offset of `array[indices[0]][indices[1]]...` from the start of an array of
+    /// this type. Arrays are laid out row-major, so for `T a[n][m]` the element `a[i][j]` is
+    /// at `(i * m + j) * size(T)`; codegen computes the same addresses at run time. Indices are
+    /// not checked against the lengths. `None` if there are more indices than dimensions.
+    pub fn element_offset(&self, indices: &[u64], structs: &[StructDef]) -> Option<u64> {
+        let mut ty = self;
+        let mut offset = 0u64;
+        for &i in indices {
+            let Type::Array { elem, .. } = ty else {
+                return None;
+            };
+            offset = offset.checked_add(i.checked_mul(elem.size(structs)?)?)?;
+            ty = elem;
+        }
+        Some(offset)
+    }
+
+    /// The type with `quals` added. Qualifying an array qualifies its elements, as in C.
+    pub fn qualified(self, quals: Quals) -> Type {
+        match self {
+            _ if quals.is_empty() => self,
+            Type::Qualified(own, ty) => Type::Qualified(own | quals, ty),
+            Type::Array { elem, len } => Type::Array {
+                elem: Box::new(elem.qualified(quals)),
+                len,
+            },
+            ty => Type::Qualified(quals, Box::new(ty)),
+        }
+    }
+
+    /// The qualifiers of the type itself, not of anything it points to or contains.
+    pub fn quals(&self) -> Quals {
+        match self {
+            Type::Qualified(quals, _) => *quals,
+            _ => Quals::default(),
+        }
+    }
+
+    /// The type without its own qualifiers.
+    pub fn unqualified(&self) -> &Type {
+        match self {
+            Type::Qualified(_, ty) => ty,
+            ty => ty,
+        }
+    }
+
+    /// Whether the type is an integer type, which arithmetic can be done on.
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            Type::Bool
+                | Type::Char
+                | Type::UChar
+                | Type::Int
+                | Type::UInt
+                | Type::Long
+                | Type::ULong
+                | Type::Enum(_)
+        )
+    }
+
+    /// Whether the type is an unsigned integer type.
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self.unqualified(), Type::UChar | Type::UInt | Type::ULong)
+    }
+
+    /// The type a value of this type has when used in an expression: arrays decay to pointers
+    /// to their first element, and functions to pointers to themselves. A value has no
+    /// qualifiers of its own, only the object it was read from.
+    pub fn decayed(&self) -> Type {
+        match self.unqualified() {
+            Type::Array { elem, .. } => Type::Pointer(elem.clone()),
+            ty @ Type::Function { .. } => Type::Pointer(Box::new(ty.clone())),
+            ty => ty.clone(),
+        }
+    }
+
+    /// The type of `cond ? a : b` when `a` has this type and `b` has `other`, or `None` if the
+    /// arms are incompatible. Integer arms give the wider of the two and other arms must have
+    /// the same type, except that a pointer to `void` is compatible with any pointer and gives
+    /// `void*`, qualified as both pointees are. The literal `0` is a null pointer and fits
+    /// either arm, which only the checker can see.
+    ///
+    /// For integers this is also the type arithmetic is done in. Hex8 doesn't promote narrow
+    /// operands to `int` the way C does, so `char + char` stays a `char`.
+    pub fn common(&self, other: &Type) -> Option<Type> {
+        let (a, b) = (self.decayed(), other.decayed());
+        if a.is_integer() && b.is_integer() {
+            // an enum is a signed byte here
+            let size = |ty: &Type| ty.size(&[]).unwrap_or(1);
+            // a `bool` arm widens to whatever the other is
+            match (&a, &b) {
+                _ if a == b => return Some(a),
+                (Type::Bool, _) => return Some(b),
+                (_, Type::Bool) => return Some(a),
+                _ => {}
+            }
+            let wider = match size(&a).cmp(&size(&b)) {
+                Ordering::Greater => return Some(a),
+                Ordering::Less => return Some(b),
+                Ordering::Equal => size(&a),
+            };
+            let unsigned = a.is_unsigned() || b.is_unsigned();
+            return Some(match (wider, unsigned) {
+                (1, false) => Type::Char,
+                (1, true) => Type::UChar,
+                (2, false) => Type::Int,
+                (2, true) => Type::UInt,
+                (_, false) => Type::Long,
+                (_, true) => Type::ULong,
+            });
+        }
+        match (&a, &b) {
+            _ if a == b => Some(a),
+            (Type::Pointer(x), Type::Pointer(y))
+                if *x.unqualified() == Type::Void || *y.unqualified() == Type::Void =>
+            {
+                let void = Type::Void.qualified(x.quals() | y.quals());
+                Some(Type::Pointer(Box::new(void)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A whole source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// A top-level name the file shares with other files through the linker, and whether this
+/// file defines it or leaves that to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct External<'a> {
+    pub name: &'a Ident,
+    pub defined: bool,
+}
+
+impl Program {
+    /// The names with external linkage, in the order they are first declared. That is every
+    /// top-level variable and function not declared `static`. A variable is defined unless
+    /// it is `extern`, and a function if it has a body.
+    pub fn externals(&self) -> Vec<External<'_>> {
+        let mut externals: Vec<External<'_>> = Vec::new();
+        for stmt in &self.stmts {
+            let StmtKind::Decl(decls) = &stmt.kind else {
+                continue;
+            };
+            for decl in decls {
+                let (name, storage, defined) = match decl {
+                    Decl::Var(var) => {
+                        (&var.name, var.storage, var.storage != Some(Storage::Extern))
+                    }
+                    Decl::Func(func) => (&func.name, func.storage, func.body.is_some()),
+                    _ => continue,
+                };
+                if storage == Some(Storage::Static) {
+                    continue;
+                }
+                match externals.iter_mut().find(|e| e.name.name == name.name) {
+                    Some(external) => external.defined |= defined,
+                    None => externals.push(External { name, defined }),
+                }
+            }
+        }
+        externals
+    }
+}
+
+/// A storage class given to a declaration. A `Static` top-level name has internal linkage,
+/// hidden from other files, and a `Static` local variable lives in the data segment like a
+/// global, keeping its value between calls. An `Extern` declaration refers to a variable or
+/// function defined elsewhere, usually in another file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Static,
+    Extern,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decl {
+    Var(VarDecl),
+    Func(Function),
+    Struct(StructDef),
+    Enum(EnumDef),
+    Typedef(Typedef),
+}
+
+impl Spanned for Decl {
+    fn span(&self) -> Span {
+        match self {
+            Decl::Var(var) => var.span,
+            Decl::Func(func) => func.span,
+            Decl::Struct(def) => def.span,
+            Decl::Enum(def) => def.span,
+            Decl::Typedef(def) => def.span,
+        }
+    }
+}
+
+/// `struct tag { fields }`, or `union tag { fields }` when `union` is set. A struct's fields are
+/// laid out in order with no padding between them, since Hex8 has no alignment requirements.
+/// A union's fields all start at offset 0, overlapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructDef {
+    pub union: bool,
+    pub tag: Ident,
+    pub fields: Vec<Field>,
+    /// The total size of the fields in bytes, or the size of the largest for a union.
+    pub size: u64,
+    pub span: Span,
+}
+
+impl StructDef {
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name.name == name)
+    }
+}
+
+/// One declarator of `typedef ty name;`, making `name` another way to write its type. Where
+/// the name is used as a type, the AST holds the type itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Typedef {
+    pub ty: Type,
+    pub name: Ident,
+    pub span: Span,
+}
+
+/// `enum tag { enumerators }`. The tag may be left out, in which case the enum only names
+/// constants and a variable declared with it is a `char`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumDef {
+    pub tag: Option<Ident>,
+    pub enumerators: Vec<Enumerator>,
+    pub span: Span,
+}
+
+/// `name` or `name = value` in an enum, spanning both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enumerator {
+    pub name: Ident,
+    pub value: i64,
+    pub span: Span,
+}
+
+/// One field of a struct, spanning its declarator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub ty: Type,
+    pub name: Ident,
+    /// The byte offset of the field from the start of the struct.
+    pub offset: u64,
+    pub span: Span,
+}
+
+/// One declarator `name = init` of a declaration `ty a, b = init;`, spanning from the name to
+/// the end of `init`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDecl {
+    pub storage: Option<Storage>,
+    pub ty: Type,
+    pub name: Ident,
+    pub init: Option<Init>,
+    pub span: Span,
+}
+
+/// The initial value of a variable: an expression, or a braced list such as `{1, {2, 3}}` for
+/// an array or struct. Whatever a list leaves out is zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Init {
+    Expr(Expr),
+    List { items: Vec<Init>, span: Span },
+}
+
+impl Spanned for Init {
+    fn span(&self) -> Span {
+        match self {
+            Init::Expr(expr) => expr.span,
+            Init::List { span, .. } => *span,
+        }
+    }
+}
+
+impl Init {
+    /// The length an array of unspecified length takes from this initializer: the number of
+    /// items in a list, or the bytes of a string literal including its terminating zero.
+    pub fn implied_len(&self) -> Option<u64> {
+        match self {
+            Init::List { items, .. } => Some(items.len() as u64),
+            Init::Expr(Expr {
+                kind: ExprKind::Str(bytes),
+                ..
+            }) => Some(bytes.len() as u64 + 1),
+            Init::Expr(_) => None,
+        }
+    }
+
+    /// Each expression the initializer gives an object of type `ty`, with the byte offset
+    /// within the object it initializes, in order. A string literal initializing a `char`
+    /// array stands for its bytes. Every byte not covered is zero, which is how the data
+    /// segment lays the object out. `None` if a struct in `ty` isn't in `structs`.
+    pub fn data<'a>(&'a self, ty: &Type, structs: &[StructDef]) -> Option<Vec<(u64, &'a Expr)>> {
+        let mut data = Vec::new();
+        self.collect_data(ty, 0, structs, &mut data)?;
+        Some(data)
+    }
+
+    fn collect_data<'a>(
+        &'a self,
+        ty: &Type,
+        offset: u64,
+        structs: &[StructDef],
+        data: &mut Vec<(u64, &'a Expr)>,
+    ) -> Option<()> {
+        let items = match self {
+            Init::Expr(expr) => {
+                data.push((offset, expr));
+                return Some(());
+            }
+            Init::List { items, .. } => items,
+        };
+        match ty {
+            Type::Array { elem, .. } => {
+                let size = elem.size(structs)?;
+                for (i, item) in (0..).zip(items) {
+                    item.collect_data(elem, offset + i * size, structs, data)?;
+                }
+            }
+            Type::Struct(tag) | Type::Union(tag) => {
+                let def = structs.iter().find(|s| s.tag.name == *tag)?;
+                for (item, field) in items.iter().zip(&def.fields) {
+                    item.collect_data(&field.ty, offset + field.offset, structs, data)?;
+                }
+            }
+            ty => {
+                if let Some(item) = items.first() {
+                    item.collect_data(ty, offset, structs, data)?;
+                }
+            }
+        }
+        Some(())
+    }
+}
+
+/// A function definition, `ret name(params) { ... }`, or a prototype `ret name(params);`. The
+/// span starts at the name, like a variable declarator's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub storage: Option<Storage>,
+    pub ret: Type,
+    pub name: Ident,
+    pub params: Vec<Param>,
+    /// A `StmtKind::Block`, or `None` for a prototype.
+    pub body: Option<Box<Stmt>>,
+    pub span: Span,
+}
+
+impl Function {
+    /// The number of arguments a call must pass.
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// The `Type::Function` its name has in expressions.
+    pub fn ty(&self) -> Type {
+        Type::Function {
+            ret: Box::new(self.ret.clone()),
+            params: self.params.iter().map(|p| p.ty.clone()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub ty: Type,
+    /// Only a prototype may leave parameters unnamed.
+    pub name: Option<Ident>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StmtKind {
+    /// The declarators of one declaration, in order. A function definition is always alone.
+    Decl(Vec<Decl>),
+    /// An expression evaluated for its side effects, such as an assignment.
+    Expr(Expr),
+    Block(Vec<Stmt>),
+    /// An `else` belongs to the nearest `if` without one, so `if (a) if (b) x; else y;`
+    /// runs `y` when `a` holds and `b` doesn't.
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        els: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    /// `do body while (cond);`, which runs `body` once before the first test.
+    DoWhile {
+        body: Box<Stmt>,
+        cond: Expr,
+    },
+    /// `for (init; cond; step) body`, any clause of which may be empty. `init` is a
+    /// declaration or expression statement, scoped to the loop. See `lower::lower_for`.
+    For {
+        init: Option<Box<Stmt>>,
+        cond: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    /// `switch (cond) body`. Control jumps to the matching `Case` or the `Default` inside
+    /// `body` and falls through from there until a `break`.
+    Switch {
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    /// `case value: stmt`, where `value` is a constant expression.
+    Case {
+        value: Expr,
+        stmt: Box<Stmt>,
+    },
+    /// `default: stmt`.
+    Default(Box<Stmt>),
+    /// Leaves the innermost loop or `switch`.
+    Break,
+    /// Skips to the next iteration of the innermost loop.
+    Continue,
+    /// `label: stmt`, a target for `goto`.
+    Label {
+        label: Ident,
+        stmt: Box<Stmt>,
+    },
+    Goto(Ident),
+    /// `return value;`, or a bare `return;` from a `void` function.
+    Return(Option<Expr>),
+    /// A lone `;`.
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    Int(u64),
+    Char(u8),
+    /// The bytes of a string literal, with escapes decoded and adjacent literals joined.
+    Str(Vec<u8>),
+    Var(String),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    /// `lhs = rhs`, or a compound assignment such as `lhs += rhs` when an operator is given.
+    Assign(Option<BinOp>, Box<Expr>, Box<Expr>),
+    /// `array[index]`. Indexing a multi-dimensional array nests, `a[i][j]` being
+    /// `Index(Index(a, i), j)`.
+    Index(Box<Expr>, Box<Expr>),
+    /// `value.field`.
+    Member(Box<Expr>, Ident),
+    /// `ptr->field`, the same as `(*ptr).field`.
+    Arrow(Box<Expr>, Ident),
+    /// `(ty) expr`, converting the value to `ty`: truncating or extending integers, or
+    /// reinterpreting pointers. A cast also marks a narrowing conversion as intended.
+    Cast(Type, Box<Expr>),
+    /// `sizeof(ty)`, the size of the type in bytes.
+    Sizeof(Type),
+    /// `sizeof expr`, the size in bytes of the type of `expr`, which isn't evaluated.
+    SizeofExpr(Box<Expr>),
+    /// `lhs, rhs`, which evaluates `lhs` for its side effects and then gives `rhs`.
+    Comma(Box<Expr>, Box<Expr>),
+    /// `cond ? then : els`, which only evaluates one of the arms.
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `callee(args)`.
+    Call(Box<Expr>, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnOp {
+    Neg,
+    /// Unary `+`, which only promotes its operand.
+    Plus,
+    Not,
+    BitNot,
+    PreInc,
+    PreDec,
+    PostInc,
+    PostDec,
+    /// `*ptr`, the value a pointer points to.
+    Deref,
+    /// `&lvalue`, the address of a value.
+    Addr,
+}
+
+impl UnOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Plus => "+",
+            UnOp::Not => "!",
+            UnOp::BitNot => "~",
+            UnOp::PreInc | UnOp::PostInc => "++",
+            UnOp::PreDec | UnOp::PostDec => "--",
+            UnOp::Deref => "*",
+            UnOp::Addr => "&",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinOp {
+    /// How tightly the operator binds, following C: `*` is highest at 10 and `||` lowest at
+    /// 1. Every binary operator is left associative.
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Mod => 10,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => 7,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::BitAnd => 5,
+            BinOp::BitXor => 4,
+            BinOp::BitOr => 3,
+            BinOp::And => 2,
+            BinOp::Or => 1,
+        }
+    }
+
+    /// Whether the operator is a comparison or logical operator, giving a `bool`.
+    pub fn is_boolean(self) -> bool {
+        matches!(
+            self,
+            BinOp::Lt
+                | BinOp::Gt
+                | BinOp::Le
+                | BinOp::Ge
+                | BinOp::Eq
+                | BinOp::Ne
+                | BinOp::And
+                | BinOp::Or
+        )
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::BitAnd => "&",
+            BinOp::BitXor => "^",
+            BinOp::BitOr => "|",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_test() {
+        let array = |elem, len| Type::Array {
+            elem: Box::new(elem),
+            len,
+        };
+        let grid = array(array(Type::Int, 3), 2);
+        assert_eq!(grid.to_string(), "int[2][3]");
+        assert_eq!(grid.size(&[]), Some(12));
+        assert_eq!(grid.element_offset(&[1, 2], &[]), Some(10));
+        assert_eq!(grid.element_offset(&[1], &[]), Some(6));
+        assert_eq!(grid.element_offset(&[], &[]), Some(0));
+        assert_eq!(grid.element_offset(&[0, 0, 0], &[]), None);
+        assert_eq!(array(Type::Void, 4).size(&[]), None);
+
+        let ptr = |to| Type::Pointer(Box::new(to));
+        assert_eq!(Type::Char.common(&Type::Int), Some(Type::Int));
+        assert_eq!(Type::UInt.common(&Type::Int), Some(Type::UInt));
+        assert_eq!(Type::UInt.common(&Type::Long), Some(Type::Long));
+        assert_eq!(Type::UChar.common(&Type::Char), Some(Type::UChar));
+        assert_eq!(Type::Bool.common(&Type::Char), Some(Type::Char));
+        assert_eq!(Type::ULong.size(&[]), Some(4));
+        assert_eq!(ptr(Type::UChar).to_string(), "unsigned char*");
+        assert_eq!(
+            Type::Char.common(&Type::Enum("e".to_string())),
+            Some(Type::Char)
+        );
+        assert_eq!(
+            array(Type::Char, 3).common(&ptr(Type::Char)),
+            Some(ptr(Type::Char))
+        );
+        assert_eq!(
+            ptr(Type::Int).common(&ptr(Type::Void)),
+            Some(ptr(Type::Void))
+        );
+        assert_eq!(ptr(Type::Int).common(&ptr(Type::Char)), None);
+        assert_eq!(ptr(Type::Int).common(&Type::Int), None);
+        assert_eq!(Type::Void.common(&Type::Void), Some(Type::Void));
+        assert_eq!(
+            array(Type::Pointer(Box::new(Type::Char)), 3).size(&[]),
+            Some(6)
+        );
+
+        let constant = Quals {
+            constant: true,
+            volatile: false,
+        };
+        let table = array(Type::Char, 4).qualified(constant);
+        assert_eq!(table, array(Type::Char.qualified(constant), 4));
+        assert_eq!(table.size(&[]), Some(4));
+        assert_eq!(table.decayed().to_string(), "const char*");
+        let fixed = ptr(Type::Int).qualified(constant);
+        assert_eq!(fixed.decayed(), ptr(Type::Int));
+        assert!(Type::Char.qualified(constant).is_integer());
+        assert_eq!(
+            ptr(Type::Void.qualified(constant)).common(&ptr(Type::Int)),
+            Some(ptr(Type::Void.qualified(constant)))
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Constructors for building trees in code rather than parsing them. Built nodes are
+//! synthetic: their spans are the empty `Span::default()`, so their text is empty, and
+//! `pretty::ast_to_source` is the way to see them.
+
+use super::*;
+
+impl Ident {
+    pub fn new(name: impl Into<String>) -> Ident {
+        Ident {
+            name: name.into(),
+            span: Span::default(),
+        }
+    }
+}
+
+impl Program {
+    pub fn new(stmts: Vec<Stmt>) -> Program {
+        Program {
+            stmts,
+            span: Span::default(),
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind) -> Expr {
+        Expr {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    pub fn int(value: u64) -> Expr {
+        Expr::new(ExprKind::Int(value))
+    }
+
+    pub fn char(value: u8) -> Expr {
+        Expr::new(ExprKind::Char(value))
+    }
+
+    pub fn str(bytes: impl Into<Vec<u8>>) -> Expr {
+        Expr::new(ExprKind::Str(bytes.into()))
+    }
+
+    pub fn var(name: impl Into<String>) -> Expr {
+        Expr::new(ExprKind::Var(name.into()))
+    }
+
+    pub fn unary(op: UnOp, operand: Expr) -> Expr {
+        Expr::new(ExprKind::Unary(op, Box::new(operand)))
+    }
+
+    pub fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    /// `lhs = rhs`.
+    pub fn assign(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::Assign(None, Box::new(lhs), Box::new(rhs)))
+    }
+
+    /// `lhs op= rhs`.
+    pub fn compound_assign(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::Assign(Some(op), Box::new(lhs), Box::new(rhs)))
+    }
+
+    pub fn index(array: Expr, index: Expr) -> Expr {
+        Expr::new(ExprKind::Index(Box::new(array), Box::new(index)))
+    }
+
+    /// `self.field`.
+    pub fn member(self, field: &str) -> Expr {
+        Expr::new(ExprKind::Member(Box::new(self), Ident::new(field)))
+    }
+
+    /// `self->field`.
+    pub fn arrow(self, field: &str) -> Expr {
+        Expr::new(ExprKind::Arrow(Box::new(self), Ident::new(field)))
+    }
+
+    pub fn cast(ty: Type, value: Expr) -> Expr {
+        Expr::new(ExprKind::Cast(ty, Box::new(value)))
+    }
+
+    pub fn sizeof(ty: Type) -> Expr {
+        Expr::new(ExprKind::Sizeof(ty))
+    }
+
+    pub fn comma(lhs: Expr, rhs: Expr) -> Expr {
+        Expr::new(ExprKind::Comma(Box::new(lhs), Box::new(rhs)))
+    }
+
+    pub fn cond(cond: Expr, then: Expr, els: Expr) -> Expr {
+        Expr::new(ExprKind::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(els),
+        ))
+    }
+
+    pub fn call(callee: &str, args: Vec<Expr>) -> Expr {
+        Expr::new(ExprKind::Call(Box::new(Expr::var(callee)), args))
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind) -> Stmt {
+        Stmt {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    pub fn expr(expr: Expr) -> Stmt {
+        Stmt::new(StmtKind::Expr(expr))
+    }
+
+    pub fn block(stmts: Vec<Stmt>) -> Stmt {
+        Stmt::new(StmtKind::Block(stmts))
+    }
+
+    pub fn if_then(cond: Expr, then: Stmt) -> Stmt {
+        Stmt::new(StmtKind::If {
+            cond,
+            then: Box::new(then),
+            els: None,
+        })
+    }
+
+    pub fn if_else(cond: Expr, then: Stmt, els: Stmt) -> Stmt {
+        Stmt::new(StmtKind::If {
+            cond,
+            then: Box::new(then),
+            els: Some(Box::new(els)),
+        })
+    }
+
+    pub fn while_loop(cond: Expr, body: Stmt) -> Stmt {
+        Stmt::new(StmtKind::While {
+            cond,
+            body: Box::new(body),
+        })
+    }
+
+    pub fn do_while(body: Stmt, cond: Expr) -> Stmt {
+        Stmt::new(StmtKind::DoWhile {
+            body: Box::new(body),
+            cond,
+        })
+    }
+
+    pub fn for_loop(
+        init: Option<Stmt>,
+        cond: Option<Expr>,
+        step: Option<Expr>,
+        body: Stmt,
+    ) -> Stmt {
+        Stmt::new(StmtKind::For {
+            init: init.map(Box::new),
+            cond,
+            step,
+            body: Box::new(body),
+        })
+    }
+
+    pub fn ret(value: Option<Expr>) -> Stmt {
+        Stmt::new(StmtKind::Return(value))
+    }
+
+    /// A declaration of the variable `name`, with no storage class.
+    pub fn var(ty: Type, name: &str, init: Option<Expr>) -> Stmt {
+        Stmt::new(StmtKind::Decl(vec![Decl::Var(VarDecl {
+            storage: None,
+            ty,
+            name: Ident::new(name),
+            init: init.map(Init::Expr),
+            span: Span::default(),
+        })]))
+    }
+
+    /// The definition of a function taking the named `params`, with `body` as the statements
+    /// of its block.
+    pub fn function(ret: Type, name: &str, params: Vec<(Type, &str)>, body: Vec<Stmt>) -> Stmt {
+        let params = params
+            .into_iter()
+            .map(|(ty, name)| Param {
+                ty,
+                name: Some(Ident::new(name)),
+                span: Span::default(),
+            })
+            .collect();
+        Stmt::new(StmtKind::Decl(vec![Decl::Func(Function {
+            storage: None,
+            ret,
+            name: Ident::new(name),
+            params,
+            body: Some(Box::new(Stmt::block(body))),
+            span: Span::default(),
+        })]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::same_program;
+    use crate::parser::parse;
+    use crate::pretty::ast_to_source;
+
+    #[test]
+    fn build_test() {
+        let n = || Expr::var("n");
+        let program = Program::new(vec![Stmt::function(
+            Type::Int,
+            "fact",
+            vec![(Type::Int, "n")],
+            vec![
+                Stmt::var(Type::Int, "r", Some(Expr::int(1))),
+                Stmt::while_loop(
+                    Expr::binary(BinOp::Gt, n(), Expr::int(1)),
+                    Stmt::expr(Expr::compound_assign(
+                        BinOp::Mul,
+                        Expr::var("r"),
+                        Expr::unary(UnOp::PostDec, n()),
+                    )),
+                ),
+                Stmt::if_else(
+                    Expr::binary(BinOp::Lt, n(), Expr::int(0)),
+                    Stmt::ret(Some(Expr::int(0))),
+                    Stmt::ret(Some(Expr::binary(
+                        BinOp::Mul,
+                        Expr::var("r"),
+                        Expr::binary(BinOp::Add, Expr::int(1), Expr::int(0)),
+                    ))),
+                ),
+            ],
+        )]);
+        let src = ast_to_source(&program);
+        let expected = "int fact(int n) { int r = 1; while (n > 1) r *= n--; \
+                        if (n < 0) return 0; else return r * (1 + 0); }";
+        assert!(same_program(
+            &parse(&src).unwrap(),
+            &parse(expected).unwrap()
+        ));
+    }
+}
@@ -0,0 +1,861 @@
+//! The JSON form of the tree, for tools that read parsed programs without linking the
+//! crate.
+//!
+//! Every node is an object. Nodes of an enum carry a `"kind"` tag naming the variant in
+//! snake case, followed by its fields; nodes with a span carry `"span": {"start", "end"}` in
+//! bytes. The root object also records `"version"`, `SCHEMA_VERSION`, which changes whenever
+//! a field is renamed, removed or changes meaning, so adding fields keeps the version.
+//!
+//! Numbers are JSON integers, except an integer literal too big for an `i64`, which is written
+//! as its decimal string. String literals are arrays of bytes, since they needn't be UTF-8.
+//!
+//! `program_from_json` reads the schema back. Like the token dump, this is written against the
+//! crate's own `json` module rather than serde, which the offline build can't fetch, and there
+//! is no RON form: tools wanting one can convert from the JSON.
+
+use crate::json::{FromJson, Json, JsonError, ToJson};
+
+use super::*;
+
+/// The version of the schema written by `program_to_json`.
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Dumps a program as a single JSON object in the schema described above.
+pub fn program_to_json(program: &Program) -> String {
+    program.to_json().to_string()
+}
+
+fn num(n: u64) -> Json {
+    i64::try_from(n).map_or_else(|_| Json::Str(n.to_string()), Json::Num)
+}
+
+fn str(s: &str) -> Json {
+    Json::Str(s.to_string())
+}
+
+fn opt<T: ToJson>(value: Option<&T>) -> Json {
+    value.map_or(Json::Null, ToJson::to_json)
+}
+
+/// An object with a `"kind"` tag, the given fields and a span.
+fn node<const N: usize>(kind: &str, fields: [(&str, Json); N], span: Span) -> Json {
+    let mut obj = vec![("kind".to_string(), str(kind))];
+    obj.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+    obj.push(("span".to_string(), span.to_json()));
+    Json::Obj(obj)
+}
+
+impl ToJson for Ident {
+    fn to_json(&self) -> Json {
+        Json::obj([("name", str(&self.name)), ("span", self.span.to_json())])
+    }
+}
+
+impl ToJson for Quals {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("const", Json::Bool(self.constant)),
+            ("volatile", Json::Bool(self.volatile)),
+        ])
+    }
+}
+
+/// Types have no span: `{"kind": "int"}`, `{"kind": "pointer", "to": ty}`,
+/// `{"kind": "struct", "tag": "point"}` and so on.
+impl ToJson for Type {
+    fn to_json(&self) -> Json {
+        let kind = |kind| ("kind", str(kind));
+        match self {
+            Type::Void => Json::obj([kind("void")]),
+            Type::Bool => Json::obj([kind("bool")]),
+            Type::Char => Json::obj([kind("char")]),
+            Type::UChar => Json::obj([kind("uchar")]),
+            Type::Int => Json::obj([kind("int")]),
+            Type::UInt => Json::obj([kind("uint")]),
+            Type::Long => Json::obj([kind("long")]),
+            Type::ULong => Json::obj([kind("ulong")]),
+            Type::Pointer(to) => Json::obj([kind("pointer"), ("to", to.to_json())]),
+            Type::Array { elem, len } => {
+                Json::obj([kind("array"), ("elem", elem.to_json()), ("len", num(*len))])
+            }
+            Type::Struct(tag) => Json::obj([kind("struct"), ("tag", str(tag))]),
+            Type::Union(tag) => Json::obj([kind("union"), ("tag", str(tag))]),
+            Type::Enum(tag) => Json::obj([kind("enum"), ("tag", str(tag))]),
+            Type::Function { ret, params } => Json::obj([
+                kind("function"),
+                ("ret", ret.to_json()),
+                ("params", params.to_json()),
+            ]),
+            Type::Qualified(quals, ty) => Json::obj([
+                kind("qualified"),
+                ("quals", quals.to_json()),
+                ("type", ty.to_json()),
+            ]),
+        }
+    }
+}
+
+impl ToJson for Program {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("version", Json::Num(SCHEMA_VERSION)),
+            ("stmts", self.stmts.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Storage {
+    fn to_json(&self) -> Json {
+        str(match self {
+            Storage::Static => "static",
+            Storage::Extern => "extern",
+        })
+    }
+}
+
+impl ToJson for Decl {
+    fn to_json(&self) -> Json {
+        match self {
+            Decl::Var(var) => var.to_json(),
+            Decl::Func(func) => func.to_json(),
+            Decl::Struct(def) => def.to_json(),
+            Decl::Enum(def) => def.to_json(),
+            Decl::Typedef(def) => def.to_json(),
+        }
+    }
+}
+
+impl ToJson for VarDecl {
+    fn to_json(&self) -> Json {
+        node(
+            "var",
+            [
+                ("storage", opt(self.storage.as_ref())),
+                ("type", self.ty.to_json()),
+                ("name", self.name.to_json()),
+                ("init", opt(self.init.as_ref())),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Init {
+    fn to_json(&self) -> Json {
+        match self {
+            Init::Expr(expr) => expr.to_json(),
+            Init::List { items, span } => node("init_list", [("items", items.to_json())], *span),
+        }
+    }
+}
+
+impl ToJson for Function {
+    fn to_json(&self) -> Json {
+        node(
+            "function",
+            [
+                ("storage", opt(self.storage.as_ref())),
+                ("ret", self.ret.to_json()),
+                ("name", self.name.to_json()),
+                ("params", self.params.to_json()),
+                ("body", opt(self.body.as_ref())),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Param {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("type", self.ty.to_json()),
+            ("name", opt(self.name.as_ref())),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for StructDef {
+    fn to_json(&self) -> Json {
+        node(
+            if self.union { "union" } else { "struct" },
+            [
+                ("tag", self.tag.to_json()),
+                ("fields", self.fields.to_json()),
+                ("size", num(self.size)),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Field {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("type", self.ty.to_json()),
+            ("name", self.name.to_json()),
+            ("offset", num(self.offset)),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for EnumDef {
+    fn to_json(&self) -> Json {
+        node(
+            "enum",
+            [
+                ("tag", opt(self.tag.as_ref())),
+                ("enumerators", self.enumerators.to_json()),
+            ],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Enumerator {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("name", self.name.to_json()),
+            ("value", Json::Num(self.value)),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Typedef {
+    fn to_json(&self) -> Json {
+        node(
+            "typedef",
+            [("type", self.ty.to_json()), ("name", self.name.to_json())],
+            self.span,
+        )
+    }
+}
+
+impl ToJson for Stmt {
+    fn to_json(&self) -> Json {
+        let span = self.span;
+        match &self.kind {
+            StmtKind::Decl(decls) => node("decl", [("decls", decls.to_json())], span),
+            StmtKind::Expr(expr) => node("expr", [("expr", expr.to_json())], span),
+            StmtKind::Block(stmts) => node("block", [("stmts", stmts.to_json())], span),
+            StmtKind::If { cond, then, els } => node(
+                "if",
+                [
+                    ("cond", cond.to_json()),
+                    ("then", then.to_json()),
+                    ("else", opt(els.as_ref())),
+                ],
+                span,
+            ),
+            StmtKind::While { cond, body } => node(
+                "while",
+                [("cond", cond.to_json()), ("body", body.to_json())],
+                span,
+            ),
+            StmtKind::DoWhile { body, cond } => node(
+                "do_while",
+                [("body", body.to_json()), ("cond", cond.to_json())],
+                span,
+            ),
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => node(
+                "for",
+                [
+                    ("init", opt(init.as_ref())),
+                    ("cond", opt(cond.as_ref())),
+                    ("step", opt(step.as_ref())),
+                    ("body", body.to_json()),
+                ],
+                span,
+            ),
+            StmtKind::Switch { cond, body } => node(
+                "switch",
+                [("cond", cond.to_json()), ("body", body.to_json())],
+                span,
+            ),
+            StmtKind::Case { value, stmt } => node(
+                "case",
+                [("value", value.to_json()), ("stmt", stmt.to_json())],
+                span,
+            ),
+            StmtKind::Default(stmt) => node("default", [("stmt", stmt.to_json())], span),
+            StmtKind::Break => node("break", [], span),
+            StmtKind::Continue => node("continue", [], span),
+            StmtKind::Label { label, stmt } => node(
+                "label",
+                [("label", label.to_json()), ("stmt", stmt.to_json())],
+                span,
+            ),
+            StmtKind::Goto(label) => node("goto", [("label", label.to_json())], span),
+            StmtKind::Return(value) => node("return", [("value", opt(value.as_ref()))], span),
+            StmtKind::Empty => node("empty", [], span),
+        }
+    }
+}
+
+/// Operators are written as their C symbols, the unary increments and decrements being
+/// `"pre++"`, `"post++"` and so on.
+impl ToJson for UnOp {
+    fn to_json(&self) -> Json {
+        str(match self {
+            UnOp::PreInc => "pre++",
+            UnOp::PreDec => "pre--",
+            UnOp::PostInc => "post++",
+            UnOp::PostDec => "post--",
+            op => op.symbol(),
+        })
+    }
+}
+
+impl ToJson for BinOp {
+    fn to_json(&self) -> Json {
+        str(self.symbol())
+    }
+}
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        let span = self.span;
+        match &self.kind {
+            ExprKind::Int(n) => node("int", [("value", num(*n))], span),
+            ExprKind::Char(c) => node("char", [("value", Json::Num(i64::from(*c)))], span),
+            ExprKind::Str(bytes) => {
+                let bytes = bytes.iter().map(|&b| Json::Num(i64::from(b))).collect();
+                node("str", [("bytes", Json::Arr(bytes))], span)
+            }
+            ExprKind::Var(name) => node("var", [("name", str(name))], span),
+            ExprKind::Unary(op, operand) => node(
+                "unary",
+                [("op", op.to_json()), ("operand", operand.to_json())],
+                span,
+            ),
+            ExprKind::Binary(op, lhs, rhs) => node(
+                "binary",
+                [
+                    ("op", op.to_json()),
+                    ("lhs", lhs.to_json()),
+                    ("rhs", rhs.to_json()),
+                ],
+                span,
+            ),
+            ExprKind::Assign(op, lhs, rhs) => node(
+                "assign",
+                [
+                    ("op", opt(op.as_ref())),
+                    ("lhs", lhs.to_json()),
+                    ("rhs", rhs.to_json()),
+                ],
+                span,
+            ),
+            ExprKind::Index(array, index) => node(
+                "index",
+                [("array", array.to_json()), ("index", index.to_json())],
+                span,
+            ),
+            ExprKind::Member(value, field) => node(
+                "member",
+                [("value", value.to_json()), ("field", field.to_json())],
+                span,
+            ),
+            ExprKind::Arrow(ptr, field) => node(
+                "arrow",
+                [("ptr", ptr.to_json()), ("field", field.to_json())],
+                span,
+            ),
+            ExprKind::Cast(ty, operand) => node(
+                "cast",
+                [("type", ty.to_json()), ("operand", operand.to_json())],
+                span,
+            ),
+            ExprKind::Sizeof(ty) => node("sizeof", [("type", ty.to_json())], span),
+            ExprKind::SizeofExpr(operand) => {
+                node("sizeof_expr", [("operand", operand.to_json())], span)
+            }
+            ExprKind::Comma(lhs, rhs) => node(
+                "comma",
+                [("lhs", lhs.to_json()), ("rhs", rhs.to_json())],
+                span,
+            ),
+            ExprKind::Cond(cond, then, els) => node(
+                "cond",
+                [
+                    ("cond", cond.to_json()),
+                    ("then", then.to_json()),
+                    ("else", els.to_json()),
+                ],
+                span,
+            ),
+            ExprKind::Call(callee, args) => node(
+                "call",
+                [("callee", callee.to_json()), ("args", args.to_json())],
+                span,
+            ),
+        }
+    }
+}
+
+/// An error for an object whose `"kind"` isn't one of the `expected` kinds of node.
+fn unknown_kind(kind: &str, expected: &str) -> JsonError {
+    JsonError {
+        pos: 0,
+        msg: format!("unknown {} kind `{}`", expected, kind),
+    }
+}
+
+fn kind_of(json: &Json) -> Result<&str, JsonError> {
+    json.field("kind")?
+        .as_str()
+        .ok_or_else(|| Json::type_error("a kind"))
+}
+
+/// Reads the required field `key`.
+fn get<T: FromJson>(json: &Json, key: &str) -> Result<T, JsonError> {
+    T::from_json(json.field(key)?)
+}
+
+/// Reads the field `key`, which is `null` when left out.
+fn get_opt<T: FromJson>(json: &Json, key: &str) -> Result<Option<T>, JsonError> {
+    match json.field(key)? {
+        Json::Null => Ok(None),
+        value => T::from_json(value).map(Some),
+    }
+}
+
+fn get_box<T: FromJson>(json: &Json, key: &str) -> Result<Box<T>, JsonError> {
+    get(json, key).map(Box::new)
+}
+
+fn get_span(json: &Json) -> Result<Span, JsonError> {
+    get(json, "span")
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<String, JsonError> {
+        json.as_str()
+            .map(str::to_string)
+            .ok_or_else(|| Json::type_error("a string"))
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<bool, JsonError> {
+        match json {
+            Json::Bool(b) => Ok(*b),
+            _ => Err(Json::type_error("a boolean")),
+        }
+    }
+}
+
+impl FromJson for i64 {
+    fn from_json(json: &Json) -> Result<i64, JsonError> {
+        json.as_num().ok_or_else(|| Json::type_error("a number"))
+    }
+}
+
+/// A number, or the decimal string `num` writes for one too big for an `i64`.
+impl FromJson for u64 {
+    fn from_json(json: &Json) -> Result<u64, JsonError> {
+        let n = match json {
+            Json::Num(n) => u64::try_from(*n).ok(),
+            Json::Str(s) => s.parse().ok(),
+            _ => None,
+        };
+        n.ok_or_else(|| Json::type_error("a non-negative number"))
+    }
+}
+
+impl FromJson for u8 {
+    fn from_json(json: &Json) -> Result<u8, JsonError> {
+        json.as_num()
+            .and_then(|n| u8::try_from(n).ok())
+            .ok_or_else(|| Json::type_error("a byte"))
+    }
+}
+
+impl FromJson for Ident {
+    fn from_json(json: &Json) -> Result<Ident, JsonError> {
+        Ok(Ident {
+            name: get(json, "name")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Quals {
+    fn from_json(json: &Json) -> Result<Quals, JsonError> {
+        Ok(Quals {
+            constant: get(json, "const")?,
+            volatile: get(json, "volatile")?,
+        })
+    }
+}
+
+impl FromJson for Type {
+    fn from_json(json: &Json) -> Result<Type, JsonError> {
+        Ok(match kind_of(json)? {
+            "void" => Type::Void,
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            "uchar" => Type::UChar,
+            "int" => Type::Int,
+            "uint" => Type::UInt,
+            "long" => Type::Long,
+            "ulong" => Type::ULong,
+            "pointer" => Type::Pointer(get_box(json, "to")?),
+            "array" => Type::Array {
+                elem: get_box(json, "elem")?,
+                len: get(json, "len")?,
+            },
+            "struct" => Type::Struct(get(json, "tag")?),
+            "union" => Type::Union(get(json, "tag")?),
+            "enum" => Type::Enum(get(json, "tag")?),
+            "function" => Type::Function {
+                ret: get_box(json, "ret")?,
+                params: get(json, "params")?,
+            },
+            "qualified" => Type::Qualified(get(json, "quals")?, get_box(json, "type")?),
+            kind => return Err(unknown_kind(kind, "type")),
+        })
+    }
+}
+
+/// Reads a program written by `program_to_json`, which must be of the current
+/// `SCHEMA_VERSION`.
+impl FromJson for Program {
+    fn from_json(json: &Json) -> Result<Program, JsonError> {
+        let version: i64 = get(json, "version")?;
+        if version != SCHEMA_VERSION {
+            return Err(JsonError {
+                pos: 0,
+                msg: format!(
+                    "schema version {} isn't the supported {}",
+                    version, SCHEMA_VERSION
+                ),
+            });
+        }
+        Ok(Program {
+            stmts: get(json, "stmts")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+/// Reads back a program written by `program_to_json`.
+pub fn program_from_json(json: &str) -> Result<Program, JsonError> {
+    Program::from_json(&Json::parse(json)?)
+}
+
+impl FromJson for Storage {
+    fn from_json(json: &Json) -> Result<Storage, JsonError> {
+        match json.as_str() {
+            Some("static") => Ok(Storage::Static),
+            Some("extern") => Ok(Storage::Extern),
+            _ => Err(Json::type_error("a storage class")),
+        }
+    }
+}
+
+impl FromJson for Decl {
+    fn from_json(json: &Json) -> Result<Decl, JsonError> {
+        Ok(match kind_of(json)? {
+            "var" => Decl::Var(VarDecl::from_json(json)?),
+            "function" => Decl::Func(Function::from_json(json)?),
+            "struct" | "union" => Decl::Struct(StructDef::from_json(json)?),
+            "enum" => Decl::Enum(EnumDef::from_json(json)?),
+            "typedef" => Decl::Typedef(Typedef::from_json(json)?),
+            kind => return Err(unknown_kind(kind, "declaration")),
+        })
+    }
+}
+
+impl FromJson for VarDecl {
+    fn from_json(json: &Json) -> Result<VarDecl, JsonError> {
+        Ok(VarDecl {
+            storage: get_opt(json, "storage")?,
+            ty: get(json, "type")?,
+            name: get(json, "name")?,
+            init: get_opt(json, "init")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Init {
+    fn from_json(json: &Json) -> Result<Init, JsonError> {
+        Ok(match kind_of(json)? {
+            "init_list" => Init::List {
+                items: get(json, "items")?,
+                span: get_span(json)?,
+            },
+            _ => Init::Expr(Expr::from_json(json)?),
+        })
+    }
+}
+
+impl FromJson for Function {
+    fn from_json(json: &Json) -> Result<Function, JsonError> {
+        Ok(Function {
+            storage: get_opt(json, "storage")?,
+            ret: get(json, "ret")?,
+            name: get(json, "name")?,
+            params: get(json, "params")?,
+            body: get_opt(json, "body")?.map(Box::new),
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Param {
+    fn from_json(json: &Json) -> Result<Param, JsonError> {
+        Ok(Param {
+            ty: get(json, "type")?,
+            name: get_opt(json, "name")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for StructDef {
+    fn from_json(json: &Json) -> Result<StructDef, JsonError> {
+        Ok(StructDef {
+            union: kind_of(json)? == "union",
+            tag: get(json, "tag")?,
+            fields: get(json, "fields")?,
+            size: get(json, "size")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Field {
+    fn from_json(json: &Json) -> Result<Field, JsonError> {
+        Ok(Field {
+            ty: get(json, "type")?,
+            name: get(json, "name")?,
+            offset: get(json, "offset")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for EnumDef {
+    fn from_json(json: &Json) -> Result<EnumDef, JsonError> {
+        Ok(EnumDef {
+            tag: get_opt(json, "tag")?,
+            enumerators: get(json, "enumerators")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Enumerator {
+    fn from_json(json: &Json) -> Result<Enumerator, JsonError> {
+        Ok(Enumerator {
+            name: get(json, "name")?,
+            value: get(json, "value")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Typedef {
+    fn from_json(json: &Json) -> Result<Typedef, JsonError> {
+        Ok(Typedef {
+            ty: get(json, "type")?,
+            name: get(json, "name")?,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for Stmt {
+    fn from_json(json: &Json) -> Result<Stmt, JsonError> {
+        let kind = match kind_of(json)? {
+            "decl" => StmtKind::Decl(get(json, "decls")?),
+            "expr" => StmtKind::Expr(get(json, "expr")?),
+            "block" => StmtKind::Block(get(json, "stmts")?),
+            "if" => StmtKind::If {
+                cond: get(json, "cond")?,
+                then: get_box(json, "then")?,
+                els: get_opt(json, "else")?.map(Box::new),
+            },
+            "while" => StmtKind::While {
+                cond: get(json, "cond")?,
+                body: get_box(json, "body")?,
+            },
+            "do_while" => StmtKind::DoWhile {
+                body: get_box(json, "body")?,
+                cond: get(json, "cond")?,
+            },
+            "for" => StmtKind::For {
+                init: get_opt(json, "init")?.map(Box::new),
+                cond: get_opt(json, "cond")?,
+                step: get_opt(json, "step")?,
+                body: get_box(json, "body")?,
+            },
+            "switch" => StmtKind::Switch {
+                cond: get(json, "cond")?,
+                body: get_box(json, "body")?,
+            },
+            "case" => StmtKind::Case {
+                value: get(json, "value")?,
+                stmt: get_box(json, "stmt")?,
+            },
+            "default" => StmtKind::Default(get_box(json, "stmt")?),
+            "break" => StmtKind::Break,
+            "continue" => StmtKind::Continue,
+            "label" => StmtKind::Label {
+                label: get(json, "label")?,
+                stmt: get_box(json, "stmt")?,
+            },
+            "goto" => StmtKind::Goto(get(json, "label")?),
+            "return" => StmtKind::Return(get_opt(json, "value")?),
+            "empty" => StmtKind::Empty,
+            kind => return Err(unknown_kind(kind, "statement")),
+        };
+        Ok(Stmt {
+            kind,
+            span: get_span(json)?,
+        })
+    }
+}
+
+impl FromJson for UnOp {
+    fn from_json(json: &Json) -> Result<UnOp, JsonError> {
+        use UnOp::*;
+        let ops = [
+            Neg, Plus, Not, BitNot, PreInc, PreDec, PostInc, PostDec, Deref, Addr,
+        ];
+        ops.into_iter()
+            .find(|op| op.to_json() == *json)
+            .ok_or_else(|| Json::type_error("a unary operator"))
+    }
+}
+
+impl FromJson for BinOp {
+    fn from_json(json: &Json) -> Result<BinOp, JsonError> {
+        use BinOp::*;
+        let ops = [
+            Mul, Div, Mod, Add, Sub, Shl, Shr, Lt, Gt, Le, Ge, Eq, Ne, BitAnd, BitXor, BitOr, And,
+            Or,
+        ];
+        ops.into_iter()
+            .find(|op| json.as_str() == Some(op.symbol()))
+            .ok_or_else(|| Json::type_error("a binary operator"))
+    }
+}
+
+impl FromJson for Expr {
+    fn from_json(json: &Json) -> Result<Expr, JsonError> {
+        let kind = match kind_of(json)? {
+            "int" => ExprKind::Int(get(json, "value")?),
+            "char" => ExprKind::Char(get(json, "value")?),
+            "str" => ExprKind::Str(get(json, "bytes")?),
+            "var" => ExprKind::Var(get(json, "name")?),
+            "unary" => ExprKind::Unary(get(json, "op")?, get_box(json, "operand")?),
+            "binary" => ExprKind::Binary(
+                get(json, "op")?,
+                get_box(json, "lhs")?,
+                get_box(json, "rhs")?,
+            ),
+            "assign" => ExprKind::Assign(
+                get_opt(json, "op")?,
+                get_box(json, "lhs")?,
+                get_box(json, "rhs")?,
+            ),
+            "index" => ExprKind::Index(get_box(json, "array")?, get_box(json, "index")?),
+            "member" => ExprKind::Member(get_box(json, "value")?, get(json, "field")?),
+            "arrow" => ExprKind::Arrow(get_box(json, "ptr")?, get(json, "field")?),
+            "cast" => ExprKind::Cast(get(json, "type")?, get_box(json, "operand")?),
+            "sizeof" => ExprKind::Sizeof(get(json, "type")?),
+            "sizeof_expr" => ExprKind::SizeofExpr(get_box(json, "operand")?),
+            "comma" => ExprKind::Comma(get_box(json, "lhs")?, get_box(json, "rhs")?),
+            "cond" => ExprKind::Cond(
+                get_box(json, "cond")?,
+                get_box(json, "then")?,
+                get_box(json, "else")?,
+            ),
+            "call" => ExprKind::Call(get_box(json, "callee")?, get(json, "args")?),
+            kind => return Err(unknown_kind(kind, "expression")),
+        };
+        Ok(Expr {
+            kind,
+            span: get_span(json)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn round_trip_test() -> Result<(), JsonError> {
+        let src = "struct p { char x; int y[2]; } o = {1, {2}}; union u { long l; } w; \
+                   enum e { A, B = 5 } v; typedef const unsigned char *T; static int g(int, char *);\n\
+                   extern long big; int f(int n, T t) { int s = 0xffffffff, *q = &s; \
+                   for (int i = 0; i < n; i++) { if (i == 3) continue; else s += i; } \
+                   do s--; while (s > 0 && !n); switch (n) { case A: return -1; default: break; } \
+                   l: s = n ? sizeof(int) : sizeof s, o.x = t[0], q->y; s = (char)\"a\\n\"[1] + f(1, t); \
+                   goto l; ; return s; }";
+        let program = parse(src).unwrap();
+        assert_eq!(program_from_json(&program_to_json(&program))?, program);
+
+        let json = program_to_json(&program).replacen(r#"{"version":1"#, r#"{"version":0"#, 1);
+        assert_eq!(
+            program_from_json(&json).unwrap_err().msg,
+            "schema version 0 isn't the supported 1"
+        );
+        let json = r#"{"version":1,"stmts":[{"kind":"wait","span":{"start":0,"end":1}}],"span":{"start":0,"end":1}}"#;
+        assert_eq!(
+            program_from_json(json).unwrap_err().msg,
+            "unknown statement kind `wait`"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn json_test() {
+        let program = parse("int f(char *s) { return s[0] + 'a'; }").unwrap();
+        let json = Json::parse(&program_to_json(&program)).unwrap();
+        assert_eq!(json.get("version"), Some(&Json::Num(SCHEMA_VERSION)));
+        let func = &json.field("stmts").unwrap().as_arr().unwrap()[0]
+            .field("decls")
+            .unwrap()
+            .as_arr()
+            .unwrap()[0];
+        assert_eq!(func.field("kind").unwrap().as_str(), Some("function"));
+        assert_eq!(
+            func.field("params").unwrap().to_string(),
+            concat!(
+                r#"[{"type":{"kind":"pointer","to":{"kind":"char"}},"#,
+                r#""name":{"name":"s","span":{"start":12,"end":13}},"#,
+                r#""span":{"start":6,"end":13}}]"#
+            )
+        );
+        let ret = func
+            .field("body")
+            .unwrap()
+            .field("stmts")
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            ret,
+            concat!(
+                r#"[{"kind":"return","value":{"kind":"binary","op":"+","#,
+                r#""lhs":{"kind":"index","array":{"kind":"var","name":"s","span":{"start":24,"end":25}},"#,
+                r#""index":{"kind":"int","value":0,"span":{"start":26,"end":27}},"span":{"start":24,"end":28}},"#,
+                r#""rhs":{"kind":"char","value":97,"span":{"start":31,"end":34}},"#,
+                r#""span":{"start":24,"end":34}},"span":{"start":17,"end":35}}]"#
+            )
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! `hex8fmt [--check] [FILE]...` formats Hex8 sources in place. With `--check` it only lists
+//! the files that aren't formatted, by the first line that would change, and fails if there
+//! are any. With no files it formats standard input to standard output.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+use hex8_most_raid::format::format_source;
+
+const USAGE: &str = "usage: hex8fmt [--check] [FILE]...";
+
+/// The 1-based number of the first line that differs between `old` and `new`, or the line
+/// after the last if they only differ in line breaks, such as a missing final newline or CRLF
+/// ones.
+fn first_change(old: &str, new: &str) -> usize {
+    let mut old_lines = old.lines();
+    let mut new_lines = new.lines();
+    let mut line = 1;
+    loop {
+        match (old_lines.next(), new_lines.next()) {
+            (None, None) => return line,
+            (old, new) if old == new => line += 1,
+            _ => return line,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("hex8fmt: unknown option `{}`\n{}", arg, USAGE);
+                return ExitCode::from(2);
+            }
+            _ => files.push(arg),
+        }
+    }
+
+    let mut failed = false;
+    let mut unformatted = false;
+    let stdin = files.is_empty();
+    if stdin {
+        files.push("<stdin>".to_string());
+    }
+    for path in &files {
+        let src = if stdin {
+            let mut src = String::new();
+            io::stdin().read_to_string(&mut src).map(|_| src)
+        } else {
+            fs::read_to_string(path)
+        };
+        let src = match src {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("hex8fmt: {}: {}", path, err);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match format_source(&src) {
+            Ok(formatted) => formatted,
+            Err(errs) => {
+                for err in errs {
+                    eprintln!("hex8fmt: {}: {}", path, err);
+                }
+                failed = true;
+                continue;
+            }
+        };
+        if check {
+            if formatted != src {
+                println!("{}:{}: not formatted", path, first_change(&src, &formatted));
+                unformatted = true;
+            }
+        } else if stdin {
+            let written = io::stdout().write_all(formatted.as_bytes());
+            if let Err(err) = written {
+                eprintln!("hex8fmt: {}", err);
+                failed = true;
+            }
+        } else if formatted != src {
+            if let Err(err) = fs::write(path, formatted) {
+                eprintln!("hex8fmt: {}: {}", path, err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::from(2)
+    } else if unformatted {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_change_test() {
+        assert_eq!(first_change("int x;\nint y;\n", "int x;\nint  y;\n"), 2);
+        assert_eq!(first_change("int x;", "int x;\n"), 2);
+        assert_eq!(first_change("int x;\r\nint y;\r\n", "int x;\nint y;\n"), 3);
+        assert_eq!(first_change("int x;\n", "int x;\nint y;\n"), 2);
+    }
+}
@@ -0,0 +1,514 @@
+//! Control-flow graphs of functions: their code split into basic blocks, straight-line runs
+//! of declarations and expressions that end in a jump, a branch, or leaving the function.
+//!
+//! Graphs are meant to be built from functions that `lower::desugar` has run on, but any
+//! function will do. Conditions are not split at `&&`, `||` and `?:`, so a branch is on a whole
+//! expression. Code after a `return`, `break`, `continue` or `goto` starts a block of its own that
+//! nothing jumps to, which `Cfg::reachable` can tell, and `Cfg::block_of` maps each statement to
+//! the block its code starts in, so the unreachable statements can be found from there.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::*;
+use crate::consteval;
+use crate::source::Span;
+
+/// A block of a `Cfg`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(u32);
+
+impl BlockId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// What a block does, in order, before its terminator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Item<'ast> {
+    /// A variable declaration, with its initializer if it has one.
+    Var(&'ast VarDecl),
+    /// An expression evaluated for its effects, such as an expression statement or the step
+    /// of a `for` loop.
+    Expr(&'ast Expr),
+}
+
+/// How control leaves a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator<'ast> {
+    Goto(BlockId),
+    /// To `then` if `cond` is true, else to `els`.
+    Branch {
+        cond: &'ast Expr,
+        then: BlockId,
+        els: BlockId,
+    },
+    /// To the block of the first case equal to `value`, else to `default`, which is the block
+    /// after the `switch` if it has no `default` label.
+    Switch {
+        value: &'ast Expr,
+        cases: Vec<(&'ast Expr, BlockId)>,
+        default: BlockId,
+    },
+    Return(Option<&'ast Expr>),
+    /// Falls off the end of the function without a `return`.
+    End,
+}
+
+impl Terminator<'_> {
+    /// The blocks control can go to next, in order and with repeats.
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(to) => vec![*to],
+            Terminator::Branch { then, els, .. } => vec![*then, *els],
+            Terminator::Switch { cases, default, .. } => {
+                let mut to: Vec<_> = cases.iter().map(|(_, to)| *to).collect();
+                to.push(*default);
+                to
+            }
+            Terminator::Return(_) | Terminator::End => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block<'ast> {
+    pub items: Vec<Item<'ast>>,
+    pub term: Terminator<'ast>,
+}
+
+/// The control-flow graph of a function. Blocks are numbered in the order their code appears,
+/// from the entry block `BlockId(0)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg<'ast> {
+    blocks: Vec<Block<'ast>>,
+    /// The block each statement starts in, by address.
+    starts: HashMap<*const Stmt, BlockId>,
+}
+
+impl<'ast> Cfg<'ast> {
+    /// The graph of `func`, or `None` if it is only declared.
+    pub fn build(func: &'ast Function) -> Option<Cfg<'ast>> {
+        let mut builder = Builder {
+            blocks: vec![Vec::new()],
+            terms: vec![None],
+            current: BlockId(0),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            switches: Vec::new(),
+            labels: HashMap::new(),
+            starts: HashMap::new(),
+        };
+        builder.stmt(func.body.as_ref()?);
+        let blocks = builder
+            .blocks
+            .into_iter()
+            .zip(builder.terms)
+            .map(|(items, term)| Block {
+                items,
+                term: term.unwrap_or(Terminator::End),
+            })
+            .collect();
+        Some(Cfg {
+            blocks,
+            starts: builder.starts,
+        })
+    }
+
+    pub fn entry(&self) -> BlockId {
+        BlockId(0)
+    }
+
+    /// Panics if `id` comes from another graph.
+    pub fn block(&self, id: BlockId) -> &Block<'ast> {
+        &self.blocks[id.index()]
+    }
+
+    /// The block the code of `stmt` starts in, or `None` if it isn't a statement of the
+    /// function. A labeled statement starts in the block that its label jumps to.
+    pub fn block_of(&self, stmt: &Stmt) -> Option<BlockId> {
+        self.starts.get(&(stmt as *const _)).copied()
+    }
+
+    /// Every block with its id, in order.
+    pub fn blocks(&self) -> impl Iterator<Item = (BlockId, &Block<'ast>)> {
+        (0..).map(BlockId).zip(&self.blocks)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Every edge, from each block to each of its successors in turn.
+    pub fn edges(&self) -> impl Iterator<Item = (BlockId, BlockId)> + '_ {
+        self.blocks()
+            .flat_map(|(id, block)| block.term.successors().into_iter().map(move |to| (id, to)))
+    }
+
+    /// The blocks that can go to `id`, each once, in order.
+    pub fn predecessors(&self, id: BlockId) -> Vec<BlockId> {
+        let mut from: Vec<_> = self
+            .edges()
+            .filter(|&(_, to)| to == id)
+            .map(|(from, _)| from)
+            .collect();
+        from.dedup();
+        from
+    }
+
+    /// Whether each block, by index, can be reached from the entry, not following the arm of
+    /// a branch that its condition, being a constant, never takes.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.len()];
+        let mut stack = vec![self.entry()];
+        while let Some(id) = stack.pop() {
+            if std::mem::replace(&mut reachable[id.index()], true) {
+                continue;
+            }
+            match &self.block(id).term {
+                Terminator::Branch { cond, then, els } => match consteval::eval(cond) {
+                    Some(0) => stack.push(*els),
+                    Some(_) => stack.push(*then),
+                    None => stack.extend([*then, *els]),
+                },
+                term => stack.extend(term.successors()),
+            }
+        }
+        reachable
+    }
+}
+
+/// A function that can fall off its end, although its type says it returns a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingReturn {
+    pub name: String,
+    /// The function's name in its definition.
+    pub span: Span,
+}
+
+impl fmt::Display for MissingReturn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` can reach its end without returning a value at byte {}",
+            self.name, self.span.start
+        )
+    }
+}
+
+impl std::error::Error for MissingReturn {}
+
+/// Checks that every reachable path through each function of `program` that returns a value
+/// ends in `return expr;`. The parser rejects a `return;` there, so the paths left to report
+/// are those falling off the end of the function.
+pub fn check_returns(program: &Program) -> Vec<MissingReturn> {
+    let mut errs = Vec::new();
+    for func in program.functions() {
+        if *func.ret.unqualified() == Type::Void {
+            continue;
+        }
+        let Some(cfg) = Cfg::build(func) else {
+            continue;
+        };
+        let reachable = cfg.reachable();
+        let ends = cfg
+            .blocks()
+            .any(|(id, block)| block.term == Terminator::End && reachable[id.index()]);
+        if ends {
+            errs.push(MissingReturn {
+                name: func.name.name.clone(),
+                span: func.name.span,
+            });
+        }
+    }
+    errs
+}
+
+type Cases<'ast> = Vec<(&'ast Expr, BlockId)>;
+
+struct Builder<'ast> {
+    blocks: Vec<Vec<Item<'ast>>>,
+    /// `None` until a block is terminated.
+    terms: Vec<Option<Terminator<'ast>>>,
+    /// The block code is being added to.
+    current: BlockId,
+    /// Where a `break` and a `continue` go in each enclosing loop or `switch`, innermost last.
+    breaks: Vec<BlockId>,
+    continues: Vec<BlockId>,
+    /// The cases and `default` of each enclosing `switch`.
+    switches: Vec<(Cases<'ast>, Option<BlockId>)>,
+    labels: HashMap<String, BlockId>,
+    starts: HashMap<*const Stmt, BlockId>,
+}
+
+impl<'ast> Builder<'ast> {
+    fn block(&mut self) -> BlockId {
+        self.blocks.push(Vec::new());
+        self.terms.push(None);
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    /// Ends the current block with `term`, going on in `next`.
+    fn end(&mut self, term: Terminator<'ast>, next: BlockId) {
+        self.terms[self.current.index()] = Some(term);
+        self.current = next;
+    }
+
+    /// Ends the current block with `term`, going on in a block nothing jumps to yet.
+    fn leave(&mut self, term: Terminator<'ast>) {
+        let next = self.block();
+        self.end(term, next);
+    }
+
+    /// Goes on in `next`, falling into it from the current block.
+    fn fall(&mut self, next: BlockId) {
+        self.end(Terminator::Goto(next), next);
+    }
+
+    fn label(&mut self, name: &str) -> BlockId {
+        if let Some(&id) = self.labels.get(name) {
+            return id;
+        }
+        let id = self.block();
+        self.labels.insert(name.to_string(), id);
+        id
+    }
+
+    /// Builds the body of a loop that breaks to `after` and continues at `next`.
+    fn body(&mut self, body: &'ast Stmt, after: BlockId, next: BlockId) {
+        self.breaks.push(after);
+        self.continues.push(next);
+        self.stmt(body);
+        self.breaks.pop();
+        self.continues.pop();
+    }
+
+    /// Records that `stmt` starts in the current block.
+    fn start(&mut self, stmt: &'ast Stmt) {
+        self.starts.insert(stmt, self.current);
+    }
+
+    fn stmt(&mut self, stmt: &'ast Stmt) {
+        self.start(stmt);
+        let items = &mut self.blocks[self.current.index()];
+        match &stmt.kind {
+            StmtKind::Decl(decls) => items.extend(decls.iter().filter_map(|decl| match decl {
+                Decl::Var(var) => Some(Item::Var(var)),
+                _ => None,
+            })),
+            StmtKind::Expr(expr) => items.push(Item::Expr(expr)),
+            StmtKind::Empty => {}
+            StmtKind::Block(stmts) => stmts.iter().for_each(|stmt| self.stmt(stmt)),
+            StmtKind::If { cond, then, els } => {
+                let (then_block, after) = (self.block(), self.block());
+                let els_block = match els {
+                    Some(_) => self.block(),
+                    None => after,
+                };
+                let branch = Terminator::Branch {
+                    cond,
+                    then: then_block,
+                    els: els_block,
+                };
+                self.end(branch, then_block);
+                self.stmt(then);
+                if let Some(els) = els {
+                    self.end(Terminator::Goto(after), els_block);
+                    self.stmt(els);
+                }
+                self.fall(after);
+            }
+            StmtKind::While { cond, body } => {
+                let (test, body_block, after) = (self.block(), self.block(), self.block());
+                self.fall(test);
+                let branch = Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    els: after,
+                };
+                self.end(branch, body_block);
+                self.body(body, after, test);
+                self.end(Terminator::Goto(test), after);
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let (body_block, test, after) = (self.block(), self.block(), self.block());
+                self.fall(body_block);
+                self.body(body, after, test);
+                self.fall(test);
+                let branch = Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    els: after,
+                };
+                self.end(branch, after);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                let (test, body_block) = (self.block(), self.block());
+                let (next, after) = (self.block(), self.block());
+                self.fall(test);
+                let enter = match cond {
+                    Some(cond) => Terminator::Branch {
+                        cond,
+                        then: body_block,
+                        els: after,
+                    },
+                    None => Terminator::Goto(body_block),
+                };
+                self.end(enter, body_block);
+                self.body(body, after, next);
+                self.fall(next);
+                if let Some(step) = step {
+                    self.blocks[next.index()].push(Item::Expr(step));
+                }
+                self.end(Terminator::Goto(test), after);
+            }
+            StmtKind::Switch { cond, body } => {
+                let (test, after) = (self.current, self.block());
+                // code before the first label is only reached by jumping into it
+                self.current = self.block();
+                self.switches.push((Vec::new(), None));
+                self.breaks.push(after);
+                self.stmt(body);
+                self.breaks.pop();
+                self.fall(after);
+                let (cases, default) = self.switches.pop().unwrap();
+                self.terms[test.index()] = Some(Terminator::Switch {
+                    value: cond,
+                    cases,
+                    default: default.unwrap_or(after),
+                });
+            }
+            StmtKind::Case { value, stmt: inner } => {
+                let case = self.block();
+                self.fall(case);
+                self.start(stmt);
+                if let Some((cases, _)) = self.switches.last_mut() {
+                    cases.push((value, case));
+                }
+                self.stmt(inner);
+            }
+            StmtKind::Default(inner) => {
+                let case = self.block();
+                self.fall(case);
+                self.start(stmt);
+                if let Some((_, default)) = self.switches.last_mut() {
+                    *default = Some(case);
+                }
+                self.stmt(inner);
+            }
+            StmtKind::Label { label, stmt: inner } => {
+                let to = self.label(&label.name);
+                self.fall(to);
+                self.start(stmt);
+                self.stmt(inner);
+            }
+            StmtKind::Goto(label) => {
+                let to = self.label(&label.name);
+                self.leave(Terminator::Goto(to));
+            }
+            StmtKind::Return(value) => self.leave(Terminator::Return(value.as_ref())),
+            StmtKind::Break | StmtKind::Continue => {
+                let targets = match stmt.kind {
+                    StmtKind::Break => &self.breaks,
+                    _ => &self.continues,
+                };
+                // the parser rejects these outside a loop or `switch`
+                if let Some(&to) = targets.last() {
+                    self.leave(Terminator::Goto(to));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lower::{desugar, Types};
+    use crate::parser::parse;
+
+    #[test]
+    fn check_returns_test() {
+        let src = "int f(int n) { if (n) return 1; } int g(int n) { while (1) if (n) return n; } \
+                   int h(int n) { switch (n) { case 0: return 0; default: n = 1; } } \
+                   int k(int n) { if (n) return 1; else return 2; n = 3; } void v() {}";
+        let errs: Vec<_> = check_returns(&parse(src).unwrap())
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errs,
+            [
+                "`f` can reach its end without returning a value at byte 4",
+                "`h` can reach its end without returning a value at byte 82",
+            ]
+        );
+    }
+
+    #[test]
+    fn cfg_test() {
+        let src = "int f(int n) { int s = 0; for (int i = 0; i < n; i++) { if (i == 3) continue; \
+                   s += i; } switch (s) { case 1: return 1; default: break; } return s; }";
+        let program = parse(src).unwrap();
+        let stmt = desugar(program.stmts[0].clone(), &Types::new());
+        let StmtKind::Decl(decls) = &stmt.kind else {
+            unreachable!()
+        };
+        let Decl::Func(func) = &decls[0] else {
+            unreachable!()
+        };
+        let cfg = Cfg::build(func).unwrap();
+        let blocks: Vec<_> = cfg
+            .blocks()
+            .map(|(id, block)| {
+                let term = match &block.term {
+                    Terminator::Goto(_) => "goto",
+                    Terminator::Branch { .. } => "branch",
+                    Terminator::Switch { .. } => "switch",
+                    Terminator::Return(_) => "return",
+                    Terminator::End => "end",
+                };
+                let to: Vec<_> = block.term.successors().iter().map(|b| b.index()).collect();
+                format!("{}: {} {} {:?}", id.index(), block.items.len(), term, to)
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                "0: 2 goto [1]",
+                "1: 0 branch [2, 3]",
+                "2: 0 branch [4, 5]",
+                "3: 0 switch [9, 11]",
+                "4: 1 goto [1]",
+                "5: 2 goto [1]",
+                "6: 0 goto [5]",
+                "7: 0 return []",
+                "8: 0 goto [9]",
+                "9: 0 return []",
+                "10: 0 goto [11]",
+                "11: 0 goto [7]",
+                "12: 0 goto [7]",
+                "13: 0 end []",
+            ]
+        );
+        let unreachable: Vec<_> = (0..cfg.len()).filter(|&i| !cfg.reachable()[i]).collect();
+        assert_eq!(unreachable, [6, 8, 10, 12, 13]);
+        assert_eq!(
+            cfg.predecessors(BlockId(1)),
+            [BlockId(0), BlockId(4), BlockId(5)]
+        );
+    }
+}
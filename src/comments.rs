@@ -0,0 +1,171 @@
+//! Attaching comments to the statements and declarations they document, so that tools
+//! working on the tree can put them back.
+//!
+//! The heuristic, applied to each comment in source order:
+//!
+//! - A comment on the same line as the end of a statement, field or enumerator, after its
+//!   last token or the `;` or `,` that follows it, trails the outermost such node ending there.
+//! - Any other comment leads the outermost node starting at the next token, or failing that
+//!   the next node to start before a `;`, `,`, `{` or `}`, unless a blank line comes first.
+//!   Comments left over, such as a file's header or a note after the last statement of a
+//!   block, are detached.
+
+use std::collections::HashMap;
+
+use crate::arena::{Arena, Node, NodeId, SideTable};
+use crate::ast::*;
+use crate::lexer::{tokenize, LexError, Tok, TokKind::*};
+use crate::source::Span;
+use crate::trivia::attach_trivia;
+
+/// A comment of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comment {
+    /// Covers the comment markers as well.
+    pub span: Span,
+    /// Whether it is a `///` or `/** */` doc comment.
+    pub doc: bool,
+}
+
+/// The comments of a program, keyed by the `NodeId`s of its `Arena`.
+#[derive(Debug, Clone, Default)]
+pub struct Comments {
+    pub leading: SideTable<Vec<Comment>>,
+    pub trailing: SideTable<Vec<Comment>>,
+    pub detached: Vec<Comment>,
+}
+
+impl Comments {
+    /// The doc comments leading `id`, if it has any.
+    pub fn docs(&self, id: NodeId) -> impl Iterator<Item = &Comment> {
+        self.leading.get(id).into_iter().flatten().filter(|c| c.doc)
+    }
+}
+
+fn documented(node: Node<'_>) -> bool {
+    matches!(node, Node::Stmt(_) | Node::Field(_) | Node::Enumerator(_))
+}
+
+fn push(table: &mut SideTable<Vec<Comment>>, id: NodeId, comment: Comment) {
+    match table.get_mut(id) {
+        Some(comments) => comments.push(comment),
+        None => {
+            table.insert(id, vec![comment]);
+        }
+    }
+}
+
+/// Attaches the comments of `src` to the nodes of `arena`, which must be numbering the program
+/// parsed from `src`.
+pub fn attach_comments(src: &str, arena: &Arena) -> Result<Comments, LexError> {
+    // the outermost nodes come first, so they keep the positions they share with inner ones
+    let (mut starts, mut ends) = (HashMap::new(), HashMap::new());
+    for (id, node) in arena.iter().filter(|(_, node)| documented(*node)) {
+        starts.entry(node.span().start).or_insert(id);
+        ends.entry(node.span().end).or_insert(id);
+    }
+
+    let toks = tokenize(src)?;
+    let stream = attach_trivia(&toks);
+    let mut comments = Comments::default();
+    // comments waiting for the next token, and the newlines since the last of them
+    let mut pending = Vec::new();
+    let mut newlines = 0;
+    let mut prev_end = None;
+    for tt in &stream.toks {
+        gap(tt.leading, &mut newlines, &mut comments, &mut pending);
+        let blank = newlines > 1;
+        newlines = 0;
+        match starts.get(&tt.tok.pos) {
+            Some(&id) if !blank => pending
+                .drain(..)
+                .for_each(|c| push(&mut comments.leading, id, c)),
+            // fields start at their names, after the type
+            None if !blank && !matches!(tt.tok.kind, SCol | Comma | LBrc | RBrc) => {}
+            _ => comments.detached.append(&mut pending),
+        }
+
+        let end = tt.tok.pos + tt.tok.str.len();
+        let owner = ends.get(&end).or_else(|| match tt.tok.kind {
+            SCol | Comma => ends.get(prev_end.as_ref()?),
+            _ => None,
+        });
+        prev_end = Some(end);
+        gap(tt.trailing, &mut newlines, &mut comments, &mut pending);
+        if let Some(&id) = owner {
+            pending
+                .drain(..)
+                .for_each(|c| push(&mut comments.trailing, id, c));
+        }
+    }
+    gap(stream.eof, &mut newlines, &mut comments, &mut pending);
+    comments.detached.append(&mut pending);
+    comments.detached.sort_by_key(|c| c.span.start);
+    Ok(comments)
+}
+
+/// Adds the comments among `trivia` to `pending`, first detaching those already pending if a
+/// blank line separates them from the new ones.
+fn gap(trivia: &[Tok], newlines: &mut usize, comments: &mut Comments, pending: &mut Vec<Comment>) {
+    for tok in trivia {
+        match tok.kind {
+            Cmt | DocCmt => {
+                if *newlines > 1 {
+                    comments.detached.append(pending);
+                }
+                *newlines = 0;
+                pending.push(Comment {
+                    span: Span::new(tok.pos, tok.pos + tok.str.len()),
+                    doc: tok.kind == DocCmt,
+                });
+            }
+            Nl => *newlines += 1,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn comments_test() -> Result<(), LexError> {
+        let src = "// header\n\n/// a point\nstruct p {\n    int x; // across\n    /* down */ int y;\n};\nint f() {\n    // first\n    // assignment\n    p.x = 1; /* set */\n    // the end\n}\n";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let comments = attach_comments(src, &arena)?;
+        let text = |c: &Comment| c.span.text(src);
+        let attached = |table: &SideTable<Vec<Comment>>| {
+            let mut attached: Vec<(&str, Vec<&str>)> = table
+                .iter()
+                .map(|(id, cs)| (arena.get(id).text(src), cs.iter().map(text).collect()))
+                .collect();
+            attached.sort();
+            attached
+        };
+        assert_eq!(
+            attached(&comments.leading),
+            [
+                ("p.x = 1;", vec!["// first", "// assignment"]),
+                (
+                    "struct p {\n    int x; // across\n    /* down */ int y;\n};",
+                    vec!["/// a point"]
+                ),
+                ("y", vec!["/* down */"]),
+            ]
+        );
+        assert_eq!(
+            attached(&comments.trailing),
+            [("p.x = 1;", vec!["/* set */"]), ("x", vec!["// across"])]
+        );
+        let detached: Vec<_> = comments.detached.iter().map(text).collect();
+        assert_eq!(detached, ["// header", "// the end"]);
+        let docs: Vec<_> = comments
+            .docs(arena.stmt_id(&program.stmts[0]).unwrap())
+            .collect();
+        assert_eq!(docs.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+//! Evaluation of constant expressions at compile time, and folding them into literals.
+
+use std::fmt;
+
+use crate::ast::*;
+use crate::source::Span;
+use crate::typeck::int_type;
+use crate::visit::*;
+
+/// The value of `expr` if it is built only from literals and operators, as a case label
+/// must be. Arithmetic is on `i64`; anything that isn't constant, or divides by zero, has no
+/// value.
+pub fn eval(expr: &Expr) -> Option<i64> {
+    eval_in(expr, &())
+}
+
+/// What evaluating an expression needs to know beyond the expression itself.
+pub trait Env {
+    /// The value of a name that is a constant, such as an enumerator.
+    fn constant(&self, name: &str) -> Option<i64>;
+
+    /// The structs and unions that `sizeof` can measure.
+    fn structs(&self) -> &[StructDef];
+
+    /// The type of an expression, for `sizeof expr`.
+    fn type_of(&self, expr: &Expr) -> Option<Type>;
+}
+
+/// Knows no names, structs or expression types.
+impl Env for () {
+    fn constant(&self, _: &str) -> Option<i64> {
+        None
+    }
+
+    fn structs(&self) -> &[StructDef] {
+        &[]
+    }
+
+    fn type_of(&self, _: &Expr) -> Option<Type> {
+        None
+    }
+}
+
+/// Like `eval`, but looking up names and types in `env`, so that enumerators and `sizeof`
+/// are constants too.
+pub fn eval_in(expr: &Expr, env: &dyn Env) -> Option<i64> {
+    let eval = |expr| eval_in(expr, env);
+    let size = |ty: &Type| i64::try_from(ty.size(env.structs())?).ok();
+    let value = match &expr.kind {
+        ExprKind::Int(n) => i64::try_from(*n).ok()?,
+        ExprKind::Char(c) => i64::from(*c),
+        ExprKind::Unary(op, operand) => {
+            let v = eval(operand)?;
+            match op {
+                UnOp::Neg => v.wrapping_neg(),
+                UnOp::Plus => v,
+                UnOp::Not => i64::from(v == 0),
+                UnOp::BitNot => !v,
+                UnOp::PreInc | UnOp::PreDec | UnOp::PostInc | UnOp::PostDec => return None,
+                UnOp::Deref | UnOp::Addr => return None,
+            }
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let (l, r) = (eval(lhs)?, eval(rhs)?);
+            match op {
+                BinOp::Mul => l.wrapping_mul(r),
+                BinOp::Div => l.checked_div(r)?,
+                BinOp::Mod => l.checked_rem(r)?,
+                BinOp::Add => l.wrapping_add(r),
+                BinOp::Sub => l.wrapping_sub(r),
+                BinOp::Shl => l.checked_shl(u32::try_from(r).ok()?)?,
+                BinOp::Shr => l.checked_shr(u32::try_from(r).ok()?)?,
+                BinOp::Lt => i64::from(l < r),
+                BinOp::Gt => i64::from(l > r),
+                BinOp::Le => i64::from(l <= r),
+                BinOp::Ge => i64::from(l >= r),
+                BinOp::Eq => i64::from(l == r),
+                BinOp::Ne => i64::from(l != r),
+                BinOp::BitAnd => l & r,
+                BinOp::BitXor => l ^ r,
+                BinOp::BitOr => l | r,
+                BinOp::And => i64::from(l != 0 && r != 0),
+                BinOp::Or => i64::from(l != 0 || r != 0),
+            }
+        }
+        ExprKind::Var(name) => env.constant(name)?,
+        ExprKind::Cast(ty, operand) => convert(eval(operand)?, ty)?,
+        ExprKind::Sizeof(ty) => size(ty)?,
+        ExprKind::SizeofExpr(operand) => size(&env.type_of(operand)?)?,
+        ExprKind::Cond(cond, then, els) => match eval(cond)? {
+            0 => eval(els)?,
+            _ => eval(then)?,
+        },
+        // a constant expression can't contain a comma operator, as in C
+        ExprKind::Str(_)
+        | ExprKind::Comma(..)
+        | ExprKind::Assign(..)
+        | ExprKind::Index(..)
+        | ExprKind::Member(..)
+        | ExprKind::Arrow(..)
+        | ExprKind::Call(..) => return None,
+    };
+    Some(value)
+}
+
+/// `value` converted to the integer type `ty`, keeping as many of its low bytes as fit.
+fn convert(value: i64, ty: &Type) -> Option<i64> {
+    Some(match ty.unqualified() {
+        Type::Bool => i64::from(value != 0),
+        Type::Char | Type::Enum(_) => i64::from(value as i8),
+        Type::UChar => i64::from(value as u8),
+        Type::Int => i64::from(value as i16),
+        Type::UInt => i64::from(value as u16),
+        Type::Long => i64::from(value as i32),
+        Type::ULong => i64::from(value as u32),
+        _ => return None,
+    })
+}
+
+/// A constant whose arithmetic doesn't fit the type Hex8 computes it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overflow {
+    pub span: Span,
+    /// The value the arithmetic has on paper.
+    pub value: i128,
+    pub ty: Type,
+    /// The value it has on Hex8.
+    pub wrapped: i64,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the constant {} overflows `{}`, wrapping to {} at byte {}",
+            self.value, self.ty, self.wrapped, self.span.start
+        )
+    }
+}
+
+/// Replaces each constant subexpression of `expr` by a literal of its value, so that
+/// `3 + 4 * 2` becomes `11`, returning the operations that overflow along the way.
+///
+/// Unlike `eval`, which works out values on paper, folding computes what Hex8 would: each
+/// operation is done in the type of its operands, as `Type::common` gives it, and wraps to
+/// that type's width, so `200 * 200` folds to `-25536`. That is an overflow, as is anything
+/// else that wraps, except for a cast and the bitwise operators, which drop bits on purpose.
+/// A literal of another type than the value it replaces is cast to it, so the tree keeps its
+/// types, and it keeps the span of the expression it replaces, so its source stays at hand.
+pub fn fold(expr: &mut Expr, env: &dyn Env) -> Vec<Overflow> {
+    let mut folder = Folder {
+        env,
+        overflows: Vec::new(),
+    };
+    folder.visit_expr_mut(expr);
+    folder.overflows
+}
+
+/// Folds the constant expressions of `program`, in which `sizeof` can measure the structs and
+/// unions defined, but enumerators are names like any other.
+pub fn fold_program(program: &mut Program) -> Vec<Overflow> {
+    let env = Structs(program.find_all::<StructDef>().cloned().collect());
+    let mut folder = Folder {
+        env: &env,
+        overflows: Vec::new(),
+    };
+    folder.visit_program_mut(program);
+    folder.overflows
+}
+
+struct Structs(Vec<StructDef>);
+
+impl Env for Structs {
+    fn constant(&self, _: &str) -> Option<i64> {
+        None
+    }
+
+    fn structs(&self) -> &[StructDef] {
+        &self.0
+    }
+
+    fn type_of(&self, _: &Expr) -> Option<Type> {
+        None
+    }
+}
+
+struct Folder<'a> {
+    env: &'a dyn Env,
+    overflows: Vec<Overflow>,
+}
+
+impl Folder<'_> {
+    /// The value Hex8 computes for `expr` and its type, if it is constant, adding the
+    /// operations that overflow to `overflows`.
+    fn value(&self, expr: &Expr, overflows: &mut Vec<Overflow>) -> Option<(i64, Type)> {
+        let mut value = |expr| self.value(expr, overflows);
+        let size = |ty: &Type| i64::try_from(ty.size(self.env.structs())?).ok();
+        let (raw, ty) = match &expr.kind {
+            ExprKind::Int(n) => return Some((i64::try_from(*n).ok()?, int_type(*n))),
+            ExprKind::Char(c) => return Some((i64::from(*c as i8), Type::Char)),
+            ExprKind::Var(name) => return Some((self.env.constant(name)?, Type::Int)),
+            ExprKind::Sizeof(ty) => return Some((size(ty)?, Type::Int)),
+            ExprKind::SizeofExpr(operand) => {
+                return Some((size(&self.env.type_of(operand)?)?, Type::Int))
+            }
+            ExprKind::Cast(ty, operand) => {
+                let (v, _) = value(operand)?;
+                return Some((convert(v, ty)?, ty.unqualified().clone()));
+            }
+            ExprKind::Unary(op, operand) => {
+                let (v, ty) = value(operand)?;
+                match op {
+                    UnOp::Neg => (-i128::from(v), ty),
+                    UnOp::Plus => (i128::from(v), ty),
+                    UnOp::Not => return Some((i64::from(v == 0), Type::Bool)),
+                    UnOp::BitNot => return Some((convert(!v, &ty)?, ty)),
+                    UnOp::PreInc | UnOp::PreDec | UnOp::PostInc | UnOp::PostDec => return None,
+                    UnOp::Deref | UnOp::Addr => return None,
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let ((l, a), (r, b)) = (value(lhs)?, value(rhs)?);
+                if let BinOp::Shl | BinOp::Shr = op {
+                    let bits = 8 * a.size(&[])?;
+                    let r = u32::try_from(r).ok().filter(|&r| u64::from(r) < bits)?;
+                    match op {
+                        BinOp::Shl => (i128::from(l) << r, a),
+                        _ => return Some((l >> r, a)),
+                    }
+                } else {
+                    let ty = a.common(&b)?;
+                    let (l, r) = (convert(l, &ty)?, convert(r, &ty)?);
+                    let (wide_l, wide_r) = (i128::from(l), i128::from(r));
+                    let bool = |b| Some((i64::from(b), Type::Bool));
+                    let bits = |v| Some((convert(v, &ty)?, ty.clone()));
+                    match op {
+                        BinOp::Mul => (wide_l * wide_r, ty),
+                        BinOp::Div => (wide_l.checked_div(wide_r)?, ty),
+                        BinOp::Mod => (wide_l.checked_rem(wide_r)?, ty),
+                        BinOp::Add => (wide_l + wide_r, ty),
+                        BinOp::Sub => (wide_l - wide_r, ty),
+                        BinOp::Lt => return bool(l < r),
+                        BinOp::Gt => return bool(l > r),
+                        BinOp::Le => return bool(l <= r),
+                        BinOp::Ge => return bool(l >= r),
+                        BinOp::Eq => return bool(l == r),
+                        BinOp::Ne => return bool(l != r),
+                        BinOp::And => return bool(l != 0 && r != 0),
+                        BinOp::Or => return bool(l != 0 || r != 0),
+                        BinOp::BitAnd => return bits(l & r),
+                        BinOp::BitXor => return bits(l ^ r),
+                        BinOp::BitOr => return bits(l | r),
+                        BinOp::Shl | BinOp::Shr => unreachable!(),
+                    }
+                }
+            }
+            ExprKind::Cond(cond, then, els) => {
+                let ((c, _), (t, a), (e, b)) = (value(cond)?, value(then)?, value(els)?);
+                let ty = a.common(&b)?;
+                let v = if c != 0 { t } else { e };
+                return Some((convert(v, &ty)?, ty));
+            }
+            ExprKind::Str(_)
+            | ExprKind::Comma(..)
+            | ExprKind::Assign(..)
+            | ExprKind::Index(..)
+            | ExprKind::Member(..)
+            | ExprKind::Arrow(..)
+            | ExprKind::Call(..) => return None,
+        };
+        // operands are at most 32 bits, so the arithmetic itself can't overflow an `i128`
+        let wrapped = convert(raw as i64, &ty)?;
+        if i128::from(wrapped) != raw {
+            overflows.push(Overflow {
+                span: expr.span,
+                value: raw,
+                ty: ty.clone(),
+                wrapped,
+            });
+        }
+        Some((wrapped, ty))
+    }
+}
+
+/// A literal of `value` with type `ty`.
+fn literal(value: i64, ty: &Type, span: Span) -> Expr {
+    let n = ExprKind::Int(value.unsigned_abs());
+    let (kind, own) = match value < 0 {
+        false => (n, int_type(value.unsigned_abs())),
+        true => {
+            let n = Box::new(Expr { kind: n, span });
+            (
+                ExprKind::Unary(UnOp::Neg, n),
+                int_type(value.unsigned_abs()),
+            )
+        }
+    };
+    let lit = Expr { kind, span };
+    match own == *ty {
+        true => lit,
+        false => Expr {
+            kind: ExprKind::Cast(ty.clone(), Box::new(lit)),
+            span,
+        },
+    }
+}
+
+impl VisitMut for Folder<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let ExprKind::Int(_) | ExprKind::Char(_) = expr.kind {
+            return;
+        }
+        let mut overflows = Vec::new();
+        match self.value(expr, &mut overflows) {
+            Some((value, ty)) => {
+                self.overflows.append(&mut overflows);
+                *expr = literal(value, &ty, expr.span);
+            }
+            None => walk_expr_mut(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Parser};
+    use crate::pretty::ast_to_source;
+
+    fn eval_str(src: &str) -> Option<i64> {
+        eval(&Parser::new(src).unwrap().parse_expr().unwrap())
+    }
+
+    #[test]
+    fn eval_test() {
+        assert_eq!(eval_str("3 + 4 * 2"), Some(11));
+        assert_eq!(eval_str("'a' - 1"), Some(96));
+        assert_eq!(eval_str("-(1 << 4) | ~0 & 3"), Some(-13));
+        assert_eq!(eval_str("!0 + (2 < 1) + (1 && 2)"), Some(2));
+        assert_eq!(eval_str("7 / 0"), None);
+        assert_eq!(eval_str("x + 1"), None);
+        assert_eq!(eval_str("1 << 99"), None);
+        assert_eq!(eval_str("2 > 1 ? 10 : 7 / 0"), Some(10));
+        assert_eq!(eval_str("1, 2"), None);
+
+        assert_eq!(eval_str("sizeof(int) * sizeof(char *[3])"), Some(12));
+        assert_eq!(eval_str("sizeof x"), None);
+        assert_eq!(eval_str("(char)255 + (char)256 + (int)70000"), Some(4463));
+        assert_eq!(eval_str("(char *)0"), None);
+        assert_eq!(eval_str("(unsigned char)-1 + (unsigned)-1"), Some(65790));
+        assert_eq!(eval_str("(long)70000 + sizeof(long)"), Some(70004));
+        assert_eq!(eval_str("(bool)5 + (_Bool)256 + (bool)0"), Some(2));
+
+        struct Names;
+        impl Env for Names {
+            fn constant(&self, name: &str) -> Option<i64> {
+                (name == "x").then_some(4)
+            }
+
+            fn structs(&self) -> &[StructDef] {
+                &[]
+            }
+
+            fn type_of(&self, _: &Expr) -> Option<Type> {
+                Some(Type::Int)
+            }
+        }
+        let expr = |src| Parser::new(src).unwrap().parse_expr().unwrap();
+        assert_eq!(eval_in(&expr("x * 2 + y"), &Names), None);
+        assert_eq!(eval_in(&expr("x * 2 + sizeof y"), &Names), Some(10));
+    }
+
+    #[test]
+    fn fold_test() {
+        let mut program = parse(
+            "struct p { int x; char y; }; int f(int x) { return x * (3 + 4 * 2) + sizeof(struct p); } \
+             int a = 200 * 200; char c = 'a' + 'b'; unsigned u = (unsigned)-1 >> 8; int b = 1 < 2;",
+        )
+        .unwrap();
+        let overflows: Vec<_> = fold_program(&mut program)
+            .iter()
+            .map(|o| o.to_string())
+            .collect();
+        assert_eq!(
+            overflows,
+            [
+                "the constant 40000 overflows `int`, wrapping to -25536 at byte 97",
+                "the constant 195 overflows `char`, wrapping to -61 at byte 117",
+            ]
+        );
+        assert_eq!(
+            ast_to_source(&program),
+            "struct p {\n    int x;\n    char y;\n};\n\nint f(int x) {\n    return x * 11 + 3;\n}\n\n\
+             int a = -25536;\nchar c = (char)-61;\nunsigned int u = (unsigned int)255;\n\
+             int b = (bool)1;\n"
+        );
+    }
+}
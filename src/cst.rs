@@ -0,0 +1,413 @@
+//! A lossless concrete syntax tree. Every token of the source, whitespace and comments
+//! included, sits in a tree of nodes shaped like the AST, so the tree reproduces the source
+//! exactly and can be walked both down to tokens and up from them, as a formatter or editor
+//! needs.
+//!
+//! As in rowan, an immutable "green" tree holds the kinds and text, and the `SyntaxNode`s
+//! handed out over it know their parent and position. The nodes come from the spans of the
+//! AST the parser builds, so each one covers exactly the tokens of an AST node.
+//!
+//! Unlike rowan, the parser doesn't build this tree, and the AST isn't derived from it: the
+//! parser builds the AST, and `build` lays the tokens over it afterwards. That is enough to
+//! round-trip and navigate the source, but a node only exists where the AST has one.
+
+use std::rc::Rc;
+
+use crate::ast::*;
+use crate::lexer::{tokenize_all, TokKind};
+use crate::parser::{parse_all, ParseError};
+use crate::source::Span;
+use crate::visit::{walk_decl, walk_expr, walk_init, walk_stmt, Visit};
+
+/// Which kind of AST node a syntax node covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Program,
+    Stmt,
+    /// One declarator, or a struct, union or enum definition.
+    Decl,
+    Param,
+    Field,
+    Enumerator,
+    /// A braced initializer list.
+    InitList,
+    Expr,
+}
+
+#[derive(Debug)]
+struct GreenNode {
+    kind: NodeKind,
+    len: usize,
+    children: Vec<Green>,
+}
+
+#[derive(Debug)]
+enum Green {
+    Node(Rc<GreenNode>),
+    Token(TokKind, Rc<str>),
+}
+
+impl Green {
+    fn len(&self) -> usize {
+        match self {
+            Green::Node(node) => node.len,
+            Green::Token(_, text) => text.len(),
+        }
+    }
+}
+
+/// A node of the tree, positioned in the source.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+#[derive(Debug)]
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    offset: usize,
+}
+
+/// A token of the tree, positioned in the source.
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    kind: TokKind,
+    text: Rc<str>,
+    parent: SyntaxNode,
+    offset: usize,
+}
+
+/// A child of a node: either a node or a token.
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.len)
+    }
+
+    /// The source text the node covers, trivia inside it included.
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|t| t.text()).collect()
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    /// The parent, its parent, and so on up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(self.parent().cloned(), |node| node.parent().cloned())
+    }
+
+    /// The nodes and tokens directly inside this node, in source order.
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut children = Vec::new();
+        for green in &self.0.green.children {
+            children.push(match green {
+                Green::Node(node) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: node.clone(),
+                    parent: Some(self.clone()),
+                    offset,
+                }))),
+                Green::Token(kind, text) => SyntaxElement::Token(SyntaxToken {
+                    kind: *kind,
+                    text: text.clone(),
+                    parent: self.clone(),
+                    offset,
+                }),
+            });
+            offset += green.len();
+        }
+        children
+    }
+
+    /// The nodes directly inside this node.
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        let children = self.children_with_tokens().into_iter();
+        children
+            .filter_map(|child| match child {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// This node and every node inside it, parents before their children.
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// Every token inside this node, however deeply, in source order.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(tok) => tokens.push(tok),
+            }
+        }
+        tokens
+    }
+
+    /// The token covering byte `pos`, if it is inside this node.
+    pub fn token_at(&self, pos: usize) -> Option<SyntaxToken> {
+        if !self.span().contains(pos) {
+            return None;
+        }
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) if node.span().contains(pos) => {
+                    return node.token_at(pos)
+                }
+                SyntaxElement::Token(tok) if tok.span().contains(pos) => return Some(tok),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The innermost node whose span contains `span`.
+    pub fn covering_node(&self, span: Span) -> SyntaxNode {
+        let inside = |node: &SyntaxNode| {
+            let own = node.span();
+            own.start <= span.start && span.end <= own.end
+        };
+        match self.children().into_iter().find(inside) {
+            Some(child) => child.covering_node(span),
+            None => self.clone(),
+        }
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.text.len())
+    }
+
+    /// The node the token is directly inside.
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+/// Builds the tree of `src`, which `program` was parsed from. A node with no tokens is an
+/// empty node just before the token after it.
+///
+/// Returns `None` if the nodes of `program` don't fit the tokens of `src`: if one starts
+/// inside a token or overlaps the end of another it is inside of. The spans of a program
+/// parsed from `src` always fit, but those of one rewritten by `lower::desugar` needn't.
+pub fn build(src: &str, program: &Program) -> Option<SyntaxNode> {
+    let mut spans = Spans(vec![(NodeKind::Program, program.span)]);
+    spans.visit_program(program);
+    let mut nodes = spans.0;
+    // parents come before their children, even when they share a span
+    nodes.sort_by_key(|&(_, span)| (span.start, std::cmp::Reverse(span.end)));
+    let mut nodes = nodes.into_iter().peekable();
+    // the nodes still open around the next token, with the children collected so far
+    let (kind, root) = nodes.next().expect("the program is a node");
+    let mut open: Vec<(NodeKind, Span, Vec<Green>)> = vec![(kind, root, Vec::new())];
+    let close = |open: &mut Vec<(NodeKind, Span, Vec<Green>)>| {
+        let (kind, span, children) = open.pop().unwrap();
+        if span.end > open.last().unwrap().1.end {
+            return None;
+        }
+        let len = children.iter().map(Green::len).sum();
+        let node = Green::Node(Rc::new(GreenNode {
+            kind,
+            len,
+            children,
+        }));
+        open.last_mut().unwrap().2.push(node);
+        Some(())
+    };
+    let toks = tokenize_all(src).0;
+    // a last round at the end of the source closes the nodes there
+    for i in 0..=toks.len() {
+        let pos = toks.get(i).map_or(src.len(), |tok| tok.pos());
+        while open.len() > 1 && open.last().unwrap().1.end <= pos {
+            close(&mut open)?;
+        }
+        while let Some(&(kind, span)) = nodes.peek() {
+            if span.start > pos {
+                break;
+            }
+            nodes.next();
+            // a node starting inside a token would split it
+            if span.start != pos {
+                return None;
+            }
+            open.push((kind, span, Vec::new()));
+            if span.is_empty() {
+                close(&mut open)?;
+            }
+        }
+        if let Some(tok) = toks.get(i) {
+            let green = Green::Token(tok.kind(), Rc::from(tok.text()));
+            open.last_mut().unwrap().2.push(green);
+        }
+    }
+    // a node past the end of the source is never opened or closed
+    if open.len() > 1 || nodes.next().is_some() {
+        return None;
+    }
+    let (kind, _, children) = open.pop().unwrap();
+    let len = children.iter().map(Green::len).sum();
+    Some(SyntaxNode(Rc::new(NodeData {
+        green: Rc::new(GreenNode {
+            kind,
+            len,
+            children,
+        }),
+        parent: None,
+        offset: 0,
+    })))
+}
+
+/// Parses `src` with `parse_all` and builds its tree, which covers the whole source even if
+/// it has errors.
+pub fn parse_cst(src: &str) -> (SyntaxNode, Program, Vec<ParseError>) {
+    let (program, errs) = parse_all(src);
+    let root = build(src, &program).expect("a parsed program fits its source");
+    (root, program, errs)
+}
+
+/// The span of every node, in the order they are visited.
+struct Spans(Vec<(NodeKind, Span)>);
+
+impl Visit<'_> for Spans {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.0.push((NodeKind::Stmt, stmt.span));
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_decl(&mut self, decl: &Decl) {
+        self.0.push((NodeKind::Decl, decl.span()));
+        walk_decl(self, decl);
+    }
+
+    fn visit_param(&mut self, param: &Param) {
+        self.0.push((NodeKind::Param, param.span));
+    }
+
+    fn visit_field(&mut self, field: &Field) {
+        self.0.push((NodeKind::Field, field.span));
+    }
+
+    fn visit_enumerator(&mut self, enumerator: &Enumerator) {
+        self.0.push((NodeKind::Enumerator, enumerator.span));
+    }
+
+    fn visit_init(&mut self, init: &Init) {
+        if let Init::List { span, .. } = init {
+            self.0.push((NodeKind::InitList, *span));
+        }
+        walk_init(self, init);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.0.push((NodeKind::Expr, expr.span));
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cst_test() {
+        let src = "// counts\nint f(int n) {\n  return n * (2 + n); /* twice */\n}\nint t[] = { 1, 2 };\n";
+        let (root, _, errs) = parse_cst(src);
+        assert!(errs.is_empty());
+        assert_eq!(root.text(), src);
+        assert_eq!(root.kind(), NodeKind::Program);
+        let kinds: Vec<NodeKind> = root.children().iter().map(|n| n.kind()).collect();
+        assert_eq!(kinds, [NodeKind::Stmt, NodeKind::Stmt]);
+        // the leading comment belongs to the program, not the function
+        assert!(matches!(&root.children_with_tokens()[0],
+            SyntaxElement::Token(t) if t.kind() == TokKind::Cmt));
+
+        let two = root.token_at(src.find('2').unwrap()).unwrap();
+        assert_eq!(two.text(), "2");
+        let path: Vec<String> = std::iter::once(two.parent().clone())
+            .chain(two.parent().ancestors())
+            .map(|n| n.text())
+            .collect();
+        assert_eq!(
+            path,
+            [
+                "2",
+                "(2 + n)",
+                "n * (2 + n)",
+                "return n * (2 + n);",
+                "{\n  return n * (2 + n); /* twice */\n}",
+                "f(int n) {\n  return n * (2 + n); /* twice */\n}",
+                "int f(int n) {\n  return n * (2 + n); /* twice */\n}",
+                src,
+            ]
+        );
+        let param = root.covering_node(Span::new(16, 17));
+        assert_eq!(
+            (param.kind(), param.text().as_str()),
+            (NodeKind::Param, "int n")
+        );
+        let list = root
+            .descendants()
+            .into_iter()
+            .find(|n| n.kind() == NodeKind::InitList);
+        assert_eq!(list.unwrap().text(), "{ 1, 2 }");
+
+        // broken sources still round-trip
+        let src = "int x = @;\n} y = 1;";
+        let (root, _, errs) = parse_cst(src);
+        assert_eq!(errs.len(), 2);
+        assert_eq!(root.text(), src);
+
+        // every node of the AST is in the tree
+        let src = "struct s { int x, y; } t, *u[2] = {0}; int (*fp)(char, int *);\n\
+                   void h(int n) { a: for (int i = 0; i < n; i++) if (i) n = (n); else goto a; }";
+        let (root, program, errs) = parse_cst(src);
+        assert!(errs.is_empty());
+        let mut spans = Spans(vec![(NodeKind::Program, program.span)]);
+        spans.visit_program(&program);
+        let key = |&(kind, span): &(NodeKind, Span)| (span.start, span.end, kind as u8);
+        let mut nodes: Vec<_> = root
+            .descendants()
+            .iter()
+            .map(|n| (n.kind(), n.span()))
+            .collect();
+        nodes.sort_by_key(key);
+        spans.0.sort_by_key(key);
+        assert_eq!(nodes, spans.0);
+
+        // a program whose spans don't fit the source has no tree
+        let src = "int x; int y;";
+        let mut program = parse_all(src).0;
+        program.stmts[0].span.start = 1;
+        assert!(build(src, &program).is_none());
+        program.stmts[0].span = Span::new(0, 9);
+        assert!(build(src, &program).is_none());
+        program.stmts[0].span = Span::new(7, 20);
+        assert!(build(src, &program).is_none());
+    }
+}
@@ -0,0 +1,408 @@
+//! Diffing sources by their tokens, ignoring changes to whitespace and comments, and programs
+//! by their trees, which also ignores how expressions are parenthesized.
+
+use crate::ast::*;
+use crate::lexer::{tokenize_all, Tok};
+use crate::source::Span;
+use crate::visit::*;
+
+/// A run of significant tokens that differs between the two sources. `old` and `new` cover
+/// the tokens that were replaced; an empty span marks where tokens were only inserted or
+/// only deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Span,
+    pub new: Span,
+}
+
+/// The pairs of indices of a longest common subsequence of `a` and `b`, in order, found with
+/// Myers' O((n + m) d) algorithm.
+fn common<T: PartialEq>(a: &[T], b: &[T]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max as usize;
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace = Vec::new();
+    let mut end = None;
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let at = |k: isize| (k + offset as isize) as usize;
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                end = Some(d);
+                break 'search;
+            }
+        }
+    }
+    // walk back through the saved frontiers, collecting the diagonal moves
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..=end.unwrap_or(0)).rev() {
+        let v = &trace[d as usize];
+        let at = |k: isize| (k + offset as isize) as usize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = if d == 0 { 0 } else { v[at(prev_k)] };
+        let prev_y = if d == 0 { 0 } else { prev_x - prev_k };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// The span of `toks[range]`, or an empty span just after `toks[range.start - 1]` if the range
+/// is empty.
+fn span_of(toks: &[Tok], start: usize, end: usize) -> Span {
+    if start < end {
+        toks[start].span().to(toks[end - 1].span())
+    } else {
+        let pos = start.checked_sub(1).map_or(0, |i| toks[i].span().end);
+        Span::new(pos, pos)
+    }
+}
+
+/// Compares the significant tokens of two sources, by kind and text, returning where they
+/// differ.
+pub fn diff_tokens(old: &str, new: &str) -> Vec<Hunk> {
+    let significant = |src| -> Vec<Tok> {
+        tokenize_all(src)
+            .0
+            .into_iter()
+            .filter(|t| !t.kind().is_trivia())
+            .collect()
+    };
+    let (a, b) = (significant(old), significant(new));
+    let a_keys: Vec<_> = a.iter().map(|t| (t.kind(), t.text())).collect();
+    let b_keys: Vec<_> = b.iter().map(|t| (t.kind(), t.text())).collect();
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let sentinel = (a.len(), b.len());
+    for (x, y) in common(&a_keys, &b_keys).into_iter().chain([sentinel]) {
+        if x > i || y > j {
+            hunks.push(Hunk {
+                old: span_of(&a, i, x),
+                new: span_of(&b, j, y),
+            });
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+    hunks
+}
+
+/// Whether two sources differ only in whitespace and comments.
+pub fn equivalent(old: &str, new: &str) -> bool {
+    diff_tokens(old, new).is_empty()
+}
+
+/// A statement that differs between two programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Added(&'a Stmt),
+    Removed(&'a Stmt),
+    /// A statement that was edited in place. It is as deep as the edit allows: a function
+    /// whose body changed is reported by the statements of the body that did.
+    Changed {
+        old: &'a Stmt,
+        new: &'a Stmt,
+    },
+}
+
+/// Compares two programs by their ASTs, ignoring spans, and returns the statements that
+/// differ in source order. An empty result means the change is purely cosmetic.
+pub fn diff_programs<'a>(old: &'a Program, new: &'a Program) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    let stmts = |p: &'a Program| p.stmts.iter().collect::<Vec<_>>();
+    diff_stmts(&stmts(old), &stmts(new), &mut changes);
+    changes
+}
+
+/// Whether two programs have the same AST apart from spans.
+pub fn same_program(old: &Program, new: &Program) -> bool {
+    diff_programs(old, new).is_empty()
+}
+
+/// Sets every span to an empty one at 0, so that trees can be compared by shape.
+struct Unspan;
+
+impl VisitMut for Unspan {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        stmt.span = Span::default();
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_var_decl_mut(&mut self, var: &mut VarDecl) {
+        var.span = Span::default();
+        walk_var_decl_mut(self, var);
+    }
+
+    fn visit_function_mut(&mut self, func: &mut Function) {
+        func.span = Span::default();
+        walk_function_mut(self, func);
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        param.span = Span::default();
+        walk_param_mut(self, param);
+    }
+
+    fn visit_struct_def_mut(&mut self, def: &mut StructDef) {
+        def.span = Span::default();
+        walk_struct_def_mut(self, def);
+    }
+
+    fn visit_field_mut(&mut self, field: &mut Field) {
+        field.span = Span::default();
+        walk_field_mut(self, field);
+    }
+
+    fn visit_enum_def_mut(&mut self, def: &mut EnumDef) {
+        def.span = Span::default();
+        walk_enum_def_mut(self, def);
+    }
+
+    fn visit_enumerator_mut(&mut self, enumerator: &mut Enumerator) {
+        enumerator.span = Span::default();
+        walk_enumerator_mut(self, enumerator);
+    }
+
+    fn visit_typedef_mut(&mut self, def: &mut Typedef) {
+        def.span = Span::default();
+        walk_typedef_mut(self, def);
+    }
+
+    fn visit_init_mut(&mut self, init: &mut Init) {
+        if let Init::List { span, .. } = init {
+            *span = Span::default();
+        }
+        walk_init_mut(self, init);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        expr.span = Span::default();
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_ident_mut(&mut self, ident: &mut Ident) {
+        ident.span = Span::default();
+    }
+}
+
+fn unspanned(stmt: &Stmt) -> Stmt {
+    let mut stmt = stmt.clone();
+    Unspan.visit_stmt_mut(&mut stmt);
+    stmt
+}
+
+/// The statement lists directly inside `stmt`, compared one against another when two
+/// statements otherwise match.
+fn bodies(stmt: &Stmt) -> Vec<Vec<&Stmt>> {
+    match &stmt.kind {
+        StmtKind::Block(stmts) => vec![stmts.iter().collect()],
+        StmtKind::If { then, els, .. } => {
+            let mut bodies = vec![vec![&**then]];
+            bodies.extend(els.as_deref().map(|els| vec![els]));
+            bodies
+        }
+        StmtKind::While { body, .. }
+        | StmtKind::DoWhile { body, .. }
+        | StmtKind::For { body, .. }
+        | StmtKind::Switch { body, .. }
+        | StmtKind::Case { stmt: body, .. }
+        | StmtKind::Default(body)
+        | StmtKind::Label { stmt: body, .. } => vec![vec![&**body]],
+        StmtKind::Decl(decls) => match &decls[..] {
+            [Decl::Func(Function {
+                body: Some(body), ..
+            })] => vec![vec![&**body]],
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// `stmt` without spans or the statements of its `bodies`, which are left empty.
+fn shell(stmt: &Stmt) -> Stmt {
+    let mut stmt = unspanned(stmt);
+    let empty = || {
+        Box::new(Stmt {
+            kind: StmtKind::Empty,
+            span: Span::default(),
+        })
+    };
+    match &mut stmt.kind {
+        StmtKind::Block(stmts) => stmts.clear(),
+        StmtKind::If { then, els, .. } => {
+            *then = empty();
+            if let Some(els) = els {
+                *els = empty();
+            }
+        }
+        StmtKind::While { body, .. }
+        | StmtKind::DoWhile { body, .. }
+        | StmtKind::For { body, .. }
+        | StmtKind::Switch { body, .. }
+        | StmtKind::Case { stmt: body, .. }
+        | StmtKind::Default(body)
+        | StmtKind::Label { stmt: body, .. } => *body = empty(),
+        StmtKind::Decl(decls) => {
+            if let [Decl::Func(func)] = &mut decls[..] {
+                if func.body.is_some() {
+                    func.body = Some(empty());
+                }
+            }
+        }
+        _ => {}
+    }
+    stmt
+}
+
+/// Whether two statements are the same sort of statement, so that one is an edit of the
+/// other rather than a replacement: the same kind, declaring the same first name.
+fn same_sort(old: &Stmt, new: &Stmt) -> bool {
+    let name = |stmt: &Stmt| match &stmt.kind {
+        StmtKind::Decl(decls) => decls.first().map(|decl| match decl {
+            Decl::Var(var) => var.name.name.clone(),
+            Decl::Func(func) => func.name.name.clone(),
+            Decl::Struct(def) => def.tag.name.clone(),
+            Decl::Enum(def) => def.tag.as_ref().map_or(String::new(), |t| t.name.clone()),
+            Decl::Typedef(def) => def.name.name.clone(),
+        }),
+        _ => None,
+    };
+    std::mem::discriminant(&old.kind) == std::mem::discriminant(&new.kind) && name(old) == name(new)
+}
+
+fn diff_stmts<'a>(old: &[&'a Stmt], new: &[&'a Stmt], changes: &mut Vec<Change<'a>>) {
+    let a: Vec<Stmt> = old.iter().map(|s| unspanned(s)).collect();
+    let b: Vec<Stmt> = new.iter().map(|s| unspanned(s)).collect();
+    let (mut i, mut j) = (0, 0);
+    let sentinel = (a.len(), b.len());
+    for (x, y) in common(&a, &b).into_iter().chain([sentinel]) {
+        // statements between matches are paired up in order while they can be
+        let (mut old_gap, mut new_gap) = (&old[i..x], &new[j..y]);
+        while let ([o, old_rest @ ..], [n, new_rest @ ..]) = (old_gap, new_gap) {
+            if !same_sort(o, n) {
+                break;
+            }
+            let (old_bodies, new_bodies) = (bodies(o), bodies(n));
+            if old_bodies.len() == new_bodies.len() && shell(o) == shell(n) {
+                for (o, n) in old_bodies.iter().zip(&new_bodies) {
+                    diff_stmts(o, n, changes);
+                }
+            } else {
+                changes.push(Change::Changed { old: o, new: n });
+            }
+            (old_gap, new_gap) = (old_rest, new_rest);
+        }
+        changes.extend(old_gap.iter().map(|s| Change::Removed(s)));
+        changes.extend(new_gap.iter().map(|s| Change::Added(s)));
+        i = x + 1;
+        j = y + 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_test() {
+        let old = "int f(int a) {\n  return a + 1; // one\n}\n";
+        let new = "int f(int a)\n{\n    /* bumped */ return a + 2;\n}\n";
+        let hunks = diff_tokens(old, new);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].old.text(old), "1");
+        assert_eq!(hunks[0].new.text(new), "2");
+
+        assert!(equivalent("a=b+c;", "a = b + c ; // same"));
+        assert!(!equivalent("x = \"a b\";", "x = \"a  b\";"));
+
+        let hunks = diff_tokens("a; b;", "a; c; b; d");
+        assert_eq!(
+            hunks,
+            [
+                Hunk {
+                    old: Span::new(2, 2),
+                    new: Span::new(3, 5)
+                },
+                Hunk {
+                    old: Span::new(5, 5),
+                    new: Span::new(9, 10)
+                },
+            ]
+        );
+        assert_eq!(diff_tokens("a b c", "")[0].old, Span::new(0, 5));
+        assert!(diff_tokens("", "").is_empty());
+    }
+
+    #[test]
+    fn program_diff_test() {
+        use crate::parser::parse;
+
+        let old_src = "int x; int f(int a) { a = a + 1; return a; } int g() { x = 1; }";
+        let new_src =
+            "int x;\n// f\nint f(int a)\n{\n    a = (a + 1);\n    return a * 2; }\nchar c;";
+        let (old, new) = (parse(old_src).unwrap(), parse(new_src).unwrap());
+        let changes: Vec<String> = diff_programs(&old, &new)
+            .into_iter()
+            .map(|change| match change {
+                Change::Added(s) => format!("+ {}", s.text(new_src)),
+                Change::Removed(s) => format!("- {}", s.text(old_src)),
+                Change::Changed { old, new } => {
+                    format!("{} => {}", old.text(old_src), new.text(new_src))
+                }
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "return a; => return a * 2;",
+                "- int g() { x = 1; }",
+                "+ char c;"
+            ]
+        );
+
+        let src = "int f(int a) { if (a) return (a); else { a--; } }";
+        let reformatted = "int f(int a)\n{\n  if (a) return a;\n  else { a--; } } // same";
+        assert!(same_program(
+            &parse(src).unwrap(),
+            &parse(reformatted).unwrap()
+        ));
+        assert!(!same_program(
+            &parse(src).unwrap(),
+            &parse("int f(int b) { if (b) return b; else { b--; } }").unwrap()
+        ));
+    }
+
+    #[test]
+    fn common_test() {
+        let a: Vec<char> = "abcabba".chars().collect();
+        let b: Vec<char> = "cbabac".chars().collect();
+        let pairs = common(&a, &b);
+        assert_eq!(pairs.len(), 4);
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert!(pairs.iter().all(|&(x, y)| a[x] == b[y]));
+    }
+}
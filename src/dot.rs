@@ -0,0 +1,144 @@
+//! Graphviz export of the tree, to render a program's structure with `dot -Tpng`.
+
+use std::fmt::Write;
+
+use crate::arena::{Arena, Node};
+use crate::ast::*;
+use crate::pretty::{escape, join};
+
+/// Writes `program` as a DOT digraph with a box per node of its `Arena`, labelled with what
+/// the node is, any name or literal value it holds, and its span on a second line. Nodes are
+/// named `n0`, `n1` and so on by `NodeId`, and each has an edge from its parent.
+pub fn ast_to_dot(program: &Program) -> String {
+    let arena = Arena::new(program);
+    let mut out = String::from("digraph ast {\n    node [shape=box, fontname=monospace];\n");
+    for (id, node) in arena.iter() {
+        let span = node.span();
+        let label = format!("{}\n{}..{}", label(node), span.start, span.end);
+        writeln!(out, "    n{} [label=\"{}\"];", id.index(), quote(&label)).unwrap();
+        if let Some(parent) = arena.parent(id) {
+            writeln!(out, "    n{} -> n{};", parent.index(), id.index()).unwrap();
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Escapes `s` for a double-quoted DOT string.
+fn quote(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn declaration(ty: &Type, name: &str) -> String {
+    let (specifier, declarator) = ty.declaration(name);
+    join(&specifier, &declarator)
+}
+
+fn label(node: Node) -> String {
+    match node {
+        Node::Program(_) => "program".to_string(),
+        Node::Stmt(stmt) => match &stmt.kind {
+            StmtKind::Decl(_) => "decl".to_string(),
+            StmtKind::Expr(_) => "expr stmt".to_string(),
+            StmtKind::Block(_) => "block".to_string(),
+            StmtKind::If { .. } => "if".to_string(),
+            StmtKind::While { .. } => "while".to_string(),
+            StmtKind::DoWhile { .. } => "do while".to_string(),
+            StmtKind::For { .. } => "for".to_string(),
+            StmtKind::Switch { .. } => "switch".to_string(),
+            StmtKind::Case { .. } => "case".to_string(),
+            StmtKind::Default(_) => "default".to_string(),
+            StmtKind::Break => "break".to_string(),
+            StmtKind::Continue => "continue".to_string(),
+            StmtKind::Label { label, .. } => format!("label {}", label.name),
+            StmtKind::Goto(label) => format!("goto {}", label.name),
+            StmtKind::Return(_) => "return".to_string(),
+            StmtKind::Empty => "empty".to_string(),
+        },
+        Node::Decl(decl) => match decl {
+            Decl::Var(var) => format!("var {}", declaration(&var.ty, &var.name.name)),
+            Decl::Func(func) => format!("function {}", func.name.name),
+            Decl::Struct(def) if def.union => format!("union {}", def.tag.name),
+            Decl::Struct(def) => format!("struct {}", def.tag.name),
+            Decl::Enum(def) => match &def.tag {
+                Some(tag) => format!("enum {}", tag.name),
+                None => "enum".to_string(),
+            },
+            Decl::Typedef(def) => format!("typedef {}", declaration(&def.ty, &def.name.name)),
+        },
+        Node::Param(param) => {
+            let name = param.name.as_ref().map_or("", |name| &name.name);
+            format!("param {}", declaration(&param.ty, name))
+        }
+        Node::Field(field) => format!("field {}", declaration(&field.ty, &field.name.name)),
+        Node::Enumerator(e) => format!("enumerator {} = {}", e.name.name, e.value),
+        Node::InitList(_) => "init list".to_string(),
+        Node::Expr(expr) => match &expr.kind {
+            ExprKind::Int(n) => format!("int {}", n),
+            ExprKind::Char(c) => format!("char '{}'", escape(&[*c], b'\'')),
+            ExprKind::Str(bytes) => format!("str \"{}\"", escape(bytes, b'"')),
+            ExprKind::Var(name) => format!("var {}", name),
+            ExprKind::Unary(op @ (UnOp::PostInc | UnOp::PostDec), _) => {
+                format!("postfix {}", op.symbol())
+            }
+            ExprKind::Unary(op, _) => format!("unary {}", op.symbol()),
+            ExprKind::Binary(op, ..) => format!("binary {}", op.symbol()),
+            ExprKind::Assign(Some(op), ..) => format!("assign {}=", op.symbol()),
+            ExprKind::Assign(None, ..) => "assign =".to_string(),
+            ExprKind::Index(..) => "index".to_string(),
+            ExprKind::Member(_, field) => format!("member .{}", field.name),
+            ExprKind::Arrow(_, field) => format!("member ->{}", field.name),
+            ExprKind::Cast(ty, _) => format!("cast ({})", ty),
+            ExprKind::Sizeof(ty) => format!("sizeof ({})", ty),
+            ExprKind::SizeofExpr(_) => "sizeof".to_string(),
+            ExprKind::Comma(..) => "comma".to_string(),
+            ExprKind::Cond(..) => "cond ?:".to_string(),
+            ExprKind::Call(..) => "call".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn dot_test() {
+        let program = parse("int f(char *s) { return s[1] + \"a\\\"b\"[0]; }").unwrap();
+        assert_eq!(
+            ast_to_dot(&program),
+            r#"digraph ast {
+    node [shape=box, fontname=monospace];
+    n0 [label="program\n0..43"];
+    n1 [label="decl\n0..43"];
+    n0 -> n1;
+    n2 [label="function f\n4..43"];
+    n1 -> n2;
+    n3 [label="param char *s\n6..13"];
+    n2 -> n3;
+    n4 [label="block\n15..43"];
+    n2 -> n4;
+    n5 [label="return\n17..41"];
+    n4 -> n5;
+    n6 [label="binary +\n24..40"];
+    n5 -> n6;
+    n7 [label="index\n24..28"];
+    n6 -> n7;
+    n8 [label="var s\n24..25"];
+    n7 -> n8;
+    n9 [label="int 1\n26..27"];
+    n7 -> n9;
+    n10 [label="index\n31..40"];
+    n6 -> n10;
+    n11 [label="str \"a\\\"b\"\n31..37"];
+    n10 -> n11;
+    n12 [label="int 0\n38..39"];
+    n10 -> n12;
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! A source formatter. Unlike `pretty::ast_to_source`, which prints the AST, it rewrites
+//! only the whitespace between the tokens of the lossless tree, so comments, parentheses,
+//! typedef names and the spelling of literals all stay as written.
+
+use std::collections::HashMap;
+
+use crate::arena::{Arena, Node};
+use crate::ast::*;
+use crate::cst::{parse_cst, NodeKind, SyntaxNode, SyntaxToken};
+use crate::lexer::TokKind::*;
+use crate::parser::ParseError;
+use crate::source::Span;
+
+/// Reformats `src` in the style of `pretty::ast_to_source`: four spaces of indentation, braces
+/// on the line that opens them, `case` and other labels outdented by one level, single spaces
+/// around binary operators and after commas, and a blank line around each function
+/// definition. Other blank lines are kept, squeezed to one, and comments keep their place on
+/// a line of their own or after code. Formatting the result changes nothing. A source that
+/// doesn't parse is left alone, giving its errors instead.
+pub fn format_source(src: &str) -> Result<String, Vec<ParseError>> {
+    let (root, program, errs) = parse_cst(src);
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+    let arena = Arena::new(&program);
+    let nodes: HashMap<(NodeKind, Span), Node> = arena
+        .iter()
+        .map(|(_, n)| ((n.kind(), n.span()), n))
+        .collect();
+    let ast = |node: &SyntaxNode| nodes.get(&(node.kind(), node.span())).copied();
+
+    let mut formatter = Formatter {
+        out: String::new(),
+        depth: 0,
+        bodies: Vec::new(),
+        parens: 0,
+        newline: false,
+        opened: true,
+        spaced: false,
+    };
+    let toks = root.tokens();
+    let significant: Vec<usize> = (0..toks.len())
+        .filter(|&i| !toks[i].kind().is_trivia())
+        .collect();
+    // the top-level statements, by where they start, and whether each is a function definition
+    let items: HashMap<usize, (usize, bool)> = program
+        .stmts
+        .iter()
+        .enumerate()
+        .map(|(i, stmt)| (stmt.span.start, (i, is_definition(stmt))))
+        .collect();
+    let definition = |i: usize| is_definition(&program.stmts[i]);
+
+    let mut gap_start = 0;
+    for (n, &i) in significant.iter().enumerate() {
+        let tok = &toks[i];
+        let role = role(tok, &ast);
+        let blank = match items.get(&tok.span().start) {
+            Some(&(item, def)) => item > 0 && (def || definition(item - 1)),
+            None => false,
+        };
+        let closing = matches!(role, Role::BlockClose | Role::StructClose);
+        formatter.gap(&toks[gap_start..i], blank, closing);
+        if closing {
+            formatter.depth -= 1;
+            formatter.newline = true;
+        }
+        // a directive takes up its line
+        formatter.newline |= tok.kind() == Dir;
+        let prev = n.checked_sub(1).map(|p| &toks[significant[p]]);
+        formatter.token(tok, role, prev.map(|p| (p, self::role(p, &ast))));
+
+        let next = significant.get(n + 1).map(|&j| &toks[j]);
+        let next_is = |text: &str| next.is_some_and(|t| t.text() == text);
+        let next_role = next.map(|t| self::role(t, &ast));
+        formatter.newline = match role {
+            _ if tok.kind() == SCol => formatter.parens == 0,
+            _ if tok.kind() == Dir => true,
+            Role::BlockOpen => {
+                formatter.depth += 1;
+                true
+            }
+            Role::BlockClose => !next_is("else") && next_role != Some(Role::DoWhile),
+            Role::StructClose => false,
+            Role::HeadClose(end) | Role::BodyKeyword(end) => {
+                let inline = next_is("{") || (tok.text() == "else" && next_is("if"));
+                if !inline {
+                    formatter.bodies.push(end);
+                }
+                !inline
+            }
+            Role::LabelColon => true,
+            _ => false,
+        };
+        let end = tok.span().end;
+        while formatter.bodies.last().is_some_and(|&body| body <= end) {
+            formatter.bodies.pop();
+        }
+        gap_start = i + 1;
+    }
+    formatter.gap(&toks[gap_start..], false, false);
+    if !formatter.out.is_empty() && !formatter.out.ends_with('\n') {
+        formatter.out.push('\n');
+    }
+    Ok(formatter.out)
+}
+
+fn is_definition(stmt: &Stmt) -> bool {
+    matches!(&stmt.kind, StmtKind::Decl(decls)
+        if matches!(&decls[..], [Decl::Func(f)] if f.body.is_some()))
+}
+
+/// What a token does, as far as the space around it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Other,
+    /// A binary, assignment or conditional operator, or the `=` of an initializer.
+    Binary,
+    Prefix,
+    Postfix,
+    /// The `*` of a pointer declarator or type name, written against what follows it.
+    Star,
+    /// The `)` of a cast, written against the operand.
+    CastClose,
+    /// The first token of a `case`, `default` or label, which is outdented.
+    LabelStart,
+    LabelColon,
+    /// The `)` ending the head of a compound statement whose body ends at the byte given.
+    HeadClose(usize),
+    /// An `else` or `do`, whose body ends at the byte given.
+    BodyKeyword(usize),
+    /// The `while` of a `do` loop, which follows a braced body on its line.
+    DoWhile,
+    /// The braces of a block or of a struct or union's fields.
+    BlockOpen,
+    BlockClose,
+    /// The `}` of a struct or union, followed on its line by what the declaration declares.
+    StructClose,
+    /// The braces of an initializer list, written against the items.
+    ListOpen,
+    ListClose,
+}
+
+fn role<'ast>(tok: &SyntaxToken, ast: &impl Fn(&SyntaxNode) -> Option<Node<'ast>>) -> Role {
+    let kind = tok.kind();
+    let paren = matches!(kind, LPar | RPar);
+    match ast(tok.parent()) {
+        Some(Node::Expr(expr)) => match &expr.kind {
+            ExprKind::Binary(..)
+            | ExprKind::Assign(..)
+            | ExprKind::Cond(..)
+            | ExprKind::Comma(..) => {
+                if paren || kind == Comma {
+                    Role::Other
+                } else {
+                    Role::Binary
+                }
+            }
+            ExprKind::Unary(UnOp::PostInc | UnOp::PostDec, _) if matches!(kind, Inc | Dec) => {
+                Role::Postfix
+            }
+            ExprKind::Unary(..) if !paren => Role::Prefix,
+            ExprKind::Cast(_, operand) if kind == RPar && tok.span().end <= operand.span.start => {
+                Role::CastClose
+            }
+            ExprKind::Cast(..) | ExprKind::Sizeof(_) if kind == Mul => Role::Star,
+            _ => Role::Other,
+        },
+        Some(Node::Stmt(stmt)) => match (&stmt.kind, kind) {
+            (StmtKind::If { then: body, .. }, RPar)
+            | (StmtKind::While { body, .. }, RPar)
+            | (StmtKind::For { body, .. }, RPar)
+            | (StmtKind::Switch { body, .. }, RPar) => Role::HeadClose(body.span.end),
+            (
+                StmtKind::If {
+                    els: Some(body), ..
+                },
+                Key,
+            ) if tok.text() == "else" => Role::BodyKeyword(body.span.end),
+            (StmtKind::DoWhile { body, .. }, Key) if tok.text() == "do" => {
+                Role::BodyKeyword(body.span.end)
+            }
+            (StmtKind::DoWhile { .. }, Key) if tok.text() == "while" => Role::DoWhile,
+            (StmtKind::Case { .. } | StmtKind::Default(_) | StmtKind::Label { .. }, _) => {
+                if kind == Colon {
+                    Role::LabelColon
+                } else if tok.span().start == stmt.span.start {
+                    Role::LabelStart
+                } else {
+                    Role::Other
+                }
+            }
+            (StmtKind::Block(_), LBrc) => Role::BlockOpen,
+            (StmtKind::Block(_), RBrc) => Role::BlockClose,
+            (StmtKind::Decl(_), Mul) => Role::Star,
+            (StmtKind::Decl(_), As) => Role::Binary,
+            _ => Role::Other,
+        },
+        Some(Node::Decl(decl)) => match (decl, kind) {
+            (Decl::Struct(_), LBrc) => Role::BlockOpen,
+            (Decl::Struct(_), RBrc) => Role::StructClose,
+            (_, Mul) => Role::Star,
+            (_, As) => Role::Binary,
+            _ => Role::Other,
+        },
+        Some(Node::Param(_) | Node::Field(_)) if kind == Mul => Role::Star,
+        Some(Node::Enumerator(_)) if kind == As => Role::Binary,
+        Some(Node::InitList(_)) if kind == LBrc => Role::ListOpen,
+        Some(Node::InitList(_)) if kind == RBrc => Role::ListClose,
+        _ => Role::Other,
+    }
+}
+
+/// Whether a space goes between `prev` and `tok` on one line.
+fn space(prev: (&SyntaxToken, Role), tok: (&SyntaxToken, Role)) -> bool {
+    let ((prev, prev_role), (tok, role)) = (prev, tok);
+    let (a, b) = (prev.text(), tok.text());
+    // `- -x` and `& &x` mustn't run together into other operators
+    let last = a.chars().last().unwrap_or(' ');
+    if "+-&".contains(last) && b.starts_with(last) {
+        return true;
+    }
+    if matches!(tok.kind(), SCol | Comma | RPar | RBrk | LBrk | Dot | Arrow)
+        || matches!(prev.kind(), LPar | LBrk | Dot | Arrow)
+    {
+        return false;
+    }
+    match (prev_role, role) {
+        (Role::Prefix | Role::Star | Role::CastClose | Role::ListOpen, _) => false,
+        (_, Role::Postfix | Role::LabelColon | Role::ListClose) => false,
+        _ if tok.kind() == LPar => !(matches!(prev.kind(), Var | RPar | RBrk) || a == "sizeof"),
+        _ => true,
+    }
+}
+
+struct Formatter {
+    out: String,
+    /// The blocks open around the next token.
+    depth: usize,
+    /// The ends of the unbraced bodies open around the next token, each indented a level.
+    bodies: Vec<usize>,
+    parens: usize,
+    /// Whether the next token starts a line.
+    newline: bool,
+    /// Whether nothing has been written since a block opened, so no blank line goes there.
+    opened: bool,
+    /// Whether a block comment ends the line so far, so a space goes before any token after it.
+    spaced: bool,
+}
+
+impl Formatter {
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    fn indent(&mut self, outdent: bool) {
+        let level = self.depth + self.bodies.len();
+        let level = if outdent {
+            level.saturating_sub(1)
+        } else {
+            level
+        };
+        self.out.push_str(&"    ".repeat(level));
+    }
+
+    /// Ends the current line, if anything is on it.
+    fn end_line(&mut self) {
+        if !self.at_line_start() {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes the comments among the trivia `gap` before the next token. `blank` asks for a
+    /// blank line before what comes next, which the source can also ask for by having one.
+    fn gap(&mut self, gap: &[SyntaxToken], blank: bool, closing: bool) {
+        let mut lines = 0;
+        let mut blank = blank;
+        let mut inline = false;
+        let mut wrote = false;
+        for tok in gap {
+            match tok.kind() {
+                Nl => lines += 1,
+                Cmt | DocCmt | Shebang => {
+                    // a line comment runs to the end of its line
+                    let line = tok.kind() == Shebang || tok.text().starts_with("//");
+                    if lines == 0 && !self.at_line_start() {
+                        self.out.push(' ');
+                        self.newline |= line;
+                    } else {
+                        self.end_line();
+                        if (blank || lines > 1) && !self.opened && !self.out.is_empty() {
+                            self.out.push('\n');
+                        }
+                        self.indent(false);
+                        self.newline = line;
+                    }
+                    self.out.push_str(tok.text().trim_end());
+                    blank = false;
+                    self.opened = false;
+                    wrote = true;
+                    lines = 0;
+                    inline = !line;
+                }
+                Bom if self.out.is_empty() => self.out.push_str(tok.text()),
+                _ => {}
+            }
+        }
+        if self.newline || (wrote && lines > 0) {
+            self.end_line();
+            if (blank || lines > 1) && !self.opened && !closing && !self.out.is_empty() {
+                self.out.push('\n');
+            }
+        } else {
+            self.spaced = inline;
+        }
+    }
+
+    fn token(&mut self, tok: &SyntaxToken, role: Role, prev: Option<(&SyntaxToken, Role)>) {
+        if self.newline || self.at_line_start() {
+            self.end_line();
+            self.indent(role == Role::LabelStart);
+        } else if self.spaced {
+            self.out.push(' ');
+        } else if let Some(prev) = prev {
+            if !self.out.ends_with(' ') && space(prev, (tok, role)) {
+                self.out.push(' ');
+            }
+        }
+        self.spaced = false;
+        self.out.push_str(tok.text());
+        self.opened = role == Role::BlockOpen;
+        match tok.kind() {
+            LPar => self.parens += 1,
+            RPar => self.parens = self.parens.saturating_sub(1),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::equivalent;
+
+    #[test]
+    fn format_test() {
+        let src = "// counts down\nint   f(int n){if(n<2)return n;/* small */\nelse{n=-n;}\n\n\nwhile(n)n--;return n*(2+n);}\nstruct p{char*s;int y[2];}o={1,{2}};\nint main(void){switch(f(3)){case 1:x=(char)- -x;break;default:;}do{x++;}while(x<9);}";
+        let formatted = format_source(src).unwrap();
+        assert_eq!(
+            formatted,
+            "// counts down
+int f(int n) {
+    if (n < 2)
+        return n; /* small */
+    else {
+        n = -n;
+    }
+
+    while (n)
+        n--;
+    return n * (2 + n);
+}
+
+struct p {
+    char *s;
+    int y[2];
+} o = {1, {2}};
+
+int main(void) {
+    switch (f(3)) {
+    case 1:
+        x = (char)- -x;
+        break;
+    default:
+        ;
+    }
+    do {
+        x++;
+    } while (x < 9);
+}
+"
+        );
+        assert!(equivalent(src, &formatted));
+
+        let srcs = [
+            formatted.as_str(),
+            "typedef unsigned long T; T t[2] = {1, 2}; enum e{A,B=5}; int (*fp)(char, int *);",
+            "void g() { a: for (;;) if (x) if (y) x = 2; else ; else { x = sizeof(char *) + sizeof x; } goto a; }",
+            "int a; // one\n\n\n// two\n/* three */ int b; /** four */\nint c = a ? b : c, *d = &a;\n",
+            "void h() {\n    // only a comment\n}\nvoid k() { x = a /* in */ + b->c.d [1] ; }",
+            "/* c */",
+            "// c",
+            "int x; /* c */",
+            "int f() {\n #pragma hex8 allow(shadow)\n#pragma other\n int n = 2; return n; }",
+        ];
+        for src in srcs {
+            let once = format_source(src).unwrap();
+            assert!(equivalent(src, &once), "{}", once);
+            assert_eq!(format_source(&once).unwrap(), once, "{}", once);
+        }
+        assert_eq!(
+            format_source(srcs[3]).unwrap(),
+            "int a; // one\n\n// two\n/* three */ int b; /** four */\nint c = a ? b : c, *d = &a;\n"
+        );
+        assert_eq!(format_source("/* c */").unwrap(), "/* c */\n");
+        assert_eq!(
+            format_source(srcs[8]).unwrap(),
+            "int f() {\n    #pragma hex8 allow(shadow)\n    #pragma other\n    int n = 2;\n    return n;\n}\n"
+        );
+        assert!(format_source("int x = ;").is_err());
+    }
+}
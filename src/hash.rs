@@ -0,0 +1,377 @@
+//! Structural hashes of trees, equal for subtrees that differ only in spans, so only in
+//! formatting, comments and parentheses. Each node writes its fields in a fixed order of its
+//! own, as `Feed` says, rather than through the derived `Hash`, whose output isn't promised to
+//! stay the same between compilers, and the bytes go through a fixed hasher rather than `std`'s
+//! randomly seeded one. So the hashes are the same on every run and platform, and change only
+//! when the tree types do, which makes them fit to key a cache kept on disk.
+
+use std::hash::Hasher;
+
+use crate::ast::*;
+
+/// 64-bit FNV-1a, reading every integer as little-endian bytes and `usize` as 64 bits.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHasher {
+    pub fn new() -> StableHasher {
+        StableHasher::default()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as i64 as u64);
+    }
+}
+
+/// A tree that can be hashed by its structure alone.
+pub trait StructuralHash {
+    fn structural_hash(&self) -> u64;
+}
+
+macro_rules! structural_hash {
+    ($($node:ty,)*) => {
+        $(impl StructuralHash for $node {
+            fn structural_hash(&self) -> u64 {
+                let mut hasher = StableHasher::new();
+                self.feed(&mut hasher);
+                hasher.finish()
+            }
+        })*
+    };
+}
+
+structural_hash! {
+    Program,
+    Stmt,
+    Decl,
+    Function,
+    Expr,
+}
+
+/// Writes the structure of a node to a hasher, field by field in declaration order and
+/// leaving out spans. A variant is written as its position in its enum, and a string or list
+/// as its length and then its items, so no two trees write the same bytes.
+pub trait Feed {
+    fn feed(&self, h: &mut StableHasher);
+}
+
+impl Feed for bool {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u8(*self as u8);
+    }
+}
+
+impl Feed for u8 {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u8(*self);
+    }
+}
+
+impl Feed for u64 {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u64(*self);
+    }
+}
+
+impl Feed for i64 {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u64(*self as u64);
+    }
+}
+
+impl Feed for str {
+    fn feed(&self, h: &mut StableHasher) {
+        self.as_bytes().feed(h);
+    }
+}
+
+impl Feed for String {
+    fn feed(&self, h: &mut StableHasher) {
+        self.as_str().feed(h);
+    }
+}
+
+impl<T: Feed> Feed for [T] {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u64(self.len() as u64);
+        self.iter().for_each(|item| item.feed(h));
+    }
+}
+
+impl<T: Feed> Feed for Vec<T> {
+    fn feed(&self, h: &mut StableHasher) {
+        self.as_slice().feed(h);
+    }
+}
+
+impl<T: Feed + ?Sized> Feed for Box<T> {
+    fn feed(&self, h: &mut StableHasher) {
+        (**self).feed(h);
+    }
+}
+
+impl<T: Feed> Feed for Option<T> {
+    fn feed(&self, h: &mut StableHasher) {
+        match self {
+            None => h.write_u8(0),
+            Some(value) => {
+                h.write_u8(1);
+                value.feed(h);
+            }
+        }
+    }
+}
+
+/// Feeds each of a node's fields in turn.
+macro_rules! feed {
+    ($h:expr; $($field:expr),* $(,)?) => {
+        {
+            $($field.feed($h);)*
+        }
+    };
+}
+
+impl Feed for Ident {
+    fn feed(&self, h: &mut StableHasher) {
+        self.name.feed(h);
+    }
+}
+
+impl Feed for Quals {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.constant, self.volatile);
+    }
+}
+
+impl Feed for Storage {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u8(*self as u8);
+    }
+}
+
+impl Feed for UnOp {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u8(*self as u8);
+    }
+}
+
+impl Feed for BinOp {
+    fn feed(&self, h: &mut StableHasher) {
+        h.write_u8(*self as u8);
+    }
+}
+
+impl Feed for Type {
+    fn feed(&self, h: &mut StableHasher) {
+        match self {
+            Type::Void => h.write_u8(0),
+            Type::Bool => h.write_u8(1),
+            Type::Char => h.write_u8(2),
+            Type::UChar => h.write_u8(3),
+            Type::Int => h.write_u8(4),
+            Type::UInt => h.write_u8(5),
+            Type::Long => h.write_u8(6),
+            Type::ULong => h.write_u8(7),
+            Type::Pointer(to) => feed!(h; 8u8, to),
+            Type::Array { elem, len } => feed!(h; 9u8, elem, len),
+            Type::Struct(tag) => feed!(h; 10u8, tag),
+            Type::Union(tag) => feed!(h; 11u8, tag),
+            Type::Enum(tag) => feed!(h; 12u8, tag),
+            Type::Function { ret, params } => feed!(h; 13u8, ret, params),
+            Type::Qualified(quals, ty) => feed!(h; 14u8, quals, ty),
+        }
+    }
+}
+
+impl Feed for Program {
+    fn feed(&self, h: &mut StableHasher) {
+        self.stmts.feed(h);
+    }
+}
+
+impl Feed for Decl {
+    fn feed(&self, h: &mut StableHasher) {
+        match self {
+            Decl::Var(var) => feed!(h; 0u8, var),
+            Decl::Func(func) => feed!(h; 1u8, func),
+            Decl::Struct(def) => feed!(h; 2u8, def),
+            Decl::Enum(def) => feed!(h; 3u8, def),
+            Decl::Typedef(def) => feed!(h; 4u8, def),
+        }
+    }
+}
+
+impl Feed for VarDecl {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.storage, self.ty, self.name, self.init);
+    }
+}
+
+impl Feed for Init {
+    fn feed(&self, h: &mut StableHasher) {
+        match self {
+            Init::Expr(expr) => feed!(h; 0u8, expr),
+            Init::List { items, .. } => feed!(h; 1u8, items),
+        }
+    }
+}
+
+impl Feed for Function {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.storage, self.ret, self.name, self.params, self.body);
+    }
+}
+
+impl Feed for Param {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.ty, self.name);
+    }
+}
+
+impl Feed for StructDef {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.union, self.tag, self.fields, self.size);
+    }
+}
+
+impl Feed for Field {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.ty, self.name, self.offset);
+    }
+}
+
+impl Feed for Typedef {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.ty, self.name);
+    }
+}
+
+impl Feed for EnumDef {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.tag, self.enumerators);
+    }
+}
+
+impl Feed for Enumerator {
+    fn feed(&self, h: &mut StableHasher) {
+        feed!(h; self.name, self.value);
+    }
+}
+
+impl Feed for Stmt {
+    fn feed(&self, h: &mut StableHasher) {
+        match &self.kind {
+            StmtKind::Decl(decls) => feed!(h; 0u8, decls),
+            StmtKind::Expr(expr) => feed!(h; 1u8, expr),
+            StmtKind::Block(stmts) => feed!(h; 2u8, stmts),
+            StmtKind::If { cond, then, els } => feed!(h; 3u8, cond, then, els),
+            StmtKind::While { cond, body } => feed!(h; 4u8, cond, body),
+            StmtKind::DoWhile { body, cond } => feed!(h; 5u8, body, cond),
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => feed!(h; 6u8, init, cond, step, body),
+            StmtKind::Switch { cond, body } => feed!(h; 7u8, cond, body),
+            StmtKind::Case { value, stmt } => feed!(h; 8u8, value, stmt),
+            StmtKind::Default(stmt) => feed!(h; 9u8, stmt),
+            StmtKind::Break => h.write_u8(10),
+            StmtKind::Continue => h.write_u8(11),
+            StmtKind::Label { label, stmt } => feed!(h; 12u8, label, stmt),
+            StmtKind::Goto(label) => feed!(h; 13u8, label),
+            StmtKind::Return(value) => feed!(h; 14u8, value),
+            StmtKind::Empty => h.write_u8(15),
+        }
+    }
+}
+
+impl Feed for Expr {
+    fn feed(&self, h: &mut StableHasher) {
+        match &self.kind {
+            ExprKind::Int(n) => feed!(h; 0u8, n),
+            ExprKind::Char(c) => feed!(h; 1u8, c),
+            ExprKind::Str(bytes) => feed!(h; 2u8, bytes),
+            ExprKind::Var(name) => feed!(h; 3u8, name),
+            ExprKind::Unary(op, operand) => feed!(h; 4u8, op, operand),
+            ExprKind::Binary(op, lhs, rhs) => feed!(h; 5u8, op, lhs, rhs),
+            ExprKind::Assign(op, lhs, rhs) => feed!(h; 6u8, op, lhs, rhs),
+            ExprKind::Index(array, index) => feed!(h; 7u8, array, index),
+            ExprKind::Member(base, field) => feed!(h; 8u8, base, field),
+            ExprKind::Arrow(base, field) => feed!(h; 9u8, base, field),
+            ExprKind::Cast(ty, operand) => feed!(h; 10u8, ty, operand),
+            ExprKind::Sizeof(ty) => feed!(h; 11u8, ty),
+            ExprKind::SizeofExpr(operand) => feed!(h; 12u8, operand),
+            ExprKind::Comma(lhs, rhs) => feed!(h; 13u8, lhs, rhs),
+            ExprKind::Cond(cond, then, els) => feed!(h; 14u8, cond, then, els),
+            ExprKind::Call(callee, args) => feed!(h; 15u8, callee, args),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_expr};
+
+    #[test]
+    fn hash_test() {
+        let hash = |src| parse(src).unwrap().structural_hash();
+        let src = "int f(int a) { return a * (a + 1); }";
+        assert_eq!(
+            hash(src),
+            hash("// f\nint f(int a)\n{\n    return (a) * (a + 1);\n}\n")
+        );
+        assert_ne!(hash(src), hash("int f(int b) { return b * (b + 1); }"));
+        assert_ne!(hash(src), hash("int f(int a) { return a * (a - 1); }"));
+
+        // fixed between runs, unlike `DefaultHasher`
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+
+        let x = parse_expr("x + 1").unwrap();
+        // pinned, so a change to the encoding is noticed
+        assert_eq!(x.structural_hash(), 0x9ac8_3254_b093_bb6e);
+        let program = parse("int g(int x) { int y = x + 1; }").unwrap();
+        let repeated = program
+            .find_all::<Expr>()
+            .find(|e| e.structural_hash() == x.structural_hash());
+        assert!(repeated.is_some());
+    }
+}
@@ -0,0 +1,237 @@
+//! Syntax highlighting of source text as ANSI escape sequences or HTML.
+
+use crate::lexer::{tokenize_all, TokKind, TokKind::*};
+
+/// The highlighting category of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Keyword,
+    Ident,
+    Number,
+    Str,
+    Char,
+    Comment,
+    DocComment,
+    Directive,
+    Operator,
+    Punct,
+    Error,
+    /// Whitespace, which is never styled.
+    Plain,
+}
+
+impl Class {
+    pub const ALL: [Class; 12] = [
+        Class::Keyword,
+        Class::Ident,
+        Class::Number,
+        Class::Str,
+        Class::Char,
+        Class::Comment,
+        Class::DocComment,
+        Class::Directive,
+        Class::Operator,
+        Class::Punct,
+        Class::Error,
+        Class::Plain,
+    ];
+
+    pub fn of(kind: TokKind) -> Class {
+        match kind {
+            Key => Class::Keyword,
+            Var => Class::Ident,
+            IntLit | HexLit | OctLit | BinLit | FltLit => Class::Number,
+            StrLit => Class::Str,
+            ChrLit => Class::Char,
+            Cmt | Shebang => Class::Comment,
+            DocCmt => Class::DocComment,
+            Dir => Class::Directive,
+            LPar | RPar | LBrc | RBrc | LBrk | RBrk | SCol | Comma | Dot | Colon => Class::Punct,
+            Error => Class::Error,
+            Spc | Nl | CR | Tab | Cont | Bom => Class::Plain,
+            _ => Class::Operator,
+        }
+    }
+
+    /// The CSS class used for this category in HTML output, e.g. `hx-keyword`.
+    pub fn css_class(self) -> &'static str {
+        match self {
+            Class::Keyword => "hx-keyword",
+            Class::Ident => "hx-ident",
+            Class::Number => "hx-number",
+            Class::Str => "hx-string",
+            Class::Char => "hx-char",
+            Class::Comment => "hx-comment",
+            Class::DocComment => "hx-doc",
+            Class::Directive => "hx-directive",
+            Class::Operator => "hx-operator",
+            Class::Punct => "hx-punct",
+            Class::Error => "hx-error",
+            Class::Plain => "hx-plain",
+        }
+    }
+}
+
+/// How to render one category: SGR parameters such as `1;34` for ANSI output, and CSS
+/// declarations for the HTML stylesheet. Empty strings leave the category unstyled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Style {
+    pub ansi: String,
+    pub css: String,
+}
+
+impl Style {
+    pub fn new(ansi: &str, css: &str) -> Style {
+        Style {
+            ansi: ansi.to_string(),
+            css: css.to_string(),
+        }
+    }
+}
+
+/// A style for every `Class`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    styles: Vec<Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::plain()
+            .with(
+                Class::Keyword,
+                Style::new("1;35", "color:#a626a4;font-weight:bold"),
+            )
+            .with(Class::Number, Style::new("33", "color:#986801"))
+            .with(Class::Str, Style::new("32", "color:#50a14f"))
+            .with(Class::Char, Style::new("32", "color:#50a14f"))
+            .with(
+                Class::Comment,
+                Style::new("2;37", "color:#a0a1a7;font-style:italic"),
+            )
+            .with(
+                Class::DocComment,
+                Style::new("36", "color:#0184bc;font-style:italic"),
+            )
+            .with(Class::Directive, Style::new("34", "color:#4078f2"))
+            .with(
+                Class::Error,
+                Style::new("1;41", "background:#e45649;color:#fff"),
+            )
+    }
+}
+
+impl Theme {
+    /// A theme leaving everything unstyled.
+    pub fn plain() -> Theme {
+        Theme {
+            styles: vec![Style::default(); Class::ALL.len()],
+        }
+    }
+
+    pub fn with(mut self, class: Class, style: Style) -> Theme {
+        self.styles[class as usize] = style;
+        self
+    }
+
+    pub fn style(&self, class: Class) -> &Style {
+        &self.styles[class as usize]
+    }
+
+    /// CSS rules for the classes used by `highlight_html`.
+    pub fn stylesheet(&self) -> String {
+        let mut out = String::new();
+        for class in Class::ALL {
+            let css = &self.style(class).css;
+            if !css.is_empty() {
+                out.push_str(&format!(".{} {{ {} }}\n", class.css_class(), css));
+            }
+        }
+        out
+    }
+}
+
+/// Renders `src` with ANSI escape sequences for a terminal. Styles are reset before every
+/// newline so that pagers showing a subset of lines stay readable.
+pub fn highlight_ansi(src: &str, theme: &Theme) -> String {
+    let mut out = String::with_capacity(src.len() * 2);
+    for tok in tokenize_all(src).0 {
+        let class = Class::of(tok.kind());
+        let ansi = &theme.style(class).ansi;
+        if class == Class::Plain || ansi.is_empty() {
+            out.push_str(tok.text());
+            continue;
+        }
+        for (i, line) in tok.text().split('\n').enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            if !line.is_empty() {
+                out.push_str(&format!("\x1b[{}m{}\x1b[0m", ansi, line));
+            }
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Renders `src` as HTML, wrapping each styled token in a `<span>` with its `css_class`, inside
+/// a `<pre class="hx">`. Use `Theme::stylesheet` for the matching CSS.
+pub fn highlight_html(src: &str, theme: &Theme) -> String {
+    let mut out = String::with_capacity(src.len() * 2);
+    out.push_str("<pre class=\"hx\">");
+    for tok in tokenize_all(src).0 {
+        let class = Class::of(tok.kind());
+        if class == Class::Plain || theme.style(class).css.is_empty() {
+            escape_html(tok.text(), &mut out);
+            continue;
+        }
+        out.push_str(&format!("<span class=\"{}\">", class.css_class()));
+        escape_html(tok.text(), &mut out);
+        out.push_str("</span>");
+    }
+    out.push_str("</pre>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi_test() {
+        let theme = Theme::plain()
+            .with(Class::Keyword, Style::new("1", ""))
+            .with(Class::Comment, Style::new("2", ""));
+        assert_eq!(
+            highlight_ansi("int x; /* a\nb */", &theme),
+            "\x1b[1mint\x1b[0m x; \x1b[2m/* a\x1b[0m\n\x1b[2mb */\x1b[0m"
+        );
+        assert_eq!(highlight_ansi("int x;", &Theme::plain()), "int x;");
+    }
+
+    #[test]
+    fn html_test() {
+        let html = highlight_html("if (a < \"&\") @", &Theme::default());
+        assert_eq!(
+            html,
+            "<pre class=\"hx\"><span class=\"hx-keyword\">if</span> (a &lt; \
+             <span class=\"hx-string\">&quot;&amp;&quot;</span>) \
+             <span class=\"hx-error\">@</span></pre>"
+        );
+        let css = Theme::default().stylesheet();
+        assert!(css.contains(".hx-keyword { color:#a626a4;font-weight:bold }\n"));
+        assert!(!css.contains("hx-ident"));
+    }
+}
@@ -0,0 +1,74 @@
+//! Interning of identifier text, so names can be compared and stored as small integers.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// An interned string, only meaningful together with the `Interner` that produced it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Maps strings to `Symbol`s, storing each distinct string once.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    map: HashMap<Arc<str>, Symbol>,
+    strings: Vec<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, str: &str) -> Symbol {
+        if let Some(&sym) = self.map.get(str) {
+            return sym;
+        }
+        let sym = Symbol(self.strings.len() as u32);
+        let str: Arc<str> = Arc::from(str);
+        self.strings.push(str.clone());
+        self.map.insert(str, sym);
+        sym
+    }
+
+    /// The symbol for `str` if it has been interned, without interning it.
+    pub fn get(&self, str: &str) -> Option<Symbol> {
+        self.map.get(str).copied()
+    }
+
+    /// Panics if `sym` comes from a different `Interner`.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        &self.strings[sym.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_test() {
+        let mut interner = Interner::new();
+        let a = interner.intern("count");
+        let b = interner.intern("café");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("count"), a);
+        assert_eq!(interner.get("café"), Some(b));
+        assert_eq!(interner.get("other"), None);
+        assert_eq!(interner.resolve(b), "café");
+        assert_eq!(interner.len(), 2);
+    }
+}
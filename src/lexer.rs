@@ -1,163 +1,1563 @@
-use lazy_static::lazy_static;
-use regex::Regex;
+use core::fmt;
+
 use TokKind::*;
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Tok {
+#[cfg(feature = "std")]
+use crate::intern::{Interner, Symbol};
+use crate::json::{FromJson, Json, JsonError, ToJson};
+use crate::literal;
+use crate::prelude::*;
+use crate::source::{LineCol, LineIndex, Span};
+
+mod buffer;
+mod config;
+mod lookup;
+#[cfg(test)]
+mod oracle;
+#[cfg(feature = "std")]
+mod parallel;
+mod relex;
+#[cfg(feature = "std")]
+mod stream;
+mod unicode;
+
+pub use buffer::TokenBuffer;
+pub use config::LexerConfig;
+use config::DEFAULT_CONFIG;
+pub use lookup::{token_at_offset, token_before_offset, tokens_in_range};
+#[cfg(feature = "std")]
+pub use parallel::{tokenize_all_parallel, tokenize_files, LexedFile};
+pub use relex::{relex, relex_with_config, Edit};
+#[cfg(feature = "std")]
+pub use stream::{tokenize_reader, ReaderLexer, StreamError};
+
+/// A token borrowing its text from the source it was lexed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tok<'src> {
+    pub(crate) kind: TokKind,
+    pub(crate) pos: usize,
+    pub(crate) str: &'src str,
+}
+
+/// A token that owns its text, for callers that need to outlive the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedTok {
     kind: TokKind,
     pos: usize,
     str: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl<'src> Tok<'src> {
+    pub fn kind(&self) -> TokKind {
+        self.kind
+    }
+
+    /// The source text of the token.
+    pub fn text(&self) -> &'src str {
+        self.str
+    }
+
+    /// Byte offset of the start of the token.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.pos, self.pos + self.str.len())
+    }
+
+    pub fn line_col(&self, idx: &LineIndex) -> LineCol {
+        idx.line_col(self.pos)
+    }
+
+    /// For a `Dir` token, the directive name, e.g. `define` in `#define X 1`.
+    pub fn directive_name(&self) -> Option<&'src str> {
+        self.directive_parts().map(|(name, _)| name)
+    }
+
+    /// For a `Dir` token, the raw text following the directive name, without surrounding
+    /// whitespace.
+    pub fn directive_args(&self) -> Option<&'src str> {
+        self.directive_parts().map(|(_, args)| args)
+    }
+
+    fn directive_parts(&self) -> Option<(&'src str, &'src str)> {
+        if self.kind != Dir {
+            return None;
+        }
+        let rest = self.str[1..].trim_start_matches([' ', '\t']);
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        Some((&rest[..name_len], rest[name_len..].trim()))
+    }
+
+    /// The value of an `IntLit`, `HexLit`, `OctLit` or `BinLit`.
+    pub fn int_value(&self) -> Result<u64, LexError> {
+        literal::parse_int(self)
+    }
+
+    /// The byte value of a `ChrLit`.
+    pub fn char_value(&self) -> Result<u8, LexError> {
+        literal::decode_char(self)
+    }
+
+    /// The bytes a `StrLit` denotes at runtime, with escapes decoded.
+    pub fn str_bytes(&self) -> Result<Vec<u8>, LexError> {
+        literal::decode_str(self)
+    }
+
+    /// Interns the text of a `Var` or `Key` token. Other kinds have no symbol.
+    #[cfg(feature = "std")]
+    pub fn symbol(&self, interner: &mut Interner) -> Option<Symbol> {
+        matches!(self.kind, Var | Key).then(|| interner.intern(self.str))
+    }
+
+    pub fn to_owned_tok(&self) -> OwnedTok {
+        OwnedTok {
+            kind: self.kind,
+            pos: self.pos,
+            str: self.str.to_string(),
+        }
+    }
+}
+
+impl OwnedTok {
+    pub fn kind(&self) -> TokKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.str
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn span(&self) -> Span {
+        self.as_tok().span()
+    }
+
+    pub fn as_tok(&self) -> Tok<'_> {
+        Tok {
+            kind: self.kind,
+            pos: self.pos,
+            str: &self.str,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokKind {
     As,
     LPar,
     RPar,
     LBrc,
     RBrc,
+    LBrk,
+    RBrk,
     SCol,
+    Comma,
+    Dot,
+    Arrow,
+    Colon,
+    Quest,
     Add,
     Sub,
     Inc,
     Dec,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    LAnd,
+    LOr,
+    Not,
+    BAnd,
+    BOr,
+    Xor,
+    BNot,
+    Shl,
+    Shr,
+    AddAs,
+    SubAs,
+    MulAs,
+    DivAs,
+    ModAs,
+    AndAs,
+    OrAs,
+    XorAs,
+    ShlAs,
+    ShrAs,
     Key,
     Var,
-    Lit,
+    IntLit,
+    HexLit,
+    OctLit,
+    BinLit,
+    FltLit,
+    ChrLit,
+    StrLit,
     Cmt,
+    /// A `///` line or `/** */` block documentation comment.
+    DocCmt,
+    Dir,
     Spc,
     Nl,
     CR,
     Tab,
+    /// A backslash at the end of a line, splicing it with the next one.
+    Cont,
+    /// A UTF-8 byte order mark at the very start of the source.
+    Bom,
+    /// A `#!` line at the start of the source, after any `Bom`.
+    Shebang,
+    /// Characters that could not be lexed, only produced by `tokenize_all`.
+    Error,
+}
+
+impl TokKind {
+    pub const ALL: &'static [TokKind] = &[
+        As, LPar, RPar, LBrc, RBrc, LBrk, RBrk, SCol, Comma, Dot, Arrow, Colon, Quest, Add, Sub,
+        Inc, Dec, Mul, Div, Mod, Eq, Neq, Lt, Gt, Le, Ge, LAnd, LOr, Not, BAnd, BOr, Xor, BNot,
+        Shl, Shr, AddAs, SubAs, MulAs, DivAs, ModAs, AndAs, OrAs, XorAs, ShlAs, ShrAs, Key, Var,
+        IntLit, HexLit, OctLit, BinLit, FltLit, ChrLit, StrLit, Cmt, DocCmt, Dir, Spc, Nl, CR, Tab,
+        Cont, Bom, Shebang, Error,
+    ];
+
+    /// The variant name, e.g. `"LPar"`.
+    pub fn name(self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn from_name(name: &str) -> Option<TokKind> {
+        TokKind::ALL.iter().copied().find(|k| k.name() == name)
+    }
+
+    /// Whitespace, line continuations, comments and file prefixes, which carry no meaning for the parser.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            Spc | Tab | CR | Nl | Cont | Cmt | DocCmt | Bom | Shebang
+        )
+    }
+}
+
+impl ToJson for TokKind {
+    fn to_json(&self) -> Json {
+        Json::Str(self.name())
+    }
 }
 
-lazy_static! {
-    static ref KEYWORDS: Vec<String> = ["char", "int"].iter().map(|s| s.to_string()).collect();
-    static ref REGEXES: Vec<(Regex, TokKind)> = [
-            (r"=", As),
-            (r"\(", LPar),
-            (r"\)", RPar),
-            (r"\{", LBrc),
-            (r"\}", RBrc),
-            (r";", SCol),
-            (r"\+", Add),
-            (r"-", Sub),
-            (r"\+\+", Inc),
-            (r"--", Dec),
-            (&KEYWORDS.iter().map(|s| s.to_owned()).reduce(|acc: String, key: String| acc + "|" + &key).unwrap() , Key),
-            (r"[a-zA-Z_]\w*", Var), // NOTE: Var MUST come after Key, otherwise keywords would be matched as variables
-            (concat!(
-                r#""(\\.|[^\\"])*?""#, // string literal
-                "|",
-                r"('[^\']?')|('\\.+?')", // char literal
-                "|",
-                r"\d+" // int literal
-            ), Lit),
-            (concat!(
-                r"//.*", // single line comment
-                "|",
-                r"\/\*(.|[\r\n])*?\*\/" // multiline comment
-            ), Cmt),
-            (r" +", Spc),
-            (r"\n", Nl),
-            (r"\r", CR),
-            (r"\t", Tab),
-        ].iter().map(|(s, t)| (Regex::new(s).unwrap(), t.clone())).collect();
-}
-
-fn longest_match(str: &str, ind: usize) -> Option<(Tok, usize)> {
-    let mut max: Option<(Tok, usize)> = None;
-    for (rgx, tk_k) in REGEXES.iter() {
-        let find = rgx.find_at(str, ind);
-        if find.is_none() {
-            continue;
+impl FromJson for TokKind {
+    fn from_json(json: &Json) -> Result<TokKind, JsonError> {
+        json.as_str()
+            .and_then(TokKind::from_name)
+            .ok_or_else(|| Json::type_error("a token kind"))
+    }
+}
+
+impl ToJson for Tok<'_> {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("kind", self.kind.to_json()),
+            ("pos", Json::Num(self.pos as i64)),
+            ("text", Json::Str(self.str.to_string())),
+        ])
+    }
+}
+
+impl ToJson for OwnedTok {
+    fn to_json(&self) -> Json {
+        self.as_tok().to_json()
+    }
+}
+
+impl FromJson for OwnedTok {
+    fn from_json(json: &Json) -> Result<OwnedTok, JsonError> {
+        let pos = json.field("pos")?.as_num().filter(|&p| p >= 0);
+        Ok(OwnedTok {
+            kind: TokKind::from_json(json.field("kind")?)?,
+            pos: pos.ok_or_else(|| Json::type_error("a position"))? as usize,
+            str: json
+                .field("text")?
+                .as_str()
+                .ok_or_else(|| Json::type_error("a string"))?
+                .to_string(),
+        })
+    }
+}
+
+/// Dumps a token stream as a JSON array of `{"kind", "pos", "text"}` objects.
+pub fn tokens_to_json(toks: &[Tok]) -> String {
+    toks.to_json().to_string()
+}
+
+/// Reads back a token stream written by `tokens_to_json`.
+pub fn tokens_from_json(json: &str) -> Result<Vec<OwnedTok>, JsonError> {
+    Vec::from_json(&Json::parse(json)?)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    /// No token matches at `pos`. `found` is the run of characters that could not be lexed
+    /// and `snippet` is the source line it appears on, cut to `SNIPPET_CONTEXT` bytes either
+    /// side of `pos`.
+    Unrecognized {
+        pos: usize,
+        found: String,
+        snippet: String,
+    },
+    /// A literal that starts out well-formed but is missing digits or has invalid ones.
+    MalformedLit {
+        pos: usize,
+        found: String,
+        msg: &'static str,
+    },
+    /// A block comment opened at `pos` that is never closed.
+    UnterminatedCmt { pos: usize },
+    /// A char literal opened at `pos` that isn't closed on the same line.
+    UnterminatedChr { pos: usize },
+    /// An unknown escape sequence such as `\q` inside a string or char literal.
+    InvalidEscape { pos: usize, found: String },
+    /// A string or char literal whose contents do not decode to a valid value.
+    InvalidLit {
+        pos: usize,
+        found: String,
+        msg: &'static str,
+    },
+    /// One of the limits set in `LexerConfig` was exceeded at `pos`.
+    LimitExceeded {
+        pos: usize,
+        msg: &'static str,
+        limit: usize,
+    },
+}
+
+impl LexError {
+    pub fn pos(&self) -> usize {
+        match self {
+            LexError::Unrecognized { pos, .. }
+            | LexError::MalformedLit { pos, .. }
+            | LexError::UnterminatedCmt { pos }
+            | LexError::UnterminatedChr { pos }
+            | LexError::InvalidEscape { pos, .. }
+            | LexError::InvalidLit { pos, .. }
+            | LexError::LimitExceeded { pos, .. } => *pos,
         }
-        let find = find.unwrap();
-        if find.start() != ind {
-            continue;
-        }
-        if max.is_none() || find.len() > max.as_ref().unwrap().1 {
-            max = Some((
-                Tok {
-                    pos: find.start(),
-                    str: find.as_str().to_owned(),
-                    kind: tk_k.clone(),
-                },
-                find.len(),
-            ));
+    }
+
+    /// Moves the error `by` bytes later, for errors found in a slice of a larger input.
+    pub(crate) fn shifted(mut self, by: usize) -> LexError {
+        match &mut self {
+            LexError::Unrecognized { pos, .. }
+            | LexError::MalformedLit { pos, .. }
+            | LexError::UnterminatedCmt { pos }
+            | LexError::UnterminatedChr { pos }
+            | LexError::InvalidEscape { pos, .. }
+            | LexError::InvalidLit { pos, .. }
+            | LexError::LimitExceeded { pos, .. } => *pos += by,
         }
+        self
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::Unrecognized {
+                pos,
+                found,
+                snippet,
+            } => write!(
+                f,
+                "unrecognized character(s) `{}` at byte {}: `{}`",
+                found.escape_debug(),
+                pos,
+                snippet.escape_debug()
+            ),
+            LexError::MalformedLit { pos, found, msg } => {
+                write!(f, "malformed literal `{}` at byte {}: {}", found, pos, msg)
+            }
+            LexError::UnterminatedCmt { pos } => {
+                write!(f, "unterminated block comment opened at byte {}", pos)
+            }
+            LexError::UnterminatedChr { pos } => {
+                write!(f, "unterminated char literal opened at byte {}", pos)
+            }
+            LexError::InvalidEscape { pos, found } => {
+                write!(f, "invalid escape sequence `{}` at byte {}", found, pos)
+            }
+            LexError::InvalidLit { pos, found, msg } => {
+                write!(f, "invalid literal {} at byte {}: {}", found, pos, msg)
+            }
+            LexError::LimitExceeded { pos, msg, limit } => {
+                write!(f, "{} at byte {} (limit {})", msg, pos, limit)
+            }
+        }
+    }
+}
+
+impl core::error::Error for LexError {}
+
+/// Every reserved word of the language. Anything else matching the identifier pattern lexes
+/// as a `Var`.
+pub const KEYWORDS: &[&str] = &[
+    "char", "int", "if", "else", "while", "for", "do", "return", "void", "break", "continue",
+    "switch", "case", "default", "struct", "enum", "const", "static", "goto", "union", "typedef",
+    "volatile", "sizeof", "extern", "unsigned", "signed", "long", "_Bool", "bool",
+];
+
+pub fn is_keyword(str: &str) -> bool {
+    KEYWORDS.contains(&str)
+}
+
+/// Operators and punctuation, longest first so that the first prefix match is the maximal
+/// munch.
+const OPERATORS: &[(&str, TokKind)] = &[
+    ("<<=", ShlAs),
+    (">>=", ShrAs),
+    ("++", Inc),
+    ("--", Dec),
+    ("->", Arrow),
+    ("+=", AddAs),
+    ("-=", SubAs),
+    ("*=", MulAs),
+    ("/=", DivAs),
+    ("%=", ModAs),
+    ("&=", AndAs),
+    ("|=", OrAs),
+    ("^=", XorAs),
+    ("==", Eq),
+    ("!=", Neq),
+    ("<=", Le),
+    (">=", Ge),
+    ("&&", LAnd),
+    ("||", LOr),
+    ("<<", Shl),
+    (">>", Shr),
+    ("=", As),
+    ("(", LPar),
+    (")", RPar),
+    ("{", LBrc),
+    ("}", RBrc),
+    ("[", LBrk),
+    ("]", RBrk),
+    (";", SCol),
+    (",", Comma),
+    (".", Dot),
+    (":", Colon),
+    ("?", Quest),
+    ("+", Add),
+    ("-", Sub),
+    ("*", Mul),
+    ("/", Div),
+    ("%", Mod),
+    ("<", Lt),
+    (">", Gt),
+    ("!", Not),
+    ("&", BAnd),
+    ("|", BOr),
+    ("^", Xor),
+    ("~", BNot),
+];
+
+fn is_ident_start(c: char, config: &LexerConfig) -> bool {
+    c.is_ascii_alphabetic()
+        || c == '_'
+        || (c == '$' && config.allows_dollar())
+        || (!c.is_ascii() && unicode::is_xid_start(c))
+}
+
+fn is_ident_continue(c: char, config: &LexerConfig) -> bool {
+    c.is_ascii_alphanumeric()
+        || c == '_'
+        || (c == '$' && config.allows_dollar())
+        || (!c.is_ascii() && unicode::is_xid_continue(c))
+}
+
+/// Length of the run of bytes at the start of `bytes` satisfying `pred`.
+fn run(bytes: &[u8], pred: impl Fn(u8) -> bool) -> usize {
+    bytes.iter().position(|&b| !pred(b)).unwrap_or(bytes.len())
+}
+
+/// Length of a line continuation (`\\` followed by a newline) at the start of `bytes`, or 0.
+fn continuation(bytes: &[u8]) -> usize {
+    match bytes {
+        [b'\\', b'\n', ..] => 2,
+        [b'\\', b'\r', b'\n', ..] => 3,
+        _ => 0,
     }
-    max
 }
 
-pub fn tokenize(src: &str) -> Result<Vec<Tok>, String> {
-    let mut ind = 0;
-    let mut out = Vec::new();
+/// Like `run`, stopping at the first byte satisfying `stop` but running on across line
+/// continuations.
+fn logical_line(bytes: &[u8], stop: impl Fn(u8) -> bool) -> usize {
+    let mut i = 0;
+    while i < bytes.len() {
+        match continuation(&bytes[i..]) {
+            0 if stop(bytes[i]) => break,
+            0 => i += 1,
+            len => i += len,
+        }
+    }
+    i
+}
+
+/// Like `run`, also allowing single `_` or `'` digit separators between two matching bytes.
+fn digits(bytes: &[u8], pred: impl Fn(u8) -> bool) -> usize {
+    let mut i = 0;
     loop {
-        if ind == src.len() {
-            return Ok(out);
+        i += run(&bytes[i..], &pred);
+        let sep = matches!(bytes.get(i), Some(b'_' | b'\''));
+        if i == 0 || !sep || !bytes.get(i + 1).is_some_and(|&b| pred(b)) {
+            return i;
+        }
+        i += 1;
+    }
+}
+
+/// Length of an exponent such as `e-3` at the start of `bytes`, or 0 if there isn't a complete
+/// one.
+fn exponent(bytes: &[u8]) -> usize {
+    if !matches!(bytes.first(), Some(b'e' | b'E')) {
+        return 0;
+    }
+    let sign = matches!(bytes.get(1), Some(b'+' | b'-')) as usize;
+    match run(&bytes[1 + sign..], |b| b.is_ascii_digit()) {
+        0 => 0,
+        digits => 1 + sign + digits,
+    }
+}
+
+/// Scans a number starting with a digit or a `.` followed by a digit.
+fn number(bytes: &[u8]) -> (TokKind, usize) {
+    let radix_kind = match bytes {
+        [b'0', b'x' | b'X', ..] => Some(HexLit),
+        [b'0', b'o' | b'O', ..] => Some(OctLit),
+        [b'0', b'b' | b'B', ..] => Some(BinLit),
+        _ => None,
+    };
+    if let Some(kind) = radix_kind {
+        // digits are validated later, so that `0x` and `0xG` get a dedicated diagnostic
+        return (
+            kind,
+            2 + digits(&bytes[2..], |b| b.is_ascii_alphanumeric() || b == b'_'),
+        );
+    }
+    let int = digits(bytes, |b| b.is_ascii_digit());
+    let mut len = int;
+    let mut float = false;
+    if bytes.get(len) == Some(&b'.') {
+        len += 1 + digits(&bytes[len + 1..], |b| b.is_ascii_digit());
+        float = true;
+    }
+    let exp = exponent(&bytes[len..]);
+    if exp > 0 {
+        len += exp;
+        float = true;
+    }
+    if !float {
+        return (IntLit, int);
+    }
+    if matches!(bytes.get(len), Some(b'f' | b'F')) {
+        len += 1;
+    }
+    (FltLit, len)
+}
+
+/// Scans a string or char literal delimited by `quote`, returning its length if it is
+/// terminated, or else the length up to the end of its line (or of the input, if newlines are
+/// allowed). A backslash escapes the next character or splices the line with the next.
+pub(crate) fn quoted(rest: &str, quote: char, allow_newline: bool) -> Result<usize, usize> {
+    let mut chars = rest.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if c == quote => return Ok(i + 1),
+            '\\' => {
+                if rest[i + 1..].starts_with("\r\n") {
+                    chars.next();
+                }
+                chars.next().ok_or(rest.len())?;
+            }
+            '\n' if !allow_newline => return Err(i),
+            _ => {}
+        }
+    }
+    Err(rest.len())
+}
+
+/// Scans a block comment, which may nest. Returns `None` if it is never closed.
+fn block_comment(bytes: &[u8]) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i + 1 < bytes.len() {
+        match &bytes[i..i + 2] {
+            b"/*" => depth += 1,
+            b"*/" => depth -= 1,
+            _ => {
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+        if depth == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// The kind and length of the longest token starting at `ind`.
+fn scan(src: &str, ind: usize, config: &LexerConfig) -> Option<(TokKind, usize)> {
+    let rest = &src[ind..];
+    let bytes = rest.as_bytes();
+    let next = bytes.get(1).copied();
+    let tok = match *bytes.first()? {
+        b' ' => (Spc, run(bytes, |b| b == b' ')),
+        b'\n' => (Nl, 1),
+        b'\r' if next == Some(b'\n') && config.merges_crlf() => (Nl, 2),
+        b'\r' => (CR, 1),
+        b'\t' => (Tab, 1),
+        b'\\' if continuation(bytes) > 0 => (Cont, continuation(bytes)),
+        b'0'..=b'9' => number(bytes),
+        b'.' if next.is_some_and(|b| b.is_ascii_digit()) => number(bytes),
+        b'"' => (StrLit, quoted(rest, '"', true).ok()?),
+        // an unterminated char literal still lexes, up to the end of its line, so that
+        // `validate` can report it
+        b'\'' => (ChrLit, quoted(rest, '\'', false).unwrap_or_else(|len| len)),
+        0xef if ind == 0 && rest.starts_with('\u{feff}') => (Bom, 3),
+        b'#' if next == Some(b'!') && (ind == 0 || &src[..ind] == "\u{feff}") => {
+            (Shebang, run(bytes, |b| b != b'\r' && b != b'\n'))
+        }
+        b'#' => (Dir, logical_line(bytes, |b| b == b'\r' || b == b'\n')),
+        b'/' if next == Some(b'/') && config.allows_line_comments() => {
+            let doc = bytes.get(2) == Some(&b'/') && bytes.get(3) != Some(&b'/');
+            let mut len = logical_line(bytes, |b| b == b'\n');
+            if config.merges_crlf() && bytes[..len].ends_with(b"\r") && len < bytes.len() {
+                len -= 1;
+            }
+            (if doc { DocCmt } else { Cmt }, len)
+        }
+        b'/' if next == Some(b'*') => {
+            let doc = bytes.get(2) == Some(&b'*') && !matches!(bytes.get(3), Some(b'*' | b'/'));
+            let len = block_comment(bytes).unwrap_or(bytes.len());
+            (if doc { DocCmt } else { Cmt }, len)
+        }
+        _ => {
+            let c = rest.chars().next().unwrap();
+            if is_ident_start(c, config) {
+                let len = rest
+                    .char_indices()
+                    .find(|&(_, c)| !is_ident_continue(c, config))
+                    .map_or(rest.len(), |(i, _)| i);
+                let kind = if config.is_keyword(&rest[..len]) {
+                    Key
+                } else {
+                    Var
+                };
+                (kind, len)
+            } else {
+                let (op, kind) = OPERATORS.iter().find(|(op, _)| rest.starts_with(op))?;
+                (*kind, op.len())
+            }
+        }
+    };
+    Some(tok)
+}
+
+fn validate(tok: &Tok) -> Result<(), LexError> {
+    let is_cmt = matches!(tok.kind, Cmt | DocCmt);
+    if is_cmt && tok.str.starts_with("/*") && block_comment(tok.str.as_bytes()).is_none() {
+        return Err(LexError::UnterminatedCmt { pos: tok.pos });
+    }
+    if tok.kind == ChrLit && quoted(tok.str, '\'', false).is_err() {
+        return Err(LexError::UnterminatedChr { pos: tok.pos });
+    }
+    match tok.kind {
+        IntLit | HexLit | OctLit | BinLit => tok.int_value().map(|_| ()),
+        ChrLit => tok.char_value().map(|_| ()),
+        StrLit => tok.str_bytes().map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// How much of the line around an unrecognized character its error quotes.
+pub(super) const SNIPPET_CONTEXT: usize = 160;
+
+fn unrecognized(src: &str, ind: usize, config: &LexerConfig) -> LexError {
+    let mut end = ind;
+    for (i, c) in src[ind..].char_indices() {
+        if i > 0 && (c.is_whitespace() || scan(src, ind + i, config).is_some()) {
+            break;
+        }
+        end = ind + i + c.len_utf8();
+    }
+    // searching a bounded window keeps many errors on one long line from going quadratic
+    let mut lo = ind.saturating_sub(SNIPPET_CONTEXT);
+    while !src.is_char_boundary(lo) {
+        lo += 1;
+    }
+    let mut hi = (end + SNIPPET_CONTEXT).min(src.len());
+    while !src.is_char_boundary(hi) {
+        hi -= 1;
+    }
+    let line_start = src[lo..ind].rfind('\n').map_or(lo, |i| lo + i + 1);
+    let line_end = src[ind..hi].find('\n').map_or(hi, |i| ind + i);
+    LexError::Unrecognized {
+        pos: ind,
+        found: src[ind..end].to_string(),
+        snippet: src[line_start..line_end].trim_end_matches('\r').to_string(),
+    }
+}
+
+/// Lazily lexes a source string, one token per call to `next`.
+///
+/// After an error, lexing resumes just past the characters that caused it.
+pub struct Lexer<'src> {
+    src: &'src str,
+    config: &'src LexerConfig,
+    ind: usize,
+    at_line_start: bool,
+    /// Tokens produced so far, for `LexerConfig::max_tokens`.
+    count: usize,
+    peeked: Option<Option<Result<Tok<'src>, LexError>>>,
+}
+
+impl<'src> Lexer<'src> {
+    pub fn new(src: &'src str) -> Lexer<'src> {
+        Lexer::with_config(src, &DEFAULT_CONFIG)
+    }
+
+    pub fn with_config(src: &'src str, config: &'src LexerConfig) -> Lexer<'src> {
+        Lexer {
+            src,
+            config,
+            ind: 0,
+            at_line_start: true,
+            count: 0,
+            peeked: None,
+        }
+    }
+
+    /// Returns the next token without consuming it.
+    pub fn peek(&mut self) -> Option<&Result<Tok<'src>, LexError>> {
+        if self.peeked.is_none() {
+            let next = self.lex_next();
+            self.peeked = Some(next);
+        }
+        self.peeked.as_ref().unwrap().as_ref()
+    }
+
+    /// Drops whitespace and comments from the stream. Errors are still reported.
+    pub fn without_trivia(self) -> impl Iterator<Item = Result<Tok<'src>, LexError>> {
+        self.filter(|res| !matches!(res, Ok(tok) if tok.kind.is_trivia()))
+    }
+
+    /// Lexes the rest of the source like `tokenize_all`.
+    pub fn collect_all(mut self) -> (Vec<Tok<'src>>, Vec<LexError>) {
+        let mut toks = Vec::new();
+        let mut errs = Vec::new();
+        while let Some((tok, err)) = self.next_recovering() {
+            toks.push(tok);
+            errs.extend(err);
+        }
+        (toks, errs)
+    }
+
+    /// Starts lexing `src` at `ind`, which must be the start of a token. `at_line_start` is
+    /// whether only whitespace and comments precede it on its line.
+    fn resume(
+        src: &'src str,
+        config: &'src LexerConfig,
+        ind: usize,
+        at_line_start: bool,
+    ) -> Lexer<'src> {
+        Lexer {
+            src,
+            config,
+            ind,
+            at_line_start,
+            count: 0,
+            peeked: None,
         }
-        let find = longest_match(src, ind);
-        if find.is_none() {
-            return Err("L + Bozo".to_string());
+    }
+
+    /// Like `next`, but also produces an `Error` token covering the characters of each error.
+    fn next_recovering(&mut self) -> Option<(Tok<'src>, Option<LexError>)> {
+        let start = self.ind;
+        match self.lex_next()? {
+            Ok(tok) => Some((tok, None)),
+            Err(e) => {
+                let tok = Tok {
+                    kind: Error,
+                    pos: start,
+                    str: &self.src[start..self.ind],
+                };
+                Some((tok, Some(e)))
+            }
+        }
+    }
+
+    fn lex_next(&mut self) -> Option<Result<Tok<'src>, LexError>> {
+        if self.ind == self.src.len() {
+            return None;
+        }
+        if let Some(err) = self.check_limits() {
+            // nothing past a limit is lexed, so the error covers the rest of the source
+            self.ind = self.src.len();
+            return Some(Err(err));
+        }
+        self.count += 1;
+        let find = scan(self.src, self.ind, self.config).map(|(kind, len)| {
+            let tok = Tok {
+                kind,
+                pos: self.ind,
+                str: &self.src[self.ind..self.ind + len],
+            };
+            (tok, len)
+        });
+        if find.is_none() || (find.as_ref().unwrap().0.kind == Dir && !self.at_line_start) {
+            let err = unrecognized(self.src, self.ind, self.config);
+            if let LexError::Unrecognized { found, .. } = &err {
+                self.ind += found.len();
+            }
+            self.at_line_start = false;
+            return Some(Err(err));
         }
         let find = find.unwrap();
-        out.push(find.0);
-        ind += find.1;
+        if let Some(limit) = self.config.token_len_limit().filter(|&max| find.1 > max) {
+            self.ind += find.1;
+            self.at_line_start = false;
+            return Some(Err(LexError::LimitExceeded {
+                pos: find.0.pos,
+                msg: "token too long",
+                limit,
+            }));
+        }
+        if let Err(e) = validate(&find.0) {
+            self.ind += find.1;
+            self.at_line_start = false;
+            return Some(Err(e));
+        }
+        match find.0.kind {
+            Nl => self.at_line_start = true,
+            // continuations join physical lines, so `a \` + newline + `#x` isn't a directive
+            Spc | Tab | CR | Cont | Cmt | DocCmt | Bom => {}
+            _ => self.at_line_start = false,
+        }
+        self.ind += find.1;
+        Some(Ok(find.0))
+    }
+}
+
+impl Lexer<'_> {
+    fn check_limits(&self) -> Option<LexError> {
+        if let Some(limit) = self.config.source_len_limit() {
+            if self.src.len() > limit {
+                return Some(LexError::LimitExceeded {
+                    pos: self.ind,
+                    msg: "source too large",
+                    limit,
+                });
+            }
+        }
+        let limit = self.config.token_limit()?;
+        (self.count >= limit).then_some(LexError::LimitExceeded {
+            pos: self.ind,
+            msg: "too many tokens",
+            limit,
+        })
+    }
+}
+
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Result<Tok<'src>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.lex_next(),
+        }
     }
 }
 
+/// Lexes all of `src`, stopping at the first error.
+pub fn tokenize(src: &str) -> Result<Vec<Tok<'_>>, LexError> {
+    Lexer::new(src).collect()
+}
+
+/// Lexes all of `src`, covering the characters of each lexical error with an `Error` token
+/// and collecting the errors instead of stopping.
+pub fn tokenize_all(src: &str) -> (Vec<Tok<'_>>, Vec<LexError>) {
+    Lexer::new(src).collect_all()
+}
+
+/// Like `tokenize`, also interning every identifier and keyword. Entry `i` of the symbols is
+/// for token `i`.
+#[cfg(feature = "std")]
+pub fn tokenize_interned<'src>(
+    src: &'src str,
+    interner: &mut Interner,
+) -> Result<(Vec<Tok<'src>>, Vec<Option<Symbol>>), LexError> {
+    let toks = tokenize(src)?;
+    let syms = toks.iter().map(|t| t.symbol(interner)).collect();
+    Ok((toks, syms))
+}
+
+/// Merges each run of string literals separated only by trivia into one `StrLit` spanning the
+/// run, the way C joins `"foo" "bar"` into `"foobar"`. `toks` must have been lexed from `src`.
+/// The merged token's text includes the quotes and trivia in between; `Tok::str_bytes`
+/// decodes it to the concatenated bytes.
+pub fn concat_strings<'src>(src: &'src str, toks: Vec<Tok<'src>>) -> Vec<Tok<'src>> {
+    let mut out: Vec<Tok<'src>> = Vec::with_capacity(toks.len());
+    // the index in `out` of a string literal that only trivia has followed so far
+    let mut open: Option<usize> = None;
+    for tok in toks {
+        match (tok.kind, open) {
+            (StrLit, Some(i)) => {
+                out.truncate(i + 1);
+                let start = out[i].pos;
+                out[i].str = &src[start..tok.pos + tok.str.len()];
+            }
+            (StrLit, None) => {
+                open = Some(out.len());
+                out.push(tok);
+            }
+            (kind, _) => {
+                if !kind.is_trivia() {
+                    open = None;
+                }
+                out.push(tok);
+            }
+        }
+    }
+    out
+}
+
+/// Concatenates the text of `toks`. For the output of `tokenize_all`, which covers every
+/// byte of the source with some token, this reproduces the source exactly.
+pub fn detokenize(toks: &[Tok]) -> String {
+    let mut out = String::with_capacity(toks.iter().map(|t| t.str.len()).sum());
+    for tok in toks {
+        out.push_str(tok.str);
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn simple_lexer_test() -> Result<(), String> {
+    fn simple_lexer_test() -> Result<(), LexError> {
         let src = "char c = 3;";
         let actual = tokenize(src)?;
         let expected = vec![
             Tok {
                 pos: 0,
-                str: "char".to_string(),
+                str: "char",
                 kind: Key,
             },
             Tok {
                 pos: 4,
-                str: " ".to_string(),
+                str: " ",
                 kind: Spc,
             },
             Tok {
                 pos: 5,
-                str: "c".to_string(),
+                str: "c",
                 kind: Var,
             },
             Tok {
                 pos: 6,
-                str: " ".to_string(),
+                str: " ",
                 kind: Spc,
             },
             Tok {
                 pos: 7,
-                str: "=".to_string(),
+                str: "=",
                 kind: As,
             },
             Tok {
                 pos: 8,
-                str: " ".to_string(),
+                str: " ",
                 kind: Spc,
             },
             Tok {
                 pos: 9,
-                str: "3".to_string(),
-                kind: Lit,
+                str: "3",
+                kind: IntLit,
             },
             Tok {
                 pos: 10,
-                str: ";".to_string(),
+                str: ";",
                 kind: SCol,
             },
         ];
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    #[test]
+    fn token_line_col_test() -> Result<(), LexError> {
+        let src = "int a;\n  a = 3;";
+        let idx = LineIndex::new(src);
+        let toks = tokenize(src)?;
+        let lit = toks.iter().find(|t| t.kind == IntLit).unwrap();
+        assert_eq!(lit.line_col(&idx), LineCol { line: 2, col: 7 });
+        Ok(())
+    }
+
+    #[test]
+    fn accessor_test() -> Result<(), LexError> {
+        let toks = tokenize("int  xy;")?;
+        assert_eq!(toks[2].kind(), Var);
+        assert_eq!(toks[2].text(), "xy");
+        assert_eq!(toks[2].span(), Span::new(5, 7));
+        assert_eq!(toks[2].to_owned_tok().span(), toks[2].span());
+        Ok(())
+    }
+
+    #[test]
+    fn json_test() -> Result<(), JsonError> {
+        let toks = tokenize("x = \"a\\n\";").unwrap();
+        let json = tokens_to_json(&toks);
+        assert!(json.starts_with(r#"[{"kind":"Var","pos":0,"text":"x"},"#));
+        let back = tokens_from_json(&json)?;
+        assert_eq!(back.iter().map(OwnedTok::as_tok).collect::<Vec<_>>(), toks);
+        for kind in TokKind::ALL {
+            assert_eq!(TokKind::from_name(&kind.name()), Some(*kind));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn owned_tok_test() -> Result<(), LexError> {
+        let owned: Vec<OwnedTok> = tokenize("int x;")?.iter().map(Tok::to_owned_tok).collect();
+        let toks: Vec<Tok> = owned.iter().map(OwnedTok::as_tok).collect();
+        assert_eq!(toks, tokenize("int x;")?);
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_iter_test() {
+        let mut lexer = Lexer::new("a++ $ b");
+        assert_eq!(lexer.peek().unwrap().as_ref().unwrap().kind, Var);
+        assert_eq!(lexer.next().unwrap().unwrap().str, "a");
+        assert_eq!(lexer.next().unwrap().unwrap().kind, Inc);
+        assert_eq!(lexer.next().unwrap().unwrap().kind, Spc);
+        assert_eq!(lexer.next().unwrap().unwrap_err().pos(), 4);
+        assert_eq!(lexer.next().unwrap().unwrap().kind, Spc);
+        assert_eq!(lexer.next().unwrap().unwrap().str, "b");
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn radix_lit_test() -> Result<(), LexError> {
+        let kinds: Vec<TokKind> = tokenize("0x3F 0b1010 0o17 017")?
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != Spc)
+            .collect();
+        assert_eq!(kinds, vec![HexLit, BinLit, OctLit, IntLit]);
+        for bad in ["0x", "0b102", "0o8", "0xG1"] {
+            assert!(matches!(
+                tokenize(bad),
+                Err(LexError::MalformedLit { pos: 0, .. })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn literal_value_test() -> Result<(), LexError> {
+        let toks = tokenize(r#"42 0x2A 0o52 0b101010 'a' '\n' "hi\t""#)?;
+        let lits: Vec<&Tok> = toks.iter().filter(|t| t.kind != Spc).collect();
+        for tok in &lits[..4] {
+            assert_eq!(tok.int_value()?, 42);
+        }
+        assert_eq!(lits[4].kind, ChrLit);
+        assert_eq!(lits[4].char_value()?, b'a');
+        assert_eq!(lits[5].char_value()?, b'\n');
+        assert_eq!(lits[6].kind, StrLit);
+        assert_eq!(lits[6].str_bytes()?, b"hi\t");
+        assert!(lits[6].int_value().is_err());
+        assert!(matches!(
+            tokenize("99999999999999999999"),
+            Err(LexError::MalformedLit { pos: 0, .. })
+        ));
+        assert!(matches!(
+            tokenize(r#"x = "\q";"#),
+            Err(LexError::InvalidEscape { pos: 5, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn float_lit_test() -> Result<(), LexError> {
+        for src in ["1.5", "2e3", ".25f", "1.", "6.02E+23", "1e-3F"] {
+            let toks = tokenize(src)?;
+            assert_eq!(toks.len(), 1, "{}", src);
+            assert_eq!(toks[0].kind, FltLit, "{}", src);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn operator_test() -> Result<(), LexError> {
+        let kinds: Vec<TokKind> = tokenize("a<b<<c<=d>>e&&!f|~g^h%i/j*k!=l==m||n&o>p>=q")?
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != Var)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Lt, Shl, Le, Shr, LAnd, Not, BOr, BNot, Xor, Mod, Div, Mul, Neq, Eq, LOr, BAnd, Gt,
+                Ge
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compound_assign_test() -> Result<(), LexError> {
+        let kinds: Vec<TokKind> = tokenize("+= -= *= /= %= &= |= ^= <<= >>= == <= >=")?
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != Spc)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![AddAs, SubAs, MulAs, DivAs, ModAs, AndAs, OrAs, XorAs, ShlAs, ShrAs, Eq, Le, Ge]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keyword_test() -> Result<(), LexError> {
+        for tok in tokenize("if else while for do return void break continue switch case")? {
+            assert!(tok.kind == Spc || (tok.kind == Key && is_keyword(tok.str)));
+        }
+        let toks = tokenize("iffy doubt constant")?;
+        assert!(toks.iter().all(|t| t.kind != Key));
+        Ok(())
+    }
+
+    #[test]
+    fn punctuation_test() -> Result<(), LexError> {
+        let kinds: Vec<TokKind> = tokenize("a[i].b->c ? f(x, y) : .5")?
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| *k != Spc)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                Var, LBrk, Var, RBrk, Dot, Var, Arrow, Var, Quest, Var, LPar, Var, Comma, Var,
+                RPar, Colon, FltLit
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn directive_test() -> Result<(), LexError> {
+        let toks = tokenize("#include <hex8.h>\n  #  define MAX 10\nint x;")?;
+        let dirs: Vec<(&str, &str)> = toks
+            .iter()
+            .filter_map(|t| Some((t.directive_name()?, t.directive_args()?)))
+            .collect();
+        assert_eq!(dirs, vec![("include", "<hex8.h>"), ("define", "MAX 10")]);
+        assert!(tokenize("int x; #define Y").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn without_trivia_test() -> Result<(), LexError> {
+        let toks = Lexer::new("int  x = 1; // one\n")
+            .without_trivia()
+            .collect::<Result<Vec<_>, _>>()?;
+        let strs: Vec<&str> = toks.iter().map(|t| t.str).collect();
+        assert_eq!(strs, vec!["int", "x", "=", "1", ";"]);
+        Ok(())
+    }
+
+    #[test]
+    fn unicode_ident_test() -> Result<(), LexError> {
+        let toks = tokenize("café = naïve_1 + 变量;")?;
+        let vars: Vec<(usize, &str)> = toks
+            .iter()
+            .filter(|t| t.kind == Var)
+            .map(|t| (t.pos, t.str))
+            .collect();
+        assert_eq!(vars, vec![(0, "café"), (8, "naïve_1"), (19, "变量")]);
+        assert_eq!(tokenize("x€").unwrap_err().pos(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn utf8_test() -> Result<(), LexError> {
+        let src = "char *s = \"héllo 😀 wörld\"; // café ☕\n/* ünïcödé\n 🎉 */ /// ∀x\n\
+                   #define GREETING \"¡hola!\"\nx = '😀' + 'é';";
+        let (toks, errs) = tokenize_all(src);
+        assert_eq!(detokenize(&toks), src);
+        let str_lit = toks.iter().find(|t| t.kind == StrLit).unwrap();
+        assert_eq!(str_lit.str_bytes()?, "héllo 😀 wörld".as_bytes());
+        assert_eq!(str_lit.span().text(src), "\"héllo 😀 wörld\"");
+        let kinds: Vec<TokKind> = toks
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| !k.is_trivia())
+            .collect();
+        assert_eq!(
+            kinds,
+            [Key, Mul, Var, As, StrLit, SCol, Dir, Var, As, Error, Add, Error, SCol]
+        );
+        // chars are single bytes, so multi-byte ones are rejected rather than truncated
+        assert_eq!(errs.len(), 2);
+        assert!(errs
+            .iter()
+            .all(|e| matches!(e, LexError::InvalidLit { .. })));
+        assert_eq!(errs[0].pos(), src.find("'😀'").unwrap());
+
+        // lexing any prefix ending on a char boundary must neither panic nor lose bytes
+        for (end, _) in src.char_indices() {
+            let toks = tokenize_all(&src[..end]).0;
+            assert_eq!(detokenize(&toks), &src[..end]);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn interned_test() -> Result<(), LexError> {
+        let mut interner = Interner::new();
+        let (toks, syms) = tokenize_interned("int x = x + y;", &mut interner)?;
+        assert_eq!(syms.len(), toks.len());
+        assert_eq!(syms[2], syms[6]);
+        assert_ne!(syms[2], syms[10]);
+        assert_eq!(syms[1], None);
+        assert_eq!(interner.resolve(syms[0].unwrap()), "int");
+        assert_eq!(interner.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn config_test() -> Result<(), LexError> {
+        let kinds = |src, config: &LexerConfig| -> Result<Vec<TokKind>, LexError> {
+            Lexer::with_config(src, config)
+                .without_trivia()
+                .map(|t| t.map(|t| t.kind))
+                .collect()
+        };
+        let config = LexerConfig::new()
+            .keywords(["Loop", "var"])
+            .dollar_in_idents(true)
+            .case_sensitive(false);
+        assert_eq!(
+            kinds("VAR $a = loop int", &config)?,
+            vec![Key, Var, As, Key, Var]
+        );
+        let config = LexerConfig::new().line_comments(false);
+        assert_eq!(kinds("a // b", &config)?, vec![Var, Div, Div, Var]);
+        assert_eq!(kinds("INT $", &LexerConfig::new()).unwrap_err().pos(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_test() -> Result<(), LexError> {
+        let kinds = |src| -> Result<Vec<TokKind>, LexError> {
+            Ok(tokenize(src)?.iter().map(|t| t.kind).collect())
+        };
+        assert_eq!(
+            kinds("\u{feff}#!/usr/bin/hex8 -r\nint")?,
+            [Bom, Shebang, Nl, Key]
+        );
+        assert_eq!(kinds("#!x\r\n#!y")?, [Shebang, CR, Nl, Dir]);
+        assert_eq!(kinds("\u{feff}#define X")?, [Bom, Dir]);
+        assert!(kinds(" \u{feff}").is_err());
+        assert_eq!(kinds(" #!x")?, [Spc, Dir]);
+        Ok(())
+    }
+
+    /// Each of these used to cost time proportional to everything before it on the line.
+    #[test]
+    fn long_line_errors_test() {
+        let src = "`x ".repeat(100_000);
+        let (toks, errs) = tokenize_all(&src);
+        assert_eq!(errs.len(), 100_000);
+        assert_eq!(toks.len(), 300_000);
+        match &errs[50_000] {
+            LexError::Unrecognized { snippet, .. } => {
+                assert!(snippet.len() <= 2 * SNIPPET_CONTEXT + 1)
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn limits_test() {
+        let config = LexerConfig::new().max_token_len(8);
+        let (toks, errs) = Lexer::with_config("a /* long comment */ b", &config).collect_all();
+        let kinds: Vec<TokKind> = toks.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, [Var, Spc, Error, Spc, Var]);
+        assert!(matches!(
+            errs[..],
+            [LexError::LimitExceeded {
+                pos: 2,
+                limit: 8,
+                ..
+            }]
+        ));
+
+        let config = LexerConfig::new().max_tokens(3);
+        let (toks, errs) = Lexer::with_config("a b c d", &config).collect_all();
+        assert_eq!(toks.len(), 4);
+        assert_eq!(toks[3].kind, Error);
+        assert_eq!(toks[3].str, " c d");
+        assert_eq!(errs[0].to_string(), "too many tokens at byte 3 (limit 3)");
+
+        let config = LexerConfig::new().max_source_len(4);
+        let err = Lexer::with_config("int x;", &config)
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            LexError::LimitExceeded {
+                pos: 0,
+                limit: 4,
+                ..
+            }
+        ));
+        assert!(Lexer::with_config("int;", &config).all(|t| t.is_ok()));
+    }
+
+    #[test]
+    fn crlf_test() -> Result<(), LexError> {
+        let src = "a; // c\r\n#x\r\n\r\rb";
+        let config = LexerConfig::new().crlf_newlines(true);
+        let toks: Vec<Tok> = Lexer::with_config(src, &config).collect::<Result<_, _>>()?;
+        let kinds: Vec<TokKind> = toks.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, [Var, SCol, Spc, Cmt, Nl, Dir, Nl, CR, CR, Var]);
+        assert_eq!((toks[3].str, toks[4].str, toks[4].pos), ("// c", "\r\n", 7));
+        assert_eq!(detokenize(&toks), src);
+        let kinds: Vec<TokKind> = tokenize(src)?.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, [Var, SCol, Spc, Cmt, Nl, Dir, CR, Nl, CR, CR, Var]);
+        Ok(())
+    }
+
+    #[test]
+    fn tokenize_all_test() {
+        let src = "int @x = 0x;\nchar $$ c;";
+        let (toks, errs) = tokenize_all(src);
+        assert_eq!(
+            errs.iter().map(LexError::pos).collect::<Vec<_>>(),
+            vec![4, 9, 18]
+        );
+        let bad: Vec<&str> = toks
+            .iter()
+            .filter(|t| t.kind == Error)
+            .map(|t| t.str)
+            .collect();
+        assert_eq!(bad, vec!["@", "0x", "$$"]);
+        assert_eq!(toks.iter().map(|t| t.str).collect::<String>(), src);
+    }
+
+    #[test]
+    fn nested_comment_test() -> Result<(), LexError> {
+        let toks = tokenize("/* a /* b */ c */x")?;
+        assert_eq!(toks[0].kind, Cmt);
+        assert_eq!(toks[0].str, "/* a /* b */ c */");
+        assert_eq!(toks[1].str, "x");
+        let src = "int x;\n/* a /* b */ c\nint y;";
+        assert_eq!(tokenize(src), Err(LexError::UnterminatedCmt { pos: 7 }));
+        let (toks, _) = tokenize_all(src);
+        assert_eq!(toks.last().unwrap().kind, Error);
+        assert_eq!(toks.last().unwrap().pos, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn doc_comment_test() -> Result<(), LexError> {
+        let src = "/// doc\n//// not\n// plain\n/** doc */ /**/ /*** not */ /* plain */\n///";
+        let kinds: Vec<TokKind> = tokenize(src)?
+            .iter()
+            .map(|t| t.kind)
+            .filter(|k| !matches!(k, Spc | Nl))
+            .collect();
+        assert_eq!(kinds, vec![DocCmt, Cmt, Cmt, DocCmt, Cmt, Cmt, Cmt, DocCmt]);
+        Ok(())
+    }
+
+    #[test]
+    fn continuation_test() {
+        let src = "a \\\n+ b;\n#define X 1 + \\\r\n  2\n  \\\n#if\nc \\\n#d";
+        let toks = tokenize_all(src).0;
+        let kinds: Vec<_> = toks.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                Var, Spc, Cont, Add, Spc, Var, SCol, Nl, Dir, Nl, Spc, Cont, Dir, Nl, Var, Spc,
+                Cont, Error, Var
+            ]
+        );
+        assert_eq!(toks[8].directive_args(), Some("X 1 + \\\r\n  2"));
+        // positions stay physical, so diagnostics point at the right line
+        let idx = LineIndex::new(src);
+        assert_eq!(toks[3].line_col(&idx), LineCol { line: 2, col: 1 });
+        assert_eq!(toks[17].line_col(&idx), LineCol { line: 8, col: 1 });
+
+        let toks = tokenize("\"ab\\\ncd\" '\\\r\nx'").unwrap();
+        assert_eq!(toks[0].str_bytes(), Ok(b"abcd".to_vec()));
+        assert_eq!(toks[2].char_value(), Ok(b'x'));
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let alphabet = [
+            "a",
+            "Z9",
+            "_",
+            "$",
+            " ",
+            "\t",
+            "\n",
+            "\r",
+            "\\",
+            "\"",
+            "'",
+            "#",
+            "/",
+            "*",
+            "+",
+            "=",
+            "<",
+            ">",
+            ".",
+            "0",
+            "x",
+            "e",
+            "'",
+            "é",
+            "变",
+            "\u{1f600}",
+            "@",
+            "`",
+            "\u{0}",
+            "\u{2028}",
+        ];
+        let mut seed: u64 = 0x70c5;
+        let mut rand = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        for _ in 0..2000 {
+            let len = rand(24);
+            let src: String = (0..len).map(|_| alphabet[rand(alphabet.len())]).collect();
+            let toks = tokenize_all(&src).0;
+            assert_eq!(detokenize(&toks), src);
+            let mut end = 0;
+            for tok in &toks {
+                assert!(
+                    !tok.str.is_empty() && tok.pos == end,
+                    "{:?} in {:?}",
+                    tok,
+                    src
+                );
+                end += tok.str.len();
+            }
+        }
+    }
+
+    /// The oracle doesn't know about nested or unterminated comments, so those are left out.
+    #[test]
+    fn oracle_test() {
+        let src = concat!(
+            "#include <hex8.h>\n",
+            "/* block\n comment */ int main() {\r\n",
+            "\tchar c = '\\n', d = 'x', e = '\\'';\n",
+            "  char *s = \"a \\\"quoted\\\" string\\n\";\n",
+            "  x <<= 0x3F | 0b101 ^ 0o17 >> 1.5e3f % .25 + 2E-3 - 017;\n",
+            "  1_000 1'000'0 0xFF_FF 0b1'0 1__0 1_ 2'a' 3._5 1_2.3'4e5 0x'1 0x_1'_\n",
+            "  if (a->b.c[i] != ~d && !e || f ? g : h) x++; else y--;\n",
+            "  café += naïve_1 * 变量 / q; // trailing\n",
+            "/// doc\n//// not doc\n///\n/** doc */ /**/ /*** x */ /***/\n",
+            "  0x 0xG 1e 1.e5 intx int_ doubt do $ @ \" '' 'ab' 'open '\\'\n",
+            "#define M(a) \\\n  a + \\\r\n  1\n",
+            "  x = \\\n y; \"s\\\nt\" '\\\n' // c \\\n d\n",
+            "}\n",
+        );
+        for (ind, _) in src.char_indices() {
+            assert_eq!(
+                scan(src, ind, &DEFAULT_CONFIG),
+                oracle::longest_match(src, ind),
+                "at {}",
+                ind
+            );
+        }
+    }
+
+    #[test]
+    fn char_literal_test() {
+        let err = |src| tokenize_all(src).1;
+        assert_eq!(err("'a' '\\''"), []);
+        assert_eq!(
+            err("x = '';"),
+            [LexError::InvalidLit {
+                pos: 4,
+                found: "''".to_string(),
+                msg: "empty char literal",
+            }]
+        );
+        assert_eq!(
+            err("'ab' 'é'")
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "invalid literal 'ab' at byte 0: char literal contains more than one byte",
+                "invalid literal 'é' at byte 5: char literal contains more than one byte",
+            ]
+        );
+        // an unterminated literal runs to the end of its line, leaving the next one intact
+        let (toks, errs) = tokenize_all("c = 'x;\nint '\\'\n'\\");
+        assert_eq!(
+            errs,
+            [
+                LexError::UnterminatedChr { pos: 4 },
+                LexError::UnterminatedChr { pos: 12 },
+                LexError::UnterminatedChr { pos: 16 },
+            ]
+        );
+        assert_eq!(toks[4].text(), "'x;");
+        assert_eq!(toks[5].kind, Nl);
+        assert_eq!(toks[6].text(), "int");
+        assert_eq!(toks[8].text(), "'\\'");
+        assert_eq!(toks[10].text(), "'\\");
+    }
+
+    #[test]
+    fn lex_error_test() {
+        let src = "int a;\nchar @@b = 1;\n";
+        let err = tokenize(src).unwrap_err();
+        assert_eq!(
+            err,
+            LexError::Unrecognized {
+                pos: 12,
+                found: "@@".to_string(),
+                snippet: "char @@b = 1;".to_string(),
+            }
+        );
+    }
 }
@@ -0,0 +1,120 @@
+//! A compact, struct-of-arrays token store for holding many tokens at once.
+
+use super::{LexError, Lexer, LexerConfig, Tok, TokKind};
+use crate::prelude::*;
+use crate::source::Span;
+
+/// Tokens stored as parallel arrays of kinds and `u32` offsets: 9 bytes per token, against 32
+/// for a `Tok` on 64-bit targets and more for an `OwnedTok` with its heap text. The text is
+/// not stored but sliced from the source on access, so the same source must be passed to
+/// every accessor.
+///
+/// Offsets must fit in a `u32`, so pushing a token past 4 GiB into a source panics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenBuffer {
+    kinds: Vec<TokKind>,
+    starts: Vec<u32>,
+    lens: Vec<u32>,
+}
+
+impl TokenBuffer {
+    pub fn new() -> TokenBuffer {
+        TokenBuffer::default()
+    }
+
+    /// Lexes `src` like `tokenize_all`, straight into a buffer.
+    pub fn lex(src: &str, config: &LexerConfig) -> (TokenBuffer, Vec<LexError>) {
+        let mut buf = TokenBuffer::new();
+        let mut errs = Vec::new();
+        let mut lexer = Lexer::with_config(src, config);
+        while let Some((tok, err)) = lexer.next_recovering() {
+            buf.push(&tok);
+            errs.extend(err);
+        }
+        (buf, errs)
+    }
+
+    pub fn push(&mut self, tok: &Tok) {
+        let offset = |n: usize| u32::try_from(n).expect("token offset does not fit in a u32");
+        self.kinds.push(tok.kind);
+        self.starts.push(offset(tok.pos));
+        self.lens.push(offset(tok.str.len()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn kinds(&self) -> &[TokKind] {
+        &self.kinds
+    }
+
+    pub fn kind(&self, i: usize) -> TokKind {
+        self.kinds[i]
+    }
+
+    pub fn span(&self, i: usize) -> Span {
+        let start = self.starts[i] as usize;
+        Span::new(start, start + self.lens[i] as usize)
+    }
+
+    /// Token `i`, with its text borrowed from `src`.
+    pub fn get<'src>(&self, i: usize, src: &'src str) -> Tok<'src> {
+        let span = self.span(i);
+        Tok {
+            kind: self.kinds[i],
+            pos: span.start,
+            str: span.text(src),
+        }
+    }
+
+    pub fn iter<'a, 'src>(&'a self, src: &'src str) -> impl Iterator<Item = Tok<'src>> + 'a
+    where
+        'src: 'a,
+    {
+        (0..self.len()).map(move |i| self.get(i, src))
+    }
+
+    /// Indices of the tokens that aren't trivia.
+    pub fn significant(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter(|&i| !self.kinds[i].is_trivia())
+    }
+}
+
+impl<'a, 'src> FromIterator<&'a Tok<'src>> for TokenBuffer
+where
+    'src: 'a,
+{
+    fn from_iter<I: IntoIterator<Item = &'a Tok<'src>>>(toks: I) -> TokenBuffer {
+        let mut buf = TokenBuffer::new();
+        for tok in toks {
+            buf.push(tok);
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize_all, TokKind::*};
+
+    #[test]
+    fn buffer_test() {
+        let src = "int x = 0x1F; @ // done\n";
+        let (toks, errs) = tokenize_all(src);
+        let (buf, buf_errs) = TokenBuffer::lex(src, &LexerConfig::default());
+        assert_eq!(buf_errs, errs);
+        assert_eq!(buf.len(), toks.len());
+        assert_eq!(buf.iter(src).collect::<Vec<_>>(), toks);
+        assert_eq!(toks.iter().collect::<TokenBuffer>(), buf);
+        assert_eq!(buf.get(6, src).text(), "0x1F");
+        assert_eq!(buf.span(6), Span::new(8, 12));
+        let significant: Vec<TokKind> = buf.significant().map(|i| buf.kind(i)).collect();
+        assert_eq!(significant, [Key, Var, As, HexLit, SCol, Error]);
+    }
+}
@@ -0,0 +1,135 @@
+use super::KEYWORDS;
+use crate::prelude::*;
+
+/// Dialect options for the lexer, built up from the standard language with `new`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexerConfig {
+    /// `None` means the standard `KEYWORDS`.
+    keywords: Option<Vec<String>>,
+    dollar_in_idents: bool,
+    line_comments: bool,
+    case_sensitive: bool,
+    crlf_newlines: bool,
+    max_source_len: Option<usize>,
+    max_tokens: Option<usize>,
+    max_token_len: Option<usize>,
+}
+
+/// The configuration used by `Lexer::new` and `tokenize`.
+pub(super) static DEFAULT_CONFIG: LexerConfig = LexerConfig::new();
+
+impl Default for LexerConfig {
+    fn default() -> LexerConfig {
+        LexerConfig::new()
+    }
+}
+
+impl LexerConfig {
+    pub const fn new() -> LexerConfig {
+        LexerConfig {
+            keywords: None,
+            dollar_in_idents: false,
+            line_comments: true,
+            case_sensitive: true,
+            crlf_newlines: false,
+            max_source_len: None,
+            max_tokens: None,
+            max_token_len: None,
+        }
+    }
+
+    /// Replaces the reserved words. Anything else that looks like an identifier lexes as a
+    /// `Var`.
+    pub fn keywords<I, S>(mut self, keywords: I) -> LexerConfig
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.keywords = Some(keywords.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Whether `$` may appear anywhere in an identifier. Off by default.
+    pub fn dollar_in_idents(mut self, allow: bool) -> LexerConfig {
+        self.dollar_in_idents = allow;
+        self
+    }
+
+    /// Whether `//` starts a comment running to the end of the line. On by default.
+    pub fn line_comments(mut self, enable: bool) -> LexerConfig {
+        self.line_comments = enable;
+        self
+    }
+
+    /// Whether keywords must match case exactly. On by default; when off, `INT` lexes as the
+    /// keyword `int`. Identifiers keep their original text either way.
+    pub fn case_sensitive(mut self, sensitive: bool) -> LexerConfig {
+        self.case_sensitive = sensitive;
+        self
+    }
+
+    /// Whether `\r\n` lexes as a single `Nl` token. Off by default, leaving a `CR` before each
+    /// `Nl`. Either way every byte stays in some token, so offsets are unaffected.
+    pub fn crlf_newlines(mut self, merge: bool) -> LexerConfig {
+        self.crlf_newlines = merge;
+        self
+    }
+
+    /// Rejects sources longer than `bytes` up front. Unlimited by default.
+    pub fn max_source_len(mut self, bytes: usize) -> LexerConfig {
+        self.max_source_len = Some(bytes);
+        self
+    }
+
+    /// Stops lexing with an error once a source has more than `count` tokens, trivia
+    /// included. Unlimited by default.
+    pub fn max_tokens(mut self, count: usize) -> LexerConfig {
+        self.max_tokens = Some(count);
+        self
+    }
+
+    /// Rejects any single token, such as a comment or literal, longer than `bytes`. Unlimited
+    /// by default.
+    pub fn max_token_len(mut self, bytes: usize) -> LexerConfig {
+        self.max_token_len = Some(bytes);
+        self
+    }
+
+    pub fn is_keyword(&self, str: &str) -> bool {
+        let matches = |kw: &str| {
+            if self.case_sensitive {
+                kw == str
+            } else {
+                kw.eq_ignore_ascii_case(str)
+            }
+        };
+        match &self.keywords {
+            Some(keywords) => keywords.iter().any(|kw| matches(kw)),
+            None => KEYWORDS.iter().any(|kw| matches(kw)),
+        }
+    }
+
+    pub(super) fn allows_dollar(&self) -> bool {
+        self.dollar_in_idents
+    }
+
+    pub(super) fn allows_line_comments(&self) -> bool {
+        self.line_comments
+    }
+
+    pub(super) fn merges_crlf(&self) -> bool {
+        self.crlf_newlines
+    }
+
+    pub(super) fn source_len_limit(&self) -> Option<usize> {
+        self.max_source_len
+    }
+
+    pub(super) fn token_limit(&self) -> Option<usize> {
+        self.max_tokens
+    }
+
+    pub(super) fn token_len_limit(&self) -> Option<usize> {
+        self.max_token_len
+    }
+}
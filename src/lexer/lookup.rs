@@ -0,0 +1,111 @@
+//! Finding tokens by source position, with binary searches over tokens sorted by position.
+
+use core::ops::Range;
+
+use super::{Tok, TokenBuffer};
+use crate::source::Span;
+
+/// The first index in `0..len` for which `pred` is false, `pred` being true for a prefix.
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut lo, mut hi) = (0, len);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if pred(mid) {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+fn at_offset(len: usize, span: impl Fn(usize) -> Span, pos: usize) -> Option<usize> {
+    let i = partition_point(len, |i| span(i).end <= pos);
+    (i < len && span(i).contains(pos)).then_some(i)
+}
+
+fn before_offset(len: usize, span: impl Fn(usize) -> Span, pos: usize) -> Option<usize> {
+    partition_point(len, |i| span(i).end <= pos).checked_sub(1)
+}
+
+fn in_range(len: usize, span: impl Fn(usize) -> Span, range: Span) -> Range<usize> {
+    // an empty range still selects the token it falls inside
+    let end = range.end.max(range.start + 1);
+    let lo = partition_point(len, |i| span(i).end <= range.start);
+    let hi = partition_point(len, |i| span(i).start < end);
+    lo..hi.max(lo)
+}
+
+/// The index of the token covering byte `pos`, if any. `toks` must be in source order, as
+/// lexed.
+pub fn token_at_offset(toks: &[Tok], pos: usize) -> Option<usize> {
+    at_offset(toks.len(), |i| toks[i].span(), pos)
+}
+
+/// The index of the last token ending at or before `pos`: the token just left of a cursor at
+/// `pos`, which is what completion wants when the cursor is at the end of a word.
+pub fn token_before_offset(toks: &[Tok], pos: usize) -> Option<usize> {
+    before_offset(toks.len(), |i| toks[i].span(), pos)
+}
+
+/// The indices of the tokens overlapping `span`. An empty span selects the token it lies
+/// inside, if any.
+pub fn tokens_in_range(toks: &[Tok], span: Span) -> Range<usize> {
+    in_range(toks.len(), |i| toks[i].span(), span)
+}
+
+impl TokenBuffer {
+    /// Like `token_at_offset`.
+    pub fn token_at_offset(&self, pos: usize) -> Option<usize> {
+        at_offset(self.len(), |i| self.span(i), pos)
+    }
+
+    /// Like `token_before_offset`.
+    pub fn token_before_offset(&self, pos: usize) -> Option<usize> {
+        before_offset(self.len(), |i| self.span(i), pos)
+    }
+
+    /// Like `tokens_in_range`.
+    pub fn tokens_in_range(&self, span: Span) -> Range<usize> {
+        in_range(self.len(), |i| self.span(i), span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize, tokenize_all, LexerConfig};
+
+    #[test]
+    fn lookup_test() {
+        let src = "int foo = bar;";
+        let toks = tokenize(src).unwrap();
+        assert_eq!(token_at_offset(&toks, 0), Some(0));
+        assert_eq!(
+            token_at_offset(&toks, 5).map(|i| toks[i].text()),
+            Some("foo")
+        );
+        assert_eq!(token_at_offset(&toks, 7).map(|i| toks[i].text()), Some(" "));
+        assert_eq!(token_at_offset(&toks, 14), None);
+        assert_eq!(
+            token_before_offset(&toks, 7).map(|i| toks[i].text()),
+            Some("foo")
+        );
+        assert_eq!(token_before_offset(&toks, 2), None);
+        assert_eq!(token_before_offset(&toks, 99), Some(toks.len() - 1));
+
+        assert_eq!(tokens_in_range(&toks, Span::new(5, 11)), 2..7);
+        assert_eq!(tokens_in_range(&toks, Span::new(8, 8)), 4..5);
+        assert_eq!(tokens_in_range(&toks, Span::new(20, 30)), 8..8);
+        assert_eq!(tokens_in_range(&[], Span::new(0, 3)), 0..0);
+
+        // errors leave gaps in the output of `tokenize`, but not of `tokenize_all`
+        let (all, _) = tokenize_all("a @ b");
+        let buf = TokenBuffer::lex("a @ b", &LexerConfig::default()).0;
+        for pos in 0..6 {
+            assert_eq!(buf.token_at_offset(pos), token_at_offset(&all, pos));
+            assert_eq!(buf.token_before_offset(pos), token_before_offset(&all, pos));
+        }
+        assert_eq!(buf.tokens_in_range(Span::new(1, 4)), 1..4);
+    }
+}
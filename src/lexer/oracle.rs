@@ -0,0 +1,106 @@
+//! The original regex-driven lexer, kept as a reference to check the hand-written scanner
+//! against.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use super::TokKind::{self, *};
+use super::KEYWORDS;
+
+lazy_static! {
+    static ref REGEXES: Vec<(Regex, TokKind)> = [
+            (r"=", As),
+            (r"\(", LPar),
+            (r"\)", RPar),
+            (r"\{", LBrc),
+            (r"\}", RBrc),
+            (r"\[", LBrk),
+            (r"\]", RBrk),
+            (r";", SCol),
+            (r",", Comma),
+            (r"\.", Dot),
+            (r"->", Arrow),
+            (r":", Colon),
+            (r"\?", Quest),
+            (r"\+", Add),
+            (r"-", Sub),
+            (r"\+\+", Inc),
+            (r"--", Dec),
+            (r"\*", Mul),
+            (r"/", Div),
+            (r"%", Mod),
+            (r"==", Eq),
+            (r"!=", Neq),
+            (r"<", Lt),
+            (r">", Gt),
+            (r"<=", Le),
+            (r">=", Ge),
+            (r"&&", LAnd),
+            (r"\|\|", LOr),
+            (r"!", Not),
+            (r"&", BAnd),
+            (r"\|", BOr),
+            (r"\^", Xor),
+            (r"~", BNot),
+            (r"<<", Shl),
+            (r">>", Shr),
+            (r"\+=", AddAs),
+            (r"-=", SubAs),
+            (r"\*=", MulAs),
+            (r"/=", DivAs),
+            (r"%=", ModAs),
+            (r"&=", AndAs),
+            (r"\|=", OrAs),
+            (r"\^=", XorAs),
+            (r"<<=", ShlAs),
+            (r">>=", ShrAs),
+            (&format!(r"(?:{})\b", KEYWORDS.join("|")), Key),
+            (r"[\p{XID_Start}_]\p{XID_Continue}*", Var), // NOTE: Var MUST come after Key, otherwise keywords would be matched as variables
+            (r#""(\\(?s:.)|[^\\"])*""#, StrLit),
+            (r"'(\\\r?\n|\\.|[^\\'\n])*(\\\z|'?)", ChrLit), // unterminated up to the end of the line
+            (r"[0-9]+([_'][0-9]+)*", IntLit),
+            (r"0[xX]([0-9A-Za-z_]+('[0-9A-Za-z_]+)*)?", HexLit),
+            (r"0[oO]([0-9A-Za-z_]+('[0-9A-Za-z_]+)*)?", OctLit),
+            (r"0[bB]([0-9A-Za-z_]+('[0-9A-Za-z_]+)*)?", BinLit),
+            (concat!(
+                r"([0-9]+([_'][0-9]+)*\.([0-9]+([_'][0-9]+)*)?|\.[0-9]+([_'][0-9]+)*)([eE][+-]?[0-9]+)?[fF]?", // with a decimal point
+                "|",
+                r"[0-9]+([_'][0-9]+)*[eE][+-]?[0-9]+[fF]?" // exponent only
+            ), FltLit),
+            (concat!(
+                r"///([^/\n].*)?", // single line doc comment
+                "|",
+                r"/\*\*[^*/](.|[\r\n])*?\*/" // multiline doc comment
+            ), DocCmt),
+            (concat!(
+                r"//(\\\r?\n|.)*", // single line comment
+                "|",
+                r"\/\*(.|[\r\n])*?\*\/" // multiline comment
+            ), Cmt),
+            (r"#(\\\r?\n|[^\r\n])*", Dir),
+            (r" +", Spc),
+            (r"\n", Nl),
+            (r"\r", CR),
+            (r"\t", Tab),
+            (r"\\\r?\n", Cont),
+        ].iter().map(|(s, t)| (Regex::new(&format!(r"\A(?:{})", s)).unwrap(), *t)).collect();
+}
+
+/// The kind and length of the longest token starting at `ind`, earlier table entries winning
+/// ties.
+///
+/// The patterns are anchored with `\A` and run on the remainder of the input, so a pattern
+/// that doesn't match here fails immediately rather than searching the rest of the input.
+pub(super) fn longest_match(str: &str, ind: usize) -> Option<(TokKind, usize)> {
+    let rest = &str[ind..];
+    let mut max: Option<(TokKind, usize)> = None;
+    for (rgx, tk_k) in REGEXES.iter() {
+        let Some(find) = rgx.find(rest) else {
+            continue;
+        };
+        if max.is_none() || find.len() > max.unwrap().1 {
+            max = Some((*tk_k, find.len()));
+        }
+    }
+    max
+}
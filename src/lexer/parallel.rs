@@ -0,0 +1,113 @@
+//! Lexing many independent inputs at once.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use super::{LexError, Lexer, LexerConfig, OwnedTok, Tok};
+use crate::source::{FileId, SourceManager};
+
+/// The tokens of one file, as `tokenize_all` produces them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexedFile {
+    pub file: FileId,
+    pub toks: Vec<OwnedTok>,
+    pub errs: Vec<LexError>,
+}
+
+/// Applies `f` to every item on a pool of scoped threads, returning the results in the order
+/// of `items`. Threads take the next unclaimed item as they finish, so a few large inputs
+/// don't hold up the rest.
+fn par_map<T: Sync, U: Send>(items: &[T], f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+    thread::scope(|s| {
+        for _ in 0..threads.min(items.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else { break };
+                let res = f(item);
+                results.lock().unwrap().push((i, res));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, res)| res).collect()
+}
+
+/// Lexes each of `srcs` like `tokenize_all`, in parallel. Result `i` is for `srcs[i]`.
+pub fn tokenize_all_parallel<'src>(
+    config: &'src LexerConfig,
+    srcs: &[&'src str],
+) -> Vec<(Vec<Tok<'src>>, Vec<LexError>)> {
+    par_map(srcs, |src| Lexer::with_config(src, config).collect_all())
+}
+
+/// Reads and lexes each of `paths` in parallel, registering the files with `sources`.
+/// Result `i` is for `paths[i]`.
+pub fn tokenize_files<P: AsRef<Path> + Sync>(
+    config: &LexerConfig,
+    sources: &mut SourceManager,
+    paths: &[P],
+) -> Vec<io::Result<LexedFile>> {
+    let lexed: Vec<io::Result<_>> = par_map(paths, |path| {
+        let src = std::fs::read_to_string(path)?;
+        let (toks, errs) = Lexer::with_config(&src, config).collect_all();
+        let toks: Vec<OwnedTok> = toks.iter().map(Tok::to_owned_tok).collect();
+        Ok((src, toks, errs))
+    });
+    paths
+        .iter()
+        .zip(lexed)
+        .map(|(path, res)| {
+            let (src, toks, errs) = res?;
+            let file = sources.add(path.as_ref(), src);
+            Ok(LexedFile { file, toks, errs })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize_all;
+    use std::path::PathBuf;
+
+    #[test]
+    fn parallel_test() -> io::Result<()> {
+        let srcs: Vec<String> = (0..50)
+            .map(|i| format!("int x{} = {};\n@ /* {} */", i, i * 7, "y".repeat(i)))
+            .collect();
+        let srcs: Vec<&str> = srcs.iter().map(String::as_str).collect();
+        let config = LexerConfig::default();
+        let lexed = tokenize_all_parallel(&config, &srcs);
+        assert_eq!(lexed.len(), srcs.len());
+        for (src, res) in srcs.iter().zip(&lexed) {
+            assert_eq!(*res, tokenize_all(src));
+        }
+
+        let dir = std::env::temp_dir().join(format!("hex8-parallel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let paths: Vec<PathBuf> = (0..3).map(|i| dir.join(format!("{}.c8", i))).collect();
+        for (path, src) in paths.iter().zip(&srcs) {
+            std::fs::write(path, src)?;
+        }
+        let mut with_missing = paths.clone();
+        with_missing.push(dir.join("missing.c8"));
+        let mut sources = SourceManager::new();
+        let files = tokenize_files(&config, &mut sources, &with_missing);
+        std::fs::remove_dir_all(&dir)?;
+        for (i, file) in files[..3].iter().enumerate() {
+            let file = file.as_ref().unwrap();
+            assert_eq!(sources.get(file.file).path(), paths[i]);
+            assert_eq!(file.toks[2].text(), format!("x{}", i));
+            assert_eq!(file.errs.len(), 1);
+        }
+        assert!(files[3].is_err());
+        Ok(())
+    }
+}
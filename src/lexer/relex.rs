@@ -0,0 +1,167 @@
+//! Relexing only the part of a token stream affected by an edit.
+
+use core::ops::Range;
+
+use super::{Lexer, LexerConfig, Tok, TokKind::*, DEFAULT_CONFIG};
+use crate::prelude::*;
+
+/// A replacement of the bytes `range` of a source string with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit<'a> {
+    pub range: Range<usize>,
+    pub replacement: &'a str,
+}
+
+impl<'a> Edit<'a> {
+    pub fn apply(&self, src: &str) -> String {
+        let mut out = String::with_capacity(src.len() + self.replacement.len());
+        out.push_str(&src[..self.range.start]);
+        out.push_str(self.replacement);
+        out.push_str(&src[self.range.end..]);
+        out
+    }
+
+    /// End of the replaced text in the edited source.
+    fn new_end(&self) -> usize {
+        self.range.start + self.replacement.len()
+    }
+}
+
+/// Given `old`, the tokens of a source as produced by `tokenize_all`, and `new_src`, that source
+/// after `edit`, returns the tokens `tokenize_all` would produce for `new_src` while only
+/// lexing the lines around the edit.
+pub fn relex<'src>(old: &[Tok<'_>], edit: &Edit, new_src: &'src str) -> Vec<Tok<'src>> {
+    relex_with_config(&DEFAULT_CONFIG, old, edit, new_src)
+}
+
+/// Like `relex`, for tokens lexed with `config`.
+pub fn relex_with_config<'src>(
+    config: &'src LexerConfig,
+    old: &[Tok<'_>],
+    edit: &Edit,
+    new_src: &'src str,
+) -> Vec<Tok<'src>> {
+    let delta = edit.replacement.len() as isize - edit.range.len() as isize;
+    // A token ending exactly where the edit starts may be extended by it, e.g. `a` + `b`.
+    let mut first = old.partition_point(|t| t.pos + t.str.len() < edit.range.start);
+    // Anything that failed to lex, such as an unterminated literal, may turn into a token
+    // reaching as far as the edit.
+    if let Some(opener) = old[..first].iter().position(|t| t.kind == Error) {
+        first = first.min(opener);
+    }
+    // Restart from the beginning of the line so the lexer knows where directives may start.
+    while first > 0 && old[first - 1].kind != Nl {
+        first -= 1;
+    }
+
+    let mut out: Vec<Tok<'src>> = old[..first]
+        .iter()
+        .map(|t| Tok {
+            kind: t.kind,
+            pos: t.pos,
+            str: &new_src[t.pos..t.pos + t.str.len()],
+        })
+        .collect();
+    let start = old
+        .get(first)
+        .map_or(edit.range.start.min(new_src.len()), |t| t.pos);
+    let mut lexer = Lexer::resume(new_src, config, start, true);
+    lexer.count = first;
+    let mut j = first;
+    while let Some((tok, _)) = lexer.next_recovering() {
+        let after_nl = out.last().is_none_or(|t| t.kind == Nl);
+        out.push(tok);
+        if tok.pos < edit.new_end() || !after_nl {
+            continue;
+        }
+        // Once a line past the edit starts with the same token as before, the rest of the
+        // stream is unchanged apart from being shifted.
+        let old_pos = (tok.pos as isize - delta) as usize;
+        while j < old.len() && old[j].pos < old_pos {
+            j += 1;
+        }
+        let synced = old.get(j).is_some_and(|t| {
+            t.pos == old_pos
+                && t.kind == tok.kind
+                && t.str.len() == tok.str.len()
+                && (j == 0 || old[j - 1].kind == Nl)
+        });
+        if synced {
+            out.extend(old[j + 1..].iter().map(|t| {
+                let pos = (t.pos as isize + delta) as usize;
+                Tok {
+                    kind: t.kind,
+                    pos,
+                    str: &new_src[pos..pos + t.str.len()],
+                }
+            }));
+            break;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize_all;
+
+    #[test]
+    fn relex_test() {
+        let src = "int a = 1;\n/* c */ char b;\n#define X 2\nb = a + X;\n";
+        let edits = [
+            (4..5, "abc"),
+            (8..9, "0x"),
+            (11..13, "/"),
+            (0..0, "#"),
+            (25..25, "\n  #"),
+            (src.len()..src.len(), "/* open"),
+            (9..9, " \"unterminated"),
+            (0..src.len(), ""),
+        ];
+        for (range, replacement) in edits {
+            let old = tokenize_all(src).0;
+            let edit = Edit { range, replacement };
+            let new_src = edit.apply(src);
+            assert_eq!(
+                relex(&old, &edit, &new_src),
+                tokenize_all(&new_src).0,
+                "{:?}",
+                edit
+            );
+        }
+    }
+
+    #[test]
+    fn relex_random_test() {
+        let src = "int f() {\n  char c = 'x'; /* a */\n  return c + 0x1F;\n}\n// end\n";
+        let pieces = [
+            "", "a", " ", "\n", "/*", "*/", "\"", "'", "#", "+", "0x", "1.5",
+        ];
+        let mut seed: u64 = 0x5eed;
+        let mut rand = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        let mut cur = src.to_string();
+        for _ in 0..500 {
+            let old = tokenize_all(&cur).0;
+            let start = rand(cur.len() + 1);
+            let end = (start + rand(4)).min(cur.len());
+            let edit = Edit {
+                range: start..end,
+                replacement: pieces[rand(pieces.len())],
+            };
+            let new_src = edit.apply(&cur);
+            assert_eq!(
+                relex(&old, &edit, &new_src),
+                tokenize_all(&new_src).0,
+                "{:?}",
+                edit
+            );
+            cur = new_src;
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Lexing from an `io::Read` without reading the whole input up front.
+
+use std::fmt;
+use std::io::{self, Read};
+
+use super::{LexError, Lexer, LexerConfig, OwnedTok, DEFAULT_CONFIG, SNIPPET_CONTEXT};
+
+/// How many bytes to ask the reader for at once.
+const CHUNK: usize = 64 * 1024;
+
+/// How far past the end of a token the scanner may look to decide where it ends, e.g. `1e+5`
+/// needs three bytes beyond `1` to tell it isn't an `IntLit`.
+const LOOKAHEAD: usize = 4;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    /// A lexical error, with positions counted from the start of the stream.
+    Lex(LexError),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Io(e) => write!(f, "read error: {}", e),
+            StreamError::Lex(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> StreamError {
+        StreamError::Io(e)
+    }
+}
+
+/// Lexes the contents of a reader, yielding the same tokens as `Lexer` would for the whole
+/// input but only keeping a window of it in memory.
+///
+/// The window has to grow to hold a whole token, so a long comment or string is buffered in
+/// full, as is everything after an unterminated string, which may only end at EOF.
+pub struct ReaderLexer<'c, R> {
+    reader: R,
+    config: &'c LexerConfig,
+    /// The window of decoded input, starting at stream offset `base`.
+    text: String,
+    base: usize,
+    ind: usize,
+    at_line_start: bool,
+    count: usize,
+    /// Bytes of a UTF-8 sequence split by the end of the last read.
+    partial: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> ReaderLexer<'static, R> {
+    pub fn new(reader: R) -> ReaderLexer<'static, R> {
+        ReaderLexer::with_config(reader, &DEFAULT_CONFIG)
+    }
+}
+
+impl<'c, R: Read> ReaderLexer<'c, R> {
+    pub fn with_config(reader: R, config: &'c LexerConfig) -> ReaderLexer<'c, R> {
+        ReaderLexer {
+            reader,
+            config,
+            text: String::new(),
+            base: 0,
+            ind: 0,
+            at_line_start: true,
+            count: 0,
+            partial: Vec::new(),
+            eof: false,
+        }
+    }
+
+    /// Ends iteration, after an error past which nothing is lexed.
+    fn stop(&mut self) {
+        self.eof = true;
+        self.text.clear();
+        self.ind = 0;
+    }
+
+    /// Reads more input into the window, dropping the part already lexed.
+    fn fill(&mut self) -> Result<(), StreamError> {
+        // the current line is kept for error snippets, and the newline before it so that the
+        // window never looks like the start of the file
+        let keep = self.text[..self.ind].rfind('\n').unwrap_or(0);
+        if keep > self.text.len() / 2 {
+            self.text.drain(..keep);
+            self.base += keep;
+            self.ind -= keep;
+        }
+        // growing geometrically keeps rescanning a long token linear overall
+        let want = CHUNK.max(self.text.len() - self.ind);
+        let mut bytes = std::mem::take(&mut self.partial);
+        let old_len = bytes.len();
+        bytes.resize(old_len + want, 0);
+        let read = loop {
+            match self.reader.read(&mut bytes[old_len..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                res => break res?,
+            }
+        };
+        bytes.truncate(old_len + read);
+        if read == 0 {
+            self.eof = true;
+        }
+        if let Some(limit) = self.config.source_len_limit() {
+            if self.base + self.text.len() + bytes.len() > limit {
+                self.stop();
+                return Err(StreamError::Lex(LexError::LimitExceeded {
+                    pos: limit,
+                    msg: "source too large",
+                    limit,
+                }));
+            }
+        }
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() && !self.eof => e.valid_up_to(),
+            Err(e) => {
+                let pos = self.base + self.text.len() + e.valid_up_to();
+                return Err(StreamError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid UTF-8 at byte {}", pos),
+                )));
+            }
+        };
+        self.text
+            .push_str(std::str::from_utf8(&bytes[..valid]).unwrap());
+        self.partial = bytes.split_off(valid);
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ReaderLexer<'_, R> {
+    type Item = Result<OwnedTok, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.ind == self.text.len() && self.eof {
+                return None;
+            }
+            if self.ind + LOOKAHEAD > self.text.len() && !self.eof {
+                if let Err(e) = self.fill() {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            let mut lexer = Lexer::resume(&self.text, self.config, self.ind, self.at_line_start);
+            lexer.count = self.count;
+            let res = lexer.lex_next()?.map(|tok| tok.to_owned_tok());
+            // a token or error that more input could still change has to wait for it
+            let reaches_end = lexer.ind + LOOKAHEAD > self.text.len();
+            let (unterminated, line_done) = match &res {
+                Err(LexError::Unrecognized { found, .. }) => (
+                    found.starts_with('"'),
+                    self.text.len() - lexer.ind > SNIPPET_CONTEXT
+                        || self.text[lexer.ind..].contains('\n'),
+                ),
+                _ => (false, true),
+            };
+            if !self.eof && (reaches_end || unterminated || !line_done) {
+                if let Err(e) = self.fill() {
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            self.ind = lexer.ind;
+            self.at_line_start = lexer.at_line_start;
+            // only the token count limit stops the lexer without counting a token
+            let fatal = lexer.count == self.count && res.is_err();
+            self.count = lexer.count;
+            if fatal {
+                self.stop();
+            }
+            return Some(match res {
+                Ok(mut tok) => {
+                    tok.pos += self.base;
+                    Ok(tok)
+                }
+                Err(e) => Err(StreamError::Lex(e.shifted(self.base))),
+            });
+        }
+    }
+}
+
+/// Lexes everything `reader` produces, stopping at the first error.
+pub fn tokenize_reader(reader: impl Read) -> Result<Vec<OwnedTok>, StreamError> {
+    ReaderLexer::new(reader).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize_all, TokKind};
+
+    /// Hands out its input a few bytes at a time, to split tokens across reads.
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(self.0.len()).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reader_test() {
+        let src = "#define X \\\n 1\nint café = 1e+5 + 0x1F; /* a\n long comment */\n\
+                   char *s = \"multi\nline\" @ 'x';\n\"unterminated\n";
+        let (toks, errs) = tokenize_all(src);
+        for step in [1, 2, 3, 7, 64] {
+            let got: Vec<_> = ReaderLexer::new(Trickle(src.as_bytes(), step)).collect();
+            let got_toks: Vec<_> = got.iter().filter_map(|r| r.as_ref().ok()).collect();
+            let got_errs: Vec<_> = got
+                .iter()
+                .filter_map(|r| match r {
+                    Err(StreamError::Lex(e)) => Some(e.clone()),
+                    _ => None,
+                })
+                .collect();
+            let want: Vec<_> = toks.iter().filter(|t| t.kind != TokKind::Error).collect();
+            assert_eq!(got_toks.len(), want.len(), "step {}", step);
+            for (got, want) in got_toks.iter().zip(want) {
+                assert_eq!(got.as_tok(), *want, "step {}", step);
+            }
+            assert_eq!(got_errs, errs, "step {}", step);
+        }
+    }
+
+    #[test]
+    fn limits_test() {
+        let config = LexerConfig::new().max_source_len(10).max_tokens(4);
+        let res: Vec<_> = ReaderLexer::with_config(Trickle(b"a b c", 1), &config).collect();
+        assert_eq!(res.len(), 5);
+        let res: Vec<_> = ReaderLexer::with_config(Trickle(b"a b c d e f", 1), &config).collect();
+        assert!(matches!(
+            res.last(),
+            Some(Err(StreamError::Lex(LexError::LimitExceeded {
+                pos: 10,
+                ..
+            })))
+        ));
+        let config = LexerConfig::new().max_tokens(4);
+        let src = "a b c d ".repeat(CHUNK);
+        let res: Vec<_> = ReaderLexer::with_config(src.as_bytes(), &config).collect();
+        assert_eq!(res.len(), 5);
+        assert!(matches!(
+            res[4],
+            Err(StreamError::Lex(LexError::LimitExceeded { pos: 4, .. }))
+        ));
+    }
+
+    #[test]
+    fn invalid_utf8_test() {
+        let err = tokenize_reader(&b"int \xff;"[..]).unwrap_err();
+        assert!(matches!(err, StreamError::Io(e) if e.kind() == io::ErrorKind::InvalidData));
+    }
+}
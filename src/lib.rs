@@ -1,4 +1,66 @@
-mod lexer;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod arena;
+#[cfg(feature = "std")]
+pub mod ast;
+#[cfg(feature = "std")]
+pub mod cfg;
+#[cfg(feature = "std")]
+pub mod comments;
+#[cfg(feature = "std")]
+pub mod consteval;
+#[cfg(feature = "std")]
+pub mod cst;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod dot;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "std")]
+pub mod highlight;
+#[cfg(feature = "std")]
+pub mod intern;
+pub mod json;
+pub mod lexer;
+#[cfg(feature = "std")]
+pub mod lint;
+pub mod literal;
+#[cfg(feature = "std")]
+pub mod lower;
+#[cfg(feature = "std")]
+pub mod parser;
+#[cfg(feature = "std")]
+pub mod preprocess;
+#[cfg(feature = "std")]
+pub mod pretty;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod resolve;
+pub mod source;
+pub mod trivia;
+#[cfg(feature = "std")]
+pub mod typeck;
+#[cfg(feature = "std")]
+pub mod visit;
+
+/// The `alloc` items of the standard prelude, for the modules that also build without `std`.
+#[allow(unused_imports)]
+mod prelude {
+    pub(crate) use alloc::borrow::ToOwned;
+    pub(crate) use alloc::boxed::Box;
+    pub(crate) use alloc::string::{String, ToString};
+    pub(crate) use alloc::vec::Vec;
+    pub(crate) use alloc::{format, vec};
+}
+
+use prelude::*;
 
 pub struct Compiler {
     src: String,
@@ -6,6 +68,12 @@ pub struct Compiler {
 
 impl Compiler {
     pub fn compile(src: &str) -> Compiler {
-        todo!()
+        Compiler {
+            src: src.to_string(),
+        }
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
     }
 }
@@ -0,0 +1,965 @@
+//! Warnings about code that compiles but is probably a mistake.
+//!
+//! Narrowing conversions are the first of them. Hex8 computes with the width of its operands
+//! rather than promoting them to `int` as C does: `char + char` is a `char`, and an operation on
+//! two integers of different widths is done at the wider one, as `Type::common` says, unsigned
+//! if either is. A value converts implicitly to any other integer type by keeping its low bytes,
+//! so a conversion to a narrower type loses the upper ones. Converting between signed and
+//! unsigned types of the same width keeps every bit and isn't reported, and neither is a
+//! conversion to `bool`, which tests the value instead.
+//!
+//! Unused variables and functions are reported too, since every byte they take is one of the
+//! 256 the program has, and so are reads of locals that may not have been assigned yet, which
+//! on Hex8 read whatever the memory last held. Code that can't be reached only takes up room,
+//! so that is reported as well, and so are declarations hiding a variable of an enclosing
+//! scope, which are easily mistaken for it.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::arena::{Arena, Node, SideTable};
+use crate::ast::*;
+use crate::cfg::Cfg;
+use crate::consteval;
+use crate::lexer::{tokenize, LexError, TokKind};
+use crate::resolve::{SymbolId, SymbolKind, Symbols};
+use crate::source::{LineIndex, Span};
+use crate::visit::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub span: Span,
+    pub msg: String,
+    /// Another place the warning concerns, such as a declaration.
+    pub note: Option<Note>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub span: Span,
+    pub msg: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.msg, self.span.start)?;
+        if let Some(note) = &self.note {
+            write!(f, "\nnote: {} at byte {}", note.msg, note.span.start)?;
+        }
+        Ok(())
+    }
+}
+
+/// Warns about the values that an assignment, initializer, argument or `return` implicitly
+/// converts to a narrower integer type, given the `symbols` and expression `types` of
+/// `program`, which `arena` must be numbering.
+///
+/// The width of a value is that of its type, except where it can be seen to be less: a
+/// constant is as wide as the value needs, a comparison is a byte, `a & b` is as wide as the
+/// narrower operand, and other arithmetic is as wide as its widest operand, so neither
+/// `c = c + 1` nor `c = i & 0xff` is reported for a `char c`. A constant is reported only if
+/// its value doesn't fit, and a cast marks the conversion as intended.
+pub fn narrowing(
+    program: &Program,
+    arena: &Arena,
+    symbols: &Symbols,
+    types: &SideTable<Type>,
+) -> Vec<Warning> {
+    let mut narrowing = Narrowing {
+        arena,
+        symbols,
+        types,
+        structs: program.find_all::<StructDef>().cloned().collect(),
+        ret: None,
+        warnings: Vec::new(),
+    };
+    narrowing.visit_program(program);
+    narrowing.warnings
+}
+
+/// The number of bytes that hold `value`, as either a signed or an unsigned integer.
+fn bytes_for(value: i64) -> u64 {
+    [1, 2, 4].into_iter().find(|&n| fits(value, n)).unwrap_or(8)
+}
+
+fn fits(value: i64, bytes: u64) -> bool {
+    let bits = 8 * bytes as u32;
+    bits >= 64 || (-(1 << (bits - 1))..1 << bits).contains(&value)
+}
+
+/// `value` as the low `bytes` of it read back as a `target`.
+fn truncate(value: i64, bytes: u64, target: &Type) -> i64 {
+    let bits = 8 * bytes as u32;
+    let low = value & ((1 << bits) - 1);
+    match target.is_unsigned() || low < 1 << (bits - 1) {
+        true => low,
+        false => low - (1 << bits),
+    }
+}
+
+struct Narrowing<'a, 'ast> {
+    arena: &'a Arena<'ast>,
+    symbols: &'a Symbols,
+    types: &'a SideTable<Type>,
+    structs: Vec<StructDef>,
+    /// The return type of the function being checked.
+    ret: Option<Type>,
+    warnings: Vec<Warning>,
+}
+
+impl Narrowing<'_, '_> {
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        let ty = self.types.get(self.arena.expr_id(expr)?)?;
+        Some(ty.decayed())
+    }
+
+    fn constant(&self, expr: &Expr) -> Option<i64> {
+        if let ExprKind::Var(_) = expr.kind {
+            let symbol = self.symbols.uses.get(self.arena.expr_id(expr)?)?;
+            if let SymbolKind::Constant(value) = self.symbols.get(*symbol).kind {
+                return Some(value);
+            }
+        }
+        consteval::eval(expr)
+    }
+
+    /// The number of bytes `expr` can be seen to need, or 0 if it has no type.
+    fn width(&self, expr: &Expr) -> u64 {
+        if let Some(value) = self.constant(expr) {
+            return bytes_for(value);
+        }
+        match &expr.kind {
+            ExprKind::Cast(ty, _) => ty.size(&self.structs).unwrap_or(0),
+            ExprKind::Binary(op, _, _) if op.is_boolean() => 1,
+            ExprKind::Unary(UnOp::Not, _) => 1,
+            ExprKind::Binary(BinOp::BitAnd, a, b) => self.width(a).min(self.width(b)),
+            // shifting right by whole bytes drops them
+            ExprKind::Binary(BinOp::Shr, a, b) => {
+                let dropped = self.constant(b).map_or(0, |n| n.clamp(0, 64) as u64 / 8);
+                self.width(a).saturating_sub(dropped).max(1)
+            }
+            ExprKind::Binary(BinOp::Shl, ..) => self.type_width(expr),
+            ExprKind::Binary(_, a, b) | ExprKind::Cond(_, a, b) => self.width(a).max(self.width(b)),
+            ExprKind::Unary(UnOp::Neg | UnOp::Plus | UnOp::BitNot, a) => self.width(a),
+            ExprKind::Comma(_, b) => self.width(b),
+            _ => self.type_width(expr),
+        }
+    }
+
+    fn type_width(&self, expr: &Expr) -> u64 {
+        let ty = self.type_of(expr);
+        ty.and_then(|ty| ty.size(&self.structs)).unwrap_or(0)
+    }
+
+    /// Warns if `value` converts implicitly to the narrower integer type `target`.
+    fn check(&mut self, target: &Type, value: &Expr) {
+        let target = target.decayed();
+        if !target.is_integer() || target == Type::Bool {
+            return;
+        }
+        match self.type_of(value) {
+            Some(ty) if ty.is_integer() => {
+                let bytes = target.size(&self.structs).unwrap_or(1);
+                let msg = match self.constant(value) {
+                    Some(v) if !fits(v, bytes) => format!(
+                        "converting {} to `{}` truncates it to {}",
+                        v,
+                        target,
+                        truncate(v, bytes, &target)
+                    ),
+                    Some(_) => return,
+                    None if self.width(value) > bytes => format!(
+                        "converting `{}` to `{}` may truncate it; cast it if that is intended",
+                        ty, target
+                    ),
+                    None => return,
+                };
+                self.warnings.push(Warning {
+                    span: value.span,
+                    msg,
+                    note: None,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn init(&mut self, ty: &Type, init: &Init) {
+        match init {
+            Init::Expr(expr) => self.check(ty, expr),
+            Init::List { items, .. } => {
+                let types: Vec<Type> = match ty.unqualified() {
+                    Type::Array { elem, .. } => vec![(**elem).clone(); items.len()],
+                    Type::Struct(tag) | Type::Union(tag) => self
+                        .structs
+                        .iter()
+                        .find(|s| s.tag.name == *tag)
+                        .map(|def| def.fields.iter().map(|f| f.ty.clone()).collect())
+                        .unwrap_or_default(),
+                    ty => vec![ty.clone()],
+                };
+                for (item, ty) in items.iter().zip(&types) {
+                    self.init(ty, item);
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Narrowing<'_, 'ast> {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        if let (StmtKind::Return(Some(value)), Some(ret)) = (&stmt.kind, self.ret.clone()) {
+            self.check(&ret, value);
+        }
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_var_decl(&mut self, var: &'ast VarDecl) {
+        if let Some(init) = &var.init {
+            self.init(&var.ty, init);
+        }
+        walk_var_decl(self, var);
+    }
+
+    fn visit_function(&mut self, func: &'ast Function) {
+        let outer = self.ret.replace(func.ret.clone());
+        walk_function(self, func);
+        self.ret = outer;
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            // a shift's right operand is only a count
+            ExprKind::Assign(Some(BinOp::Shl | BinOp::Shr), ..) => {}
+            ExprKind::Assign(_, lhs, rhs) => {
+                if let Some(target) = self.type_of(lhs) {
+                    self.check(&target, rhs);
+                }
+            }
+            ExprKind::Call(callee, args) => {
+                if let Some(Type::Pointer(to)) = self.type_of(callee) {
+                    if let Type::Function { params, .. } = *to {
+                        for (param, arg) in params.iter().zip(args) {
+                            self.check(param, arg);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// Warns about the variables of `program` that are never read and the functions that are
+/// never called, at their first declaration and their definition, given the `symbols` that
+/// `arena` numbers. Assigning to a variable doesn't read it, and a function calling itself
+/// doesn't use it. `extern` variables and functions are defined elsewhere, so they aren't
+/// reported, and neither are parameters, which the callers must pass anyway, nor `main`.
+pub fn unused(program: &Program, arena: &Arena, symbols: &Symbols) -> Vec<Warning> {
+    let mut reads = Reads {
+        arena,
+        symbols,
+        read: vec![false; symbols.len()],
+        current: None,
+    };
+    reads.visit_program(program);
+
+    #[derive(Default)]
+    struct Declared {
+        first: Option<Span>,
+        definition: Option<Span>,
+        external: bool,
+    }
+    let mut declared: HashMap<SymbolId, Declared> = HashMap::new();
+    for (id, node) in arena.iter() {
+        let (Node::Decl(decl), Some(&symbol)) = (node, symbols.decls.get(id)) else {
+            continue;
+        };
+        let declared = declared.entry(symbol).or_default();
+        declared.first.get_or_insert(decl.span());
+        match decl {
+            Decl::Var(var) => declared.external |= var.storage == Some(Storage::Extern),
+            Decl::Func(func) => {
+                declared.external |= func.storage == Some(Storage::Extern);
+                if func.body.is_some() {
+                    declared.definition = Some(func.span);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut warnings = Vec::new();
+    for (id, symbol) in symbols.iter() {
+        let Some(declared) = declared.get(&id).filter(|d| !d.external) else {
+            continue;
+        };
+        let warning = match symbol.kind {
+            SymbolKind::Global | SymbolKind::Local => declared
+                .first
+                .map(|span| (span, format!("`{}` is never read", symbol.name))),
+            SymbolKind::Function if symbol.name != "main" => declared
+                .definition
+                .map(|span| (span, format!("`{}` is never called", symbol.name))),
+            _ => None,
+        };
+        if let Some((span, msg)) = warning.filter(|_| !reads.read[id.index()]) {
+            warnings.push(Warning {
+                span,
+                msg,
+                note: None,
+            });
+        }
+    }
+    warnings.sort_by_key(|w| w.span.start);
+    warnings
+}
+
+/// Warns about the reads of local variables along paths on which they may not have been
+/// assigned, with a note at the declaration. Only the scalar locals declared without an
+/// initializer are followed: `static` ones start out zero, and arrays and structs are assigned a
+/// part at a time. Taking a variable's address counts as assigning it, since it may be assigned
+/// through the pointer, and each variable is reported at its first such read only.
+///
+/// This is a definite assignment analysis of the tree: a variable is assigned after a branch
+/// if it is on every arm, after a loop if it is before the loop or at every `break` out of a
+/// loop that only ends by breaking, and after a label if it is at the label and at every
+/// `goto` that reaches it from before.
+pub fn uninitialized(program: &Program, arena: &Arena, symbols: &Symbols) -> Vec<Warning> {
+    let mut flow = Flow {
+        arena,
+        symbols,
+        tracked: HashSet::new(),
+        assigned: None,
+        breaks: Vec::new(),
+        continues: Vec::new(),
+        switches: Vec::new(),
+        labels: HashMap::new(),
+        warnings: Vec::new(),
+    };
+    for stmt in &program.stmts {
+        let StmtKind::Decl(decls) = &stmt.kind else {
+            continue;
+        };
+        for decl in decls {
+            if let Decl::Func(Function {
+                body: Some(body), ..
+            }) = decl
+            {
+                flow.assigned = Some(HashSet::new());
+                flow.labels.clear();
+                flow.stmt(body);
+            }
+        }
+    }
+    flow.warnings
+}
+
+/// The variables assigned on every path to a point, or `None` if no path reaches it.
+type Assigned = Option<HashSet<SymbolId>>;
+
+/// The variables assigned at a point reached from either `a` or `b`.
+fn meet(a: Assigned, b: Assigned) -> Assigned {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).copied().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Whether `cond` is a constant that is true, so that a loop on it only ends by breaking.
+fn always(cond: Option<&Expr>) -> bool {
+    cond.is_none_or(|cond| consteval::eval(cond).is_some_and(|v| v != 0))
+}
+
+struct Flow<'a, 'ast> {
+    arena: &'a Arena<'ast>,
+    symbols: &'a Symbols,
+    /// The locals whose reads are checked.
+    tracked: HashSet<SymbolId>,
+    assigned: Assigned,
+    /// The variables assigned at the `break`s and `continue`s of each enclosing loop or
+    /// `switch`, innermost last.
+    breaks: Vec<Assigned>,
+    continues: Vec<Assigned>,
+    /// The variables assigned on entering each enclosing `switch`, and whether it has a
+    /// `default`.
+    switches: Vec<(Assigned, bool)>,
+    /// The variables assigned at the `goto`s seen so far to each label.
+    labels: HashMap<String, Assigned>,
+    warnings: Vec<Warning>,
+}
+
+impl<'ast> Flow<'_, 'ast> {
+    fn assign(&mut self, symbol: SymbolId) {
+        if let Some(assigned) = &mut self.assigned {
+            assigned.insert(symbol);
+        }
+    }
+
+    fn symbol(&self, expr: &'ast Expr) -> Option<SymbolId> {
+        self.symbols.uses.get(self.arena.expr_id(expr)?).copied()
+    }
+
+    /// Walks a loop body, returning the variables assigned where it ends or continues.
+    fn body(&mut self, body: &'ast Stmt) -> (Assigned, Assigned) {
+        self.breaks.push(None);
+        self.continues.push(None);
+        self.stmt(body);
+        let end = meet(self.assigned.take(), self.continues.pop().unwrap());
+        (end, self.breaks.pop().unwrap())
+    }
+
+    fn stmt(&mut self, stmt: &'ast Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl(decls) => {
+                for decl in decls {
+                    let Decl::Var(var) = decl else {
+                        continue;
+                    };
+                    let id = self.arena.id(Node::Decl(decl));
+                    let Some(&symbol) = id.and_then(|id| self.symbols.decls.get(id)) else {
+                        continue;
+                    };
+                    match &var.init {
+                        Some(init) => {
+                            self.init(init);
+                            self.assign(symbol);
+                        }
+                        None if var.storage.is_none() && is_scalar(&var.ty) => {
+                            self.tracked.insert(symbol);
+                            // a new iteration's variable is a new one
+                            if let Some(assigned) = &mut self.assigned {
+                                assigned.remove(&symbol);
+                            }
+                        }
+                        None => self.assign(symbol),
+                    }
+                }
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::Block(stmts) => stmts.iter().for_each(|stmt| self.stmt(stmt)),
+            StmtKind::If { cond, then, els } => {
+                self.expr(cond);
+                let before = self.assigned.clone();
+                self.stmt(then);
+                let then = std::mem::replace(&mut self.assigned, before);
+                if let Some(els) = els {
+                    self.stmt(els);
+                }
+                self.assigned = meet(then, self.assigned.take());
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                let entry = self.assigned.clone();
+                let (_, breaks) = self.body(body);
+                let done = if always(Some(cond)) { None } else { entry };
+                self.assigned = meet(done, breaks);
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let (end, breaks) = self.body(body);
+                self.assigned = end;
+                self.expr(cond);
+                let done = if always(Some(cond)) {
+                    None
+                } else {
+                    self.assigned.take()
+                };
+                self.assigned = meet(done, breaks);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                }
+                let entry = self.assigned.clone();
+                let (end, breaks) = self.body(body);
+                self.assigned = end;
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                let done = if always(cond.as_ref()) { None } else { entry };
+                self.assigned = meet(done, breaks);
+            }
+            StmtKind::Switch { cond, body } => {
+                self.expr(cond);
+                self.switches.push((self.assigned.clone(), false));
+                self.breaks.push(None);
+                // the body is only entered at its labels
+                self.assigned = None;
+                self.stmt(body);
+                let breaks = self.breaks.pop().unwrap();
+                let (entry, default) = self.switches.pop().unwrap();
+                let end = meet(self.assigned.take(), breaks);
+                self.assigned = if default { end } else { meet(end, entry) };
+            }
+            StmtKind::Case { value, stmt } => {
+                self.expr(value);
+                if let Some((entry, _)) = self.switches.last() {
+                    self.assigned = meet(self.assigned.take(), entry.clone());
+                }
+                self.stmt(stmt);
+            }
+            StmtKind::Default(stmt) => {
+                if let Some((entry, default)) = self.switches.last_mut() {
+                    *default = true;
+                    self.assigned = meet(self.assigned.take(), entry.clone());
+                }
+                self.stmt(stmt);
+            }
+            StmtKind::Label { label: name, stmt } => {
+                if let Some(gotos) = self.labels.remove(&name.name) {
+                    self.assigned = meet(self.assigned.take(), gotos);
+                }
+                self.stmt(stmt);
+            }
+            StmtKind::Goto(name) => {
+                let gotos = self.labels.remove(&name.name).unwrap_or_default();
+                let gotos = meet(gotos, self.assigned.take());
+                self.labels.insert(name.name.clone(), gotos);
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.assigned = None;
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                let targets = match stmt.kind {
+                    StmtKind::Break => &mut self.breaks,
+                    _ => &mut self.continues,
+                };
+                if let Some(target) = targets.last_mut() {
+                    *target = meet(target.take(), self.assigned.take());
+                }
+                self.assigned = None;
+            }
+            StmtKind::Empty => {}
+        }
+    }
+
+    fn init(&mut self, init: &'ast Init) {
+        match init {
+            Init::Expr(expr) => self.expr(expr),
+            Init::List { items, .. } => items.iter().for_each(|item| self.init(item)),
+        }
+    }
+
+    fn expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Int(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+            ExprKind::Sizeof(_) | ExprKind::SizeofExpr(_) => {}
+            ExprKind::Var(name) => {
+                let Some(symbol) = self.symbol(expr) else {
+                    return;
+                };
+                let unassigned = matches!(&self.assigned, Some(a) if !a.contains(&symbol));
+                if self.tracked.contains(&symbol) && unassigned {
+                    self.warnings.push(Warning {
+                        span: expr.span,
+                        msg: format!("`{}` may be read before it is assigned", name),
+                        note: Some(Note {
+                            span: self.symbols.get(symbol).span,
+                            msg: format!("`{}` is declared here", name),
+                        }),
+                    });
+                    self.assign(symbol);
+                }
+            }
+            ExprKind::Assign(None, lhs, rhs) if matches!(lhs.kind, ExprKind::Var(_)) => {
+                self.expr(rhs);
+                if let Some(symbol) = self.symbol(lhs) {
+                    self.assign(symbol);
+                }
+            }
+            ExprKind::Unary(UnOp::Addr, operand) if matches!(operand.kind, ExprKind::Var(_)) => {
+                if let Some(symbol) = self.symbol(operand) {
+                    self.assign(symbol);
+                }
+            }
+            ExprKind::Binary(BinOp::And | BinOp::Or, lhs, rhs) => {
+                self.expr(lhs);
+                // the right operand may not be evaluated
+                let before = self.assigned.clone();
+                self.expr(rhs);
+                self.assigned = before;
+            }
+            ExprKind::Cond(cond, then, els) => {
+                self.expr(cond);
+                let before = self.assigned.clone();
+                self.expr(then);
+                let then = std::mem::replace(&mut self.assigned, before);
+                self.expr(els);
+                self.assigned = meet(then, self.assigned.take());
+            }
+            ExprKind::Unary(_, operand)
+            | ExprKind::Member(operand, _)
+            | ExprKind::Arrow(operand, _)
+            | ExprKind::Cast(_, operand) => self.expr(operand),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(_, lhs, rhs)
+            | ExprKind::Index(lhs, rhs)
+            | ExprKind::Comma(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Call(callee, args) => {
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+        }
+    }
+}
+
+/// Whether a variable of the type is assigned all at once: an integer or a pointer.
+fn is_scalar(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty.unqualified(), Type::Pointer(_))
+}
+
+/// Warns about the first statement of each run that `Cfg::reachable` finds can't be reached,
+/// either because it follows a statement that doesn't complete, such as a `return`, `break`,
+/// `goto`, or an `if` whose arms all return, or because it is the branch or loop body of a
+/// condition that is a constant. A note points at what makes it unreachable. A statement with a
+/// `case`, `default` or ordinary label starts a new run if something jumps to it, and `while (1)`
+/// and `do ... while (0)` are the usual idioms rather than mistakes.
+pub fn unreachable(program: &Program) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for func in program.functions() {
+        let (Some(body), Some(cfg)) = (&func.body, Cfg::build(func)) else {
+            continue;
+        };
+        let mut reach = Reach {
+            reachable: cfg.reachable(),
+            cfg,
+            warnings,
+        };
+        reach.stmt(body, None);
+        warnings = reach.warnings;
+    }
+    warnings
+}
+
+/// What makes a statement unreachable although the code before it isn't.
+type Cause = Option<(Span, &'static str)>;
+
+struct Reach<'ast> {
+    cfg: Cfg<'ast>,
+    reachable: Vec<bool>,
+    warnings: Vec<Warning>,
+}
+
+impl Reach<'_> {
+    fn reachable(&self, stmt: &Stmt) -> bool {
+        self.cfg
+            .block_of(stmt)
+            .is_none_or(|id| self.reachable[id.index()])
+    }
+
+    /// Walks `stmt`, warning at it if it is unreachable and there is a `cause`.
+    fn stmt(&mut self, stmt: &Stmt, cause: Cause) {
+        let reachable = self.reachable(stmt);
+        if let (false, Some((span, why))) = (reachable, cause) {
+            if stmt.kind != StmtKind::Empty {
+                self.warnings.push(Warning {
+                    span: stmt.span,
+                    msg: "this statement is unreachable".to_string(),
+                    note: Some(Note {
+                        span,
+                        msg: why.to_string(),
+                    }),
+                });
+            }
+        }
+        // the cause of a branch or body that isn't taken when the rest is
+        let constant = |cond: &Expr, taken: bool| match consteval::eval(cond) {
+            Some(value) if reachable && (value != 0) != taken => {
+                let why = match value {
+                    0 => "the condition is always false",
+                    _ => "the condition is always true",
+                };
+                Some((cond.span, why))
+            }
+            _ => None,
+        };
+        match &stmt.kind {
+            StmtKind::Block(stmts) => self.stmts(stmts, None),
+            StmtKind::If { cond, then, els } => {
+                self.stmt(then, constant(cond, true));
+                if let Some(els) = els {
+                    self.stmt(els, constant(cond, false));
+                }
+            }
+            StmtKind::While { cond, body }
+            | StmtKind::For {
+                cond: Some(cond),
+                body,
+                ..
+            } => self.stmt(body, constant(cond, true)),
+            StmtKind::For { body, .. } | StmtKind::DoWhile { body, .. } => self.stmt(body, None),
+            StmtKind::Switch { cond, body } => {
+                // code before the first label is jumped over, but the body itself isn't
+                let cause = reachable.then_some((cond.span, "the `switch` jumps past this"));
+                match &body.kind {
+                    StmtKind::Block(stmts) => self.stmts(stmts, cause),
+                    _ => self.stmt(body, cause),
+                }
+            }
+            StmtKind::Case { stmt: inner, .. }
+            | StmtKind::Default(inner)
+            | StmtKind::Label { stmt: inner, .. } => self.stmt(inner, None),
+            StmtKind::Decl(_)
+            | StmtKind::Expr(_)
+            | StmtKind::Empty
+            | StmtKind::Break
+            | StmtKind::Continue
+            | StmtKind::Goto(_)
+            | StmtKind::Return(_) => {}
+        }
+    }
+
+    /// Walks a run of statements, the first of which is unreachable because of `cause`.
+    fn stmts(&mut self, stmts: &[Stmt], mut cause: Cause) {
+        for stmt in stmts {
+            self.stmt(stmt, cause);
+            if stmt.kind == StmtKind::Empty {
+                continue;
+            }
+            // the statement that control doesn't get past, if the next one is unreachable
+            cause = self
+                .reachable(stmt)
+                .then_some((stmt.span, "control doesn't get past this"));
+        }
+    }
+}
+
+/// The pragma that silences `shadowing` for the declarations on the line after it.
+pub const ALLOW_SHADOW: &str = "#pragma hex8 allow(shadow)";
+
+/// Warns about the parameters and locals that hide a variable or parameter of an enclosing
+/// scope, with a note at the one hidden, given the `symbols` of the program parsed from `src`.
+/// A declaration on the line after an `ALLOW_SHADOW` pragma shadows on purpose and isn't
+/// reported.
+pub fn shadowing(src: &str, symbols: &Symbols) -> Result<Vec<Warning>, LexError> {
+    let lines = LineIndex::new(src);
+    // however the pragma is spaced
+    let words = |text: &str| text.split_whitespace().collect::<String>();
+    let allowed: HashSet<usize> = tokenize(src)?
+        .iter()
+        .filter(|tok| tok.kind == TokKind::Dir && words(tok.str) == words(ALLOW_SHADOW))
+        .map(|tok| lines.line_col(tok.pos).line + 1)
+        .collect();
+    let variable = |kind| {
+        let kind = match kind {
+            SymbolKind::Global => "global",
+            SymbolKind::Param => "parameter",
+            SymbolKind::Local => "local",
+            SymbolKind::Function | SymbolKind::Constant(_) => return None,
+        };
+        Some(kind)
+    };
+    let mut warnings = Vec::new();
+    for &(inner, outer) in &symbols.shadows {
+        let (inner, outer) = (symbols.get(inner), symbols.get(outer));
+        let Some(kind) = variable(outer.kind).filter(|_| variable(inner.kind).is_some()) else {
+            continue;
+        };
+        if allowed.contains(&lines.line_col(inner.span.start).line) {
+            continue;
+        }
+        warnings.push(Warning {
+            span: inner.span,
+            msg: format!("`{}` shadows a {} of the same name", inner.name, kind),
+            note: Some(Note {
+                span: outer.span,
+                msg: format!("the {} `{}` is declared here", kind, outer.name),
+            }),
+        });
+    }
+    Ok(warnings)
+}
+
+/// Finds the symbols each expression reads.
+struct Reads<'a, 'ast> {
+    arena: &'a Arena<'ast>,
+    symbols: &'a Symbols,
+    read: Vec<bool>,
+    /// The function whose body is being visited.
+    current: Option<SymbolId>,
+}
+
+impl<'ast> Visit<'ast> for Reads<'_, 'ast> {
+    fn visit_decl(&mut self, decl: &'ast Decl) {
+        let outer = self.current;
+        if let Decl::Func(_) = decl {
+            let id = self.arena.id(Node::Decl(decl));
+            self.current = id.and_then(|id| self.symbols.decls.get(id)).copied();
+        }
+        walk_decl(self, decl);
+        self.current = outer;
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Assign(None, lhs, rhs) if matches!(lhs.kind, ExprKind::Var(_)) => {
+                self.visit_expr(rhs);
+                return;
+            }
+            ExprKind::Var(_) => {
+                let id = self.arena.expr_id(expr);
+                if let Some(&symbol) = id.and_then(|id| self.symbols.uses.get(id)) {
+                    if Some(symbol) != self.current {
+                        self.read[symbol.index()] = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+    use crate::typeck::check_types;
+
+    #[test]
+    fn uninitialized_test() {
+        let src = "int f(int n) { int a, b, c, d, e; int *p; static int s; \
+                   if (n) a = 1; else a = 2; \
+                   if (n) b = 1; \
+                   while (1) { c = 1; break; } \
+                   for (int i = 0; i < n; i++) d = i; \
+                   p = &e; \
+                   return a + b + b + c + d + *p + s; }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let warnings: Vec<_> = uninitialized(&program, &arena, &symbols)
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "`b` may be read before it is assigned at byte 178\nnote: `b` is declared here at byte 22",
+                "`d` may be read before it is assigned at byte 190\nnote: `d` is declared here at byte 28",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_test() {
+        let src = "int f(int n) { if (n > 9) goto out; if (n) return 1; else { n++; return 2; } \
+                   n = 3; n = 4; out: if (0) n = 5; while (1) { if (n) break; } \
+                   switch (n) { n = 8; case 1: return 1; n = 6; default: goto out; } n = 7; } \
+                   int g() { return 0; skip: return 1; }";
+        let warnings: Vec<_> = unreachable(&parse(src).unwrap())
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        let past = "note: control doesn't get past this";
+        assert_eq!(
+            warnings,
+            [
+                format!("this statement is unreachable at byte 77\n{} at byte 36", past),
+                "this statement is unreachable at byte 103\nnote: the condition is always false at byte 100"
+                    .to_string(),
+                "this statement is unreachable at byte 151\nnote: the `switch` jumps past this at byte 146"
+                    .to_string(),
+                format!("this statement is unreachable at byte 176\n{} at byte 158", past),
+                format!("this statement is unreachable at byte 204\n{} at byte 138", past),
+                // nothing jumps to the label
+                format!("this statement is unreachable at byte 233\n{} at byte 223", past),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowing_test() -> Result<(), LexError> {
+        let src = "int n;\nenum { K };\nint f(int n) {\n    int K = n;\n    {\n        int n = K;\n    \
+                   }\n    {\n        #pragma hex8 allow(shadow)\n        int K = 1, n = 2;\n    }\n    \
+                   return n;\n}\n";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let warnings: Vec<_> = shadowing(src, &symbols)?
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "`n` shadows a global of the same name at byte 29\n\
+                 note: the global `n` is declared here at byte 4",
+                "`n` shadows a parameter of the same name at byte 67\n\
+                 note: the parameter `n` is declared here at byte 29",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unused_test() {
+        let src =
+            "extern int e; int g; int h; static int count(int n) { return n ? count(n - 1) : 0; } \
+                   int p(int a); int p(int a) { int x = 1, y; y = a; h = x; return a; } \
+                   int main() { return p(g); }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let warnings: Vec<_> = unused(&program, &arena, &symbols)
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "`h` is never read at byte 25",
+                "`count` is never called at byte 39",
+                "`y` is never read at byte 125",
+            ]
+        );
+    }
+
+    #[test]
+    fn narrowing_test() {
+        let src = "enum { K = 100 }; void put(char c); char f(int i, char c, unsigned char u) { \
+                   char a = i; c = c + 1; c = i & 0xff; c = i >> 8; c += i; u = 255; u = c; \
+                   c = (char)i; put(i); put(300); c = K; c = i == 2; return i; }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let (types, errs) = check_types(&program, &arena, &symbols);
+        assert_eq!(errs, []);
+        let warnings: Vec<_> = narrowing(&program, &arena, &symbols, &types)
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        let may = "may truncate it; cast it if that is intended";
+        assert_eq!(
+            warnings,
+            [
+                format!("converting `int` to `char` {} at byte 86", may),
+                format!("converting `int` to `char` {} at byte 131", may),
+                format!("converting `int` to `char` {} at byte 167", may),
+                "converting 300 to `char` truncates it to 44 at byte 175".to_string(),
+                format!("converting `int` to `char` {} at byte 207", may),
+            ]
+        );
+    }
+}
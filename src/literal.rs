@@ -0,0 +1,286 @@
+use crate::lexer::{quoted, LexError, Lexer, Tok, TokKind::*};
+use crate::prelude::*;
+
+/// Decodes the escape sequences in the body of a string or char literal, `pos` being the
+/// byte offset of the body in the source.
+fn decode_escapes(body: &str, pos: usize) -> Result<Vec<u8>, LexError> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let byte = match chars.next() {
+            // line continuations splice the literal without adding anything to it
+            Some((_, '\n')) => continue,
+            Some((_, '\r')) if body[i + 2..].starts_with('\n') => {
+                chars.next();
+                continue;
+            }
+            Some((j, 'x')) => {
+                let len = body[j + 1..]
+                    .find(|c: char| !c.is_ascii_hexdigit())
+                    .unwrap_or(body.len() - j - 1);
+                let digits = &body[j + 1..j + 1 + len];
+                let escape = &body[i..j + 1 + len];
+                if digits.is_empty() {
+                    return Err(LexError::InvalidEscape {
+                        pos: pos + i,
+                        found: escape.to_string(),
+                    });
+                }
+                for _ in 0..len {
+                    chars.next();
+                }
+                numeric_escape(digits, 16, escape, pos + i)?
+            }
+            Some((j, '0'..='7')) => {
+                // up to three octal digits, the first of which is already taken
+                let len = body[j..]
+                    .bytes()
+                    .take(3)
+                    .take_while(|b| (b'0'..=b'7').contains(b))
+                    .count();
+                for _ in 1..len {
+                    chars.next();
+                }
+                numeric_escape(&body[j..j + len], 8, &body[i..j + len], pos + i)?
+            }
+            Some((_, 'n')) => b'\n',
+            Some((_, 't')) => b'\t',
+            Some((_, 'r')) => b'\r',
+            Some((_, 'a')) => 0x07,
+            Some((_, 'b')) => 0x08,
+            Some((_, 'f')) => 0x0c,
+            Some((_, 'v')) => 0x0b,
+            Some((_, '\\')) => b'\\',
+            Some((_, '\'')) => b'\'',
+            Some((_, '"')) => b'"',
+            Some((_, '?')) => b'?',
+            Some((_, c)) => {
+                return Err(LexError::InvalidEscape {
+                    pos: pos + i,
+                    found: format!("\\{}", c),
+                })
+            }
+            None => {
+                return Err(LexError::InvalidEscape {
+                    pos: pos + i,
+                    found: "\\".to_string(),
+                })
+            }
+        };
+        out.push(byte);
+    }
+    Ok(out)
+}
+
+/// The byte value of the digits of a `\x` or octal escape, which must fit in a byte.
+fn numeric_escape(digits: &str, radix: u32, escape: &str, pos: usize) -> Result<u8, LexError> {
+    u32::from_str_radix(digits, radix)
+        .ok()
+        .and_then(|n| u8::try_from(n).ok())
+        .ok_or_else(|| LexError::InvalidLit {
+            pos,
+            found: escape.to_string(),
+            msg: "escape sequence out of range",
+        })
+}
+
+/// Parses an integer literal token of any radix.
+pub fn parse_int(tok: &Tok) -> Result<u64, LexError> {
+    let (digits, radix, msg) = match tok.kind {
+        IntLit => (tok.str, 10, ""),
+        HexLit => (&tok.str[2..], 16, "expected hexadecimal digits after `0x`"),
+        OctLit => (&tok.str[2..], 8, "expected octal digits after `0o`"),
+        BinLit => (&tok.str[2..], 2, "expected binary digits after `0b`"),
+        _ => {
+            return Err(LexError::InvalidLit {
+                pos: tok.pos,
+                found: tok.str.to_string(),
+                msg: "not an integer literal",
+            })
+        }
+    };
+    let err = |msg| LexError::MalformedLit {
+        pos: tok.pos,
+        found: tok.str.to_string(),
+        msg,
+    };
+    let is_sep = |c| c == '_' || c == '\'';
+    let cleaned: String = digits.chars().filter(|&c| !is_sep(c)).collect();
+    if cleaned.is_empty() || !cleaned.chars().all(|c| c.is_digit(radix)) {
+        return Err(err(msg));
+    }
+    // separators may only appear singly between two digits
+    let bytes = digits.as_bytes();
+    let misplaced = (0..bytes.len()).any(|i| {
+        let is_digit = |j: usize| bytes.get(j).is_some_and(|&b| (b as char).is_digit(radix));
+        is_sep(bytes[i] as char) && (i == 0 || !is_digit(i - 1) || !is_digit(i + 1))
+    });
+    if misplaced {
+        return Err(err("digit separators must be between two digits"));
+    }
+    u64::from_str_radix(&cleaned, radix).map_err(|_| err("integer literal is too large"))
+}
+
+/// Returns the text between the delimiters of `tok`, or an error if it isn't delimited by
+/// `quote`.
+fn body<'src>(tok: &Tok<'src>, quote: char) -> Result<&'src str, LexError> {
+    let str = tok.str;
+    if str.len() < 2 || !str.starts_with(quote) || !str.ends_with(quote) {
+        return Err(LexError::InvalidLit {
+            pos: tok.pos,
+            found: str.to_string(),
+            msg: "not a quoted literal",
+        });
+    }
+    Ok(&str[1..str.len() - 1])
+}
+
+/// Decodes a string literal token, possibly merged by `concat_strings`, into the bytes it
+/// denotes at runtime.
+pub fn decode_str(tok: &Tok) -> Result<Vec<u8>, LexError> {
+    if tok.kind != StrLit {
+        return Err(LexError::InvalidLit {
+            pos: tok.pos,
+            found: tok.str.to_string(),
+            msg: "not a string literal",
+        });
+    }
+    if quoted(tok.str, '"', true).is_ok_and(|len| len < tok.str.len()) {
+        return decode_concatenated(tok);
+    }
+    decode_escapes(body(tok, '"')?, tok.pos + 1)
+}
+
+/// Decodes a string literal merged from several by `concat_strings`.
+fn decode_concatenated(tok: &Tok) -> Result<Vec<u8>, LexError> {
+    let mut out = Vec::new();
+    for piece in Lexer::new(tok.str).without_trivia() {
+        let piece = piece.map_err(|e| e.shifted(tok.pos))?;
+        let piece = Tok {
+            pos: tok.pos + piece.pos,
+            ..piece
+        };
+        out.extend(decode_str(&piece)?);
+    }
+    Ok(out)
+}
+
+/// Decodes a char literal token into its byte value.
+pub fn decode_char(tok: &Tok) -> Result<u8, LexError> {
+    if tok.kind != ChrLit {
+        return Err(LexError::InvalidLit {
+            pos: tok.pos,
+            found: tok.str.to_string(),
+            msg: "not a char literal",
+        });
+    }
+    let bytes = decode_escapes(body(tok, '\'')?, tok.pos + 1)?;
+    let msg = match bytes[..] {
+        [b] => return Ok(b),
+        [] => "empty char literal",
+        _ => "char literal contains more than one byte",
+    };
+    Err(LexError::InvalidLit {
+        pos: tok.pos,
+        found: tok.str.to_string(),
+        msg,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{concat_strings, tokenize, tokenize_all};
+
+    #[test]
+    fn decode_str_test() -> Result<(), LexError> {
+        let toks = tokenize(r#""a\n\t\\\"\0""#)?;
+        assert_eq!(decode_str(&toks[0])?, b"a\n\t\\\"\0");
+        let toks = tokenize(r"'\n' 'x'")?;
+        assert_eq!(decode_char(&toks[0])?, b'\n');
+        assert_eq!(decode_char(&toks[2])?, b'x');
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_escape_test() -> Result<(), LexError> {
+        let toks = tokenize(r#""\x41\x7f\xFF\0\012\1234\08" '\x0a' '\377' '\0'"#)?;
+        assert_eq!(decode_str(&toks[0])?, b"\x41\x7f\xff\0\n\x534\08");
+        assert_eq!(decode_char(&toks[2])?, b'\n');
+        assert_eq!(decode_char(&toks[4])?, 0xff);
+        assert_eq!(decode_char(&toks[6])?, 0);
+
+        let err = |src| tokenize(src).unwrap_err();
+        assert_eq!(
+            err(r#"s = "a\xg""#),
+            LexError::InvalidEscape {
+                pos: 6,
+                found: r"\x".to_string(),
+            }
+        );
+        for (src, found) in [(r#""\x100""#, r"\x100"), (r"'\400'", r"\400")] {
+            let expected = LexError::InvalidLit {
+                pos: 1,
+                found: found.to_string(),
+                msg: "escape sequence out of range",
+            };
+            assert_eq!(err(src), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn concatenated_str_test() -> Result<(), LexError> {
+        let src = "s = \"ab\" /* c */ \"\\n\"\n  \"d\"; t = \"e\" x \"f\";";
+        let toks = concat_strings(src, tokenize(src)?);
+        let strs: Vec<&Tok> = toks.iter().filter(|t| t.kind == StrLit).collect();
+        assert_eq!(strs.len(), 3);
+        assert_eq!(strs[0].text(), "\"ab\" /* c */ \"\\n\"\n  \"d\"");
+        assert_eq!(decode_str(strs[0])?, b"ab\nd");
+        assert_eq!(decode_str(strs[1])?, b"e");
+        assert_eq!(toks[5].kind, SCol);
+
+        // a literal that fails to lex is left out of the run
+        let src = "\"a\" \"\\q\"";
+        let toks = concat_strings(src, tokenize_all(src).0);
+        let kinds: Vec<_> = toks.iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, [StrLit, Spc, Error]);
+        Ok(())
+    }
+
+    #[test]
+    fn digit_separator_test() -> Result<(), LexError> {
+        let toks = tokenize("1_000 1'000'000 0xFF_FF 0b1010'0101")?;
+        let values: Vec<u64> = toks
+            .iter()
+            .filter(|t| t.kind != Spc)
+            .map(parse_int)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(values, [1000, 1_000_000, 0xffff, 0b1010_0101]);
+        for src in ["0x_FF", "0xFF__FF", "0b1_"] {
+            let err = tokenize(src).unwrap_err();
+            assert!(matches!(err, LexError::MalformedLit { .. }), "{}", src);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_escape_test() {
+        let (toks, errs) = tokenize_all(r#"x = "ab\q";"#);
+        let err = LexError::InvalidEscape {
+            pos: 7,
+            found: r"\q".to_string(),
+        };
+        assert_eq!(errs, vec![err.clone()]);
+        let lit = Tok {
+            kind: StrLit,
+            ..toks[4]
+        };
+        assert_eq!(decode_str(&lit), Err(err));
+    }
+}
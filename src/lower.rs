@@ -0,0 +1,506 @@
+//! Rewrites of the AST into the smaller language the backend handles.
+
+use std::collections::HashMap;
+
+use crate::arena::{Arena, Node, SideTable};
+use crate::ast::*;
+use crate::source::Span;
+
+/// The types `typeck::check_types` gave the expressions of a program, by span rather than
+/// `NodeId`, since the rewrites move expressions into new trees but keep their spans.
+pub type Types = HashMap<Span, Type>;
+
+/// The types of the expressions numbered by `arena`, for the rewrites that need them.
+pub fn expr_types(arena: &Arena, types: &SideTable<Type>) -> Types {
+    let mut by_span = Types::new();
+    for (id, node) in arena.iter() {
+        if let (Node::Expr(expr), Some(ty)) = (node, types.get(id)) {
+            // the outermost of the expressions sharing a span comes first
+            by_span.entry(expr.span).or_insert_with(|| ty.clone());
+        }
+    }
+    by_span
+}
+
+/// Applies `f` to each statement directly inside `kind`.
+fn map_children(kind: StmtKind, mut f: impl FnMut(Stmt) -> Stmt) -> StmtKind {
+    let mut f = |stmt: Box<Stmt>| Box::new(f(*stmt));
+    match kind {
+        StmtKind::Block(stmts) => {
+            StmtKind::Block(stmts.into_iter().map(|s| *f(Box::new(s))).collect())
+        }
+        StmtKind::If { cond, then, els } => StmtKind::If {
+            cond,
+            then: f(then),
+            els: els.map(f),
+        },
+        StmtKind::While { cond, body } => StmtKind::While {
+            cond,
+            body: f(body),
+        },
+        StmtKind::DoWhile { body, cond } => StmtKind::DoWhile {
+            body: f(body),
+            cond,
+        },
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => StmtKind::For {
+            init: init.map(&mut f),
+            cond,
+            step,
+            body: f(body),
+        },
+        StmtKind::Switch { cond, body } => StmtKind::Switch {
+            cond,
+            body: f(body),
+        },
+        StmtKind::Case { value, stmt } => StmtKind::Case {
+            value,
+            stmt: f(stmt),
+        },
+        StmtKind::Default(stmt) => StmtKind::Default(f(stmt)),
+        StmtKind::Decl(decls) => StmtKind::Decl(
+            decls
+                .into_iter()
+                .map(|decl| match decl {
+                    Decl::Func(mut func) => {
+                        func.body = func.body.map(&mut f);
+                        Decl::Func(func)
+                    }
+                    decl => decl,
+                })
+                .collect(),
+        ),
+        StmtKind::Label { label, stmt } => StmtKind::Label {
+            label,
+            stmt: f(stmt),
+        },
+        kind @ (StmtKind::Expr(_)
+        | StmtKind::Break
+        | StmtKind::Continue
+        | StmtKind::Goto(_)
+        | StmtKind::Return(_)
+        | StmtKind::Empty) => kind,
+    }
+}
+
+/// Lowers every `for` loop in `stmt`, however deeply nested, into a `while` loop, so
+/// that later passes only deal with one kind of loop:
+///
+/// ```text
+/// for (init; cond; step) body      {
+///                              =>      init;
+///                                      while (cond) { body step; }
+///                                  }
+/// ```
+///
+/// The block scopes a declaration in `init` to the loop, as C does. A missing `cond` becomes
+/// `1`, and each `continue` of the loop becomes `{ step; continue; }` so that the step still
+/// runs. The new nodes take the span of the `for` statement, while `init`, `cond`, `step` and
+/// `body` keep their own.
+pub fn lower_for(stmt: Stmt) -> Stmt {
+    let span = stmt.span;
+    let kind = match stmt.kind {
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            let cond = cond.unwrap_or(Expr {
+                kind: ExprKind::Int(1),
+                span,
+            });
+            let mut body = lower_for(*body);
+            let mut body_stmts = Vec::new();
+            if let Some(step) = step {
+                let step = Stmt {
+                    span: step.span,
+                    kind: StmtKind::Expr(step),
+                };
+                body = step_before_continue(body, &step);
+                body_stmts.push(body);
+                body_stmts.push(step);
+            } else {
+                body_stmts.push(body);
+            }
+            let body = Box::new(Stmt {
+                kind: StmtKind::Block(body_stmts),
+                span,
+            });
+            let mut stmts: Vec<Stmt> = init.map(|init| *init).into_iter().collect();
+            stmts.push(Stmt {
+                kind: StmtKind::While { cond, body },
+                span,
+            });
+            StmtKind::Block(stmts)
+        }
+        kind => map_children(kind, lower_for),
+    };
+    Stmt { kind, span }
+}
+
+/// Lowers `stmt` into the core language, with `lower_for`, `lower_cond` and then
+/// `lower_assigns`, leaving no `for` loops and only the compound assignments, increments and
+/// conditional expressions that can't be rewritten without a temporary, or without `types`.
+pub fn desugar(stmt: Stmt, types: &Types) -> Stmt {
+    lower_assigns(lower_cond(lower_for(stmt), types))
+}
+
+/// Applies `f` to each operand of `kind`, besides the operand of `sizeof`, which isn't
+/// evaluated.
+fn map_operands(kind: ExprKind, mut f: impl FnMut(Expr) -> Expr) -> ExprKind {
+    let mut f = |expr: Box<Expr>| Box::new(f(*expr));
+    match kind {
+        ExprKind::Unary(op, e) => ExprKind::Unary(op, f(e)),
+        ExprKind::Binary(op, a, b) => ExprKind::Binary(op, f(a), f(b)),
+        ExprKind::Assign(op, a, b) => ExprKind::Assign(op, f(a), f(b)),
+        ExprKind::Index(a, b) => ExprKind::Index(f(a), f(b)),
+        ExprKind::Member(e, field) => ExprKind::Member(f(e), field),
+        ExprKind::Arrow(e, field) => ExprKind::Arrow(f(e), field),
+        ExprKind::Cast(ty, e) => ExprKind::Cast(ty, f(e)),
+        ExprKind::Comma(a, b) => ExprKind::Comma(f(a), f(b)),
+        ExprKind::Cond(c, a, b) => ExprKind::Cond(f(c), f(a), f(b)),
+        ExprKind::Call(callee, args) => ExprKind::Call(
+            f(callee),
+            args.into_iter().map(|arg| *f(Box::new(arg))).collect(),
+        ),
+        kind @ (ExprKind::Int(_)
+        | ExprKind::Char(_)
+        | ExprKind::Str(_)
+        | ExprKind::Var(_)
+        | ExprKind::Sizeof(_)
+        | ExprKind::SizeofExpr(_)) => kind,
+    }
+}
+
+/// Applies `f` to each expression directly inside `kind`, telling it whether the value of the
+/// expression is used. Those of nested statements, `case` labels and array sizes are left
+/// alone.
+fn map_exprs(kind: StmtKind, mut f: impl FnMut(Expr, bool) -> Expr) -> StmtKind {
+    fn map_init(init: Init, f: &mut impl FnMut(Expr, bool) -> Expr) -> Init {
+        match init {
+            Init::Expr(expr) => Init::Expr(f(expr, true)),
+            Init::List { items, span } => Init::List {
+                items: items.into_iter().map(|item| map_init(item, f)).collect(),
+                span,
+            },
+        }
+    }
+    match kind {
+        StmtKind::Expr(expr) => StmtKind::Expr(f(expr, false)),
+        StmtKind::If { cond, then, els } => StmtKind::If {
+            cond: f(cond, true),
+            then,
+            els,
+        },
+        StmtKind::While { cond, body } => StmtKind::While {
+            cond: f(cond, true),
+            body,
+        },
+        StmtKind::DoWhile { body, cond } => StmtKind::DoWhile {
+            body,
+            cond: f(cond, true),
+        },
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => StmtKind::For {
+            init,
+            cond: cond.map(|cond| f(cond, true)),
+            step: step.map(|step| f(step, false)),
+            body,
+        },
+        StmtKind::Switch { cond, body } => StmtKind::Switch {
+            cond: f(cond, true),
+            body,
+        },
+        StmtKind::Return(value) => StmtKind::Return(value.map(|value| f(value, true))),
+        StmtKind::Decl(decls) => StmtKind::Decl(
+            decls
+                .into_iter()
+                .map(|decl| match decl {
+                    Decl::Var(mut var) => {
+                        var.init = var.init.map(|init| map_init(init, &mut f));
+                        Decl::Var(var)
+                    }
+                    decl => decl,
+                })
+                .collect(),
+        ),
+        kind => kind,
+    }
+}
+
+/// Whether evaluating `expr` twice has the same effect as evaluating it once: it assigns
+/// nothing and calls nothing.
+fn is_pure(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Int(_)
+        | ExprKind::Char(_)
+        | ExprKind::Str(_)
+        | ExprKind::Var(_)
+        | ExprKind::Sizeof(_)
+        | ExprKind::SizeofExpr(_) => true,
+        ExprKind::Unary(UnOp::PreInc | UnOp::PreDec | UnOp::PostInc | UnOp::PostDec, _)
+        | ExprKind::Assign(..)
+        | ExprKind::Call(..) => false,
+        ExprKind::Unary(_, e)
+        | ExprKind::Member(e, _)
+        | ExprKind::Arrow(e, _)
+        | ExprKind::Cast(_, e) => is_pure(e),
+        ExprKind::Binary(_, a, b) | ExprKind::Index(a, b) | ExprKind::Comma(a, b) => {
+            is_pure(a) && is_pure(b)
+        }
+        ExprKind::Cond(c, a, b) => is_pure(c) && is_pure(a) && is_pure(b),
+    }
+}
+
+/// Rewrites the compound assignments, increments and decrements in `stmt` into plain
+/// assignments:
+///
+/// ```text
+/// a op= b  =>  a = a op b
+/// ++a      =>  a = a + 1
+/// ```
+///
+/// Only targets that can be evaluated twice are rewritten, so `p[i++] += 1` is kept. So is
+/// `a++` where its value is used, since that is the value from before the assignment, but
+/// not in an expression statement or a `for` step, where it becomes `a = a + 1` as well.
+/// The new nodes take the span of the expression they replace.
+pub fn lower_assigns(stmt: Stmt) -> Stmt {
+    let kind = map_exprs(stmt.kind, lower_assign);
+    Stmt {
+        kind: map_children(kind, lower_assigns),
+        span: stmt.span,
+    }
+}
+
+/// Lowers the assignments of `expr` for `lower_assigns`, given whether its value is used.
+fn lower_assign(expr: Expr, used: bool) -> Expr {
+    let span = expr.span;
+    let binary = |op, lhs: Box<Expr>, rhs| Expr {
+        kind: ExprKind::Binary(op, lhs, Box::new(rhs)),
+        span,
+    };
+    let kind = match expr.kind {
+        ExprKind::Assign(Some(op), lhs, rhs) if is_pure(&lhs) => {
+            let rhs = lower_assign(*rhs, true);
+            ExprKind::Assign(None, lhs.clone(), Box::new(binary(op, lhs, rhs)))
+        }
+        ExprKind::Unary(
+            op @ (UnOp::PreInc | UnOp::PreDec | UnOp::PostInc | UnOp::PostDec),
+            target,
+        ) if is_pure(&target) && !(used && matches!(op, UnOp::PostInc | UnOp::PostDec)) => {
+            let op = match op {
+                UnOp::PreInc | UnOp::PostInc => BinOp::Add,
+                _ => BinOp::Sub,
+            };
+            let one = Expr {
+                kind: ExprKind::Int(1),
+                span,
+            };
+            ExprKind::Assign(None, target.clone(), Box::new(binary(op, target, one)))
+        }
+        ExprKind::Comma(a, b) => ExprKind::Comma(
+            Box::new(lower_assign(*a, false)),
+            Box::new(lower_assign(*b, used)),
+        ),
+        ExprKind::Cond(c, a, b) => ExprKind::Cond(
+            Box::new(lower_assign(*c, true)),
+            Box::new(lower_assign(*a, used)),
+            Box::new(lower_assign(*b, used)),
+        ),
+        kind => map_operands(kind, |e| lower_assign(e, true)),
+    };
+    Expr { kind, span }
+}
+
+/// Rewrites the conditional expressions in `stmt` that make up a whole expression
+/// statement, the value assigned by one, or a returned value, into `if` statements:
+///
+/// ```text
+/// c ? a : b;            =>  if (c) a; else b;
+/// x = c ? a : b;        =>  if (c) x = a; else x = b;
+/// return c ? a : b;     =>  if (c) return a; else return b;
+/// ```
+///
+/// An assignment is only split when its target can be evaluated twice. Where the value is
+/// used, the arms convert to the type of the whole expression first, as `Type::common` says,
+/// so an arm of another type is cast to it, and an expression whose type isn't in `types` is
+/// kept. The new statements take the span of the one they replace.
+pub fn lower_cond(stmt: Stmt, types: &Types) -> Stmt {
+    let span = stmt.span;
+    let split = |cond: Box<Expr>, then: StmtKind, els: StmtKind| StmtKind::If {
+        cond: *cond,
+        then: Box::new(lower_cond(Stmt { kind: then, span }, types)),
+        els: Some(Box::new(lower_cond(Stmt { kind: els, span }, types))),
+    };
+    let convert = |arm: Box<Expr>, common: &Type| match types.get(&arm.span) {
+        Some(ty) if ty.decayed() == *common => arm,
+        _ => Box::new(Expr {
+            span: arm.span,
+            kind: ExprKind::Cast(common.clone(), arm),
+        }),
+    };
+    let kind = match stmt.kind {
+        StmtKind::Expr(Expr {
+            kind: ExprKind::Cond(cond, a, b),
+            ..
+        }) => split(cond, StmtKind::Expr(*a), StmtKind::Expr(*b)),
+        StmtKind::Expr(Expr {
+            kind: ExprKind::Assign(None, lhs, rhs),
+            span: assign,
+        }) if matches!(rhs.kind, ExprKind::Cond(..))
+            && is_pure(&lhs)
+            && types.contains_key(&rhs.span) =>
+        {
+            let common = &types[&rhs.span];
+            let ExprKind::Cond(cond, a, b) = rhs.kind else {
+                unreachable!()
+            };
+            let assign = |value: Box<Expr>| {
+                StmtKind::Expr(Expr {
+                    kind: ExprKind::Assign(None, lhs.clone(), convert(value, common)),
+                    span: assign,
+                })
+            };
+            split(cond, assign(a), assign(b))
+        }
+        StmtKind::Return(Some(Expr {
+            kind: ExprKind::Cond(cond, a, b),
+            span: value,
+        })) if types.contains_key(&value) => {
+            let common = &types[&value];
+            let ret = |value| StmtKind::Return(Some(*convert(value, common)));
+            split(cond, ret(a), ret(b))
+        }
+        kind => map_children(kind, |stmt| lower_cond(stmt, types)),
+    };
+    Stmt { kind, span }
+}
+
+/// Puts `step` before each `continue` in `stmt` that isn't inside a nested loop.
+fn step_before_continue(stmt: Stmt, step: &Stmt) -> Stmt {
+    let span = stmt.span;
+    let kind = match stmt.kind {
+        StmtKind::Continue => StmtKind::Block(vec![
+            step.clone(),
+            Stmt {
+                kind: StmtKind::Continue,
+                span,
+            },
+        ]),
+        kind @ (StmtKind::While { .. } | StmtKind::DoWhile { .. } | StmtKind::For { .. }) => kind,
+        kind => map_children(kind, |s| step_before_continue(s, step)),
+    };
+    Stmt { kind, span }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+    use crate::typeck::check_types;
+    use regex::Regex;
+
+    /// The statements of `src` lowered by `pass`, printed without their spans.
+    fn lowered_by(pass: impl FnMut(Stmt) -> Stmt, src: &str) -> String {
+        let stmts: Vec<Stmt> = parse(src).unwrap().stmts.into_iter().map(pass).collect();
+        let spans = Regex::new(r"span: Span \{ start: \d+, end: \d+ \}").unwrap();
+        spans.replace_all(&format!("{:?}", stmts), "").into_owned()
+    }
+
+    fn lowered(src: &str) -> String {
+        lowered_by(lower_for, src)
+    }
+
+    #[test]
+    fn lower_for_test() {
+        assert_eq!(
+            lowered("for (int i = 0; i < 3; i++) x += i;"),
+            lowered("{ int i = 0; while (i < 3) { x += i; i++; } }")
+        );
+        assert_eq!(lowered("for (;;) ;"), lowered("{ while (1) { ; } }"));
+        assert_eq!(
+            lowered("void f() { for (;;) ; }"),
+            lowered("void f() { { while (1) { ; } } }")
+        );
+        assert_eq!(
+            lowered("for (; i; i--) { if (a) continue; while (b) continue; break; }"),
+            lowered(
+                "{ while (i) { { if (a) { i--; continue; } while (b) continue; break; } i--; } }"
+            )
+        );
+        assert_eq!(
+            lowered("if (a) for (; b; ) for (c; ; d) {}"),
+            lowered("if (a) { while (b) { { c; while (1) { {} d; } } } }")
+        );
+
+        let src = "for (i = 0; i < 3; i++) x;";
+        let stmt = lower_for(parse(src).unwrap().stmts.remove(0));
+        let StmtKind::Block(stmts) = &stmt.kind else {
+            panic!("expected a block");
+        };
+        assert_eq!(stmt.span.text(src), src);
+        assert_eq!(stmts[0].span.text(src), "i = 0;");
+        let StmtKind::While { cond, body } = &stmts[1].kind else {
+            panic!("expected a while");
+        };
+        assert_eq!(cond.span.text(src), "i < 3");
+        assert!(matches!(&body.kind, StmtKind::Block(b) if b[1].span.text(src) == "i++"));
+    }
+
+    #[test]
+    fn desugar_test() {
+        let core = |src| lowered_by(|stmt| stmt, src);
+        let desugared = |src| {
+            let program = parse(src).unwrap();
+            let arena = Arena::new(&program);
+            let (symbols, _) = resolve(&program, &arena);
+            let types = expr_types(&arena, &check_types(&program, &arena, &symbols).0);
+            lowered_by(|stmt| desugar(stmt, &types), src)
+        };
+        assert_eq!(
+            desugared("void f() { x += 2; ++y; z--; w = z++ + --z; p[i++] *= 2; }"),
+            core(
+                "void f() { x = x + 2; y = y + 1; z = z - 1; w = z++ + (z = z - 1); p[i++] *= 2; }"
+            )
+        );
+        assert_eq!(
+            desugared("for (i = 0; i < 3; i++) s += i;"),
+            core("{ i = 0; while (i < 3) { s = s + i; i = i + 1; } }")
+        );
+        assert_eq!(
+            desugared(
+                "int f(int a) { a ? g() : h(); a = a > 0 ? a : -a; return a ? 1 : a ? 2 : 3; }"
+            ),
+            core(
+                "int f(int a) { if (a) g(); else h(); if (a > 0) a = a; else a = -a; \
+                 if (a) return 1; else if (a) return 2; else return 3; }"
+            )
+        );
+        assert_eq!(
+            desugared("int x = a ? b : c; p[i++] = a ? b : c;"),
+            core("int x = a ? b : c; p[i++] = a ? b : c;")
+        );
+        // the arms convert to `unsigned char` before the split too
+        assert_eq!(
+            desugared(
+                "long f(int c) { return c ? (char)-1 : (unsigned char)200; } \
+                 int x; void g(int c, char a, unsigned char b) { x = c ? a : b; x = c ? y : b; }"
+            ),
+            core(
+                "long f(int c) { if (c) return (unsigned char)(char)-1; else return (unsigned char)200; } \
+                 int x; void g(int c, char a, unsigned char b) { if (c) x = (unsigned char)a; else x = b; \
+                 x = c ? y : b; }"
+            )
+        );
+    }
+}
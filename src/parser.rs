@@ -0,0 +1,2914 @@
+//! A recursive-descent parser from tokens to the AST.
+
+use std::fmt;
+
+use crate::ast::*;
+use crate::consteval;
+use crate::lexer::{concat_strings, tokenize, tokenize_all, LexError, Tok, TokKind, TokKind::*};
+use crate::source::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Lex(LexError),
+    /// A token that doesn't fit the grammar where it appears. `expected` describes what
+    /// would have, e.g. "an expression".
+    Unexpected {
+        span: Span,
+        found: String,
+        expected: &'static str,
+    },
+    /// The source ended in the middle of a construct.
+    UnexpectedEof {
+        pos: usize,
+        expected: &'static str,
+    },
+    /// A construct that parses but isn't allowed, such as a `case` outside a `switch`.
+    Invalid {
+        span: Span,
+        msg: String,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Lex(err) => Span::new(err.pos(), err.pos()),
+            ParseError::Unexpected { span, .. } => *span,
+            ParseError::UnexpectedEof { pos, .. } => Span::new(*pos, *pos),
+            ParseError::Invalid { span, .. } => *span,
+        }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> ParseError {
+        ParseError::Lex(err)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Lex(err) => err.fmt(f),
+            ParseError::Unexpected {
+                span,
+                found,
+                expected,
+            } => write!(
+                f,
+                "expected {} but found `{}` at byte {}",
+                expected, found, span.start
+            ),
+            ParseError::UnexpectedEof { pos, expected } => {
+                write!(
+                    f,
+                    "expected {} but the source ended at byte {}",
+                    expected, pos
+                )
+            }
+            ParseError::Invalid { span, msg } => write!(f, "{} at byte {}", msg, span.start),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a whole source file.
+pub fn parse(src: &str) -> Result<Program, ParseError> {
+    Parser::new(src)?.parse_program()
+}
+
+/// Parses a whole source file, recovering from each error at the next statement or declaration
+/// boundary instead of stopping, so that one run finds them all. The program holds every
+/// statement that parsed, and the errors, lexical ones included, are in source order.
+pub fn parse_all(src: &str) -> (Program, Vec<ParseError>) {
+    let (toks, lex_errs) = tokenize_all(src);
+    // the lexical errors already cover the characters of `Error` tokens
+    let toks = toks.into_iter().filter(|t| t.kind() != Error).collect();
+    let mut parser = Parser::with_toks(src, concat_strings(src, toks));
+    parser.recovered = Some(Vec::new());
+    let program = match parser.parse_program() {
+        Ok(program) => program,
+        Err(err) => unreachable!("a recovering parser records its errors: {}", err),
+    };
+    let mut errs: Vec<ParseError> = lex_errs.into_iter().map(ParseError::Lex).collect();
+    errs.extend(parser.recovered.unwrap_or_default());
+    errs.sort_by_key(|e| e.span().start);
+    (program, errs)
+}
+
+/// Parses a single expression, such as `a[i] + 1`, which must be the whole of `src`.
+pub fn parse_expr(src: &str) -> Result<Expr, ParseError> {
+    Parser::new(src)?.parse_fragment(Parser::parse_expr)
+}
+
+/// Parses a single statement as if it were at the top of a program, so that a declaration
+/// is global and `return` isn't allowed.
+pub fn parse_stmt(src: &str) -> Result<Stmt, ParseError> {
+    Parser::new(src)?.parse_fragment(|parser| {
+        let stmt = parser.parse_stmt()?;
+        parser.check_gotos()?;
+        Ok(stmt)
+    })
+}
+
+/// Parses a type name, such as `const char *[4]`, as written in a cast.
+pub fn parse_type(src: &str) -> Result<Type, ParseError> {
+    Parser::new(src)?.parse_fragment(Parser::parse_type_name)
+}
+
+/// One step in deriving a declarator's type from the type its declaration starts with.
+#[derive(Debug, Clone)]
+enum Derived {
+    /// A pointer, itself qualified as in `*const`.
+    Pointer(Quals),
+    /// An array of the length, if it was given.
+    Array(Option<u64>),
+    Function(Vec<Param>),
+}
+
+impl Derived {
+    fn is_function(&self) -> bool {
+        matches!(self, Derived::Function(_))
+    }
+}
+
+/// The type of a declarator, applying each step of `derived` in turn to `ty`. For
+/// `int (*table[4])(char)` the steps are a function taking `char`, then a pointer, then an
+/// array of 4, so `table` is an array of pointers to functions returning `int`. An array
+/// without a length is a pointer, as it is for a parameter; elsewhere `check_sized` rules it
+/// out first.
+fn apply(ty: Type, derived: Vec<Derived>) -> Type {
+    derived.into_iter().fold(ty, |ty, step| match step {
+        Derived::Pointer(quals) => Type::Pointer(Box::new(ty)).qualified(quals),
+        Derived::Array(None) => Type::Pointer(Box::new(ty)),
+        Derived::Array(Some(len)) => Type::Array {
+            elem: Box::new(ty),
+            len,
+        },
+        Derived::Function(params) => Type::Function {
+            ret: Box::new(ty),
+            // a parameter's own qualifiers only matter inside the function
+            params: params
+                .into_iter()
+                .map(|p| p.ty.unqualified().clone())
+                .collect(),
+        },
+    })
+}
+
+/// Checks that every array in `derived` has a length, pointing at `span` if not.
+fn check_sized(derived: &[Derived], span: Span) -> Result<(), ParseError> {
+    if derived.iter().any(|d| matches!(d, Derived::Array(None))) {
+        return Err(ParseError::Invalid {
+            span,
+            msg: "array length missing".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Holds the significant tokens of a source and the position of the next one to parse.
+pub struct Parser<'src> {
+    toks: Vec<Tok<'src>>,
+    /// Index of the next token in `toks`.
+    next: usize,
+    /// The length of the source, where errors about a missing token at the end point.
+    end: usize,
+    /// The labels seen so far in each enclosing `switch`, innermost last.
+    switches: Vec<SwitchLabels>,
+    /// How many loops enclose the next token.
+    loops: usize,
+    /// The labels defined and the `goto` targets used so far, checked against each other
+    /// once the whole body they are in has been parsed.
+    labels: Vec<Ident>,
+    gotos: Vec<Ident>,
+    /// The return type of the function being parsed, if any.
+    ret: Option<Type>,
+    /// How many statements enclose the next token, counting the one it starts.
+    depth: usize,
+    /// The structs and enums defined so far, which can be used in later declarations.
+    structs: Vec<StructDef>,
+    enums: Vec<EnumDef>,
+    /// The ordinary identifiers declared in each enclosing block, innermost last. Whether an
+    /// identifier names a type decides how a statement like `T * x;` parses, so declarations
+    /// feed back into parsing through this.
+    scopes: Vec<Vec<(Ident, Binding)>>,
+    /// The errors recovered from so far, if the parser recovers from errors at all.
+    recovered: Option<Vec<ParseError>>,
+}
+
+/// What an ordinary identifier in scope stands for.
+enum Binding {
+    /// A typedef name for the type.
+    Typedef(Type),
+    /// A variable, parameter or function of the type.
+    Value(Type),
+    /// An enumerator with the value.
+    Constant(i64),
+}
+
+#[derive(Default)]
+struct SwitchLabels {
+    cases: Vec<(i64, Span)>,
+    default: Option<Span>,
+}
+
+/// Constant expressions in declarations can use the enumerators and structs declared before
+/// them, and `sizeof` the variables.
+impl consteval::Env for Parser<'_> {
+    fn constant(&self, name: &str) -> Option<i64> {
+        match self.lookup(name)? {
+            Binding::Constant(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn structs(&self) -> &[StructDef] {
+        &self.structs
+    }
+
+    /// The types of the expressions whose type follows from the declarations alone: names,
+    /// literals, and indexing, dereferencing or member access of those.
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        let pointee = |expr| match self.type_of(expr)?.decayed() {
+            Type::Pointer(to) => Some(*to),
+            _ => None,
+        };
+        // a field of a qualified struct is qualified the same
+        let field = |aggregate: Type, field: &Ident| {
+            let (Type::Struct(tag) | Type::Union(tag)) = aggregate.unqualified() else {
+                return None;
+            };
+            let def = self.structs.iter().find(|s| s.tag.name == *tag)?;
+            let ty = def.field(&field.name)?.ty.clone();
+            Some(ty.qualified(aggregate.quals()))
+        };
+        match &expr.kind {
+            ExprKind::Int(_) | ExprKind::Sizeof(_) | ExprKind::SizeofExpr(_) => Some(Type::Int),
+            ExprKind::Char(_) => Some(Type::Char),
+            ExprKind::Cast(ty, _) => Some(ty.clone()),
+            ExprKind::Str(bytes) => Some(Type::Array {
+                elem: Box::new(Type::Char),
+                len: bytes.len() as u64 + 1,
+            }),
+            ExprKind::Var(name) => match self.lookup(name)? {
+                Binding::Value(ty) => Some(ty.clone()),
+                Binding::Constant(_) => Some(Type::Int),
+                Binding::Typedef(_) => None,
+            },
+            ExprKind::Unary(UnOp::Deref, operand) => pointee(operand),
+            ExprKind::Unary(UnOp::Not, _) => Some(Type::Bool),
+            ExprKind::Binary(op, _, _) if op.is_boolean() => Some(Type::Bool),
+            ExprKind::Index(array, _) => pointee(array),
+            ExprKind::Member(base, name) => field(self.type_of(base)?, name),
+            ExprKind::Arrow(base, name) => field(pointee(base)?, name),
+            _ => None,
+        }
+    }
+}
+
+impl<'src> Parser<'src> {
+    /// Lexes `src`, failing on the first lexical error.
+    pub fn new(src: &'src str) -> Result<Parser<'src>, ParseError> {
+        Ok(Parser::with_toks(src, concat_strings(src, tokenize(src)?)))
+    }
+
+    /// A parser of the significant tokens among `toks`, lexed from `src`.
+    fn with_toks(src: &'src str, toks: Vec<Tok<'src>>) -> Parser<'src> {
+        // pragmas are for the passes that read them from the source, such as `lint::shadowing`
+        let toks = toks
+            .into_iter()
+            .filter(|t| !t.kind().is_trivia() && t.directive_name() != Some("pragma"))
+            .collect();
+        Parser {
+            toks,
+            next: 0,
+            end: src.len(),
+            switches: Vec::new(),
+            loops: 0,
+            labels: Vec::new(),
+            gotos: Vec::new(),
+            ret: None,
+            depth: 0,
+            structs: Vec::new(),
+            enums: Vec::new(),
+            scopes: vec![Vec::new()],
+            recovered: None,
+        }
+    }
+
+    fn peek(&self) -> Option<&Tok<'src>> {
+        self.toks.get(self.next)
+    }
+
+    fn at(&self, kind: TokKind) -> bool {
+        self.peek().is_some_and(|t| t.kind() == kind)
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek()
+            .is_some_and(|t| t.kind() == Key && t.text() == keyword)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.next += 1;
+        }
+        found
+    }
+
+    /// Whether the token `n` after the next one is a `kind`.
+    fn at_nth(&self, n: usize, kind: TokKind) -> bool {
+        self.toks
+            .get(self.next + n)
+            .is_some_and(|t| t.kind() == kind)
+    }
+
+    fn bump(&mut self) -> Tok<'src> {
+        let tok = self.toks[self.next];
+        self.next += 1;
+        tok
+    }
+
+    /// Consumes the next token if it is a `kind`.
+    fn eat(&mut self, kind: TokKind) -> Option<Tok<'src>> {
+        self.at(kind).then(|| self.bump())
+    }
+
+    fn expect(&mut self, kind: TokKind, expected: &'static str) -> Result<Tok<'src>, ParseError> {
+        self.eat(kind).ok_or_else(|| self.error(expected))
+    }
+
+    /// An error for the next token not being what was `expected`.
+    fn error(&self, expected: &'static str) -> ParseError {
+        match self.peek() {
+            Some(tok) => ParseError::Unexpected {
+                span: tok.span(),
+                found: tok.text().to_string(),
+                expected,
+            },
+            None => ParseError::UnexpectedEof {
+                pos: self.end,
+                expected,
+            },
+        }
+    }
+
+    /// The span from `start` to the end of the last token consumed.
+    fn span_from(&self, start: usize) -> Span {
+        let end = self
+            .next
+            .checked_sub(1)
+            .map_or(start, |i| self.toks[i].span().end);
+        Span::new(start, end.max(start))
+    }
+
+    /// The start of the next token, or the end of the source.
+    fn start(&self) -> usize {
+        self.peek().map_or(self.end, |t| t.pos())
+    }
+
+    /// Runs `parse`, which must consume every token.
+    fn parse_fragment<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let value = parse(self)?;
+        match self.peek() {
+            Some(_) => Err(self.error("the end of the input")),
+            None => Ok(value),
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        let mut stmts = Vec::new();
+        while self.peek().is_some() {
+            stmts.extend(self.parse_item()?);
+            // recovery stops before a `}` in case a block ends there, but none does here
+            if self.recovered.is_some() {
+                self.eat(RBrc);
+            }
+        }
+        let checked = self.check_gotos();
+        self.recover(checked)?;
+        Ok(Program {
+            stmts,
+            span: Span::new(0, self.end),
+        })
+    }
+
+    /// A statement of a program or block. If the parser recovers from errors, an error is
+    /// recorded and the rest of the statement skipped, giving nothing.
+    fn parse_item(&mut self) -> Result<Option<Stmt>, ParseError> {
+        let saved = (self.scopes.len(), self.switches.len(), self.loops);
+        let first = self.next;
+        let stmt = self.parse_stmt();
+        let stmt = self.recover(stmt)?;
+        if stmt.is_none() {
+            // the statement may have stopped partway through a nested one
+            self.scopes.truncate(saved.0);
+            self.switches.truncate(saved.1);
+            self.loops = saved.2;
+            // unless the error was found only once the statement had ended
+            let ended = self.next > first && matches!(self.toks[self.next - 1].kind(), SCol | RBrc);
+            if !ended {
+                self.skip_stmt();
+            }
+        }
+        Ok(stmt)
+    }
+
+    /// Records the error of `result` if the parser recovers from errors, giving `None`
+    /// instead of it.
+    fn recover<T>(&mut self, result: Result<T, ParseError>) -> Result<Option<T>, ParseError> {
+        match (result, &mut self.recovered) {
+            (Ok(value), _) => Ok(Some(value)),
+            (Err(err), Some(recovered)) => {
+                recovered.push(err);
+                Ok(None)
+            }
+            (Err(err), None) => Err(err),
+        }
+    }
+
+    /// Skips to the end of the statement the next token is in: past the next `;` or
+    /// braced block, or up to a `}` that closes the enclosing block.
+    fn skip_stmt(&mut self) {
+        let mut nesting = 0usize;
+        while let Some(tok) = self.peek() {
+            match tok.kind() {
+                SCol if nesting == 0 => {
+                    self.bump();
+                    return;
+                }
+                LBrc => nesting += 1,
+                RBrc if nesting == 0 => return,
+                RBrc => {
+                    nesting -= 1;
+                    if nesting == 0 {
+                        self.bump();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    pub fn parse_stmt(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.start();
+        self.depth += 1;
+        let kind = self.parse_stmt_kind();
+        self.depth -= 1;
+        Ok(Stmt {
+            kind: kind?,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_stmt_kind(&mut self) -> Result<StmtKind, ParseError> {
+        let kind = if self.eat(SCol).is_some() {
+            StmtKind::Empty
+        } else if self.eat(LBrc).is_some() {
+            self.scopes.push(Vec::new());
+            let mut stmts = Vec::new();
+            while self.eat(RBrc).is_none() {
+                if self.peek().is_none() {
+                    return Err(self.error("`}`"));
+                }
+                stmts.extend(self.parse_item()?);
+            }
+            self.scopes.pop();
+            StmtKind::Block(stmts)
+        } else if self.at_keyword("break") || self.at_keyword("continue") {
+            let keyword = self.bump();
+            let is_break = keyword.text() == "break";
+            let allowed = self.loops > 0 || (is_break && !self.switches.is_empty());
+            if !allowed {
+                let place = if is_break {
+                    "a loop or `switch`"
+                } else {
+                    "a loop"
+                };
+                return Err(ParseError::Invalid {
+                    span: keyword.span(),
+                    msg: format!("`{}` outside of {}", keyword.text(), place),
+                });
+            }
+            self.expect(SCol, "`;`")?;
+            if is_break {
+                StmtKind::Break
+            } else {
+                StmtKind::Continue
+            }
+        } else if self.eat_keyword("goto") {
+            let label = self.parse_ident()?;
+            self.expect(SCol, "`;`")?;
+            self.gotos.push(label.clone());
+            StmtKind::Goto(label)
+        } else if self.at(Var) && self.at_nth(1, Colon) {
+            let label = self.parse_ident()?;
+            self.bump();
+            if let Some(first) = self.labels.iter().find(|l| l.name == label.name) {
+                return Err(ParseError::Invalid {
+                    span: label.span,
+                    msg: format!(
+                        "duplicate label `{}` (first defined at byte {})",
+                        label.name, first.span.start
+                    ),
+                });
+            }
+            self.labels.push(label.clone());
+            let stmt = Box::new(self.parse_stmt()?);
+            StmtKind::Label { label, stmt }
+        } else if self.eat_keyword("if") {
+            let cond = self.parse_cond()?;
+            let then = Box::new(self.parse_stmt()?);
+            let els = if self.eat_keyword("else") {
+                Some(Box::new(self.parse_stmt()?))
+            } else {
+                None
+            };
+            StmtKind::If { cond, then, els }
+        } else if self.eat_keyword("while") {
+            let cond = self.parse_cond()?;
+            let body = Box::new(self.parse_loop_body()?);
+            StmtKind::While { cond, body }
+        } else if self.eat_keyword("do") {
+            let body = Box::new(self.parse_loop_body()?);
+            if !self.eat_keyword("while") {
+                return Err(self.error("`while`"));
+            }
+            let cond = self.parse_cond()?;
+            self.expect(SCol, "`;`")?;
+            StmtKind::DoWhile { body, cond }
+        } else if self.eat_keyword("for") {
+            // a declaration in `init` is scoped to the loop
+            self.scopes.push(Vec::new());
+            let kind = self.parse_for();
+            self.scopes.pop();
+            kind?
+        } else if self.eat_keyword("switch") {
+            let cond = self.parse_cond()?;
+            self.switches.push(SwitchLabels::default());
+            let body = self.parse_stmt();
+            self.switches.pop();
+            StmtKind::Switch {
+                cond,
+                body: Box::new(body?),
+            }
+        } else if self.at_keyword("case") || self.at_keyword("default") {
+            self.parse_switch_label()?
+        } else if self.at_keyword("return") {
+            self.parse_return()?
+        } else if self.at_decl() {
+            StmtKind::Decl(self.parse_decl()?)
+        } else {
+            let expr = self.parse_expr()?;
+            self.expect(SCol, "`;`")?;
+            StmtKind::Expr(expr)
+        };
+        Ok(kind)
+    }
+
+    /// Checks that every `goto` since the last check jumps to a label defined since then, and
+    /// starts afresh.
+    fn check_gotos(&mut self) -> Result<(), ParseError> {
+        let labels = std::mem::take(&mut self.labels);
+        for goto in std::mem::take(&mut self.gotos) {
+            if !labels.iter().any(|l| l.name == goto.name) {
+                return Err(ParseError::Invalid {
+                    span: goto.span,
+                    msg: format!("label `{}` is not defined", goto.name),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// A statement in which `continue` and `break` are allowed.
+    fn parse_loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loops += 1;
+        let body = self.parse_stmt();
+        self.loops -= 1;
+        body
+    }
+
+    /// A parenthesized condition, as after `if` and `while`. Its span excludes the parentheses.
+    fn parse_cond(&mut self) -> Result<Expr, ParseError> {
+        self.expect(LPar, "`(`")?;
+        let cond = self.parse_expr()?;
+        self.expect(RPar, "`)`")?;
+        Ok(cond)
+    }
+
+    /// The rest of a `for` statement after the keyword.
+    fn parse_for(&mut self) -> Result<StmtKind, ParseError> {
+        self.expect(LPar, "`(`")?;
+        let init = if self.eat(SCol).is_some() {
+            None
+        } else if self.at_decl() {
+            let start = self.start();
+            let decls = self.parse_decl()?;
+            let not_var = |d: &&Decl| matches!(d, Decl::Func(_) | Decl::Typedef(_));
+            if let Some(func) = decls.iter().find(not_var) {
+                return Err(ParseError::Invalid {
+                    span: func.span(),
+                    msg: "only variables can be declared in a `for` initializer".to_string(),
+                });
+            }
+            let stored = |d: &&Decl| matches!(d, Decl::Var(v) if v.storage.is_some());
+            if let Some(var) = decls.iter().find(stored) {
+                return Err(ParseError::Invalid {
+                    span: var.span(),
+                    msg: "a `for` initializer can't declare `static` or `extern` variables"
+                        .to_string(),
+                });
+            }
+            Some(Box::new(Stmt {
+                kind: StmtKind::Decl(decls),
+                span: self.span_from(start),
+            }))
+        } else {
+            let start = self.start();
+            let expr = self.parse_expr()?;
+            self.expect(SCol, "`;`")?;
+            Some(Box::new(Stmt {
+                kind: StmtKind::Expr(expr),
+                span: self.span_from(start),
+            }))
+        };
+        let cond = match self.eat(SCol) {
+            Some(_) => None,
+            None => {
+                let cond = self.parse_expr()?;
+                self.expect(SCol, "`;`")?;
+                Some(cond)
+            }
+        };
+        let step = match self.eat(RPar) {
+            Some(_) => None,
+            None => {
+                let step = self.parse_expr()?;
+                self.expect(RPar, "`)`")?;
+                Some(step)
+            }
+        };
+        let body = Box::new(self.parse_loop_body()?);
+        Ok(StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        })
+    }
+
+    /// A `case` or `default` label and the statement after it, checked against the labels
+    /// before it in the innermost `switch`.
+    fn parse_switch_label(&mut self) -> Result<StmtKind, ParseError> {
+        let keyword = self.bump();
+        let invalid = |span, msg: String| ParseError::Invalid { span, msg };
+        if self.switches.is_empty() {
+            let msg = format!("`{}` label outside of a `switch`", keyword.text());
+            return Err(invalid(keyword.span(), msg));
+        }
+        if keyword.text() == "default" {
+            self.expect(Colon, "`:`")?;
+            let labels = self.switches.last_mut().unwrap();
+            if let Some(first) = labels.default.replace(keyword.span()) {
+                let msg = format!(
+                    "multiple `default` labels (the first at byte {})",
+                    first.start
+                );
+                return Err(invalid(keyword.span(), msg));
+            }
+            return Ok(StmtKind::Default(Box::new(self.parse_stmt()?)));
+        }
+        let value = self.parse_expr()?;
+        self.expect(Colon, "`:`")?;
+        let Some(n) = self.eval(&value) else {
+            let msg = "case value is not a constant expression".to_string();
+            return Err(invalid(value.span, msg));
+        };
+        let labels = self.switches.last_mut().unwrap();
+        if let Some((_, first)) = labels.cases.iter().find(|(m, _)| *m == n) {
+            let msg = format!(
+                "duplicate case value {} (first used at byte {})",
+                n, first.start
+            );
+            return Err(invalid(value.span, msg));
+        }
+        labels.cases.push((n, value.span));
+        let stmt = Box::new(self.parse_stmt()?);
+        Ok(StmtKind::Case { value, stmt })
+    }
+
+    fn at_type(&self) -> bool {
+        self.at_type_nth(0)
+    }
+
+    /// Whether the token `n` after the next one starts a type.
+    fn at_type_nth(&self, n: usize) -> bool {
+        let Some(tok) = self.toks.get(self.next + n) else {
+            return false;
+        };
+        match tok.kind() {
+            Key => [
+                "void", "bool", "_Bool", "char", "int", "long", "signed", "unsigned", "struct",
+                "union", "enum", "const", "volatile",
+            ]
+            .contains(&tok.text()),
+            Var => self.typedef(tok.text()).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Whether a declaration starts at the next token.
+    fn at_decl(&self) -> bool {
+        self.at_type()
+            || ["typedef", "static", "extern"]
+                .iter()
+                .any(|k| self.at_keyword(k))
+    }
+
+    /// What `name` stands for where the next token is, if it has been declared.
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        let mut bindings = self
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev());
+        let (_, binding) = bindings.find(|(ident, _)| ident.name == name)?;
+        Some(binding)
+    }
+
+    /// The type `name` stands for, if it is a typedef name where the next token is.
+    fn typedef(&self, name: &str) -> Option<&Type> {
+        match self.lookup(name)? {
+            Binding::Typedef(ty) => Some(ty),
+            _ => None,
+        }
+    }
+
+    /// Declares an ordinary identifier in the innermost scope. A typedef name can't be
+    /// redeclared in the scope it is declared in, nor another identifier redeclared as one.
+    fn declare(&mut self, name: &Ident, binding: Binding) -> Result<(), ParseError> {
+        let scope = self.scopes.last_mut().unwrap();
+        let first = scope.iter().find(|(ident, _)| ident.name == name.name);
+        if let Some((first, first_binding)) = first {
+            let typedefs = [first_binding, &binding];
+            if typedefs.iter().any(|b| matches!(b, Binding::Typedef(_))) {
+                return Err(ParseError::Invalid {
+                    span: name.span,
+                    msg: format!(
+                        "`{}` is already declared in this scope (first at byte {})",
+                        name.name, first.span.start
+                    ),
+                });
+            }
+        }
+        scope.push((name.clone(), binding));
+        Ok(())
+    }
+
+    /// A type written without a name, as in `sizeof(char *)` or a cast.
+    pub fn parse_type_name(&mut self) -> Result<Type, ParseError> {
+        let start = self.start();
+        let ty = self.parse_type()?;
+        let (name, derived) = self.parse_declarator(false)?;
+        if let Some(name) = name {
+            return Err(ParseError::Unexpected {
+                span: name.span,
+                found: name.name,
+                expected: "`)`",
+            });
+        }
+        check_sized(&derived, self.span_from(start))?;
+        Ok(apply(ty, derived))
+    }
+
+    pub fn parse_type(&mut self) -> Result<Type, ParseError> {
+        let quals = self.parse_quals();
+        let ty = self.parse_specifier()?;
+        Ok(ty.qualified(quals | self.parse_quals()))
+    }
+
+    /// Any run of `const` and `volatile`.
+    fn parse_quals(&mut self) -> Quals {
+        let mut quals = Quals::default();
+        loop {
+            if self.eat_keyword("const") {
+                quals.constant = true;
+            } else if self.eat_keyword("volatile") {
+                quals.volatile = true;
+            } else {
+                return quals;
+            }
+        }
+    }
+
+    /// A type specifier: a keyword, a struct, union or enum tag, or a typedef name.
+    fn parse_specifier(&mut self) -> Result<Type, ParseError> {
+        let ty = match self.peek().map(|t| t.text()) {
+            Some("void") if self.at(Key) => Type::Void,
+            Some("bool" | "_Bool") if self.at(Key) => Type::Bool,
+            Some("char" | "int" | "long" | "signed" | "unsigned") if self.at(Key) => {
+                return self.parse_integer_type();
+            }
+            Some(name) if self.at(Var) && self.typedef(name).is_some() => {
+                self.typedef(name).unwrap().clone()
+            }
+            Some(keyword @ ("struct" | "union" | "enum")) if self.at(Key) => {
+                self.bump();
+                let tag = self.parse_ident()?.name;
+                return Ok(match keyword {
+                    "struct" => Type::Struct(tag),
+                    "union" => Type::Union(tag),
+                    _ => Type::Enum(tag),
+                });
+            }
+            _ => return Err(self.error("a type")),
+        };
+        self.bump();
+        Ok(ty)
+    }
+
+    /// An integer type: `char`, `int` or `long int`, each of which may be left out of that,
+    /// with `signed` or `unsigned` in any order among them. `signed` or `unsigned` alone
+    /// means `int`.
+    fn parse_integer_type(&mut self) -> Result<Type, ParseError> {
+        let start = self.start();
+        let (mut sign, mut size, mut int) = (None, None, false);
+        while let Some(text) = self.peek().filter(|_| self.at(Key)).map(|t| t.text()) {
+            let repeated = match text {
+                "signed" | "unsigned" => sign.replace(text).is_some(),
+                "char" | "long" => size.replace(text).is_some(),
+                "int" => std::mem::replace(&mut int, true),
+                _ => break,
+            };
+            self.bump();
+            if repeated || (size == Some("char") && int) {
+                return Err(ParseError::Invalid {
+                    span: self.span_from(start),
+                    msg: "invalid combination of type specifiers".to_string(),
+                });
+            }
+        }
+        let unsigned = sign == Some("unsigned");
+        Ok(match size {
+            Some("char") if unsigned => Type::UChar,
+            Some("char") => Type::Char,
+            Some(_) if unsigned => Type::ULong,
+            Some(_) => Type::Long,
+            None if unsigned => Type::UInt,
+            None => Type::Int,
+        })
+    }
+
+    /// `struct tag { fields }` or `union tag { fields }`, laying the fields out as it goes.
+    /// Only types defined earlier can be fields, so a struct can only contain itself through a
+    /// pointer. Structs and unions share one namespace of tags.
+    fn parse_struct_def(&mut self) -> Result<StructDef, ParseError> {
+        let start = self.start();
+        let union = self.eat_keyword("union");
+        if !union && !self.eat_keyword("struct") {
+            return Err(self.error("`struct` or `union`"));
+        }
+        let keyword = if union { "union" } else { "struct" };
+        let tag = self.parse_ident()?;
+        self.check_tag(keyword, &tag)?;
+        self.expect(LBrc, "`{`")?;
+        let mut fields: Vec<Field> = Vec::new();
+        let mut size = 0;
+        while self.eat(RBrc).is_none() {
+            let ty = self.parse_type()?;
+            loop {
+                let field_start = self.start();
+                let (name, derived) = self.parse_declarator(true)?;
+                let name = name.expect("a named declarator has a name");
+                check_sized(&derived, name.span)?;
+                let ty = apply(ty.clone(), derived);
+                let invalid = |msg| ParseError::Invalid {
+                    span: name.span,
+                    msg,
+                };
+                let Some(field_size) = ty.size(&self.structs) else {
+                    let msg = format!("field `{}` has incomplete type `{}`", name.name, ty);
+                    return Err(invalid(msg));
+                };
+                if let Some(first) = fields.iter().find(|f| f.name.name == name.name) {
+                    return Err(invalid(format!(
+                        "duplicate field `{}` (first declared at byte {})",
+                        name.name, first.span.start
+                    )));
+                }
+                let offset = if union { 0 } else { size };
+                fields.push(Field {
+                    ty,
+                    name,
+                    offset,
+                    span: self.span_from(field_start),
+                });
+                size = if union {
+                    size.max(field_size)
+                } else {
+                    size + field_size
+                };
+                if self.eat(SCol).is_some() {
+                    break;
+                }
+                self.expect(Comma, "`,` or `;`")?;
+            }
+        }
+        let def = StructDef {
+            union,
+            tag,
+            fields,
+            size,
+            span: self.span_from(start),
+        };
+        self.structs.push(def.clone());
+        Ok(def)
+    }
+
+    /// Checks that no struct, union or enum already has the tag a new one is defined with.
+    fn check_tag(&self, keyword: &str, tag: &Ident) -> Result<(), ParseError> {
+        let same = |t: &Ident| t.name == tag.name;
+        let structs = self.structs.iter().filter(|s| same(&s.tag)).map(|s| s.span);
+        let enums = self
+            .enums
+            .iter()
+            .filter(|e| e.tag.as_ref().is_some_and(same));
+        let Some(first) = structs.chain(enums.map(|e| e.span)).next() else {
+            return Ok(());
+        };
+        Err(ParseError::Invalid {
+            span: tag.span,
+            msg: format!(
+                "redefinition of `{} {}` (first defined at byte {})",
+                keyword, tag.name, first.start
+            ),
+        })
+    }
+
+    /// `enum tag { A, B = 5, C }`. An enumerator without a value is one more than the one
+    /// before, or 0 if it is the first. Values must fit in a signed byte, as enums are one.
+    fn parse_enum_def(&mut self) -> Result<EnumDef, ParseError> {
+        let start = self.start();
+        if !self.eat_keyword("enum") {
+            return Err(self.error("`enum`"));
+        }
+        let tag = if self.at(Var) {
+            let tag = self.parse_ident()?;
+            self.check_tag("enum", &tag)?;
+            Some(tag)
+        } else {
+            None
+        };
+        self.expect(LBrc, "`{`")?;
+        // enumerators can refer to the ones before them, so the enum is visible while parsed
+        self.enums.push(EnumDef {
+            tag,
+            enumerators: Vec::new(),
+            span: self.span_from(start),
+        });
+        let mut next = 0;
+        let mut first = true;
+        while self.eat(RBrc).is_none() {
+            if !first {
+                self.expect(Comma, "`,` or `}`")?;
+                if self.eat(RBrc).is_some() {
+                    break;
+                }
+            }
+            first = false;
+            let enumerator_start = self.start();
+            let name = self.parse_ident()?;
+            let all = self.enums.iter().flat_map(|e| &e.enumerators);
+            if let Some(first) = all.into_iter().find(|e| e.name.name == name.name) {
+                return Err(ParseError::Invalid {
+                    span: name.span,
+                    msg: format!(
+                        "duplicate enumerator `{}` (first declared at byte {})",
+                        name.name, first.span.start
+                    ),
+                });
+            }
+            let value = match self.eat(As) {
+                Some(_) => {
+                    let value = self.parse_assign()?;
+                    self.eval(&value).ok_or_else(|| ParseError::Invalid {
+                        span: value.span,
+                        msg: "enumerator value is not a constant expression".to_string(),
+                    })?
+                }
+                None => next,
+            };
+            let span = self.span_from(enumerator_start);
+            // enums are signed bytes, so anything past `i8` would silently wrap
+            if i8::try_from(value).is_err() {
+                return Err(ParseError::Invalid {
+                    span,
+                    msg: format!(
+                        "enumerator `{}` value {} does not fit in a signed byte",
+                        name.name, value
+                    ),
+                });
+            }
+            next = value + 1;
+            self.declare(&name, Binding::Constant(value))?;
+            let enumerators = &mut self.enums.last_mut().unwrap().enumerators;
+            enumerators.push(Enumerator { name, value, span });
+        }
+        let span = self.span_from(start);
+        let def = self.enums.last_mut().unwrap();
+        def.span = span;
+        Ok(def.clone())
+    }
+
+    /// The value of a constant expression, which may use the enumerators in scope and
+    /// `sizeof`.
+    fn eval(&self, expr: &Expr) -> Option<i64> {
+        consteval::eval_in(expr, self)
+    }
+
+    /// A declarator, the part of a declaration naming one thing and saying how its type derives
+    /// from the declaration's type: `*p`, `buf[16]`, `f(int a)`, or nested in parentheses, as
+    /// in `(*handler)(int)`. The name may only be omitted when `named` is false, as in a
+    /// parameter of a prototype. See `apply` for how the result is read.
+    fn parse_declarator(
+        &mut self,
+        named: bool,
+    ) -> Result<(Option<Ident>, Vec<Derived>), ParseError> {
+        let mut pointers = Vec::new();
+        while self.eat(Mul).is_some() {
+            pointers.push(Derived::Pointer(self.parse_quals()));
+        }
+        let (name, inner) = if self.at(LPar) && self.at_nth(1, Mul) {
+            self.bump();
+            let inner = self.parse_declarator(named)?;
+            self.expect(RPar, "`)`")?;
+            inner
+        } else if self.at(Var) || named {
+            (Some(self.parse_ident()?), Vec::new())
+        } else {
+            (None, Vec::new())
+        };
+        let mut suffixes = Vec::new();
+        loop {
+            if self.at(LBrk) && self.at_nth(1, RBrk) {
+                self.bump();
+                self.bump();
+                suffixes.push(Derived::Array(None));
+            } else if self.at(LBrk) {
+                suffixes.push(Derived::Array(Some(self.parse_array_len()?)));
+            } else if self.at(LPar) {
+                suffixes.push(Derived::Function(self.parse_params()?));
+            } else {
+                break;
+            }
+        }
+        let mut derived = pointers;
+        // `a[2][3]` is an array of 2 arrays of 3, so the last suffix applies first
+        derived.extend(suffixes.into_iter().rev());
+        derived.extend(inner);
+        Ok((name, derived))
+    }
+
+    /// `[len]`, where the length must be a positive constant expression.
+    fn parse_array_len(&mut self) -> Result<u64, ParseError> {
+        self.expect(LBrk, "`[`")?;
+        let len = self.parse_expr()?;
+        self.expect(RBrk, "`]`")?;
+        let msg = match self.eval(&len) {
+            Some(n) if n > 0 => return Ok(n as u64),
+            Some(_) => "array length must be positive",
+            None => "array length must be a constant expression",
+        };
+        Err(ParseError::Invalid {
+            span: len.span,
+            msg: msg.to_string(),
+        })
+    }
+
+    fn parse_ident(&mut self) -> Result<Ident, ParseError> {
+        let tok = self.expect(Var, "an identifier")?;
+        Ok(Ident {
+            name: tok.text().to_string(),
+            span: tok.span(),
+        })
+    }
+
+    /// A declaration of one or more variables or function prototypes sharing a type, such as
+    /// `int a, *b = 0, f(char);`, or a single function definition. Each declarator becomes its
+    /// own `Decl`, spanning just that declarator. A declarator whose type is a function, rather
+    /// than a pointer to one, declares a function.
+    fn parse_decl(&mut self) -> Result<Vec<Decl>, ParseError> {
+        // the storage class and qualifiers can come in any order before the type
+        let mut quals = self.parse_quals();
+        let mut class = None;
+        while let Some(keyword) = ["typedef", "static", "extern"]
+            .into_iter()
+            .find(|k| self.at_keyword(k))
+        {
+            let tok = self.bump();
+            if class.is_some() {
+                return Err(ParseError::Invalid {
+                    span: tok.span(),
+                    msg: "more than one storage class in a declaration".to_string(),
+                });
+            }
+            class = Some(keyword);
+            quals = quals | self.parse_quals();
+        }
+        let typedef = class == Some("typedef");
+        let storage = match class {
+            Some("static") => Some(Storage::Static),
+            Some("extern") => Some(Storage::Extern),
+            _ => None,
+        };
+        let local = self.depth > 1;
+        let mut decls = Vec::new();
+        let aggregate = self.at_keyword("struct") || self.at_keyword("union");
+        let defined = if aggregate && self.at_nth(2, LBrc) {
+            let def = self.parse_struct_def()?;
+            let tag = def.tag.name.clone();
+            let ty = if def.union {
+                Type::Union(tag)
+            } else {
+                Type::Struct(tag)
+            };
+            Some((ty, Decl::Struct(def)))
+        } else if self.at_keyword("enum") && (self.at_nth(1, LBrc) || self.at_nth(2, LBrc)) {
+            let def = self.parse_enum_def()?;
+            let ty = match &def.tag {
+                Some(tag) => Type::Enum(tag.name.clone()),
+                None => Type::Char,
+            };
+            Some((ty, Decl::Enum(def)))
+        } else {
+            None
+        };
+        let ty = match defined {
+            Some((ty, def)) => {
+                decls.push(def);
+                if self.eat(SCol).is_some() {
+                    return Ok(decls);
+                }
+                ty.qualified(self.parse_quals())
+            }
+            None => self.parse_type()?,
+        }
+        .qualified(quals);
+        loop {
+            let start = self.start();
+            let (name, mut derived) = self.parse_declarator(true)?;
+            let name = name.expect("a named declarator has a name");
+            if typedef {
+                check_sized(&derived, name.span)?;
+                let ty = apply(ty.clone(), derived);
+                self.declare(&name, Binding::Typedef(ty.clone()))?;
+                decls.push(Decl::Typedef(Typedef {
+                    ty,
+                    name,
+                    span: self.span_from(start),
+                }));
+            } else if let Some(Derived::Function(params)) = derived.pop_if(|d| d.is_function()) {
+                let ret = apply(ty.clone(), derived);
+                let func_ty = Type::Function {
+                    ret: Box::new(ret.clone()),
+                    params: params.iter().map(|p| p.ty.clone()).collect(),
+                };
+                self.declare(&name, Binding::Value(func_ty))?;
+                if local && storage == Some(Storage::Static) {
+                    return Err(ParseError::Invalid {
+                        span: name.span,
+                        msg: "a function declared in a block can't be `static`".to_string(),
+                    });
+                }
+                let first = decls.is_empty();
+                let func = self.parse_function(start, storage, ret, name, params, first)?;
+                let defined = func.body.is_some();
+                decls.push(Decl::Func(func));
+                if defined {
+                    return Ok(decls);
+                }
+            } else {
+                // `int a[] = {1, 2}` leaves the length to the initializer
+                let open = derived.pop_if(|d| matches!(d, Derived::Array(None)));
+                check_sized(&derived, name.span)?;
+                let ty = apply(ty.clone(), derived);
+                let init = match self.eat(As) {
+                    Some(tok) if storage == Some(Storage::Extern) => {
+                        return Err(ParseError::Invalid {
+                            span: tok.span(),
+                            msg: format!("`extern` variable `{}` can't be initialized", name.name),
+                        })
+                    }
+                    Some(_) => Some(self.parse_init()?),
+                    None => None,
+                };
+                let implied = init.as_ref().and_then(Init::implied_len);
+                let ty = match (open, implied) {
+                    (None, _) => ty,
+                    (Some(_), Some(len)) => Type::Array {
+                        elem: Box::new(ty),
+                        len,
+                    },
+                    (Some(_), None) => {
+                        return Err(ParseError::Invalid {
+                            span: name.span,
+                            msg: "array length missing".to_string(),
+                        })
+                    }
+                };
+                if ty.size(&self.structs).is_none() {
+                    return Err(ParseError::Invalid {
+                        span: name.span,
+                        msg: format!("`{}` has incomplete type `{}`", name.name, ty),
+                    });
+                }
+                if let Some(init) = &init {
+                    self.check_init(&ty, init)?;
+                    if storage == Some(Storage::Static) {
+                        self.check_static_init(&ty, init)?;
+                    }
+                }
+                self.declare(&name, Binding::Value(ty.clone()))?;
+                decls.push(Decl::Var(VarDecl {
+                    storage,
+                    ty,
+                    name,
+                    init,
+                    span: self.span_from(start),
+                }));
+            }
+            if self.eat(SCol).is_some() {
+                return Ok(decls);
+            }
+            if self.eat(Comma).is_none() {
+                let expected = match decls[..] {
+                    [Decl::Func(_)] => "a function body, `,` or `;`",
+                    _ => "`,` or `;`",
+                };
+                return Err(self.error(expected));
+            }
+        }
+    }
+
+    /// An initializer: an expression, or a braced list of initializers, which may end with a
+    /// comma.
+    fn parse_init(&mut self) -> Result<Init, ParseError> {
+        let start = self.start();
+        if self.eat(LBrc).is_none() {
+            return Ok(Init::Expr(self.parse_assign()?));
+        }
+        let mut items = Vec::new();
+        while self.eat(RBrc).is_none() {
+            if !items.is_empty() {
+                self.expect(Comma, "`,` or `}`")?;
+                if self.eat(RBrc).is_some() {
+                    break;
+                }
+            }
+            items.push(self.parse_init()?);
+        }
+        Ok(Init::List {
+            items,
+            span: self.span_from(start),
+        })
+    }
+
+    /// Checks that `init` fits an object of type `ty`. A braced list can have at most one item
+    /// per element or field, and only one for a union or a scalar. An array can only be
+    /// initialized by a list, or a `char` array by a string literal that fits, not counting
+    /// its terminating zero.
+    fn check_init(&self, ty: &Type, init: &Init) -> Result<(), ParseError> {
+        let items = match init {
+            Init::Expr(expr) => {
+                let msg = match (&expr.kind, ty) {
+                    (ExprKind::Str(bytes), Type::Array { elem, len })
+                        if matches!(elem.unqualified(), Type::Char | Type::UChar) =>
+                    {
+                        if bytes.len() as u64 <= *len {
+                            return Ok(());
+                        }
+                        format!("string literal is too long for `{}`", ty)
+                    }
+                    (_, Type::Array { .. }) => {
+                        format!("`{}` must be initialized with a braced list", ty)
+                    }
+                    _ => return Ok(()),
+                };
+                return Err(ParseError::Invalid {
+                    span: expr.span,
+                    msg,
+                });
+            }
+            Init::List { items, .. } => items,
+        };
+        let types: Vec<&Type> = match ty.unqualified() {
+            Type::Array { elem, len } => {
+                let len = usize::try_from(*len).unwrap_or(usize::MAX);
+                std::iter::repeat_n(&**elem, items.len().min(len)).collect()
+            }
+            Type::Struct(tag) | Type::Union(tag) => {
+                let def = self.structs.iter().find(|s| s.tag.name == *tag).unwrap();
+                let fields = def
+                    .fields
+                    .iter()
+                    .take(if def.union { 1 } else { usize::MAX });
+                fields.map(|f| &f.ty).collect()
+            }
+            ty => vec![ty],
+        };
+        if let Some(extra) = items.get(types.len()) {
+            return Err(ParseError::Invalid {
+                span: extra.span(),
+                msg: format!("too many initializers for `{}`", ty),
+            });
+        }
+        for (item, ty) in items.iter().zip(types) {
+            self.check_init(ty, item)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that `init` can be laid out in the data segment before the program runs, as
+    /// a `static` variable's must: each expression is a constant, a string literal, or an
+    /// address, which the linker fills in.
+    fn check_static_init(&self, ty: &Type, init: &Init) -> Result<(), ParseError> {
+        let data = init.data(ty, &self.structs).unwrap_or_default();
+        let address =
+            |expr: &Expr| matches!(expr.kind, ExprKind::Str(_) | ExprKind::Unary(UnOp::Addr, _));
+        match data
+            .iter()
+            .find(|(_, e)| self.eval(e).is_none() && !address(e))
+        {
+            Some((_, expr)) => Err(ParseError::Invalid {
+                span: expr.span,
+                msg: "a `static` variable's initializer must be a constant expression".to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// A function declarator, which is a definition when `first` in its declaration and
+    /// followed by a body. Labels are local to each function.
+    fn parse_function(
+        &mut self,
+        start: usize,
+        storage: Option<Storage>,
+        ret: Type,
+        name: Ident,
+        params: Vec<Param>,
+        first: bool,
+    ) -> Result<Function, ParseError> {
+        if !(first && self.at(LBrc)) {
+            return Ok(Function {
+                storage,
+                ret,
+                name,
+                params,
+                body: None,
+                span: self.span_from(start),
+            });
+        }
+        let invalid = |span, msg: &str| ParseError::Invalid {
+            span,
+            msg: msg.to_string(),
+        };
+        if self.depth > 1 {
+            let msg = "functions can only be defined at the top level";
+            return Err(invalid(name.span, msg));
+        }
+        if let Some(param) = params.iter().find(|p| p.name.is_none()) {
+            let msg = "parameter name omitted in a function definition";
+            return Err(invalid(param.span, msg));
+        }
+        let outer_labels = (
+            std::mem::take(&mut self.labels),
+            std::mem::take(&mut self.gotos),
+        );
+        self.ret = Some(ret.clone());
+        let mut param_scope = Vec::new();
+        for param in &params {
+            let binding = Binding::Value(param.ty.clone());
+            param_scope.push((param.name.clone().unwrap(), binding));
+        }
+        self.scopes.push(param_scope);
+        let body = self.parse_stmt();
+        self.scopes.pop();
+        self.ret = None;
+        let checked = body.and_then(|body| self.check_gotos().map(|()| body));
+        (self.labels, self.gotos) = outer_labels;
+        Ok(Function {
+            storage,
+            ret,
+            name,
+            params,
+            body: Some(Box::new(checked?)),
+            span: self.span_from(start),
+        })
+    }
+
+    /// A parenthesized parameter list, where `()` and `(void)` both declare no parameters.
+    /// Names are optional, as in a prototype.
+    fn parse_params(&mut self) -> Result<Vec<Param>, ParseError> {
+        self.expect(LPar, "`(`")?;
+        let mut params = Vec::new();
+        if self.at_keyword("void") && self.at_nth(1, RPar) {
+            self.bump();
+        }
+        while self.eat(RPar).is_none() {
+            if !params.is_empty() {
+                self.expect(Comma, "`,` or `)`")?;
+            }
+            let start = self.start();
+            let ty = self.parse_type()?;
+            let (name, derived) = self.parse_declarator(false)?;
+            params.push(Param {
+                ty: apply(ty, derived),
+                name,
+                span: self.span_from(start),
+            });
+        }
+        Ok(params)
+    }
+
+    /// A `return` statement, checked against the return type of the enclosing function.
+    fn parse_return(&mut self) -> Result<StmtKind, ParseError> {
+        let keyword = self.bump();
+        let invalid = |msg: String| ParseError::Invalid {
+            span: keyword.span(),
+            msg,
+        };
+        let Some(ret) = self.ret.clone() else {
+            return Err(invalid("`return` outside of a function".to_string()));
+        };
+        let value = if self.at(SCol) {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
+        self.expect(SCol, "`;`")?;
+        match (&value, *ret.unqualified() == Type::Void) {
+            (Some(_), true) => Err(invalid(
+                "`return` with a value in a function returning `void`".to_string(),
+            )),
+            (None, false) => Err(invalid(format!(
+                "`return` without a value in a function returning `{}`",
+                ret
+            ))),
+            _ => Ok(StmtKind::Return(value)),
+        }
+    }
+
+    /// A full expression, which may use the comma operator. Where commas separate things, as
+    /// call arguments and initializers do, each item is parsed with `parse_assign` instead.
+    pub fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let mut expr = self.parse_assign()?;
+        while self.eat(Comma).is_some() {
+            let rhs = self.parse_assign()?;
+            expr = Expr {
+                kind: ExprKind::Comma(Box::new(expr), Box::new(rhs)),
+                span: self.span_from(start),
+            };
+        }
+        Ok(expr)
+    }
+
+    /// Assignment is right associative: `a = b = c` assigns `c` to `b` first.
+    fn parse_assign(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let lhs = self.parse_conditional()?;
+        let op = match self.peek().map(|t| t.kind()) {
+            Some(As) => None,
+            Some(AddAs) => Some(BinOp::Add),
+            Some(SubAs) => Some(BinOp::Sub),
+            Some(MulAs) => Some(BinOp::Mul),
+            Some(DivAs) => Some(BinOp::Div),
+            Some(ModAs) => Some(BinOp::Mod),
+            Some(ShlAs) => Some(BinOp::Shl),
+            Some(ShrAs) => Some(BinOp::Shr),
+            Some(AndAs) => Some(BinOp::BitAnd),
+            Some(XorAs) => Some(BinOp::BitXor),
+            Some(OrAs) => Some(BinOp::BitOr),
+            _ => return Ok(lhs),
+        };
+        self.bump();
+        let rhs = self.parse_assign()?;
+        Ok(Expr {
+            kind: ExprKind::Assign(op, Box::new(lhs), Box::new(rhs)),
+            span: self.span_from(start),
+        })
+    }
+
+    /// `cond ? then : els`, binding looser than `||` and right associative, so that
+    /// `a ? b : c ? d : e` is `a ? b : (c ? d : e)`. The middle arm can be any expression.
+    fn parse_conditional(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let cond = self.parse_binary(1)?;
+        if self.eat(Quest).is_none() {
+            return Ok(cond);
+        }
+        let then = self.parse_expr()?;
+        self.expect(Colon, "`:`")?;
+        let els = self.parse_conditional()?;
+        Ok(Expr {
+            kind: ExprKind::Cond(Box::new(cond), Box::new(then), Box::new(els)),
+            span: self.span_from(start),
+        })
+    }
+
+    /// The binary operator the next token denotes, if any.
+    fn peek_binop(&self) -> Option<BinOp> {
+        let op = match self.peek()?.kind() {
+            Mul => BinOp::Mul,
+            Div => BinOp::Div,
+            Mod => BinOp::Mod,
+            Add => BinOp::Add,
+            Sub => BinOp::Sub,
+            Shl => BinOp::Shl,
+            Shr => BinOp::Shr,
+            Lt => BinOp::Lt,
+            Gt => BinOp::Gt,
+            Le => BinOp::Le,
+            Ge => BinOp::Ge,
+            Eq => BinOp::Eq,
+            Neq => BinOp::Ne,
+            BAnd => BinOp::BitAnd,
+            Xor => BinOp::BitXor,
+            BOr => BinOp::BitOr,
+            LAnd => BinOp::And,
+            LOr => BinOp::Or,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Parses binary operators of at least `min_prec` by precedence climbing: each operand
+    /// takes every operator that binds tighter than the one before it, so `a + b * c` groups
+    /// as `a + (b * c)` and `a - b - c` as `(a - b) - c`.
+    fn parse_binary(&mut self, min_prec: u8) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let op = match self.peek_binop() {
+                Some(op) if op.precedence() >= min_prec => op,
+                _ => return Ok(lhs),
+            };
+            self.bump();
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Expr {
+                kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+                span: self.span_from(start),
+            };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        if self.eat_keyword("sizeof") {
+            let kind = if self.at(LPar) && self.at_type_nth(1) {
+                self.bump();
+                let ty = self.parse_type_name()?;
+                self.expect(RPar, "`)`")?;
+                if ty.size(&self.structs).is_none() {
+                    return Err(ParseError::Invalid {
+                        span: self.span_from(start),
+                        msg: format!("`sizeof` applied to incomplete type `{}`", ty),
+                    });
+                }
+                ExprKind::Sizeof(ty)
+            } else {
+                ExprKind::SizeofExpr(Box::new(self.parse_unary()?))
+            };
+            return Ok(Expr {
+                kind,
+                span: self.span_from(start),
+            });
+        }
+        if self.at(LPar) && self.at_type_nth(1) {
+            self.bump();
+            let ty = self.parse_type_name()?;
+            self.expect(RPar, "`)`")?;
+            let operand = self.parse_unary()?;
+            return Ok(Expr {
+                kind: ExprKind::Cast(ty, Box::new(operand)),
+                span: self.span_from(start),
+            });
+        }
+        let op = match self.peek().map(|t| t.kind()) {
+            Some(Sub) => UnOp::Neg,
+            Some(Add) => UnOp::Plus,
+            Some(Not) => UnOp::Not,
+            Some(BNot) => UnOp::BitNot,
+            Some(Inc) => UnOp::PreInc,
+            Some(Dec) => UnOp::PreDec,
+            Some(Mul) => UnOp::Deref,
+            Some(BAnd) => UnOp::Addr,
+            _ => return self.parse_postfix(),
+        };
+        self.bump();
+        let operand = self.parse_unary()?;
+        Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(operand)),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.eat(LPar).is_some() {
+                let mut args = Vec::new();
+                while self.eat(RPar).is_none() {
+                    if !args.is_empty() {
+                        self.expect(Comma, "`,` or `)`")?;
+                    }
+                    args.push(self.parse_assign()?);
+                }
+                expr = Expr {
+                    kind: ExprKind::Call(Box::new(expr), args),
+                    span: self.span_from(start),
+                };
+                continue;
+            }
+            if self.at(Dot) || self.at(Arrow) {
+                let arrow = self.bump().kind() == Arrow;
+                let field = self.parse_ident()?;
+                let base = Box::new(expr);
+                expr = Expr {
+                    kind: if arrow {
+                        ExprKind::Arrow(base, field)
+                    } else {
+                        ExprKind::Member(base, field)
+                    },
+                    span: self.span_from(start),
+                };
+                continue;
+            }
+            if self.eat(LBrk).is_some() {
+                let index = self.parse_expr()?;
+                self.expect(RBrk, "`]`")?;
+                expr = Expr {
+                    kind: ExprKind::Index(Box::new(expr), Box::new(index)),
+                    span: self.span_from(start),
+                };
+                continue;
+            }
+            let op = match self.peek().map(|t| t.kind()) {
+                Some(Inc) => UnOp::PostInc,
+                Some(Dec) => UnOp::PostDec,
+                _ => return Ok(expr),
+            };
+            self.bump();
+            expr = Expr {
+                kind: ExprKind::Unary(op, Box::new(expr)),
+                span: self.span_from(start),
+            };
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.start();
+        let Some(tok) = self.peek().copied() else {
+            return Err(self.error("an expression"));
+        };
+        let kind = match tok.kind() {
+            IntLit | HexLit | OctLit | BinLit => ExprKind::Int(tok.int_value()?),
+            ChrLit => ExprKind::Char(tok.char_value()?),
+            StrLit => ExprKind::Str(tok.str_bytes()?),
+            Var => ExprKind::Var(tok.text().to_string()),
+            LPar => {
+                self.bump();
+                let inner = self.parse_expr()?;
+                self.expect(RPar, "`)`")?;
+                return Ok(Expr {
+                    kind: inner.kind,
+                    span: self.span_from(start),
+                });
+            }
+            _ => return Err(self.error("an expression")),
+        };
+        self.bump();
+        Ok(Expr {
+            kind,
+            span: tok.span(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renders an expression as an s-expression, to compare trees at a glance.
+    fn sexp(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Int(n) => n.to_string(),
+            ExprKind::Char(c) => format!("'{}'", *c as char),
+            ExprKind::Str(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+            ExprKind::Var(name) => name.clone(),
+            ExprKind::Unary(op, e) => format!("({:?} {})", op, sexp(e)),
+            ExprKind::Binary(op, l, r) => format!("({:?} {} {})", op, sexp(l), sexp(r)),
+            ExprKind::Assign(None, l, r) => format!("(= {} {})", sexp(l), sexp(r)),
+            ExprKind::Assign(Some(op), l, r) => format!("({:?}= {} {})", op, sexp(l), sexp(r)),
+            ExprKind::Cast(ty, e) => format!("(cast {} {})", ty, sexp(e)),
+            ExprKind::Sizeof(ty) => format!("(sizeof {})", ty),
+            ExprKind::SizeofExpr(e) => format!("(sizeof {})", sexp(e)),
+            ExprKind::Comma(l, r) => format!("(, {} {})", sexp(l), sexp(r)),
+            ExprKind::Cond(c, t, e) => format!("(? {} {} {})", sexp(c), sexp(t), sexp(e)),
+            ExprKind::Member(base, field) => format!("(. {} {})", sexp(base), field.name),
+            ExprKind::Arrow(base, field) => format!("(-> {} {})", sexp(base), field.name),
+            ExprKind::Index(array, index) => format!("(index {} {})", sexp(array), sexp(index)),
+            ExprKind::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(|a| format!(" {}", sexp(a))).collect();
+                format!("(call {}{})", sexp(callee), args.concat())
+            }
+        }
+    }
+
+    fn expr(src: &str) -> String {
+        sexp(&Parser::new(src).unwrap().parse_expr().unwrap())
+    }
+
+    #[test]
+    fn expr_test() {
+        assert_eq!(expr("a = b + 1 - c"), "(= a (Sub (Add b 1) c))");
+        assert_eq!(expr("a = b -= 'x'"), "(= a (Sub= b 'x'))");
+        assert_eq!(expr("-x++ + --y"), "(Add (Neg (PostInc x)) (PreDec y))");
+        assert_eq!(expr("a - (b - c)"), "(Sub a (Sub b c))");
+        assert_eq!(expr("s = \"ab\" \"c\""), "(= s \"abc\")");
+        assert_eq!(expr("0x10 + 0b1"), "(Add 16 1)");
+    }
+
+    #[test]
+    fn precedence_test() {
+        assert_eq!(expr("a = b + c * -d"), "(= a (Add b (Mul c (Neg d))))");
+        assert_eq!(expr("a - b - c"), "(Sub (Sub a b) c)");
+        assert_eq!(expr("a / b * c % d"), "(Mod (Mul (Div a b) c) d)");
+        assert_eq!(expr("a << 1 + 2"), "(Shl a (Add 1 2))");
+        assert_eq!(expr("a < b == c > d"), "(Eq (Lt a b) (Gt c d))");
+        assert_eq!(expr("a | b ^ c & d"), "(BitOr a (BitXor b (BitAnd c d)))");
+        assert_eq!(expr("a || b && c == d"), "(Or a (And b (Eq c d)))");
+        assert_eq!(expr("x *= y |= z <<= 2"), "(Mul= x (BitOr= y (Shl= z 2)))");
+        assert_eq!(
+            expr("!~-+x - -1"),
+            "(Sub (Not (BitNot (Neg (Plus x)))) (Neg 1))"
+        );
+        assert_eq!(expr("a-- - --b"), "(Sub (PostDec a) (PreDec b))");
+        assert_eq!(expr("(a + b) * c"), "(Mul (Add a b) c)");
+        assert_eq!(expr("a || b ? c : d && e"), "(? (Or a b) c (And d e))");
+        assert_eq!(expr("a ? b : c ? d : e"), "(? a b (? c d e))");
+        assert_eq!(expr("a ? b = 1 : c"), "(? a (= b 1) c)");
+        assert_eq!(expr("x = a ? b : c"), "(= x (? a b c))");
+        assert_eq!(expr("a ? b ? c : d : e"), "(? a (? b c d) e)");
+        assert_eq!(expr("a = 1, b, c += 2"), "(, (, (= a 1) b) (Add= c 2))");
+        assert_eq!(expr("f((a, b), c)"), "(call f (, a b) c)");
+        assert_eq!(expr("a ? b, c : d"), "(? a (, b c) d)");
+    }
+
+    #[test]
+    fn program_test() -> Result<(), ParseError> {
+        let src = "char c = 3;\nint x;\nx = c + 1; ;\n{ x++; }\n";
+        let program = parse(src)?;
+        assert_eq!(program.stmts.len(), 5);
+        let StmtKind::Decl(decls) = &program.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let [Decl::Var(c)] = &decls[..] else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(c.ty, Type::Char);
+        assert_eq!(c.name.name, "c");
+        assert_eq!(c.name.span.text(src), "c");
+        assert!(matches!(&c.init, Some(Init::Expr(e)) if sexp(e) == "3"));
+        assert_eq!(c.span.text(src), "c = 3");
+        assert_eq!(program.stmts[0].span.text(src), "char c = 3;");
+        let StmtKind::Expr(e) = &program.stmts[2].kind else {
+            panic!("expected an expression statement");
+        };
+        assert_eq!(e.span.text(src), "x = c + 1");
+        assert_eq!(program.stmts[2].span.text(src), "x = c + 1;");
+        assert_eq!(program.stmts[3].kind, StmtKind::Empty);
+        assert!(matches!(&program.stmts[4].kind, StmtKind::Block(b) if b.len() == 1));
+        assert_eq!(program.stmts[4].span.text(src), "{ x++; }");
+        Ok(())
+    }
+
+    #[test]
+    fn control_flow_test() -> Result<(), ParseError> {
+        let src = "if (a) if (b) x = 1; else { x = 2; }\nwhile (x < 10) x++;";
+        let program = parse(src)?;
+        let StmtKind::If { cond, then, els } = &program.stmts[0].kind else {
+            panic!("expected an if");
+        };
+        assert_eq!(cond.span.text(src), "a");
+        assert!(els.is_none());
+        let StmtKind::If { cond, then, els } = &then.kind else {
+            panic!("expected a nested if");
+        };
+        assert_eq!(cond.span.text(src), "b");
+        assert_eq!(then.span.text(src), "x = 1;");
+        assert_eq!(els.as_ref().unwrap().span.text(src), "{ x = 2; }");
+
+        let StmtKind::While { cond, body } = &program.stmts[1].kind else {
+            panic!("expected a while");
+        };
+        assert_eq!(sexp(cond), "(Lt x 10)");
+        assert_eq!(body.span.text(src), "x++;");
+        assert_eq!(program.stmts[1].span.text(src), "while (x < 10) x++;");
+
+        assert_eq!(
+            parse("if x) y;").unwrap_err().to_string(),
+            "expected `(` but found `x` at byte 3"
+        );
+        assert!(parse("while (1)").is_err());
+
+        let src = "do { x = status; } while (x == 0);";
+        let StmtKind::DoWhile { body, cond } = &parse(src)?.stmts[0].kind else {
+            panic!("expected a do-while");
+        };
+        assert_eq!(body.span.text(src), "{ x = status; }");
+        assert_eq!(sexp(cond), "(Eq x 0)");
+        assert_eq!(
+            parse("do x++; until (x);").unwrap_err().to_string(),
+            "expected `while` but found `until` at byte 8"
+        );
+        assert!(parse("do x++; while (x)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn for_test() -> Result<(), ParseError> {
+        let src =
+            "for (int i = 0; i < 8; i++) x += i; for (;;) ; for (i = 1, j = 9; ; i++, j--) {}";
+        let program = parse(src)?;
+        let StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } = &program.stmts[0].kind
+        else {
+            panic!("expected a for");
+        };
+        assert!(matches!(
+            init.as_deref().map(|s| &s.kind),
+            Some(StmtKind::Decl(_))
+        ));
+        assert_eq!(init.as_ref().unwrap().span.text(src), "int i = 0;");
+        assert_eq!(cond.as_ref().map(sexp).as_deref(), Some("(Lt i 8)"));
+        assert_eq!(step.as_ref().map(sexp).as_deref(), Some("(PostInc i)"));
+        assert_eq!(body.span.text(src), "x += i;");
+        assert!(matches!(
+            &program.stmts[1].kind,
+            StmtKind::For {
+                init: None,
+                cond: None,
+                step: None,
+                ..
+            }
+        ));
+        let StmtKind::For {
+            init: Some(init),
+            cond: None,
+            step: Some(step),
+            ..
+        } = &program.stmts[2].kind
+        else {
+            panic!("expected a for with an init and a step");
+        };
+        assert!(matches!(&init.kind, StmtKind::Expr(e) if sexp(e) == "(, (= i 1) (= j 9))"));
+        assert_eq!(sexp(step), "(, (PostInc i) (PostDec j))");
+        assert!(parse("for (i = 0; i < 1) x;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn switch_test() -> Result<(), ParseError> {
+        let src = "switch (x) { case 1: case 2: y = 1; default: y = 0; case 'a' + 1: ; }";
+        let program = parse(src)?;
+        let StmtKind::Switch { cond, body } = &program.stmts[0].kind else {
+            panic!("expected a switch");
+        };
+        assert_eq!(sexp(cond), "x");
+        let StmtKind::Block(stmts) = &body.kind else {
+            panic!("expected a block");
+        };
+        // `case 1:` labels `case 2:`, which labels the assignment
+        assert_eq!(stmts.len(), 3);
+        let StmtKind::Case { value, stmt } = &stmts[0].kind else {
+            panic!("expected a case");
+        };
+        assert_eq!(sexp(value), "1");
+        assert_eq!(stmt.span.text(src), "case 2: y = 1;");
+        assert!(matches!(&stmts[1].kind, StmtKind::Default(s) if s.span.text(src) == "y = 0;"));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("case 1: x;"),
+            "`case` label outside of a `switch` at byte 0"
+        );
+        assert_eq!(
+            err("switch (x) { case 1: x; } default: y;"),
+            "`default` label outside of a `switch` at byte 26"
+        );
+        assert_eq!(
+            err("switch (x) { case 3: case 1 + 2: ; }"),
+            "duplicate case value 3 (first used at byte 18) at byte 26"
+        );
+        assert_eq!(
+            err("switch (x) { default: ; default: ; }"),
+            "multiple `default` labels (the first at byte 13) at byte 24"
+        );
+        assert_eq!(
+            err("switch (x) { case y: ; }"),
+            "case value is not a constant expression at byte 18"
+        );
+        // labels belong to the innermost switch only
+        assert!(parse("switch (a) { case 1: switch (b) { case 1: ; } }").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn jump_test() -> Result<(), ParseError> {
+        let src = "while (1) { if (x) break; continue; } \
+                   switch (x) { case 1: break; } \
+                   for (;;) switch (x) { default: continue; } \
+                   do { switch (y) { case 0: while (z) break; } } while (1);";
+        let program = parse(src)?;
+        let StmtKind::While { body, .. } = &program.stmts[0].kind else {
+            panic!("expected a while");
+        };
+        let StmtKind::Block(stmts) = &body.kind else {
+            panic!("expected a block");
+        };
+        assert!(
+            matches!(&stmts[0].kind, StmtKind::If { then, .. } if then.kind == StmtKind::Break)
+        );
+        assert_eq!(stmts[1].kind, StmtKind::Continue);
+        assert_eq!(stmts[1].span.text(src), "continue;");
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("break;"),
+            "`break` outside of a loop or `switch` at byte 0"
+        );
+        assert_eq!(
+            err("switch (x) { case 1: continue; }"),
+            "`continue` outside of a loop at byte 21"
+        );
+        assert_eq!(
+            err("while (1) ; continue;"),
+            "`continue` outside of a loop at byte 12"
+        );
+        assert!(parse("while (1) break").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn goto_test() -> Result<(), ParseError> {
+        let src = "goto done; again: x++; if (x < 3) goto again; done: ;";
+        let program = parse(src)?;
+        assert!(matches!(&program.stmts[0].kind, StmtKind::Goto(l) if l.name == "done"));
+        let StmtKind::Label { label, stmt } = &program.stmts[1].kind else {
+            panic!("expected a label");
+        };
+        assert_eq!(label.span.text(src), "again");
+        assert_eq!(stmt.span.text(src), "x++;");
+        assert_eq!(program.stmts[1].span.text(src), "again: x++;");
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("goto nowhere;"),
+            "label `nowhere` is not defined at byte 5"
+        );
+        assert_eq!(
+            err("a: ; { a: ; }"),
+            "duplicate label `a` (first defined at byte 0) at byte 7"
+        );
+        // an identifier only starts a label when a colon follows
+        assert!(matches!(&parse("a;")?.stmts[0].kind, StmtKind::Expr(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn function_test() -> Result<(), ParseError> {
+        let src = "int x;\nint add(void) { x += 1; return x; }\nvoid f() { return; }";
+        let program = parse(src)?;
+        let StmtKind::Decl(decls) = &program.stmts[1].kind else {
+            panic!("expected a declaration");
+        };
+        let [Decl::Func(add)] = &decls[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(add.ret, Type::Int);
+        assert_eq!(add.name.name, "add");
+        assert!(add.params.is_empty());
+        let add_body = add.body.as_ref().unwrap();
+        assert_eq!(add_body.span.text(src), "{ x += 1; return x; }");
+        assert_eq!(add.span.text(src), "add(void) { x += 1; return x; }");
+        assert_eq!(
+            program.stmts[1].span.text(src),
+            "int add(void) { x += 1; return x; }"
+        );
+        let StmtKind::Block(body) = &add_body.kind else {
+            panic!("expected a block");
+        };
+        assert!(matches!(&body[1].kind, StmtKind::Return(Some(e)) if sexp(e) == "x"));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(err("return 1;"), "`return` outside of a function at byte 0");
+        assert_eq!(
+            err("void f() { return 1; }"),
+            "`return` with a value in a function returning `void` at byte 11"
+        );
+        assert_eq!(
+            err("char f() { return; }"),
+            "`return` without a value in a function returning `char` at byte 11"
+        );
+        assert_eq!(
+            err("int f() { int g() {} }"),
+            "functions can only be defined at the top level at byte 14"
+        );
+        assert_eq!(
+            err("while (1) { int g() {} }"),
+            "functions can only be defined at the top level at byte 16"
+        );
+        assert_eq!(
+            err("int f() return;"),
+            "expected a function body, `,` or `;` but found `return` at byte 8"
+        );
+        // labels are per function
+        assert!(parse("void f() { a: ; } void g() { a: ; }").is_ok());
+        assert_eq!(
+            err("void f() { a: ; } void g() { goto a; }"),
+            "label `a` is not defined at byte 34"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn declarators_test() -> Result<(), ParseError> {
+        let src = "int a, b = 3, c;\nchar f(int), d = f(1) + 2, g();\nfor (int i = 0, j = i; ; ) ;";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let spans: Vec<&str> = decls(0).iter().map(|d| d.span().text(src)).collect();
+        assert_eq!(spans, ["a", "b = 3", "c"]);
+        assert_eq!(program.stmts[0].span.text(src), "int a, b = 3, c;");
+        let [Decl::Func(f), Decl::Var(d), Decl::Func(g)] = &decls(1)[..] else {
+            panic!("expected a function, a variable and a function");
+        };
+        assert_eq!((f.arity(), &f.ret), (1, &Type::Char));
+        assert_eq!(d.ty, Type::Char);
+        assert!(matches!(&d.init, Some(Init::Expr(e)) if sexp(e) == "(Add (call f 1) 2)"));
+        assert_eq!(g.span.text(src), "g()");
+        let StmtKind::For { init, .. } = &program.stmts[2].kind else {
+            panic!("expected a for");
+        };
+        assert!(matches!(&init.as_ref().unwrap().kind, StmtKind::Decl(d) if d.len() == 2));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("int a b;"),
+            "expected `,` or `;` but found `b` at byte 6"
+        );
+        assert_eq!(
+            err("int a, ;"),
+            "expected an identifier but found `;` at byte 7"
+        );
+        assert_eq!(
+            err("int a, f() {}"),
+            "expected `,` or `;` but found `{` at byte 11"
+        );
+        assert_eq!(
+            err("for (int i, f(); ; ) ;"),
+            "only variables can be declared in a `for` initializer at byte 12"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn array_test() -> Result<(), ParseError> {
+        let src = "char buf[16], c;\nint xs[2 * 4 + 1];\nbuf[i] = xs[buf[0]] + 1;";
+        let program = parse(src)?;
+        let var = |i: usize, j: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => match &decls[j] {
+                Decl::Var(var) => var,
+                _ => panic!("expected a variable"),
+            },
+            _ => panic!("expected a declaration"),
+        };
+        let char_16 = Type::Array {
+            elem: Box::new(Type::Char),
+            len: 16,
+        };
+        assert_eq!(var(0, 0).ty, char_16);
+        assert_eq!(var(0, 0).span.text(src), "buf[16]");
+        assert_eq!(var(0, 1).ty, Type::Char);
+        assert_eq!(var(1, 0).ty.to_string(), "int[9]");
+        let StmtKind::Expr(e) = &program.stmts[2].kind else {
+            panic!("expected an expression statement");
+        };
+        assert_eq!(
+            sexp(e),
+            "(= (index buf i) (Add (index xs (index buf 0)) 1))"
+        );
+        assert_eq!(expr("f(x)[1]++"), "(PostInc (index (call f x) 1))");
+        assert_eq!(expr("grid[y][x + 1]"), "(index (index grid y) (Add x 1))");
+
+        let grid = parse("char grid[8][4];")?;
+        let StmtKind::Decl(decls) = &grid.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let [Decl::Var(grid)] = &decls[..] else {
+            panic!("expected a variable");
+        };
+        assert_eq!(grid.ty.to_string(), "char[8][4]");
+        let Type::Array { elem: row, len: 8 } = &grid.ty else {
+            panic!("expected an array of 8 rows");
+        };
+        assert_eq!(row.to_string(), "char[4]");
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("int a[n];"),
+            "array length must be a constant expression at byte 6"
+        );
+        assert_eq!(
+            err("int a[1 - 1];"),
+            "array length must be positive at byte 6"
+        );
+        assert_eq!(err("int a[2;"), "expected `]` but found `;` at byte 7");
+        assert_eq!(
+            err("int a[2][0];"),
+            "array length must be positive at byte 9"
+        );
+        assert_eq!(err("x = a[1;"), "expected `]` but found `;` at byte 7");
+        Ok(())
+    }
+
+    #[test]
+    fn pointer_test() -> Result<(), ParseError> {
+        let src = "char *p, c, **pp, *names[4];\nint *find(char *s, int);";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let types: Vec<String> = decls(0)
+            .iter()
+            .map(|d| match d {
+                Decl::Var(var) => var.ty.to_string(),
+                _ => panic!("expected a variable"),
+            })
+            .collect();
+        assert_eq!(types, ["char*", "char", "char**", "char*[4]"]);
+        assert_eq!(decls(0)[2].span().text(src), "**pp");
+        let [Decl::Func(find)] = &decls(1)[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(find.ret, Type::Pointer(Box::new(Type::Int)));
+        assert_eq!(find.params[0].ty.to_string(), "char*");
+        assert_eq!(find.params[0].span.text(src), "char *s");
+
+        assert_eq!(
+            expr("*p++ = *q + 1"),
+            "(= (Deref (PostInc p)) (Add (Deref q) 1))"
+        );
+        assert_eq!(
+            expr("&a[3] - &a[0]"),
+            "(Sub (Addr (index a 3)) (Addr (index a 0)))"
+        );
+        assert_eq!(expr("**pp * 2"), "(Mul (Deref (Deref pp)) 2)");
+        assert_eq!(expr("a & *b"), "(BitAnd a (Deref b))");
+        Ok(())
+    }
+
+    #[test]
+    fn function_pointer_test() -> Result<(), ParseError> {
+        let src = "void (*handler)(), (*table[4])(char *, int);\nint (*pick(int n))(void);\nvoid run(void (*cb)(int)) { handler(); (*table[1])(s, 2); cb(3); }";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let [Decl::Var(handler), Decl::Var(table)] = &decls(0)[..] else {
+            panic!("expected two variables");
+        };
+        assert_eq!(handler.ty.to_string(), "void(*)()");
+        assert_eq!(
+            handler.ty,
+            Type::Pointer(Box::new(Type::Function {
+                ret: Box::new(Type::Void),
+                params: Vec::new(),
+            }))
+        );
+        assert_eq!(handler.span.text(src), "(*handler)()");
+        assert_eq!(table.ty.to_string(), "void(*[4])(char*, int)");
+        let [Decl::Func(pick)] = &decls(1)[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(pick.name.name, "pick");
+        assert_eq!(pick.arity(), 1);
+        assert_eq!(pick.ret.to_string(), "int(*)()");
+        let [Decl::Func(run)] = &decls(2)[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(run.params[0].ty.to_string(), "void(*)(int)");
+        assert_eq!(run.params[0].name.as_ref().unwrap().name, "cb");
+        let StmtKind::Block(body) = &run.body.as_ref().unwrap().kind else {
+            panic!("expected a block");
+        };
+        let calls: Vec<String> = body
+            .iter()
+            .map(|s| match &s.kind {
+                StmtKind::Expr(e) => sexp(e),
+                _ => panic!("expected an expression statement"),
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                "(call handler)",
+                "(call (Deref (index table 1)) s 2)",
+                "(call cb 3)"
+            ]
+        );
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(err("void (*h();"), "expected `)` but found `;` at byte 10");
+        assert_eq!(
+            err("int (*)();"),
+            "expected an identifier but found `)` at byte 6"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn struct_test() -> Result<(), ParseError> {
+        let src = "struct point { char x, y; };\nstruct node { int value; struct point at[2]; struct node *next; } head, *tail;\nstruct point p;\ntail->next->at[1].x = p.y;";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let [Decl::Struct(point)] = &decls(0)[..] else {
+            panic!("expected a struct");
+        };
+        assert_eq!(point.span.text(src), "struct point { char x, y; }");
+        assert_eq!(point.size, 2);
+        assert_eq!(
+            point.field("y").map(|f| (f.offset, f.span.text(src))),
+            Some((1, "y"))
+        );
+        let [Decl::Struct(node), Decl::Var(head), Decl::Var(tail)] = &decls(1)[..] else {
+            panic!("expected a struct and two variables");
+        };
+        let fields: Vec<(&str, String, u64)> = node
+            .fields
+            .iter()
+            .map(|f| (f.name.name.as_str(), f.ty.to_string(), f.offset))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("value", "int".to_string(), 0),
+                ("at", "struct point[2]".to_string(), 2),
+                ("next", "struct node*".to_string(), 6),
+            ]
+        );
+        assert_eq!(node.size, 8);
+        assert_eq!(head.ty, Type::Struct("node".to_string()));
+        assert_eq!(tail.ty.to_string(), "struct node*");
+        let StmtKind::Expr(e) = &program.stmts[3].kind else {
+            panic!("expected an expression statement");
+        };
+        assert_eq!(
+            sexp(e),
+            "(= (. (index (-> (-> tail next) at) 1) x) (. p y))"
+        );
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("struct s { struct s inner; };"),
+            "field `inner` has incomplete type `struct s` at byte 20"
+        );
+        assert_eq!(
+            err("struct s { int a; char a; };"),
+            "duplicate field `a` (first declared at byte 15) at byte 23"
+        );
+        assert_eq!(
+            err("struct s { int a; }; struct s { int b; };"),
+            "redefinition of `struct s` (first defined at byte 0) at byte 28"
+        );
+        assert_eq!(
+            err("struct later v;"),
+            "`v` has incomplete type `struct later` at byte 13"
+        );
+        assert_eq!(err("void v;"), "`v` has incomplete type `void` at byte 5");
+        assert_eq!(err("p.;"), "expected an identifier but found `;` at byte 2");
+        assert!(parse("struct later *p;").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn union_test() -> Result<(), ParseError> {
+        let src = "struct pair { char lo, hi; };\nunion word { int value; char bytes[2]; struct pair half; } w;\nw.bytes[1] = w.half.lo;";
+        let program = parse(src)?;
+        let StmtKind::Decl(decls) = &program.stmts[1].kind else {
+            panic!("expected a declaration");
+        };
+        let [Decl::Struct(word), Decl::Var(w)] = &decls[..] else {
+            panic!("expected a union and a variable");
+        };
+        assert!(word.union);
+        assert_eq!(word.size, 2);
+        assert!(word.fields.iter().all(|f| f.offset == 0));
+        assert_eq!(word.field("half").unwrap().ty.to_string(), "struct pair");
+        assert_eq!(w.ty.to_string(), "union word");
+        let structs = [word.clone()];
+        assert_eq!(w.ty.size(&structs), Some(2));
+        assert_eq!(Type::Struct("word".to_string()).size(&structs), None);
+        let StmtKind::Expr(e) = &program.stmts[2].kind else {
+            panic!("expected an expression statement");
+        };
+        assert_eq!(sexp(e), "(= (index (. w bytes) 1) (. (. w half) lo))");
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("struct s { int a; }; union s { int b; };"),
+            "redefinition of `union s` (first defined at byte 0) at byte 27"
+        );
+        assert_eq!(
+            err("union u { int a; }; struct u x;"),
+            "`x` has incomplete type `struct u` at byte 29"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn enum_test() -> Result<(), ParseError> {
+        let src = "enum color { RED, GREEN = 5, BLUE, LAST = BLUE * 2, } c;\nenum { SIZE = 4 };\nchar buf[SIZE + 1];\nenum color d;\nswitch (c) { case RED: case BLUE: ; }";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let [Decl::Enum(color), Decl::Var(c)] = &decls(0)[..] else {
+            panic!("expected an enum and a variable");
+        };
+        let values: Vec<(&str, i64)> = color
+            .enumerators
+            .iter()
+            .map(|e| (e.name.name.as_str(), e.value))
+            .collect();
+        assert_eq!(
+            values,
+            [("RED", 0), ("GREEN", 5), ("BLUE", 6), ("LAST", 12)]
+        );
+        assert_eq!(color.enumerators[1].span.text(src), "GREEN = 5");
+        assert_eq!(
+            color.span.text(src),
+            "enum color { RED, GREEN = 5, BLUE, LAST = BLUE * 2, }"
+        );
+        assert_eq!(c.ty.to_string(), "enum color");
+        assert!(matches!(&decls(1)[..], [Decl::Enum(e)] if e.tag.is_none()));
+        let [Decl::Var(buf)] = &decls(2)[..] else {
+            panic!("expected a variable");
+        };
+        assert_eq!(buf.ty.to_string(), "char[5]");
+        assert!(matches!(&decls(3)[..], [Decl::Var(d)] if d.ty.size(&[]) == Some(1)));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("enum big { A = 100, B = 127, C };"),
+            "enumerator `C` value 128 does not fit in a signed byte at byte 29"
+        );
+        assert_eq!(
+            err("enum big { A = 200 };"),
+            "enumerator `A` value 200 does not fit in a signed byte at byte 11"
+        );
+        assert_eq!(
+            err("enum { A = -129 };"),
+            "enumerator `A` value -129 does not fit in a signed byte at byte 7"
+        );
+        assert!(parse("enum { A = -128, B = 127 };").is_ok());
+        assert_eq!(
+            err("enum a { X }; enum b { Y, X };"),
+            "duplicate enumerator `X` (first declared at byte 9) at byte 26"
+        );
+        assert_eq!(
+            err("struct s { int a; }; enum s { X };"),
+            "redefinition of `enum s` (first defined at byte 0) at byte 26"
+        );
+        assert_eq!(
+            err("enum { A = x };"),
+            "enumerator value is not a constant expression at byte 11"
+        );
+        assert_eq!(
+            err("enum { A B };"),
+            "expected `,` or `}` but found `B` at byte 9"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn typedef_test() -> Result<(), ParseError> {
+        let src = "typedef int T, *P, (*F)(T);\ntypedef struct pt { T x; } Point;\nT * x;\na * b;\nPoint origin;\nvoid f(T n, F g) { P p; { int T; T * p; } T * q; }";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        let types: Vec<String> = decls(0)
+            .iter()
+            .map(|d| match d {
+                Decl::Typedef(def) => format!("{} {}", def.name.name, def.ty),
+                _ => panic!("expected a typedef"),
+            })
+            .collect();
+        assert_eq!(types, ["T int", "P int*", "F int(*)(int)"]);
+        assert!(
+            matches!(&decls(1)[..], [Decl::Struct(_), Decl::Typedef(t)] if t.name.name == "Point")
+        );
+        assert!(matches!(&decls(2)[..], [Decl::Var(x)] if x.ty.to_string() == "int*"));
+        assert!(matches!(&program.stmts[3].kind, StmtKind::Expr(e) if sexp(e) == "(Mul a b)"));
+        assert!(matches!(&decls(4)[..], [Decl::Var(o)] if o.ty == Type::Struct("pt".to_string())));
+        let [Decl::Func(f)] = &decls(5)[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(f.params[1].ty.to_string(), "int(*)(int)");
+        let StmtKind::Block(body) = &f.body.as_ref().unwrap().kind else {
+            panic!("expected a block");
+        };
+        assert!(matches!(&body[0].kind, StmtKind::Decl(_)));
+        // the inner `int T` shadows the typedef, so `T * p` is a multiplication there
+        let StmtKind::Block(inner) = &body[1].kind else {
+            panic!("expected a block");
+        };
+        assert!(matches!(&inner[1].kind, StmtKind::Expr(e) if sexp(e) == "(Mul T p)"));
+        assert!(matches!(&body[2].kind, StmtKind::Decl(_)));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("int T; typedef char T;"),
+            "`T` is already declared in this scope (first at byte 4) at byte 20"
+        );
+        assert_eq!(
+            err("typedef int T; int T;"),
+            "`T` is already declared in this scope (first at byte 12) at byte 19"
+        );
+        assert_eq!(
+            err("typedef int T = 1;"),
+            "expected `,` or `;` but found `=` at byte 14"
+        );
+        assert_eq!(
+            err("for (typedef int T; ; ) ;"),
+            "only variables can be declared in a `for` initializer at byte 17"
+        );
+        assert!(parse("typedef int T; { typedef char T; T c; } T i;").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn qualifier_test() -> Result<(), ParseError> {
+        let src = "const char *msg, *const p = 0, * volatile const *q;\nchar const table[2] = {1, 2};\nvolatile struct port { char data; } *io;\ntypedef const int C; volatile C n; int f(const int x, char *const s);\nconst struct port r = {1};";
+        let program = parse(src)?;
+        let types: Vec<String> = program
+            .stmts
+            .iter()
+            .flat_map(|s| match &s.kind {
+                StmtKind::Decl(decls) => decls.as_slice(),
+                _ => &[],
+            })
+            .map(|d| match d {
+                Decl::Var(var) => var.ty.to_string(),
+                Decl::Func(func) => {
+                    let params: Vec<String> =
+                        func.params.iter().map(|p| p.ty.to_string()).collect();
+                    format!("{}({})", func.ret, params.join(", "))
+                }
+                Decl::Typedef(def) => def.ty.to_string(),
+                Decl::Struct(def) => format!("struct {}", def.tag.name),
+                Decl::Enum(_) => panic!("expected no enums"),
+            })
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "const char*",
+                "const char*const",
+                "const char*const volatile*",
+                "const char[2]",
+                "struct port",
+                "volatile struct port*",
+                "const int",
+                "const volatile int",
+                "int(const int, char*const)",
+                "const struct port",
+            ]
+        );
+        assert!(parse("int * const const volatile p;").is_ok());
+        assert!(parse("int f(const int x) { return x; } int (*g)(int) = f;").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn storage_test() -> Result<(), ParseError> {
+        let src = "extern int count, lookup(char);\nstatic char buf[4] = {1};\nconst static char *name = \"hex8\";\nint lookup(char c) { static int calls = 1 + 1; extern char buf[4]; calls++; return c; }\nstatic void helper(void);\nint count;";
+        let program = parse(src)?;
+        let decls = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => decls,
+            _ => panic!("expected a declaration"),
+        };
+        assert!(matches!(&decls(0)[..], [Decl::Var(c), Decl::Func(l)]
+            if c.storage == Some(Storage::Extern) && l.storage == Some(Storage::Extern)));
+        assert!(matches!(&decls(2)[..], [Decl::Var(n)]
+            if n.storage == Some(Storage::Static) && n.ty.to_string() == "const char*"));
+        let [Decl::Func(lookup)] = &decls(3)[..] else {
+            panic!("expected a function");
+        };
+        assert_eq!(lookup.storage, None);
+        let StmtKind::Block(body) = &lookup.body.as_ref().unwrap().kind else {
+            panic!("expected a block");
+        };
+        assert!(matches!(&body[0].kind, StmtKind::Decl(d)
+            if matches!(&d[..], [Decl::Var(v)] if v.storage == Some(Storage::Static))));
+        let externals: Vec<(&str, bool)> = program
+            .externals()
+            .iter()
+            .map(|e| (e.name.name.as_str(), e.defined))
+            .collect();
+        assert_eq!(externals, [("count", true), ("lookup", true)]);
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("static extern int x;"),
+            "more than one storage class in a declaration at byte 7"
+        );
+        assert_eq!(
+            err("extern int x = 1;"),
+            "`extern` variable `x` can't be initialized at byte 13"
+        );
+        assert_eq!(
+            err("void f() { static int g(); }"),
+            "a function declared in a block can't be `static` at byte 22"
+        );
+        assert_eq!(
+            err("int f(); void g() { static int x = f(); }"),
+            "a `static` variable's initializer must be a constant expression at byte 35"
+        );
+        assert_eq!(
+            err("for (static int i = 0; ; ) ;"),
+            "a `for` initializer can't declare `static` or `extern` variables at byte 16"
+        );
+        assert!(parse("int x; static int *p = &x; typedef const int T;").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn integer_type_test() -> Result<(), ParseError> {
+        // the type of each variable declared on its own
+        let types = |src| -> Result<Vec<String>, ParseError> {
+            let types = parse(src)?.stmts.into_iter().map(|s| match &s.kind {
+                StmtKind::Decl(d) => match &d[..] {
+                    [Decl::Var(var)] => var.ty.to_string(),
+                    _ => panic!("expected a variable"),
+                },
+                _ => panic!("expected a declaration"),
+            });
+            Ok(types.collect())
+        };
+        let src = "unsigned char b; unsigned u; long int l; unsigned long n; int unsigned i;\nsigned char s; long unsigned int m; signed x; const unsigned char msg[] = \"ok\";";
+        assert_eq!(
+            types(src)?,
+            [
+                "unsigned char",
+                "unsigned int",
+                "long",
+                "unsigned long",
+                "unsigned int",
+                "char",
+                "unsigned long",
+                "int",
+                "const unsigned char[3]",
+            ]
+        );
+        assert_eq!(expr("(unsigned long)x"), "(cast unsigned long x)");
+        // comparisons and logical operators give a one-byte `bool`
+        let src = "_Bool done; int a; char s[sizeof(a < 2) + sizeof !a + sizeof(bool)];";
+        assert_eq!(types(src)?, ["bool", "int", "char[3]"]);
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("unsigned signed x;"),
+            "invalid combination of type specifiers at byte 0"
+        );
+        assert_eq!(
+            err("long long x;"),
+            "invalid combination of type specifiers at byte 0"
+        );
+        assert_eq!(
+            err("char int x;"),
+            "invalid combination of type specifiers at byte 0"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cast_test() -> Result<(), ParseError> {
+        assert_eq!(expr("(char)x + 1"), "(Add (cast char x) 1)");
+        assert_eq!(expr("(int *)p"), "(cast int* p)");
+        assert_eq!(
+            expr("(char)(int)-x++"),
+            "(cast char (cast int (Neg (PostInc x))))"
+        );
+        assert_eq!(expr("(x)-1"), "(Sub x 1)");
+        assert_eq!(expr("(void (*)(int))f"), "(cast void(*)(int) f)");
+
+        let src = "typedef char byte; int x; byte b[(byte)300]; void f() { (byte)x; (x)*2; }";
+        let program = parse(src)?;
+        let StmtKind::Decl(decls) = &program.stmts[2].kind else {
+            panic!("expected a declaration");
+        };
+        assert!(matches!(&decls[..], [Decl::Var(b)] if b.ty.to_string() == "char[44]"));
+        assert_eq!(
+            parse("int a[(char)200];").unwrap_err().to_string(),
+            "array length must be positive at byte 6"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sizeof_test() -> Result<(), ParseError> {
+        assert_eq!(
+            expr("sizeof(char *) + sizeof x * 2"),
+            "(Add (sizeof char*) (Mul (sizeof x) 2))"
+        );
+        assert_eq!(expr("sizeof (x) + 1"), "(Add (sizeof x) 1)");
+        assert_eq!(expr("sizeof -a[1]"), "(sizeof (Neg (index a 1)))");
+
+        let src =
+            "struct p { char x; int y[3]; } s, *ps;\nchar buf[sizeof(struct p) + sizeof buf2];";
+        assert_eq!(
+            parse(src).unwrap_err().to_string(),
+            "array length must be a constant expression at byte 48"
+        );
+        let src = "struct p { char x; int y[3]; } s, *ps;\nint buf2[4];\nenum { N = 3 };\nchar a[sizeof(struct p) + sizeof buf2], b[sizeof s.y + sizeof ps->x], c[sizeof *ps], d[sizeof \"hi\" + sizeof N];";
+        let program = parse(src)?;
+        let StmtKind::Decl(decls) = &program.stmts[3].kind else {
+            panic!("expected a declaration");
+        };
+        let types: Vec<String> = decls
+            .iter()
+            .map(|d| match d {
+                Decl::Var(var) => var.ty.to_string(),
+                _ => panic!("expected a variable"),
+            })
+            .collect();
+        assert_eq!(types, ["char[15]", "char[7]", "char[7]", "char[5]"]);
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("int n = sizeof(struct q);"),
+            "`sizeof` applied to incomplete type `struct q` at byte 8"
+        );
+        assert_eq!(
+            err("int n = sizeof(int x);"),
+            "expected `)` but found `x` at byte 19"
+        );
+        // a local variable shadows an enumerator
+        assert_eq!(
+            err("enum { N = 1 }; void f() { int N; char a[N]; }"),
+            "array length must be a constant expression at byte 41"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn init_test() -> Result<(), ParseError> {
+        let src = "char tab[4] = {1, 2, 3, 4}, s[] = \"hi\", t[] = {5, 6,}, u[8] = {7};\nstruct p { char x; int y[2]; } origin = {0, {1}}, ps[] = {{1}, {2, {3, 4}}};\nint n = {3};";
+        let program = parse(src)?;
+        let vars = |i: usize| -> Vec<&VarDecl> {
+            let StmtKind::Decl(decls) = &program.stmts[i].kind else {
+                panic!("expected a declaration");
+            };
+            decls
+                .iter()
+                .filter_map(|d| match d {
+                    Decl::Var(var) => Some(var),
+                    _ => None,
+                })
+                .collect()
+        };
+        let types: Vec<String> = vars(0).iter().map(|v| v.ty.to_string()).collect();
+        assert_eq!(types, ["char[4]", "char[3]", "char[2]", "char[8]"]);
+        assert_eq!(
+            vars(0)[2].init.as_ref().unwrap().span().text(src),
+            "{5, 6,}"
+        );
+        let [origin, ps] = &vars(1)[..] else {
+            panic!("expected two variables");
+        };
+        assert_eq!(ps.ty.to_string(), "struct p[2]");
+        let StmtKind::Decl(decls) = &program.stmts[1].kind else {
+            panic!("expected a declaration");
+        };
+        let Decl::Struct(p) = &decls[0] else {
+            panic!("expected a struct");
+        };
+        let structs = [p.clone()];
+        let data = |var: &VarDecl| -> Vec<(u64, String)> {
+            let init = var.init.as_ref().unwrap();
+            let data = init.data(&var.ty, &structs).unwrap();
+            data.into_iter().map(|(at, e)| (at, sexp(e))).collect()
+        };
+        let pairs = |pairs: &[(u64, &str)]| -> Vec<(u64, String)> {
+            pairs.iter().map(|&(at, e)| (at, e.to_string())).collect()
+        };
+        assert_eq!(data(origin), pairs(&[(0, "0"), (1, "1")]));
+        assert_eq!(data(ps), pairs(&[(0, "1"), (5, "2"), (6, "3"), (8, "4")]));
+        assert_eq!(data(vars(0)[1]), pairs(&[(0, "\"hi\"")]));
+        assert_eq!(data(vars(2)[0]), pairs(&[(0, "3")]));
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("char a[2] = {1, 2, 3};"),
+            "too many initializers for `char[2]` at byte 19"
+        );
+        assert_eq!(
+            err("char a[2] = \"abc\";"),
+            "string literal is too long for `char[2]` at byte 12"
+        );
+        assert_eq!(
+            err("int a[2] = 5;"),
+            "`int[2]` must be initialized with a braced list at byte 11"
+        );
+        assert_eq!(
+            err("union u { char c; int i; } v = {1, 2};"),
+            "too many initializers for `union u` at byte 35"
+        );
+        assert_eq!(err("int a[2][] = {};"), "array length missing at byte 4");
+        assert_eq!(err("int a[];"), "array length missing at byte 4");
+        assert_eq!(
+            err("int n = {1, 2};"),
+            "too many initializers for `int` at byte 12"
+        );
+        assert!(parse("char s[2] = \"hi\"; int f(char s[]);").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn call_test() -> Result<(), ParseError> {
+        let src =
+            "int max(int a, int b);\nint max(int a, int b) { return a; }\nvoid g(char, int n);";
+        let program = parse(src)?;
+        let func = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Decl(decls) => match &decls[..] {
+                [Decl::Func(f)] => f,
+                _ => panic!("expected a function"),
+            },
+            _ => panic!("expected a declaration"),
+        };
+        assert!(func(0).body.is_none());
+        assert_eq!(func(0).span.text(src), "max(int a, int b)");
+        assert_eq!(func(1).arity(), 2);
+        assert_eq!(func(1).params[1].span.text(src), "int b");
+        assert_eq!(func(1).params[1].name.as_ref().unwrap().name, "b");
+        assert_eq!(func(2).params[0].ty, Type::Char);
+        assert!(func(2).params[0].name.is_none());
+
+        assert_eq!(expr("f(a, b + 1)"), "(call f a (Add b 1))");
+        assert_eq!(expr("f()"), "(call f)");
+        assert_eq!(
+            expr("x = -g(h(1))(2)++"),
+            "(= x (Neg (PostInc (call (call g (call h 1)) 2))))"
+        );
+
+        let err = |src| parse(src).unwrap_err().to_string();
+        assert_eq!(
+            err("int f(int) { return 0; }"),
+            "parameter name omitted in a function definition at byte 6"
+        );
+        assert_eq!(
+            err("f(a b);"),
+            "expected `,` or `)` but found `b` at byte 4"
+        );
+        assert_eq!(
+            err("int f(int a,) ;"),
+            "expected a type but found `)` at byte 12"
+        );
+        // prototypes may appear anywhere
+        assert!(parse("void f() { int g(int x); }").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn fragment_test() -> Result<(), ParseError> {
+        assert_eq!(
+            sexp(&parse_expr("a[i] + f(1)")?),
+            "(Add (index a i) (call f 1))"
+        );
+        assert_eq!(
+            parse_expr("a b").unwrap_err().to_string(),
+            "expected the end of the input but found `b` at byte 2"
+        );
+        assert_eq!(
+            parse_expr("").unwrap_err().to_string(),
+            "expected an expression but the source ended at byte 0"
+        );
+
+        let stmt = parse_stmt("while (i) { i = i - 1; }")?;
+        assert!(matches!(stmt.kind, StmtKind::While { .. }));
+        assert_eq!(stmt.span, Span::new(0, 24));
+        assert!(parse_stmt("x = 1; y = 2;").is_err());
+        assert!(parse_stmt("return 1;").is_err());
+        assert!(parse_stmt("goto out;").is_err());
+
+        assert_eq!(parse_type("const char *[4]")?.to_string(), "const char*[4]");
+        assert_eq!(parse_type("unsigned long")?, Type::ULong);
+        assert!(parse_type("int x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn error_test() {
+        let err = |src| parse(src).unwrap_err();
+        assert_eq!(
+            err("int = 3;"),
+            ParseError::Unexpected {
+                span: Span::new(4, 5),
+                found: "=".to_string(),
+                expected: "an identifier",
+            }
+        );
+        assert_eq!(
+            err("x = (1 + 2;").to_string(),
+            "expected `)` but found `;` at byte 10"
+        );
+        assert_eq!(
+            err("{ x = 1;"),
+            ParseError::UnexpectedEof {
+                pos: 8,
+                expected: "`}`",
+            }
+        );
+        assert!(matches!(err("x = @;"), ParseError::Lex(_)));
+    }
+
+    #[test]
+    fn recovery_test() {
+        let src = "int a = ;\nint f(void) {\n  x = (1 + 2;\n  return 1;\n  break;\n  int b = 2;\n}\nvoid g() { return 1; y = 2; }\nint c = @ 1;\n} int d;";
+        let (program, errs) = parse_all(src);
+        let errs: Vec<String> = errs.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errs,
+            [
+                "expected an expression but found `;` at byte 8",
+                "expected `)` but found `;` at byte 36",
+                "`break` outside of a loop or `switch` at byte 52",
+                "`return` with a value in a function returning `void` at byte 85",
+                "unrecognized character(s) `@` at byte 112: `int c = @ 1;`",
+            ]
+        );
+        // `f` and `g` keep the statements that parsed, and `int c = 1` and `d` parse despite
+        // the stray character and brace
+        let names: Vec<&str> = program
+            .stmts
+            .iter()
+            .filter_map(|s| match &s.kind {
+                StmtKind::Decl(d) => match &d[..] {
+                    [Decl::Var(v)] => Some(v.name.name.as_str()),
+                    [Decl::Func(f)] => Some(f.name.name.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["f", "g", "c", "d"]);
+        let StmtKind::Decl(f) = &program.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let Decl::Func(f) = &f[0] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(&f.body.as_ref().unwrap().kind, StmtKind::Block(b) if b.len() == 2));
+
+        let (program, errs) = parse_all("{ if (x) { y = ; } z = 1;");
+        assert_eq!(program.stmts.len(), 0);
+        assert_eq!(errs.len(), 2);
+        assert_eq!(parse_all("int x; x = 1;").1, []);
+    }
+}
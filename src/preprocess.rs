@@ -0,0 +1,888 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::arena::{Arena, SideTable};
+use crate::ast::Spanned;
+use crate::lexer::{tokenize, LexError, Tok, TokKind, TokKind::*};
+use crate::source::{FileId, FileSpan, Location, SourceManager, Span};
+
+/// How deeply `#include`s may nest before we assume the inclusion is recursive.
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// Where a piece of preprocessed output came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The file in `Expanded::sources`.
+    pub file: FileId,
+    /// Byte offset in that file. For text produced by a macro expansion this is the position
+    /// of the outermost macro invocation.
+    pub pos: usize,
+    /// The macro invocations that produced the text, innermost first. Empty for text copied
+    /// from a file.
+    pub expansions: Vec<Expansion>,
+}
+
+impl Origin {
+    /// The innermost macro whose expansion produced the text, if any.
+    pub fn macro_name(&self) -> Option<&str> {
+        self.expansions.first().map(|e| e.macro_name.as_str())
+    }
+}
+
+/// One macro invocation in the chain that produced some text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub macro_name: String,
+    /// The macro's name where it was invoked: in a file, or in the body of the macro of the
+    /// next expansion out.
+    pub at: FileSpan,
+}
+
+/// A token as seen by the preprocessor, owning its text since it may come from any of the
+/// files involved or from a macro body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpTok {
+    pub kind: TokKind,
+    pub text: String,
+    pub origin: Origin,
+    /// Where the text was written, which for a token from a macro's body is in its `#define`.
+    pub spelled: FileSpan,
+}
+
+impl PpTok {
+    fn new(tok: &Tok, file: FileId, base: usize) -> PpTok {
+        let pos = base + tok.pos;
+        PpTok {
+            kind: tok.kind,
+            text: tok.str.to_string(),
+            origin: Origin {
+                file,
+                pos,
+                expansions: Vec::new(),
+            },
+            spelled: FileSpan {
+                file,
+                span: Span::new(pos, pos + tok.str.len()),
+            },
+        }
+    }
+
+    fn is_trivia(&self) -> bool {
+        self.kind.is_trivia()
+    }
+}
+
+/// A macro definition. `params` is `None` for object-like macros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Macro {
+    pub params: Option<Vec<String>>,
+    pub body: Vec<PpTok>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PpError {
+    Lex {
+        file: PathBuf,
+        err: LexError,
+    },
+    Io {
+        path: PathBuf,
+        msg: String,
+    },
+    /// A malformed or failing directive or macro invocation at `pos` in `file`.
+    Directive {
+        file: PathBuf,
+        pos: usize,
+        msg: String,
+    },
+}
+
+impl fmt::Display for PpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PpError::Lex { file, err } => write!(f, "{}: {}", file.display(), err),
+            PpError::Io { path, msg } => write!(f, "{}: {}", path.display(), msg),
+            PpError::Directive { file, pos, msg } => {
+                write!(f, "{}: at byte {}: {}", file.display(), pos, msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PpError {}
+
+/// The result of preprocessing: a single source string ready for `tokenize`, plus enough
+/// bookkeeping to map any offset in it back to the file it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expanded {
+    pub text: String,
+    /// Every file read, including `#include`d ones.
+    pub sources: SourceManager,
+    /// Sorted by output offset. Each entry covers the output up to the next one.
+    segments: Vec<(usize, Origin)>,
+}
+
+impl Expanded {
+    /// Maps a byte offset in `text` back to its origin.
+    pub fn origin(&self, pos: usize) -> Option<Origin> {
+        let i = self.segments.partition_point(|(start, _)| *start <= pos);
+        let (start, origin) = self.segments.get(i.checked_sub(1)?)?;
+        let mut origin = origin.clone();
+        if origin.expansions.is_empty() {
+            origin.pos += pos - start;
+        }
+        Some(origin)
+    }
+
+    /// Maps a byte offset in `text` back to a file, line and column.
+    pub fn location(&self, pos: usize) -> Option<Location<'_>> {
+        let origin = self.origin(pos)?;
+        Some(self.sources.location(origin.file, origin.pos))
+    }
+
+    pub fn tokenize(&self) -> Result<Vec<Tok<'_>>, LexError> {
+        tokenize(&self.text)
+    }
+
+    /// The macro expansions behind each node of a tree parsed from `text` that begins inside
+    /// one, keyed by the node's id in `arena`.
+    pub fn expansions(&self, arena: &Arena) -> SideTable<Vec<Expansion>> {
+        let mut table = SideTable::new();
+        for (id, node) in arena.iter() {
+            if let Some(origin) = self.origin(node.span().start) {
+                if !origin.expansions.is_empty() {
+                    table.insert(id, origin.expansions);
+                }
+            }
+        }
+        table
+    }
+
+    /// Renders an error at `span` in `text` as `path:line:col: error: msg`, followed by an
+    /// `in expansion of macro` note at each invocation that produced the text, innermost
+    /// first.
+    pub fn diagnostic(&self, span: Span, msg: impl fmt::Display) -> String {
+        let mut out = match self.location(span.start) {
+            Some(loc) => format!("{}: error: {}\n", loc, msg),
+            None => format!("error: {}\n", msg),
+        };
+        for expansion in self
+            .origin(span.start)
+            .map(|o| o.expansions)
+            .unwrap_or_default()
+        {
+            let at = self
+                .sources
+                .location(expansion.at.file, expansion.at.span.start);
+            out += &format!(
+                "{}: note: in expansion of macro `{}`\n",
+                at, expansion.macro_name
+            );
+        }
+        out
+    }
+}
+
+struct Cond {
+    /// Whether the enclosing region is active.
+    parent: bool,
+    active: bool,
+    /// Whether some branch of this conditional has been taken already.
+    taken: bool,
+    else_seen: bool,
+}
+
+/// Expands `#include`s, macros and conditionals ahead of lexing.
+#[derive(Default)]
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    sources: SourceManager,
+    out: String,
+    segments: Vec<(usize, Origin)>,
+    depth: usize,
+}
+
+impl Preprocessor {
+    pub fn new() -> Preprocessor {
+        Preprocessor::default()
+    }
+
+    /// Adds a directory searched by `#include <...>`, and by `#include "..."` after the
+    /// directory of the including file.
+    pub fn include_path(&mut self, path: impl Into<PathBuf>) -> &mut Preprocessor {
+        self.include_paths.push(path.into());
+        self
+    }
+
+    /// Defines an object-like macro, as if by `#define name value`.
+    pub fn define(&mut self, name: &str, value: &str) -> Result<&mut Preprocessor, LexError> {
+        let toks = tokenize(value)?;
+        let file = self.sources.add("<command line>", value);
+        let body = toks.iter().map(|t| PpTok::new(t, file, 0)).collect();
+        self.macros.insert(
+            name.to_string(),
+            Macro {
+                params: None,
+                body: trim_trivia(body),
+            },
+        );
+        Ok(self)
+    }
+
+    pub fn macro_def(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    pub fn run_file(mut self, path: impl AsRef<Path>) -> Result<Expanded, PpError> {
+        let path = path.as_ref();
+        let src = read(path)?;
+        self.process(path, &src)?;
+        Ok(self.finish())
+    }
+
+    /// Preprocesses `src` as if it were the contents of the file at `path`.
+    pub fn run_str(mut self, path: impl AsRef<Path>, src: &str) -> Result<Expanded, PpError> {
+        self.process(path.as_ref(), src)?;
+        Ok(self.finish())
+    }
+
+    fn finish(self) -> Expanded {
+        Expanded {
+            text: self.out,
+            sources: self.sources,
+            segments: self.segments,
+        }
+    }
+
+    fn process(&mut self, path: &Path, src: &str) -> Result<(), PpError> {
+        let file = self.sources.add(path, src);
+        let toks = tokenize(src).map_err(|err| PpError::Lex {
+            file: path.to_path_buf(),
+            err,
+        })?;
+        let mut conds = Vec::new();
+        let mut pending = Vec::new();
+        for tok in toks.iter() {
+            if tok.kind == Dir {
+                let expanded = self.expand(std::mem::take(&mut pending), &mut Vec::new())?;
+                self.emit(&expanded);
+                self.directive(file, tok, &mut conds)?;
+            } else if matches!(tok.kind, Bom | Shebang) {
+                // only meaningful at the start of a file, which the text may not end up at
+            } else if is_active(&conds) || tok.kind == Nl {
+                // newlines of inactive regions are kept so output lines track input lines
+                pending.push(PpTok::new(tok, file, 0));
+            }
+        }
+        let expanded = self.expand(pending, &mut Vec::new())?;
+        self.emit(&expanded);
+        if !conds.is_empty() {
+            return Err(PpError::Directive {
+                file: path.to_path_buf(),
+                pos: src.len(),
+                msg: "unterminated conditional directive".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn error(&self, origin: &Origin, msg: impl Into<String>) -> PpError {
+        PpError::Directive {
+            file: self.sources.get(origin.file).path().to_path_buf(),
+            pos: origin.pos,
+            msg: msg.into(),
+        }
+    }
+
+    fn directive(&mut self, file: FileId, tok: &Tok, conds: &mut Vec<Cond>) -> Result<(), PpError> {
+        let name = tok.directive_name().unwrap_or_default();
+        let args = tok.directive_args().unwrap_or_default();
+        let args_pos = tok.pos + (args.as_ptr() as usize - tok.str.as_ptr() as usize);
+        let origin = Origin {
+            file,
+            pos: tok.pos,
+            expansions: Vec::new(),
+        };
+        let active = is_active(conds);
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let val = active
+                    && match name {
+                        "if" => self.eval(file, args, args_pos)? != 0,
+                        "ifdef" => self.macros.contains_key(args),
+                        _ => !self.macros.contains_key(args),
+                    };
+                conds.push(Cond {
+                    parent: active,
+                    active: val,
+                    taken: val,
+                    else_seen: false,
+                });
+            }
+            "elif" => {
+                let cond = conds
+                    .last()
+                    .ok_or_else(|| self.error(&origin, "#elif without #if"))?;
+                if cond.else_seen {
+                    return Err(self.error(&origin, "#elif after #else"));
+                }
+                let val = cond.parent && !cond.taken && self.eval(file, args, args_pos)? != 0;
+                let cond = conds.last_mut().unwrap();
+                cond.active = val;
+                cond.taken |= val;
+            }
+            "else" => {
+                let cond = conds
+                    .last_mut()
+                    .ok_or_else(|| self.error(&origin, "#else without #if"))?;
+                if cond.else_seen {
+                    return Err(self.error(&origin, "duplicate #else"));
+                }
+                cond.active = cond.parent && !cond.taken;
+                cond.taken = true;
+                cond.else_seen = true;
+            }
+            "endif" => {
+                conds
+                    .pop()
+                    .ok_or_else(|| self.error(&origin, "#endif without #if"))?;
+            }
+            _ if !active => {}
+            "define" => self.define_directive(file, args, args_pos)?,
+            "undef" => {
+                self.macros.remove(args);
+            }
+            "include" => self.include(&origin, args)?,
+            "error" => return Err(self.error(&origin, format!("#error {}", args))),
+            // left in the output for later stages to interpret
+            "pragma" => self.emit(&[PpTok::new(tok, file, 0)]),
+            "" => {}
+            _ => return Err(self.error(&origin, format!("unknown directive `#{}`", name))),
+        }
+        Ok(())
+    }
+
+    fn define_directive(
+        &mut self,
+        file: FileId,
+        args: &str,
+        args_pos: usize,
+    ) -> Result<(), PpError> {
+        let origin = Origin {
+            file,
+            pos: args_pos,
+            expansions: Vec::new(),
+        };
+        let name_len = args
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(args.len());
+        let name = &args[..name_len];
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.error(&origin, "expected a macro name after #define"));
+        }
+        let mut rest = &args[name_len..];
+        let mut params = None;
+        if rest.starts_with('(') {
+            let close = rest
+                .find(')')
+                .ok_or_else(|| self.error(&origin, "unterminated macro parameter list"))?;
+            let list = rest[1..close].trim();
+            let names: Vec<String> = if list.is_empty() {
+                Vec::new()
+            } else {
+                list.split(',').map(|p| p.trim().to_string()).collect()
+            };
+            if names.iter().any(|p| {
+                p.is_empty()
+                    || !p.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    || p.starts_with(|c: char| c.is_ascii_digit())
+            }) {
+                return Err(self.error(&origin, "malformed macro parameter list"));
+            }
+            params = Some(names);
+            rest = &rest[close + 1..];
+        }
+        let body_pos = args_pos + (args.len() - rest.len());
+        let body = tokenize(rest)
+            .map_err(|e| self.error(&origin, e.to_string()))?
+            .iter()
+            .map(|t| {
+                let mut tok = PpTok::new(t, file, body_pos);
+                if tok.kind == Cont {
+                    // the body is a single logical line
+                    tok.kind = Spc;
+                    tok.text = " ".to_string();
+                }
+                tok
+            })
+            .collect();
+        self.macros.insert(
+            name.to_string(),
+            Macro {
+                params,
+                body: trim_trivia(body),
+            },
+        );
+        Ok(())
+    }
+
+    fn include(&mut self, origin: &Origin, args: &str) -> Result<(), PpError> {
+        let (name, quoted) = if args.len() >= 2 && args.starts_with('"') && args.ends_with('"') {
+            (&args[1..args.len() - 1], true)
+        } else if args.len() >= 2 && args.starts_with('<') && args.ends_with('>') {
+            (&args[1..args.len() - 1], false)
+        } else {
+            return Err(self.error(origin, "expected \"file\" or <file> after #include"));
+        };
+        let current_dir = self
+            .sources
+            .get(origin.file)
+            .path()
+            .parent()
+            .map(Path::to_path_buf);
+        let path = quoted
+            .then_some(current_dir)
+            .flatten()
+            .into_iter()
+            .chain(self.include_paths.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| self.error(origin, format!("cannot find include file `{}`", name)))?;
+        if self.depth == MAX_INCLUDE_DEPTH {
+            return Err(self.error(origin, "#include nested too deeply"));
+        }
+        let src = read(&path)?;
+        self.depth += 1;
+        let res = self.process(&path, &src);
+        self.depth -= 1;
+        res
+    }
+
+    /// Fully macro-expands `toks`, never expanding the macros in `disabled` so that
+    /// self-referential macros terminate.
+    fn expand(&self, toks: Vec<PpTok>, disabled: &mut Vec<String>) -> Result<Vec<PpTok>, PpError> {
+        let mut out = Vec::with_capacity(toks.len());
+        let mut i = 0;
+        while i < toks.len() {
+            let tok = &toks[i];
+            i += 1;
+            let mac = match self.macros.get(&tok.text) {
+                Some(mac) if tok.kind == Var && !disabled.contains(&tok.text) => mac,
+                _ => {
+                    out.push(tok.clone());
+                    continue;
+                }
+            };
+            let mut invocation = tok.origin.clone();
+            invocation.expansions.insert(
+                0,
+                Expansion {
+                    macro_name: tok.text.clone(),
+                    at: tok.spelled,
+                },
+            );
+            let from_body = |t: &PpTok| PpTok {
+                origin: invocation.clone(),
+                ..t.clone()
+            };
+            let body = match &mac.params {
+                None => mac.body.iter().map(from_body).collect(),
+                Some(params) => {
+                    let open = toks[i..].iter().position(|t| !t.is_trivia());
+                    if open.map(|o| toks[i + o].kind) != Some(LPar) {
+                        out.push(tok.clone());
+                        continue;
+                    }
+                    i += open.unwrap() + 1;
+                    let (args, end) = self.collect_args(&toks, i, &tok.origin)?;
+                    i = end;
+                    if args.len() != params.len() && !(params.is_empty() && args == [vec![]]) {
+                        return Err(self.error(
+                            &tok.origin,
+                            format!(
+                                "macro `{}` takes {} argument(s) but {} were given",
+                                tok.text,
+                                params.len(),
+                                args.len()
+                            ),
+                        ));
+                    }
+                    let args = args
+                        .into_iter()
+                        .map(|arg| self.expand(arg, disabled))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let mut body = Vec::new();
+                    for t in mac.body.iter() {
+                        match params.iter().position(|p| t.kind == Var && *p == t.text) {
+                            Some(p) => body.extend(args[p].iter().cloned()),
+                            None => body.push(from_body(t)),
+                        }
+                    }
+                    body
+                }
+            };
+            disabled.push(tok.text.clone());
+            let res = self.expand(body, disabled);
+            disabled.pop();
+            out.extend(res?);
+        }
+        Ok(out)
+    }
+
+    /// Collects the comma-separated arguments of a function-like macro invocation starting
+    /// just after its `(`, returning them along with the index just past the closing `)`.
+    fn collect_args(
+        &self,
+        toks: &[PpTok],
+        mut i: usize,
+        at: &Origin,
+    ) -> Result<(Vec<Vec<PpTok>>, usize), PpError> {
+        let mut args = vec![Vec::new()];
+        let mut depth = 0;
+        while let Some(tok) = toks.get(i) {
+            i += 1;
+            match tok.kind {
+                RPar if depth == 0 => {
+                    return Ok((args.into_iter().map(trim_trivia).collect(), i));
+                }
+                Comma if depth == 0 => {
+                    args.push(Vec::new());
+                    continue;
+                }
+                LPar => depth += 1,
+                RPar => depth -= 1,
+                _ => {}
+            }
+            args.last_mut().unwrap().push(tok.clone());
+        }
+        Err(self.error(at, "unterminated macro invocation"))
+    }
+
+    fn emit(&mut self, toks: &[PpTok]) {
+        let mut prev_expanded = false;
+        for tok in toks {
+            let expanded = !tok.origin.expansions.is_empty();
+            if (expanded || prev_expanded) && !tok.is_trivia() {
+                let prev = self.out.chars().next_back();
+                let next = tok.text.chars().next();
+                if let (Some(prev), Some(next)) = (prev, next) {
+                    if may_join(prev, next) {
+                        self.out.push(' ');
+                    }
+                }
+            }
+            prev_expanded = expanded;
+            let out_pos = self.out.len();
+            let contiguous = self.segments.last().is_some_and(|(start, o)| {
+                o.expansions.is_empty()
+                    && tok.origin.expansions.is_empty()
+                    && o.file == tok.origin.file
+                    && o.pos + (out_pos - start) == tok.origin.pos
+            });
+            if !contiguous {
+                self.segments.push((out_pos, tok.origin.clone()));
+            }
+            self.out.push_str(&tok.text);
+        }
+    }
+
+    /// Evaluates the controlling expression of an `#if` or `#elif`.
+    fn eval(&self, file: FileId, args: &str, args_pos: usize) -> Result<i64, PpError> {
+        let origin = Origin {
+            file,
+            pos: args_pos,
+            expansions: Vec::new(),
+        };
+        let toks: Vec<PpTok> = tokenize(args)
+            .map_err(|e| self.error(&origin, e.to_string()))?
+            .iter()
+            .map(|t| PpTok::new(t, file, args_pos))
+            .filter(|t| !t.is_trivia())
+            .collect();
+        // `defined` has to be resolved before expansion, or its operand would be expanded
+        let mut resolved = Vec::with_capacity(toks.len());
+        let mut i = 0;
+        while i < toks.len() {
+            if toks[i].kind != Var || toks[i].text != "defined" {
+                resolved.push(toks[i].clone());
+                i += 1;
+                continue;
+            }
+            let parens = toks.get(i + 1).map(|t| t.kind) == Some(LPar);
+            let name = toks.get(i + 1 + parens as usize).filter(|t| t.kind == Var);
+            let name = name.ok_or_else(|| {
+                self.error(&toks[i].origin, "expected a macro name after `defined`")
+            })?;
+            if parens && toks.get(i + 3).map(|t| t.kind) != Some(RPar) {
+                return Err(self.error(&toks[i].origin, "expected `)` after `defined(`"));
+            }
+            let val = if self.macros.contains_key(&name.text) {
+                "1"
+            } else {
+                "0"
+            };
+            resolved.push(PpTok {
+                kind: IntLit,
+                text: val.to_string(),
+                origin: toks[i].origin.clone(),
+                spelled: toks[i].spelled,
+            });
+            i += if parens { 4 } else { 2 };
+        }
+        let toks: Vec<PpTok> = self
+            .expand(resolved, &mut Vec::new())?
+            .into_iter()
+            .filter(|t| !t.is_trivia())
+            .collect();
+        let mut eval = Eval { toks: &toks, i: 0 };
+        let val = eval.ternary().map_err(|msg| self.error(&origin, msg))?;
+        if eval.i != toks.len() {
+            return Err(self.error(&origin, "unexpected tokens after #if expression"));
+        }
+        Ok(val)
+    }
+}
+
+fn read(path: &Path) -> Result<String, PpError> {
+    fs::read_to_string(path).map_err(|e| PpError::Io {
+        path: path.to_path_buf(),
+        msg: e.to_string(),
+    })
+}
+
+fn is_active(conds: &[Cond]) -> bool {
+    conds.last().is_none_or(|c| c.active)
+}
+
+fn trim_trivia(mut toks: Vec<PpTok>) -> Vec<PpTok> {
+    while toks.last().is_some_and(PpTok::is_trivia) {
+        toks.pop();
+    }
+    let start = toks
+        .iter()
+        .position(|t| !t.is_trivia())
+        .unwrap_or(toks.len());
+    toks.drain(..start);
+    toks
+}
+
+/// Whether two characters written next to each other could lex as part of the same token.
+fn may_join(prev: char, next: char) -> bool {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    (word(prev) && word(next))
+        || (prev.is_ascii_punctuation()
+            && next.is_ascii_punctuation()
+            && !"()[]{};,".contains(prev))
+}
+
+/// Evaluator for `#if` expressions, with C precedence and all values as `i64`.
+struct Eval<'a> {
+    toks: &'a [PpTok],
+    i: usize,
+}
+
+impl<'a> Eval<'a> {
+    fn peek(&self) -> Option<&'a PpTok> {
+        self.toks.get(self.i)
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let cond = self.binary(0)?;
+        if self.peek().map(|t| t.kind) != Some(Quest) {
+            return Ok(cond);
+        }
+        self.i += 1;
+        let then = self.ternary()?;
+        if self.peek().map(|t| t.kind) != Some(Colon) {
+            return Err("expected `:` in conditional expression".to_string());
+        }
+        self.i += 1;
+        let otherwise = self.ternary()?;
+        Ok(if cond != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+        while let Some(tok) = self.peek() {
+            let prec = match tok.kind {
+                LOr => 1,
+                LAnd => 2,
+                BOr => 3,
+                Xor => 4,
+                BAnd => 5,
+                Eq | Neq => 6,
+                Lt | Gt | Le | Ge => 7,
+                Shl | Shr => 8,
+                Add | Sub => 9,
+                Mul | Div | Mod => 10,
+                _ => break,
+            };
+            if prec <= min_prec {
+                break;
+            }
+            self.i += 1;
+            let rhs = self.binary(prec)?;
+            lhs = match tok.kind {
+                LOr => (lhs != 0 || rhs != 0) as i64,
+                LAnd => (lhs != 0 && rhs != 0) as i64,
+                BOr => lhs | rhs,
+                Xor => lhs ^ rhs,
+                BAnd => lhs & rhs,
+                Eq => (lhs == rhs) as i64,
+                Neq => (lhs != rhs) as i64,
+                Lt => (lhs < rhs) as i64,
+                Gt => (lhs > rhs) as i64,
+                Le => (lhs <= rhs) as i64,
+                Ge => (lhs >= rhs) as i64,
+                Shl => lhs.wrapping_shl(rhs as u32),
+                Shr => lhs.wrapping_shr(rhs as u32),
+                Add => lhs.wrapping_add(rhs),
+                Sub => lhs.wrapping_sub(rhs),
+                Mul => lhs.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("division by zero in #if expression".to_string()),
+                Div => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let tok = self
+            .peek()
+            .ok_or_else(|| "unexpected end of #if expression".to_string())?;
+        self.i += 1;
+        match tok.kind {
+            Not => Ok((self.unary()? == 0) as i64),
+            BNot => Ok(!self.unary()?),
+            Sub => Ok(self.unary()?.wrapping_neg()),
+            Add => self.unary(),
+            LPar => {
+                let val = self.ternary()?;
+                if self.peek().map(|t| t.kind) != Some(RPar) {
+                    return Err("expected `)` in #if expression".to_string());
+                }
+                self.i += 1;
+                Ok(val)
+            }
+            // identifiers that survive macro expansion evaluate to 0
+            Var | Key => Ok(0),
+            IntLit | HexLit | OctLit | BinLit | ChrLit => int_value(tok),
+            _ => Err(format!("unexpected `{}` in #if expression", tok.text)),
+        }
+    }
+}
+
+fn int_value(tok: &PpTok) -> Result<i64, String> {
+    let lit = Tok {
+        kind: tok.kind,
+        pos: tok.origin.pos,
+        str: &tok.text,
+    };
+    let val = match tok.kind {
+        ChrLit => lit.char_value().map(u64::from),
+        _ => lit.int_value(),
+    };
+    val.map(|v| v as i64).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pp(src: &str) -> Result<String, PpError> {
+        Ok(Preprocessor::new().run_str("test.c8", src)?.text)
+    }
+
+    #[test]
+    fn define_test() -> Result<(), PpError> {
+        let src = "#define MAX 10\n#define ADD(a, b) ((a) + (b))\nint x = ADD(MAX, 2);\n";
+        assert_eq!(pp(src)?, "\n\nint x = ((10) + (2));\n");
+        assert_eq!(pp("#define X X + 1\nX;")?, "\nX + 1;");
+        assert_eq!(pp("#define F(x) x\nF;")?, "\nF;");
+        Ok(())
+    }
+
+    #[test]
+    fn continued_define_test() -> Result<(), PpError> {
+        let out = pp("#define ADD(a, b) \\\n  ((a) + \\\n   (b))\nADD(1, 2)\n")?;
+        let words: Vec<_> = out.split_whitespace().collect();
+        assert_eq!(words, ["((1)", "+", "(2))"]);
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_test() -> Result<(), PpError> {
+        assert_eq!(pp("\u{feff}#!/bin/hex8\nint a;\n")?, "\nint a;\n");
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_test() -> Result<(), PpError> {
+        let src = "#define A 2\n#if A > 1 && !defined(B)\nyes\n#else\nno\n#endif\n#ifdef B\nb\n#elif A == 2\na2\n#endif\n";
+        assert_eq!(pp(src)?, "\n\nyes\n\n\n\n\n\n\na2\n\n");
+        assert_eq!(
+            pp("#define A\n#undef A\n#ifndef A\nok\n#endif")?,
+            "\n\n\nok\n"
+        );
+        assert!(pp("#if 1\nx\n").is_err());
+        assert!(pp("#else\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn origin_test() -> Result<(), PpError> {
+        let src = "#define ONE 1\nint a = ONE;\n";
+        let expanded = Preprocessor::new().run_str("test.c8", src)?;
+        let one = expanded.text.find('1').unwrap();
+        let origin = expanded.origin(one).unwrap();
+        assert_eq!(origin.pos, src.find("ONE;").unwrap());
+        assert_eq!(origin.macro_name(), Some("ONE"));
+        let a = expanded.text.find('a').unwrap();
+        assert_eq!(expanded.origin(a).unwrap().pos, src.find('a').unwrap());
+        assert_eq!(expanded.location(a).unwrap().to_string(), "test.c8:2:5");
+        Ok(())
+    }
+
+    #[test]
+    fn expansion_test() -> Result<(), PpError> {
+        use crate::parser::parse;
+
+        let src = "#define NEG(x) (-x)\n#define BAD NEG(1)\nint a = 1;\nint b = BAD;\n";
+        let expanded = Preprocessor::new().run_str("test.c8", src)?;
+        let neg = expanded.text.find('-').unwrap();
+        assert_eq!(
+            expanded.diagnostic(Span::new(neg, neg + 1), "negation"),
+            "test.c8:4:9: error: negation\n\
+             test.c8:2:13: note: in expansion of macro `NEG`\n\
+             test.c8:4:9: note: in expansion of macro `BAD`\n"
+        );
+
+        let program = parse(&expanded.text).unwrap();
+        let arena = Arena::new(&program);
+        let expansions = expanded.expansions(&arena);
+        let (id, chain) = expansions.iter().next().unwrap();
+        assert_eq!(arena.get(id).text(&expanded.text), "(-1)");
+        let names: Vec<_> = chain.iter().map(|e| e.macro_name.as_str()).collect();
+        assert_eq!(names, ["NEG", "BAD"]);
+        Ok(())
+    }
+
+    #[test]
+    fn include_test() -> Result<(), PpError> {
+        let dir = std::env::temp_dir().join(format!("hex8-pp-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sys")).unwrap();
+        fs::write(dir.join("sys/hex8.h"), "#define OUT 255\n").unwrap();
+        fs::write(dir.join("main.c8"), "#include <hex8.h>\nchar p = OUT;\n").unwrap();
+        let mut pp = Preprocessor::new();
+        pp.include_path(dir.join("sys"));
+        let expanded = pp.run_file(dir.join("main.c8"))?;
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(expanded.text, "\n\nchar p = 255;\n");
+        assert_eq!(expanded.sources.len(), 2);
+        Ok(())
+    }
+}
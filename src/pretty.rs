@@ -0,0 +1,532 @@
+//! Printing the AST back out as source.
+
+use crate::ast::*;
+
+/// Regenerates source from `program` in one consistent style: four spaces of indentation,
+/// braces on the line that opens them, `case` and other labels outdented by one level, and a
+/// blank line around each function definition. Parsing the result gives the same AST apart
+/// from spans, except that typedef names are replaced by the types they stand for.
+pub fn ast_to_source(program: &Program) -> String {
+    let mut printer = Printer::default();
+    let mut after_function = false;
+    for (i, stmt) in program.stmts.iter().enumerate() {
+        let function = is_definition(stmt);
+        if i > 0 && (function || after_function) {
+            printer.out.push('\n');
+        }
+        printer.stmt(stmt);
+        after_function = function;
+    }
+    printer.out
+}
+
+fn is_definition(stmt: &Stmt) -> bool {
+    matches!(&stmt.kind, StmtKind::Decl(decls)
+        if matches!(&decls[..], [Decl::Func(f)] if f.body.is_some()))
+}
+
+/// Joins a specifier and a declarator, which may be empty.
+pub(crate) fn join(specifier: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        specifier.to_string()
+    } else {
+        format!("{} {}", specifier, declarator)
+    }
+}
+
+/// The keyword for a storage class, followed by a space.
+fn storage_class(storage: Option<Storage>) -> &'static str {
+    match storage {
+        Some(Storage::Static) => "static ",
+        Some(Storage::Extern) => "extern ",
+        None => "",
+    }
+}
+
+/// The body of a string or char literal holding `bytes`, escaped so it lexes back to them.
+pub(crate) fn escape(bytes: &[u8], quote: u8) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            b'\\' => out.push_str("\\\\"),
+            _ if b == quote => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7e => out.push(b as char),
+            // three octal digits, so a following digit can't extend the escape
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out
+}
+
+/// How tightly an expression binds, from the comma operator at 1 to literals and names at
+/// 17. A binary operator of precedence `p` is at `4 + p`.
+fn level(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Comma(..) => 1,
+        ExprKind::Assign(..) => 2,
+        ExprKind::Cond(..) => 3,
+        ExprKind::Binary(op, ..) => 4 + op.precedence(),
+        ExprKind::Unary(UnOp::PostInc | UnOp::PostDec, _)
+        | ExprKind::Index(..)
+        | ExprKind::Member(..)
+        | ExprKind::Arrow(..)
+        | ExprKind::Call(..) => 16,
+        ExprKind::Unary(..)
+        | ExprKind::Cast(..)
+        | ExprKind::Sizeof(_)
+        | ExprKind::SizeofExpr(_) => 15,
+        ExprKind::Int(_) | ExprKind::Char(_) | ExprKind::Str(_) | ExprKind::Var(_) => 17,
+    }
+}
+
+/// Whether an `else` after `stmt` would attach to an `if` inside it instead of one before it.
+fn dangles(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::If { els: None, .. } => true,
+        StmtKind::If { els: Some(s), .. }
+        | StmtKind::While { body: s, .. }
+        | StmtKind::For { body: s, .. }
+        | StmtKind::Switch { body: s, .. }
+        | StmtKind::Case { stmt: s, .. }
+        | StmtKind::Default(s)
+        | StmtKind::Label { stmt: s, .. } => dangles(s),
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// A statement on lines of its own.
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(stmts) => {
+                self.line("{");
+                self.block_body(stmts);
+                self.line("}");
+            }
+            StmtKind::Decl(decls) if is_definition(stmt) => {
+                let Decl::Func(func) = &decls[0] else {
+                    unreachable!("a definition is a function");
+                };
+                self.function(func);
+            }
+            StmtKind::If { .. }
+            | StmtKind::While { .. }
+            | StmtKind::DoWhile { .. }
+            | StmtKind::For { .. }
+            | StmtKind::Switch { .. } => {
+                let head = self.head(stmt);
+                self.out.push_str(&"    ".repeat(self.indent));
+                self.out.push_str(&head);
+                self.compound(stmt);
+            }
+            StmtKind::Case { .. } | StmtKind::Default(_) | StmtKind::Label { .. } => {
+                let (label, inner) = match &stmt.kind {
+                    StmtKind::Case { value, stmt } => (format!("case {}:", self.expr(value)), stmt),
+                    StmtKind::Default(stmt) => ("default:".to_string(), stmt),
+                    StmtKind::Label { label, stmt } => (format!("{}:", label.name), stmt),
+                    _ => unreachable!(),
+                };
+                let indent = self.indent;
+                self.indent = indent.saturating_sub(1);
+                self.line(&label);
+                self.indent = indent;
+                self.stmt(inner);
+            }
+            _ => {
+                let text = self.simple(stmt);
+                self.line(&text);
+            }
+        }
+    }
+
+    fn block_body(&mut self, stmts: &[Stmt]) {
+        self.indent += 1;
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.indent -= 1;
+    }
+
+    /// The opening of a compound statement up to its body, such as `while (x)`.
+    fn head(&mut self, stmt: &Stmt) -> String {
+        match &stmt.kind {
+            StmtKind::If { cond, .. } => format!("if ({})", self.expr(cond)),
+            StmtKind::While { cond, .. } => format!("while ({})", self.expr(cond)),
+            StmtKind::DoWhile { .. } => "do".to_string(),
+            StmtKind::Switch { cond, .. } => format!("switch ({})", self.expr(cond)),
+            StmtKind::For {
+                init, cond, step, ..
+            } => {
+                let init = init.as_ref().map_or(";".to_string(), |s| self.simple(s));
+                let cond = cond.as_ref().map(|c| format!(" {}", self.expr(c)));
+                let step = step.as_ref().map(|s| format!(" {}", self.expr(s)));
+                format!(
+                    "for ({}{};{})",
+                    init,
+                    cond.unwrap_or_default(),
+                    step.unwrap_or_default()
+                )
+            }
+            _ => unreachable!("only compound statements have a head"),
+        }
+    }
+
+    /// The rest of a compound statement once its head is written, ending its last line.
+    fn compound(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::If { then, els, .. } => {
+                // an `if` without an `else` inside `then` would take this one's
+                let braced = els.is_some() && dangles(then);
+                let closed = self.body(then, braced);
+                let Some(els) = els else {
+                    return;
+                };
+                if closed {
+                    self.out.pop();
+                    self.out.push(' ');
+                } else {
+                    self.out.push_str(&"    ".repeat(self.indent));
+                }
+                self.out.push_str("else");
+                if matches!(els.kind, StmtKind::If { .. }) {
+                    let head = self.head(els);
+                    self.out.push(' ');
+                    self.out.push_str(&head);
+                    self.compound(els);
+                } else {
+                    self.body(els, false);
+                }
+            }
+            StmtKind::While { body, .. }
+            | StmtKind::For { body, .. }
+            | StmtKind::Switch { body, .. } => {
+                self.body(body, false);
+            }
+            StmtKind::DoWhile { body, cond } => {
+                let cond = self.expr(cond);
+                if self.body(body, false) {
+                    self.out.pop();
+                    self.out.push_str(&format!(" while ({});\n", cond));
+                } else {
+                    self.line(&format!("while ({});", cond));
+                }
+            }
+            _ => unreachable!("only compound statements have a body"),
+        }
+    }
+
+    /// The body of a compound statement after its head: a block opened on the same line, or a
+    /// statement indented on the next. A `braced` statement is wrapped in a block. Whether
+    /// the body ended with a `}` line.
+    fn body(&mut self, body: &Stmt, braced: bool) -> bool {
+        match &body.kind {
+            StmtKind::Block(stmts) => {
+                self.out.push_str(" {\n");
+                self.block_body(stmts);
+                self.line("}");
+                true
+            }
+            _ if braced => {
+                self.out.push_str(" {\n");
+                self.block_body(std::slice::from_ref(body));
+                self.line("}");
+                true
+            }
+            _ => {
+                self.out.push('\n');
+                self.indent += 1;
+                self.stmt(body);
+                self.indent -= 1;
+                false
+            }
+        }
+    }
+
+    /// A statement that fits on one line, with its `;`.
+    fn simple(&mut self, stmt: &Stmt) -> String {
+        match &stmt.kind {
+            StmtKind::Decl(decls) => format!("{};", self.decls(decls)),
+            StmtKind::Expr(expr) => format!("{};", self.expr(expr)),
+            StmtKind::Break => "break;".to_string(),
+            StmtKind::Continue => "continue;".to_string(),
+            StmtKind::Goto(label) => format!("goto {};", label.name),
+            StmtKind::Return(Some(value)) => format!("return {};", self.expr(value)),
+            StmtKind::Return(None) => "return;".to_string(),
+            StmtKind::Empty => ";".to_string(),
+            _ => unreachable!("compound statements take more than one line"),
+        }
+    }
+
+    /// A declaration without its `;`. The declarators share the specifier of the first, or
+    /// the struct, union or enum defined first.
+    fn decls(&mut self, decls: &[Decl]) -> String {
+        let (defined, declarators) = match decls.first() {
+            Some(Decl::Struct(def)) => (Some(self.struct_def(def)), &decls[1..]),
+            Some(Decl::Enum(def)) => (Some(self.enum_def(def)), &decls[1..]),
+            _ => (None, decls),
+        };
+        let storage = match declarators.first() {
+            Some(Decl::Var(var)) => var.storage,
+            Some(Decl::Func(func)) => func.storage,
+            _ => None,
+        };
+        let mut out = storage_class(storage).to_string();
+        if matches!(declarators.first(), Some(Decl::Typedef(_))) {
+            out.push_str("typedef ");
+        }
+        let mut specifier = None;
+        let mut parts = Vec::new();
+        for decl in declarators {
+            let (spec, declarator) = match decl {
+                Decl::Var(var) => {
+                    let (spec, mut declarator) = var.ty.declaration(&var.name.name);
+                    if let Some(init) = &var.init {
+                        declarator.push_str(" = ");
+                        declarator.push_str(&self.init(init));
+                    }
+                    (spec, declarator)
+                }
+                Decl::Func(func) => self.prototype(func),
+                Decl::Typedef(def) => def.ty.declaration(&def.name.name),
+                Decl::Struct(_) | Decl::Enum(_) => unreachable!("a definition comes first"),
+            };
+            specifier.get_or_insert(spec);
+            parts.push(declarator);
+        }
+        let specifier = specifier.unwrap_or_default();
+        let specifier = match (&defined, decls.first()) {
+            // the definition stands in for the type it defines, keeping any qualifiers
+            (Some(def), Some(Decl::Struct(s))) => {
+                let keyword = if s.union { "union" } else { "struct" };
+                let name = format!("{} {}", keyword, s.tag.name);
+                let quals = specifier.strip_suffix(&name).unwrap_or("");
+                format!("{}{}", quals, def)
+            }
+            (Some(def), Some(Decl::Enum(e))) => {
+                let name = e
+                    .tag
+                    .as_ref()
+                    .map_or("char".to_string(), |t| format!("enum {}", t.name));
+                let quals = specifier.strip_suffix(&name).unwrap_or("");
+                format!("{}{}", quals, def)
+            }
+            _ => specifier,
+        };
+        out.push_str(&join(&specifier, &parts.join(", ")));
+        out
+    }
+
+    /// The specifier and declarator of a function, with its parameters named.
+    fn prototype(&mut self, func: &Function) -> (String, String) {
+        let params: Vec<String> = func
+            .params
+            .iter()
+            .map(|p| {
+                let name = p.name.as_ref().map_or("", |n| n.name.as_str());
+                let (spec, declarator) = p.ty.declaration(name);
+                join(&spec, &declarator)
+            })
+            .collect();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        func.ret
+            .declaration(&format!("{}({})", func.name.name, params))
+    }
+
+    fn function(&mut self, func: &Function) {
+        let (spec, declarator) = self.prototype(func);
+        self.out.push_str(&"    ".repeat(self.indent));
+        self.out.push_str(storage_class(func.storage));
+        self.out.push_str(&join(&spec, &declarator));
+        let body = func.body.as_ref().expect("a definition has a body");
+        self.body(body, true);
+    }
+
+    fn struct_def(&mut self, def: &StructDef) -> String {
+        let keyword = if def.union { "union" } else { "struct" };
+        let mut out = format!("{} {} {{\n", keyword, def.tag.name);
+        let indent = "    ".repeat(self.indent + 1);
+        for field in &def.fields {
+            let (spec, declarator) = field.ty.declaration(&field.name.name);
+            out.push_str(&format!("{}{};\n", indent, join(&spec, &declarator)));
+        }
+        out.push_str(&"    ".repeat(self.indent));
+        out.push('}');
+        out
+    }
+
+    /// An enum definition, giving a value only where it isn't one more than the last.
+    fn enum_def(&mut self, def: &EnumDef) -> String {
+        let mut next = 0;
+        let enumerators: Vec<String> = def
+            .enumerators
+            .iter()
+            .map(|e| {
+                let text = if e.value == next {
+                    e.name.name.clone()
+                } else {
+                    format!("{} = {}", e.name.name, e.value)
+                };
+                next = e.value + 1;
+                text
+            })
+            .collect();
+        let tag = def.tag.as_ref().map(|t| format!(" {}", t.name));
+        format!(
+            "enum{} {{ {} }}",
+            tag.unwrap_or_default(),
+            enumerators.join(", ")
+        )
+    }
+
+    fn init(&mut self, init: &Init) -> String {
+        match init {
+            Init::Expr(expr) => self.at(expr, 2),
+            Init::List { items, .. } => {
+                let items: Vec<String> = items.iter().map(|i| self.init(i)).collect();
+                format!("{{{}}}", items.join(", "))
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        self.at(expr, 1)
+    }
+
+    /// `expr` where an expression of at least `min` is expected, parenthesized if it binds more
+    /// loosely than that.
+    fn at(&mut self, expr: &Expr, min: u8) -> String {
+        let text = self.bare(expr);
+        if level(expr) < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn bare(&mut self, expr: &Expr) -> String {
+        let own = level(expr);
+        match &expr.kind {
+            ExprKind::Int(n) => n.to_string(),
+            ExprKind::Char(c) => format!("'{}'", escape(&[*c], b'\'')),
+            ExprKind::Str(bytes) => format!("\"{}\"", escape(bytes, b'"')),
+            ExprKind::Var(name) => name.clone(),
+            ExprKind::Unary(op @ (UnOp::PostInc | UnOp::PostDec), operand) => {
+                format!("{}{}", self.at(operand, 16), op.symbol())
+            }
+            ExprKind::Unary(op, operand) => {
+                let operand = self.at(operand, 15);
+                // `- -x` mustn't run together into `--x`
+                let last = op.symbol().chars().last();
+                let space = if last.is_some_and(|c| "+-&".contains(c) && operand.starts_with(c)) {
+                    " "
+                } else {
+                    ""
+                };
+                format!("{}{}{}", op.symbol(), space, operand)
+            }
+            ExprKind::Binary(op, lhs, rhs) => format!(
+                "{} {} {}",
+                self.at(lhs, own),
+                op.symbol(),
+                self.at(rhs, own + 1)
+            ),
+            ExprKind::Assign(op, lhs, rhs) => format!(
+                "{} {}= {}",
+                self.at(lhs, 15),
+                op.map_or("", BinOp::symbol),
+                self.at(rhs, 2)
+            ),
+            ExprKind::Index(array, index) => {
+                format!("{}[{}]", self.at(array, 16), self.expr(index))
+            }
+            ExprKind::Member(base, field) => {
+                // `15.c` would lex as the number `15.`
+                let base = match base.kind {
+                    ExprKind::Int(_) => format!("({})", self.expr(base)),
+                    _ => self.at(base, 16),
+                };
+                format!("{}.{}", base, field.name)
+            }
+            ExprKind::Arrow(base, field) => format!("{}->{}", self.at(base, 16), field.name),
+            ExprKind::Cast(ty, operand) => format!("({}){}", ty, self.at(operand, 15)),
+            ExprKind::Sizeof(ty) => format!("sizeof({})", ty),
+            ExprKind::SizeofExpr(operand) => format!("sizeof {}", self.at(operand, 15)),
+            ExprKind::Comma(lhs, rhs) => format!("{}, {}", self.at(lhs, 1), self.at(rhs, 2)),
+            ExprKind::Cond(cond, then, els) => format!(
+                "{} ? {} : {}",
+                self.at(cond, 5),
+                self.expr(then),
+                self.at(els, 3)
+            ),
+            ExprKind::Call(callee, args) => {
+                let args: Vec<String> = args.iter().map(|a| self.at(a, 2)).collect();
+                format!("{}({})", self.at(callee, 16), args.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use regex::Regex;
+
+    /// The AST of `src` printed without its spans.
+    fn shape(src: &str) -> String {
+        let spans = Regex::new(r"span: Span \{ start: \d+, end: \d+ \}").unwrap();
+        let program = parse(src).unwrap_or_else(|e| panic!("{}\n{}", e, src));
+        spans
+            .replace_all(&format!("{:?}", program.stmts), "")
+            .into_owned()
+    }
+
+    #[test]
+    fn pretty_test() {
+        let src = "int f(int n){if(n<2)return n;else if(n>9){return-(-n);}return f(n-1)*(2+n);}";
+        assert_eq!(
+            ast_to_source(&parse(src).unwrap()),
+            "int f(int n) {\n    if (n < 2)\n        return n;\n    else if (n > 9) {\n        return - -n;\n    }\n    return f(n - 1) * (2 + n);\n}\n"
+        );
+
+        let srcs = [
+            "struct p { char x; int y[2]; } origin = {1, {2}}, *ps[3];",
+            "enum { A, B = 5, C } e; static const char *const names[] = {\"a\\n\", \"\\001x\"};",
+            "extern int (*handler)(char, int *); void g(void); typedef unsigned long T[2];",
+            "int x; void h(char c) { switch (c) { case 'q': x = 1; break; default: x--; } }",
+            "void k() { for (int i = 0, j; i < 3; i++, j--) while (x) do x -= 1; while (x > 0); }",
+            "void m() { if (x) if (x > 1) x = 2; else ; else x = 3; if (x) { if (x) ; } else ; }",
+            "void n() { a: x = x ? x , 1 : (x = 2, 3) ; goto a; { } ; }",
+            "int y = sizeof(int *[2]) + sizeof x + (char)- -x + !~x ++ + (x << 1 >> 2) - (1 - 2);",
+            "int z = x == (1 < 2) && (x || y) & 3, *w = &z, v = *w + w[0] + f(1, (2, 3)).a->b;",
+            "void p() { i & 0xf. c = 0; }",
+        ];
+        for src in srcs {
+            let printed = ast_to_source(&parse(src).unwrap());
+            assert_eq!(shape(&printed), shape(src), "{}", printed);
+            assert_eq!(ast_to_source(&parse(&printed).unwrap()), printed);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Finding nodes of a program without writing a visitor for each question, such as
+//! `program.find_all::<Function>()` for every function or `program.find_by_name("main")` for
+//! whatever declares `main`.
+
+use crate::arena::{Arena, Node};
+use crate::ast::*;
+
+/// A type of node that `Program::find_all` can look for.
+pub trait Find {
+    /// The node as a `Self`, if it is one.
+    fn find(node: Node<'_>) -> Option<&Self>;
+}
+
+macro_rules! find {
+    ($($ty:ty => $pat:pat => $node:expr,)*) => {
+        $(impl Find for $ty {
+            fn find(node: Node<'_>) -> Option<&Self> {
+                match node {
+                    $pat => Some($node),
+                    _ => None,
+                }
+            }
+        })*
+    };
+}
+
+find! {
+    Stmt => Node::Stmt(stmt) => stmt,
+    Decl => Node::Decl(decl) => decl,
+    VarDecl => Node::Decl(Decl::Var(var)) => var,
+    Function => Node::Decl(Decl::Func(func)) => func,
+    StructDef => Node::Decl(Decl::Struct(def)) => def,
+    EnumDef => Node::Decl(Decl::Enum(def)) => def,
+    Typedef => Node::Decl(Decl::Typedef(def)) => def,
+    Param => Node::Param(param) => param,
+    Field => Node::Field(field) => field,
+    Enumerator => Node::Enumerator(enumerator) => enumerator,
+    Expr => Node::Expr(expr) => expr,
+}
+
+/// The name a node declares, if it declares one.
+fn declared_name(node: Node<'_>) -> Option<&Ident> {
+    match node {
+        Node::Decl(Decl::Var(var)) => Some(&var.name),
+        Node::Decl(Decl::Func(func)) => Some(&func.name),
+        Node::Decl(Decl::Struct(def)) => Some(&def.tag),
+        Node::Decl(Decl::Enum(def)) => def.tag.as_ref(),
+        Node::Decl(Decl::Typedef(def)) => Some(&def.name),
+        Node::Param(param) => param.name.as_ref(),
+        Node::Field(field) => Some(&field.name),
+        Node::Enumerator(enumerator) => Some(&enumerator.name),
+        _ => None,
+    }
+}
+
+impl Program {
+    /// Every node of type `T` anywhere in the program, in source order.
+    pub fn find_all<'a, T: Find + 'a>(&'a self) -> impl Iterator<Item = &'a T> {
+        self.find_nodes(|node| T::find(node))
+    }
+
+    /// The declarations, parameters, fields and enumerators named `name` anywhere in the
+    /// program, in source order. Struct, union and enum tags count as names.
+    pub fn find_by_name<'a>(&'a self, name: &str) -> impl Iterator<Item = Node<'a>> {
+        self.find_nodes(|node| declared_name(node).filter(|n| n.name == name).map(|_| node))
+    }
+
+    /// The top-level functions, prototypes included, in source order.
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.stmts
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Decl(decls) => Some(decls),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|decl| match decl {
+                Decl::Func(func) => Some(func),
+                _ => None,
+            })
+    }
+
+    /// Every function call in the program, in source order.
+    pub fn calls(&self) -> impl Iterator<Item = &Expr> {
+        self.find_all::<Expr>()
+            .filter(|expr| matches!(expr.kind, ExprKind::Call(..)))
+    }
+
+    fn find_nodes<'a, T: 'a>(
+        &'a self,
+        find: impl FnMut(Node<'a>) -> Option<T>,
+    ) -> std::vec::IntoIter<T> {
+        let arena = Arena::new(self);
+        let found: Vec<T> = arena
+            .iter()
+            .map(|(_, node)| node)
+            .filter_map(find)
+            .collect();
+        found.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn query_test() {
+        let src =
+            "struct s { int n; }; int f(int n); int main() { int n = f(1); return f(n) + n; }";
+        let program = parse(src).unwrap();
+        let functions: Vec<_> = program.functions().map(|f| f.name.name.as_str()).collect();
+        assert_eq!(functions, ["f", "main"]);
+        let calls: Vec<_> = program.calls().map(|call| call.text(src)).collect();
+        assert_eq!(calls, ["f(1)", "f(n)"]);
+        assert_eq!(program.find_all::<VarDecl>().count(), 1);
+        assert_eq!(program.find_all::<Stmt>().count(), 6);
+
+        let ns: Vec<_> = program.find_by_name("n").map(|node| node.kind()).collect();
+        {
+            use crate::cst::NodeKind::*;
+            assert_eq!(ns, [Field, Param, Decl]);
+        }
+        assert_eq!(program.find_by_name("main").count(), 1);
+        assert_eq!(program.find_by_name("g").count(), 0);
+    }
+}
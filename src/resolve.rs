@@ -0,0 +1,350 @@
+//! Name resolution: which declaration each name used in an expression refers to. Names
+//! follow C's lexical scopes. The globals, functions and top-level enumerators share the file
+//! scope, a function's parameters share the scope of its body, and every block, and every
+//! `for` loop with a declaration in its `init`, opens a scope of its own. A name is in scope
+//! from the end of its declarator, so `int x = x;` reads the `x` being declared.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arena::{Arena, Node, NodeId, SideTable};
+use crate::ast::*;
+use crate::source::Span;
+use crate::visit::*;
+
+/// A declared name of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(u32);
+
+impl SymbolId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Global,
+    Function,
+    Param,
+    /// A variable declared in a block, `static` and `extern` ones included.
+    Local,
+    /// An enumerator with its value.
+    Constant(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub ty: Type,
+    /// The name in its first declaration.
+    pub span: Span,
+}
+
+/// The symbols of a program, the symbol each `ExprKind::Var` refers to, and the one each
+/// `Decl::Var`, `Decl::Func`, named `Param` and `Enumerator` declares, keyed by ids in the
+/// `Arena` the program was resolved with.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    symbols: Vec<Symbol>,
+    pub uses: SideTable<SymbolId>,
+    pub decls: SideTable<SymbolId>,
+    /// Each symbol declared in an inner scope with the name of one it hides from an enclosing
+    /// scope, paired with that one, in the order they are declared.
+    pub shadows: Vec<(SymbolId, SymbolId)>,
+}
+
+impl Symbols {
+    /// Panics if `id` comes from other `Symbols`.
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.index()]
+    }
+
+    /// Every symbol with its id, in the order they are declared.
+    pub fn iter(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        (0..).map(SymbolId).zip(&self.symbols)
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// A name used without a declaration in scope.
+    Undeclared { name: String, span: Span },
+    /// A second declaration of a name in the same scope that doesn't agree with the first: any
+    /// redeclaration in a block, or at file scope one of another kind or type, or a second
+    /// definition of a function.
+    Redeclared {
+        name: String,
+        span: Span,
+        first: Span,
+    },
+}
+
+impl ResolveError {
+    pub fn span(&self) -> Span {
+        match self {
+            ResolveError::Undeclared { span, .. } | ResolveError::Redeclared { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::Undeclared { name, span } => {
+                write!(f, "`{}` is not declared at byte {}", name, span.start)
+            }
+            ResolveError::Redeclared { name, span, first } => write!(
+                f,
+                "`{}` is already declared in this scope (first at byte {}) at byte {}",
+                name, first.start, span.start
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Resolves the names of `program`, which `arena` must be numbering, returning its symbols
+/// along with the undeclared and redeclared names in source order.
+pub fn resolve(program: &Program, arena: &Arena) -> (Symbols, Vec<ResolveError>) {
+    let mut resolver = Resolver {
+        arena,
+        symbols: Symbols::default(),
+        scopes: vec![HashMap::new()],
+        defined: Vec::new(),
+        decl: None,
+        errs: Vec::new(),
+    };
+    resolver.visit_program(program);
+    (resolver.symbols, resolver.errs)
+}
+
+struct Resolver<'a, 'ast> {
+    arena: &'a Arena<'ast>,
+    symbols: Symbols,
+    /// The names declared in each enclosing scope, innermost last.
+    scopes: Vec<HashMap<String, SymbolId>>,
+    /// The functions with a body so far.
+    defined: Vec<SymbolId>,
+    /// The declaration being visited.
+    decl: Option<NodeId>,
+    errs: Vec<ResolveError>,
+}
+
+impl Resolver<'_, '_> {
+    /// Declares `name` in the innermost scope, or finds the symbol it redeclares at file scope,
+    /// recording it as the symbol `decl` declares.
+    fn declare(
+        &mut self,
+        decl: Option<NodeId>,
+        name: &Ident,
+        kind: SymbolKind,
+        ty: Type,
+    ) -> SymbolId {
+        let id = self.declare_name(name, kind, ty);
+        if let Some(decl) = decl {
+            self.symbols.decls.insert(decl, id);
+        }
+        id
+    }
+
+    fn declare_name(&mut self, name: &Ident, kind: SymbolKind, ty: Type) -> SymbolId {
+        let file_scope = self.scopes.len() == 1;
+        if let Some(&id) = self.scopes.last().unwrap().get(&name.name) {
+            let first = self.symbols.get(id);
+            if !(file_scope && first.kind == kind && first.ty == ty) {
+                self.errs.push(ResolveError::Redeclared {
+                    name: name.name.clone(),
+                    span: name.span,
+                    first: first.span,
+                });
+            }
+            return id;
+        }
+        let id = SymbolId(self.symbols.symbols.len() as u32);
+        if let Some(outer) = self.lookup(&name.name).filter(|_| !file_scope) {
+            self.symbols.shadows.push((id, outer));
+        }
+        self.symbols.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            ty,
+            span: name.span,
+        });
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.name.clone(), id);
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<SymbolId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn scoped(&mut self, walk: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        walk(self);
+        self.scopes.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Resolver<'_, 'ast> {
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        match &stmt.kind {
+            StmtKind::Block(_) | StmtKind::For { .. } => self.scoped(|r| walk_stmt(r, stmt)),
+            _ => walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_decl(&mut self, decl: &'ast Decl) {
+        self.decl = self.arena.id(Node::Decl(decl));
+        walk_decl(self, decl);
+    }
+
+    fn visit_var_decl(&mut self, var: &'ast VarDecl) {
+        let kind = match self.scopes.len() {
+            1 => SymbolKind::Global,
+            _ => SymbolKind::Local,
+        };
+        let decl = self.decl.take();
+        self.declare(decl, &var.name, kind, var.ty.clone());
+        walk_var_decl(self, var);
+    }
+
+    fn visit_function(&mut self, func: &'ast Function) {
+        let errs = self.errs.len();
+        let decl = self.decl.take();
+        let id = self.declare(decl, &func.name, SymbolKind::Function, func.ty());
+        let Some(body) = &func.body else {
+            return;
+        };
+        if self.defined.contains(&id) && self.errs.len() == errs {
+            self.errs.push(ResolveError::Redeclared {
+                name: func.name.name.clone(),
+                span: func.name.span,
+                first: self.symbols.get(id).span,
+            });
+        }
+        self.defined.push(id);
+        self.scoped(|r| {
+            for param in &func.params {
+                if let Some(name) = &param.name {
+                    let decl = r.arena.id(Node::Param(param));
+                    r.declare(decl, name, SymbolKind::Param, param.ty.clone());
+                }
+            }
+            // the body shares the parameters' scope
+            match &body.kind {
+                StmtKind::Block(stmts) => stmts.iter().for_each(|stmt| r.visit_stmt(stmt)),
+                _ => r.visit_stmt(body),
+            }
+        });
+    }
+
+    fn visit_enumerator(&mut self, enumerator: &'ast Enumerator) {
+        let kind = SymbolKind::Constant(enumerator.value);
+        let decl = self.arena.id(Node::Enumerator(enumerator));
+        self.declare(decl, &enumerator.name, kind, Type::Int);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        if let ExprKind::Var(name) = &expr.kind {
+            match (self.lookup(name), self.arena.expr_id(expr)) {
+                (Some(symbol), Some(id)) => {
+                    self.symbols.uses.insert(id, symbol);
+                }
+                (Some(_), None) => {}
+                (None, _) => self.errs.push(ResolveError::Undeclared {
+                    name: name.clone(),
+                    span: expr.span,
+                }),
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn resolve_test() {
+        let src = "enum { K = 2 }; int g; int f(int a); int f(int a) { int b = a + g; \
+                   { int a = K; b = a; } for (int i = 0; i < b; i++) g = i; return f(b); }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let decls: Vec<_> = symbols
+            .decls
+            .iter()
+            .map(|(_, &symbol)| symbols.get(symbol).name.as_str())
+            .collect();
+        assert_eq!(decls, ["K", "g", "f", "f", "a", "b", "a", "i"]);
+        let uses: Vec<_> = symbols
+            .uses
+            .iter()
+            .map(|(id, &symbol)| {
+                let symbol = symbols.get(symbol);
+                let span = arena.span(id);
+                format!(
+                    "{}@{}->{:?}@{}",
+                    symbol.name, span.start, symbol.kind, symbol.span.start
+                )
+            })
+            .collect();
+        assert_eq!(
+            uses,
+            [
+                "a@60->Param@47",
+                "g@64->Global@20",
+                "K@77->Constant(2)@7",
+                "b@80->Local@56",
+                "a@84->Local@73",
+                "i@105->Local@98",
+                "b@109->Local@56",
+                "i@112->Local@98",
+                "g@117->Global@20",
+                "i@121->Local@98",
+                "f@131->Function@27",
+                "b@133->Local@56",
+            ]
+        );
+
+        let src = "int f(int a) { int a; { int b; int b; } return c; } int f() { return 0; } \
+                   int f(int a) { return a; }";
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let errs: Vec<_> = resolve(&program, &arena)
+            .1
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errs,
+            [
+                "`a` is already declared in this scope (first at byte 10) at byte 19",
+                "`b` is already declared in this scope (first at byte 28) at byte 35",
+                "`c` is not declared at byte 47",
+                "`f` is already declared in this scope (first at byte 4) at byte 56",
+                "`f` is already declared in this scope (first at byte 4) at byte 78",
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Positions in sources: byte spans, line and column lookup, and the `SourceManager` giving
+//! each file of a compilation a `FileId`.
+//!
+//! Only the preprocessor's tokens, `PpTok`, and `LexedFile`s record the `FileId` they come
+//! from. A lexer `Tok` and every `Span` of the AST are bare byte offsets into the one string
+//! that was lexed or parsed, so parser, type and lint diagnostics can't name their file by
+//! themselves: the caller that handed the source over knows its `FileId` and pairs the two
+//! as a `FileSpan`, or a `Location` through `SourceManager::location`. Carrying the file on
+//! every token and node, as multi-file builds will want, is still to do.
+
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use crate::json::{FromJson, Json, JsonError, ToJson};
+use crate::prelude::*;
+
+/// A half-open range of byte offsets `start..end` into a source string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        Json::obj([
+            ("start", Json::Num(self.start as i64)),
+            ("end", Json::Num(self.end as i64)),
+        ])
+    }
+}
+
+impl FromJson for Span {
+    fn from_json(json: &Json) -> Result<Span, JsonError> {
+        let offset = |key| {
+            let n = json.field(key)?.as_num().filter(|&n| n >= 0);
+            n.map(|n| n as usize)
+                .ok_or_else(|| Json::type_error("a byte offset"))
+        };
+        Ok(Span::new(offset("start")?, offset("end")?))
+    }
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// The text of `src` this span covers.
+    pub fn text(self, src: &str) -> &str {
+        &src[self.start..self.end]
+    }
+}
+
+/// 1-based line and column of a position in a source string.
+///
+/// Columns are counted in bytes from the start of the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Lookup table from byte offsets to line/column pairs, built once per source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(src: &str) -> LineIndex {
+        let mut line_starts = vec![0];
+        line_starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { line_starts }
+    }
+
+    /// Offsets past the end of the source map onto the last line.
+    pub fn line_col(&self, pos: usize) -> LineCol {
+        let line = self.line_starts.partition_point(|&start| start <= pos) - 1;
+        LineCol {
+            line: line + 1,
+            col: pos - self.line_starts[line] + 1,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+/// Identifies a file registered with a `SourceManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(u32);
+
+impl FileId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A span in a particular file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileSpan {
+    pub file: FileId,
+    pub span: Span,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceFile {
+    path: PathBuf,
+    src: String,
+    lines: LineIndex,
+}
+
+#[cfg(feature = "std")]
+impl SourceFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn line_index(&self) -> &LineIndex {
+        &self.lines
+    }
+}
+
+/// A position resolved to a file, line and column, displayed as `path:line:col`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    pub path: &'a Path,
+    pub line_col: LineCol,
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.path.display(),
+            self.line_col.line,
+            self.line_col.col
+        )
+    }
+}
+
+/// Owns the sources of a compilation, handing out a `FileId` for each.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceManager {
+    files: Vec<SourceFile>,
+}
+
+#[cfg(feature = "std")]
+impl SourceManager {
+    pub fn new() -> SourceManager {
+        SourceManager::default()
+    }
+
+    /// Registers `src` as the contents of the file at `path`.
+    pub fn add(&mut self, path: impl Into<PathBuf>, src: impl Into<String>) -> FileId {
+        let src = src.into();
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile {
+            path: path.into(),
+            lines: LineIndex::new(&src),
+            src,
+        });
+        id
+    }
+
+    /// Reads the file at `path` and registers it.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<FileId> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path)?;
+        Ok(self.add(path, src))
+    }
+
+    /// Panics if `id` comes from a different `SourceManager`.
+    pub fn get(&self, id: FileId) -> &SourceFile {
+        &self.files[id.index()]
+    }
+
+    /// The most recently added file at `path`, if any.
+    pub fn find(&self, path: &Path) -> Option<FileId> {
+        let i = self.files.iter().rposition(|f| f.path == path)?;
+        Some(FileId(i as u32))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        (0..).map(FileId).zip(&self.files)
+    }
+
+    pub fn location(&self, file: FileId, pos: usize) -> Location<'_> {
+        let file = self.get(file);
+        Location {
+            path: &file.path,
+            line_col: file.lines.line_col(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_test() {
+        let idx = LineIndex::new("int a;\nchar b;\n\nc");
+        assert_eq!(idx.line_col(0), LineCol { line: 1, col: 1 });
+        assert_eq!(idx.line_col(6), LineCol { line: 1, col: 7 });
+        assert_eq!(idx.line_col(7), LineCol { line: 2, col: 1 });
+        assert_eq!(idx.line_col(12), LineCol { line: 2, col: 6 });
+        assert_eq!(idx.line_col(15), LineCol { line: 3, col: 1 });
+        assert_eq!(idx.line_col(16), LineCol { line: 4, col: 1 });
+        assert_eq!(idx.line_count(), 4);
+    }
+
+    #[test]
+    fn span_test() {
+        let a = Span::new(2, 5);
+        let b = Span::new(8, 9);
+        assert_eq!(a.to(b), Span::new(2, 9));
+        assert_eq!(b.to(a), Span::new(2, 9));
+        assert!(a.contains(4) && !a.contains(5));
+        assert_eq!(a.text("int a;"), "t a");
+        assert_eq!(a.len(), 3);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn source_manager_test() {
+        let mut sm = SourceManager::new();
+        let a = sm.add("a.c8", "int a;\n");
+        let b = sm.add("inc/b.h", "\n\nchar b;");
+        assert_ne!(a, b);
+        assert_eq!(sm.len(), 2);
+        assert_eq!(sm.get(b).src(), "\n\nchar b;");
+        assert_eq!(sm.find(Path::new("inc/b.h")), Some(b));
+        assert_eq!(sm.location(b, 7).to_string(), "inc/b.h:3:6");
+        assert_eq!(sm.location(a, 4).line_col, LineCol { line: 1, col: 5 });
+    }
+}
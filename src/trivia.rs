@@ -0,0 +1,119 @@
+use crate::lexer::{Tok, TokKind::*};
+use crate::prelude::*;
+
+/// A significant token together with the trivia around it.
+///
+/// Trailing trivia runs up to, but not including, the next newline; everything else between
+/// two significant tokens is leading trivia of the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriviaTok<'a, 'src> {
+    pub leading: &'a [Tok<'src>],
+    pub tok: &'a Tok<'src>,
+    pub trailing: &'a [Tok<'src>],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriviaStream<'a, 'src> {
+    pub toks: Vec<TriviaTok<'a, 'src>>,
+    /// Trivia after the last significant token.
+    pub eof: &'a [Tok<'src>],
+}
+
+impl<'a, 'src> TriviaTok<'a, 'src> {
+    /// Iterates over the leading trivia, the token and the trailing trivia in source order.
+    pub fn all(&self) -> impl Iterator<Item = &'a Tok<'src>> {
+        self.leading
+            .iter()
+            .chain(core::iter::once(self.tok))
+            .chain(self.trailing.iter())
+    }
+}
+
+/// Iterates over the tokens of `toks` that aren't trivia.
+pub fn significant_tokens<'a, 'src>(
+    toks: &'a [Tok<'src>],
+) -> impl Iterator<Item = &'a Tok<'src>> + Clone {
+    toks.iter().filter(|t| !t.kind.is_trivia())
+}
+
+/// Groups a raw token stream into significant tokens with their attached trivia. Every token of
+/// `toks` ends up in exactly one place, so no information is lost.
+pub fn attach_trivia<'a, 'src>(toks: &'a [Tok<'src>]) -> TriviaStream<'a, 'src> {
+    let mut out = Vec::new();
+    let mut ind = 0;
+    loop {
+        let leading_start = ind;
+        while ind < toks.len() && toks[ind].kind.is_trivia() {
+            ind += 1;
+        }
+        if ind == toks.len() {
+            return TriviaStream {
+                toks: out,
+                eof: &toks[leading_start..],
+            };
+        }
+        let tok = &toks[ind];
+        let leading = &toks[leading_start..ind];
+        ind += 1;
+        let trailing_start = ind;
+        while ind < toks.len() && toks[ind].kind.is_trivia() && toks[ind].kind != Nl {
+            ind += 1;
+        }
+        out.push(TriviaTok {
+            leading,
+            tok,
+            trailing: &toks[trailing_start..ind],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{tokenize, LexError};
+
+    #[test]
+    fn attach_trivia_test() -> Result<(), LexError> {
+        let src = "// header\nint x; // trailing\n\n  x = 1;\n";
+        let toks = tokenize(src)?;
+        let stream = attach_trivia(&toks);
+        let sig: Vec<&str> = stream.toks.iter().map(|t| t.tok.str).collect();
+        assert_eq!(sig, vec!["int", "x", ";", "x", "=", "1", ";"]);
+        let first = &stream.toks[0];
+        assert_eq!(
+            first.leading.iter().map(|t| t.str).collect::<String>(),
+            "// header\n"
+        );
+        let semi = &stream.toks[2];
+        assert_eq!(
+            semi.trailing.iter().map(|t| t.str).collect::<String>(),
+            " // trailing"
+        );
+        assert_eq!(
+            stream.toks[3]
+                .leading
+                .iter()
+                .map(|t| t.str)
+                .collect::<String>(),
+            "\n\n  "
+        );
+        assert_eq!(stream.eof.len(), 1);
+        let rebuilt: String = stream
+            .toks
+            .iter()
+            .flat_map(|t| t.all())
+            .chain(stream.eof)
+            .map(|t| t.str)
+            .collect();
+        assert_eq!(rebuilt, src);
+        Ok(())
+    }
+
+    #[test]
+    fn significant_tokens_test() -> Result<(), LexError> {
+        let toks = tokenize("a /* b */ =\r\n\t1;")?;
+        let sig: Vec<(usize, &str)> = significant_tokens(&toks).map(|t| (t.pos, t.str)).collect();
+        assert_eq!(sig, vec![(0, "a"), (10, "="), (14, "1"), (15, ";")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+//! Type checking of a resolved program. Every expression gets a type, recorded by its id in
+//! the program's `Arena`, and the operations that don't fit their operands are reported:
+//! arithmetic on values that aren't integers, comparisons of unrelated pointers,
+//! assignments, arguments and returned values that can't convert to their target, calls with
+//! the wrong number of arguments, and indexing of values that aren't arrays or pointers.
+//!
+//! The type recorded is that of the expression itself, before arrays and functions decay, so
+//! that codegen can tell an array from a pointer. An expression whose operands are already in
+//! error gets no type and no further errors.
+
+use std::fmt;
+
+use crate::arena::{Arena, SideTable};
+use crate::ast::*;
+use crate::resolve::{SymbolKind, Symbols};
+use crate::source::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub span: Span,
+    pub msg: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.msg, self.span.start)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// Type checks `program`, which `arena` must be numbering and `symbols` must resolve, and
+/// returns the type of each expression along with the errors in source order.
+pub fn check_types(
+    program: &Program,
+    arena: &Arena,
+    symbols: &Symbols,
+) -> (SideTable<Type>, Vec<TypeError>) {
+    let mut checker = Checker {
+        arena,
+        symbols,
+        structs: program.find_all::<StructDef>().cloned().collect(),
+        ret: None,
+        types: SideTable::new(),
+        errs: Vec::new(),
+    };
+    for stmt in &program.stmts {
+        checker.stmt(stmt);
+    }
+    (checker.types, checker.errs)
+}
+
+/// Whether `expr` is the null pointer constant, the literal `0`.
+fn is_null(expr: &Expr) -> bool {
+    matches!(expr.kind, ExprKind::Int(0))
+}
+
+/// Whether a value of the type can be tested for truth: an integer or a pointer.
+fn is_scalar(ty: &Type) -> bool {
+    ty.is_integer() || matches!(ty.decayed(), Type::Pointer(_))
+}
+
+/// The type a pointer of type `ty` points to, if it is one after decaying.
+fn pointee(ty: &Type) -> Option<Type> {
+    match ty.decayed() {
+        Type::Pointer(to) => Some(*to),
+        _ => None,
+    }
+}
+
+/// The smallest of `int`, `unsigned int`, `long` and `unsigned long` holding `value`.
+pub(crate) fn int_type(value: u64) -> Type {
+    match value {
+        0..=0x7fff => Type::Int,
+        0x8000..=0xffff => Type::UInt,
+        0x1_0000..=0x7fff_ffff => Type::Long,
+        _ => Type::ULong,
+    }
+}
+
+struct Checker<'a, 'ast> {
+    arena: &'a Arena<'ast>,
+    symbols: &'a Symbols,
+    structs: Vec<StructDef>,
+    /// The return type of the function being checked.
+    ret: Option<Type>,
+    types: SideTable<Type>,
+    errs: Vec<TypeError>,
+}
+
+impl<'ast> Checker<'_, 'ast> {
+    fn error<T>(&mut self, span: Span, msg: String) -> Option<T> {
+        self.errs.push(TypeError { span, msg });
+        None
+    }
+
+    fn stmt(&mut self, stmt: &'ast Stmt) {
+        match &stmt.kind {
+            StmtKind::Decl(decls) => {
+                for decl in decls {
+                    match decl {
+                        Decl::Var(var) => {
+                            if let Some(init) = &var.init {
+                                self.init(&var.ty, init);
+                            }
+                        }
+                        Decl::Func(func) => {
+                            if let Some(body) = &func.body {
+                                let outer = self.ret.replace(func.ret.clone());
+                                self.stmt(body);
+                                self.ret = outer;
+                            }
+                        }
+                        Decl::Struct(_) | Decl::Enum(_) | Decl::Typedef(_) => {}
+                    }
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.expr(expr);
+            }
+            StmtKind::Block(stmts) => stmts.iter().for_each(|stmt| self.stmt(stmt)),
+            StmtKind::If { cond, then, els } => {
+                self.cond(cond);
+                self.stmt(then);
+                if let Some(els) = els {
+                    self.stmt(els);
+                }
+            }
+            StmtKind::While { cond, body } | StmtKind::DoWhile { body, cond } => {
+                self.cond(cond);
+                self.stmt(body);
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.cond(cond);
+                }
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.stmt(body);
+            }
+            StmtKind::Switch { cond, body } => {
+                if let Some(ty) = self.expr(cond) {
+                    if !ty.is_integer() {
+                        let msg = format!("can't switch on a value of type `{}`", ty);
+                        self.error::<()>(cond.span, msg);
+                    }
+                }
+                self.stmt(body);
+            }
+            StmtKind::Case { value, stmt } => {
+                self.expr(value);
+                self.stmt(stmt);
+            }
+            StmtKind::Default(stmt) | StmtKind::Label { stmt, .. } => self.stmt(stmt),
+            StmtKind::Return(value) => {
+                let ret = self.ret.clone().unwrap_or(Type::Void);
+                match value {
+                    // the parser rejects these
+                    Some(value) if ret == Type::Void => {
+                        self.expr(value);
+                    }
+                    Some(value) => self.convert(&ret, value, |ty| {
+                        format!("can't return `{}` from a function returning `{}`", ty, ret)
+                    }),
+                    None => {}
+                }
+            }
+            StmtKind::Break | StmtKind::Continue | StmtKind::Goto(_) | StmtKind::Empty => {}
+        }
+    }
+
+    /// Checks a condition, which must be a scalar.
+    fn cond(&mut self, cond: &'ast Expr) {
+        if let Some(ty) = self.expr(cond) {
+            if !is_scalar(&ty) {
+                let msg = format!("a condition can't have type `{}`", ty);
+                self.error::<()>(cond.span, msg);
+            }
+        }
+    }
+
+    /// Checks an initializer for a `ty`. The parser has checked the shape of lists already.
+    fn init(&mut self, ty: &Type, init: &'ast Init) {
+        match init {
+            // a string literal initializes a `char` array
+            Init::Expr(
+                expr @ Expr {
+                    kind: ExprKind::Str(_),
+                    ..
+                },
+            ) if matches!(ty.unqualified(), Type::Array { .. }) => {
+                self.expr(expr);
+            }
+            Init::Expr(expr) => self.convert(ty, expr, |value| {
+                format!("can't initialize `{}` with `{}`", ty, value)
+            }),
+            Init::List { items, .. } => {
+                let types: Vec<Type> = match ty.unqualified() {
+                    Type::Array { elem, .. } => vec![(**elem).clone(); items.len()],
+                    Type::Struct(tag) | Type::Union(tag) => self
+                        .structs
+                        .iter()
+                        .find(|s| s.tag.name == *tag)
+                        .map(|def| def.fields.iter().map(|f| f.ty.clone()).collect())
+                        .unwrap_or_default(),
+                    ty => vec![ty.clone()],
+                };
+                for (item, ty) in items.iter().zip(&types) {
+                    self.init(ty, item);
+                }
+            }
+        }
+    }
+
+    /// Checks that `value` converts implicitly to `target`, reporting it with `msg` of the
+    /// value's type if not.
+    fn convert(&mut self, target: &Type, value: &'ast Expr, msg: impl FnOnce(&Type) -> String) {
+        let Some(ty) = self.expr(value) else {
+            return;
+        };
+        if !self.converts(&ty, value, target) {
+            self.error::<()>(value.span, msg(&ty));
+        }
+    }
+
+    /// Whether `value`, of type `ty`, converts implicitly to `target`: integers to each
+    /// other, scalars to `bool`, a pointer to one of the same type with at least its
+    /// qualifiers, any pointer to or from `void *`, `0` to any pointer, and a struct or union
+    /// to the same.
+    fn converts(&self, ty: &Type, value: &Expr, target: &Type) -> bool {
+        let (ty, target) = (ty.decayed(), target.decayed());
+        match (&ty, &target) {
+            _ if ty.is_integer() && target.is_integer() => {
+                !matches!(target, Type::Bool) || is_scalar(&ty)
+            }
+            (Type::Pointer(_), Type::Bool) => true,
+            (_, Type::Pointer(_)) if ty.is_integer() => is_null(value),
+            (Type::Pointer(from), Type::Pointer(to)) => {
+                let quals = from.quals();
+                let void = *from.unqualified() == Type::Void || *to.unqualified() == Type::Void;
+                (void || from.unqualified() == to.unqualified()) && to.quals().contains(quals)
+            }
+            _ => ty == target,
+        }
+    }
+
+    /// The type of `expr`, recorded by its id, or `None` if it is in error.
+    fn expr(&mut self, expr: &'ast Expr) -> Option<Type> {
+        let ty = self.expr_type(expr)?;
+        if let Some(id) = self.arena.expr_id(expr) {
+            self.types.insert(id, ty.clone());
+        }
+        Some(ty)
+    }
+
+    /// Whether `expr` designates an object that can be assigned to, reporting it if not.
+    fn assignable(&mut self, expr: &Expr, ty: &Type) -> Option<()> {
+        let lvalue = match &expr.kind {
+            ExprKind::Var(_) => !matches!(ty, Type::Function { .. }),
+            ExprKind::Unary(UnOp::Deref, _) | ExprKind::Index(..) | ExprKind::Arrow(..) => true,
+            ExprKind::Member(value, _) => self.is_lvalue(value),
+            _ => false,
+        };
+        let constant = matches!(ty, Type::Array { .. }) || ty.quals().constant;
+        match (lvalue, constant) {
+            (true, false) => Some(()),
+            (false, _) => self.error(expr.span, "can't assign to this expression".to_string()),
+            (true, true) => self.error(expr.span, format!("can't assign to a `{}`", ty)),
+        }
+    }
+
+    fn is_lvalue(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Var(_) | ExprKind::Str(_) => true,
+            ExprKind::Unary(UnOp::Deref, _) | ExprKind::Index(..) | ExprKind::Arrow(..) => true,
+            ExprKind::Member(value, _) => self.is_lvalue(value),
+            _ => false,
+        }
+    }
+
+    /// The type of a field of a struct or union of type `aggregate`.
+    fn field(&mut self, aggregate: &Type, field: &Ident) -> Option<Type> {
+        let (Type::Struct(tag) | Type::Union(tag)) = aggregate.unqualified() else {
+            let msg = format!("`{}` has no fields", aggregate);
+            return self.error(field.span, msg);
+        };
+        let def = self.structs.iter().find(|s| s.tag.name == *tag);
+        match def.and_then(|def| def.field(&field.name)) {
+            Some(f) => Some(f.ty.clone().qualified(aggregate.quals())),
+            None => {
+                let msg = format!("`{}` has no field `{}`", aggregate, field.name);
+                self.error(field.span, msg)
+            }
+        }
+    }
+
+    /// The type of `lhs op rhs`, given the operand types.
+    fn binary(
+        &mut self,
+        op: BinOp,
+        lhs: (&Type, &Expr),
+        rhs: (&Type, &Expr),
+        span: Span,
+    ) -> Option<Type> {
+        let (a, b) = (lhs.0.decayed(), rhs.0.decayed());
+        let (a_ptr, b_ptr) = (pointee(&a).is_some(), pointee(&b).is_some());
+        let ints = a.is_integer() && b.is_integer();
+        let ty = match op {
+            BinOp::Add if a_ptr && b.is_integer() => Some(a.clone()),
+            BinOp::Add if a.is_integer() && b_ptr => Some(b.clone()),
+            BinOp::Sub if a_ptr && b.is_integer() => Some(a.clone()),
+            BinOp::Sub if a_ptr && b_ptr && a.common(&b) == Some(a.clone()) => Some(Type::Int),
+            BinOp::Shl | BinOp::Shr if ints => Some(a.clone()),
+            _ if op.is_boolean() => {
+                let comparable = ints
+                    || matches!(op, BinOp::And | BinOp::Or) && is_scalar(&a) && is_scalar(&b)
+                    || a_ptr && b_ptr && a.common(&b).is_some()
+                    || a_ptr && is_null(rhs.1)
+                    || b_ptr && is_null(lhs.1);
+                comparable.then_some(Type::Bool)
+            }
+            _ if ints => a.common(&b),
+            _ => None,
+        };
+        if ty.is_none() {
+            let msg = format!("can't apply `{}` to `{}` and `{}`", op.symbol(), a, b);
+            return self.error(span, msg);
+        }
+        ty
+    }
+
+    fn expr_type(&mut self, expr: &'ast Expr) -> Option<Type> {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Int(value) => Some(int_type(*value)),
+            ExprKind::Char(_) => Some(Type::Char),
+            ExprKind::Str(bytes) => Some(Type::Array {
+                elem: Box::new(Type::Char),
+                len: bytes.len() as u64 + 1,
+            }),
+            ExprKind::Var(_) => {
+                let id = self.arena.expr_id(expr)?;
+                let symbol = self.symbols.get(*self.symbols.uses.get(id)?);
+                match symbol.kind {
+                    SymbolKind::Constant(_) => Some(Type::Int),
+                    _ => Some(symbol.ty.clone()),
+                }
+            }
+            ExprKind::Unary(op, operand) => {
+                let ty = self.expr(operand)?;
+                let value = ty.decayed();
+                match op {
+                    UnOp::Neg | UnOp::Plus | UnOp::BitNot if value.is_integer() => Some(value),
+                    UnOp::Not if is_scalar(&value) => Some(Type::Bool),
+                    UnOp::PreInc | UnOp::PreDec | UnOp::PostInc | UnOp::PostDec
+                        if is_scalar(&value) =>
+                    {
+                        self.assignable(operand, &ty)?;
+                        Some(value)
+                    }
+                    UnOp::Deref => match pointee(&ty) {
+                        Some(to) if *to.unqualified() != Type::Void => Some(to),
+                        _ => self.error(span, format!("can't dereference a `{}`", ty)),
+                    },
+                    UnOp::Addr if self.is_lvalue(operand) => Some(Type::Pointer(Box::new(ty))),
+                    UnOp::Addr => self.error(span, "can't take the address of this".to_string()),
+                    _ => self.error(span, format!("can't apply `{}` to `{}`", op.symbol(), ty)),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (a, b) = (self.expr(lhs), self.expr(rhs));
+                self.binary(*op, (&a?, lhs), (&b?, rhs), span)
+            }
+            ExprKind::Assign(op, lhs, rhs) => {
+                let (target, value) = (self.expr(lhs)?, self.expr(rhs));
+                self.assignable(lhs, &target)?;
+                let value = value?;
+                match op {
+                    Some(op) => {
+                        self.binary(*op, (&target, lhs), (&value, rhs), span)?;
+                    }
+                    None if !self.converts(&value, rhs, &target) => {
+                        let msg = format!("can't assign `{}` to `{}`", value, target);
+                        return self.error(rhs.span, msg);
+                    }
+                    None => {}
+                }
+                Some(target.decayed())
+            }
+            ExprKind::Index(array, index) => {
+                let (a, i) = (self.expr(array)?, self.expr(index)?);
+                let (elem, i) = match (pointee(&a), pointee(&i)) {
+                    (Some(elem), None) => (elem, i),
+                    (None, Some(elem)) => (elem, a),
+                    _ => return self.error(span, format!("can't index a `{}`", a)),
+                };
+                if !i.is_integer() {
+                    return self.error(index.span, format!("can't index with a `{}`", i));
+                }
+                Some(elem)
+            }
+            ExprKind::Member(value, field) => {
+                let ty = self.expr(value)?;
+                self.field(&ty, field)
+            }
+            ExprKind::Arrow(value, field) => {
+                let ty = self.expr(value)?;
+                match pointee(&ty) {
+                    Some(to) => self.field(&to, field),
+                    None => self.error(span, format!("can't use `->` on a `{}`", ty)),
+                }
+            }
+            ExprKind::Cast(target, operand) => {
+                let ty = self.expr(operand)?;
+                if *target != Type::Void && !(is_scalar(&ty) && is_scalar(target)) {
+                    return self.error(span, format!("can't cast `{}` to `{}`", ty, target));
+                }
+                Some(target.clone())
+            }
+            ExprKind::Sizeof(_) => Some(Type::Int),
+            ExprKind::SizeofExpr(operand) => {
+                self.expr(operand)?;
+                Some(Type::Int)
+            }
+            ExprKind::Comma(lhs, rhs) => {
+                self.expr(lhs);
+                Some(self.expr(rhs)?.decayed())
+            }
+            ExprKind::Cond(cond, then, els) => {
+                self.cond(cond);
+                let (a, b) = (self.expr(then)?, self.expr(els)?);
+                let common = match (pointee(&a).is_some(), pointee(&b).is_some()) {
+                    (true, false) if is_null(els) => Some(a.decayed()),
+                    (false, true) if is_null(then) => Some(b.decayed()),
+                    _ => a.common(&b),
+                };
+                match common {
+                    Some(ty) => Some(ty),
+                    None => self.error(span, format!("mismatched arms `{}` and `{}`", a, b)),
+                }
+            }
+            ExprKind::Call(callee, args) => {
+                let callee_ty = self.expr(callee);
+                let arg_types: Vec<_> = args.iter().map(|arg| self.expr(arg)).collect();
+                let callee_ty = callee_ty?;
+                let Some(Type::Function { ret, params }) = pointee(&callee_ty) else {
+                    return self.error(span, format!("can't call a `{}`", callee_ty));
+                };
+                if args.len() != params.len() {
+                    let msg = format!(
+                        "the function takes {} argument(s) but {} were given",
+                        params.len(),
+                        args.len()
+                    );
+                    return self.error(span, msg);
+                }
+                for ((arg, ty), param) in args.iter().zip(arg_types).zip(&params) {
+                    if let Some(ty) = ty {
+                        if !self.converts(&ty, arg, param) {
+                            let msg = format!("can't pass `{}` as `{}`", ty, param);
+                            self.error::<()>(arg.span, msg);
+                        }
+                    }
+                }
+                Some(*ret)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    fn check(src: &str) -> (Vec<String>, Vec<String>) {
+        let program = parse(src).unwrap();
+        let arena = Arena::new(&program);
+        let (symbols, errs) = resolve(&program, &arena);
+        assert_eq!(errs, []);
+        let (types, errs) = check_types(&program, &arena, &symbols);
+        let types = types
+            .iter()
+            .map(|(id, ty)| format!("{}: {}", arena.get(id).text(src), ty))
+            .collect();
+        (types, errs.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn typeck_test() {
+        let (types, errs) =
+            check("struct p { char x; }; int f(char *s, struct p *q) { return s[1] + q->x; }");
+        assert_eq!(errs, Vec::<String>::new());
+        assert_eq!(
+            types,
+            [
+                "s[1] + q->x: char",
+                "s[1]: char",
+                "s: char*",
+                "1: int",
+                "q->x: char",
+                "q: struct p*"
+            ]
+        );
+
+        let (_, errs) = check(
+            "const int k = 1; int g(int a); void h() { int *p; char c; \
+             p = c; p = 0; k = 2; g(1, 2); g(p); c[1]; p * 2; p == 0; }\n\
+             struct s { int x; } t; int r() { return t; } int *q = t;",
+        );
+        assert_eq!(
+            errs,
+            [
+                "can't assign `char` to `int*` at byte 62",
+                "can't assign to a `const int` at byte 72",
+                "the function takes 1 argument(s) but 2 were given at byte 79",
+                "can't pass `int*` as `int` at byte 90",
+                "can't index a `char` at byte 94",
+                "can't apply `*` to `int*` and `int` at byte 100",
+                "can't return `struct s` from a function returning `int` at byte 157",
+                "can't initialize `int*` with `struct s` at byte 171",
+            ]
+        );
+    }
+}
@@ -0,0 +1,584 @@
+//! Traversal of the tree. `Visit` walks it by shared reference and `VisitMut` by mutable
+//! reference, with a method per kind of node. Each method's default calls the matching
+//! `walk_*` function, which visits the node's children in source order, so a pass overrides
+//! only the nodes it cares about and calls `walk_*` itself to keep descending.
+
+use crate::ast::*;
+
+/// Visits a tree by shared reference. The `'ast` lifetime lets a visitor keep references to
+/// the nodes it sees.
+pub trait Visit<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_stmt(&mut self, stmt: &'ast Stmt) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_decl(&mut self, decl: &'ast Decl) {
+        walk_decl(self, decl)
+    }
+
+    fn visit_var_decl(&mut self, var: &'ast VarDecl) {
+        walk_var_decl(self, var)
+    }
+
+    fn visit_function(&mut self, func: &'ast Function) {
+        walk_function(self, func)
+    }
+
+    fn visit_param(&mut self, param: &'ast Param) {
+        walk_param(self, param)
+    }
+
+    fn visit_struct_def(&mut self, def: &'ast StructDef) {
+        walk_struct_def(self, def)
+    }
+
+    fn visit_field(&mut self, field: &'ast Field) {
+        walk_field(self, field)
+    }
+
+    fn visit_enum_def(&mut self, def: &'ast EnumDef) {
+        walk_enum_def(self, def)
+    }
+
+    fn visit_enumerator(&mut self, enumerator: &'ast Enumerator) {
+        walk_enumerator(self, enumerator)
+    }
+
+    fn visit_typedef(&mut self, def: &'ast Typedef) {
+        walk_typedef(self, def)
+    }
+
+    fn visit_init(&mut self, init: &'ast Init) {
+        walk_init(self, init)
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    /// Called for the type written in a declaration, cast or `sizeof`.
+    fn visit_type(&mut self, ty: &'ast Type) {
+        walk_type(self, ty)
+    }
+
+    /// Called for every name with a span: declared names, tags, labels and fields. A variable
+    /// used in an expression is an `ExprKind::Var` instead.
+    fn visit_ident(&mut self, _ident: &'ast Ident) {}
+}
+
+pub fn walk_program<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, program: &'ast Program) {
+    for stmt in &program.stmts {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, stmt: &'ast Stmt) {
+    match &stmt.kind {
+        StmtKind::Decl(decls) => {
+            for decl in decls {
+                v.visit_decl(decl);
+            }
+        }
+        StmtKind::Expr(expr) => v.visit_expr(expr),
+        StmtKind::Block(stmts) => {
+            for stmt in stmts {
+                v.visit_stmt(stmt);
+            }
+        }
+        StmtKind::If { cond, then, els } => {
+            v.visit_expr(cond);
+            v.visit_stmt(then);
+            if let Some(els) = els {
+                v.visit_stmt(els);
+            }
+        }
+        StmtKind::While { cond, body } | StmtKind::Switch { cond, body } => {
+            v.visit_expr(cond);
+            v.visit_stmt(body);
+        }
+        StmtKind::DoWhile { body, cond } => {
+            v.visit_stmt(body);
+            v.visit_expr(cond);
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                v.visit_stmt(init);
+            }
+            for expr in cond.iter().chain(step) {
+                v.visit_expr(expr);
+            }
+            v.visit_stmt(body);
+        }
+        StmtKind::Case { value, stmt } => {
+            v.visit_expr(value);
+            v.visit_stmt(stmt);
+        }
+        StmtKind::Default(stmt) => v.visit_stmt(stmt),
+        StmtKind::Label { label, stmt } => {
+            v.visit_ident(label);
+            v.visit_stmt(stmt);
+        }
+        StmtKind::Goto(label) => v.visit_ident(label),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                v.visit_expr(value);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue | StmtKind::Empty => {}
+    }
+}
+
+pub fn walk_decl<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, decl: &'ast Decl) {
+    match decl {
+        Decl::Var(var) => v.visit_var_decl(var),
+        Decl::Func(func) => v.visit_function(func),
+        Decl::Struct(def) => v.visit_struct_def(def),
+        Decl::Enum(def) => v.visit_enum_def(def),
+        Decl::Typedef(def) => v.visit_typedef(def),
+    }
+}
+
+pub fn walk_var_decl<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, var: &'ast VarDecl) {
+    v.visit_type(&var.ty);
+    v.visit_ident(&var.name);
+    if let Some(init) = &var.init {
+        v.visit_init(init);
+    }
+}
+
+pub fn walk_function<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, func: &'ast Function) {
+    v.visit_type(&func.ret);
+    v.visit_ident(&func.name);
+    for param in &func.params {
+        v.visit_param(param);
+    }
+    if let Some(body) = &func.body {
+        v.visit_stmt(body);
+    }
+}
+
+pub fn walk_param<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, param: &'ast Param) {
+    v.visit_type(&param.ty);
+    if let Some(name) = &param.name {
+        v.visit_ident(name);
+    }
+}
+
+pub fn walk_struct_def<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, def: &'ast StructDef) {
+    v.visit_ident(&def.tag);
+    for field in &def.fields {
+        v.visit_field(field);
+    }
+}
+
+pub fn walk_field<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, field: &'ast Field) {
+    v.visit_type(&field.ty);
+    v.visit_ident(&field.name);
+}
+
+pub fn walk_enum_def<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, def: &'ast EnumDef) {
+    if let Some(tag) = &def.tag {
+        v.visit_ident(tag);
+    }
+    for enumerator in &def.enumerators {
+        v.visit_enumerator(enumerator);
+    }
+}
+
+pub fn walk_enumerator<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, enumerator: &'ast Enumerator) {
+    v.visit_ident(&enumerator.name);
+}
+
+pub fn walk_typedef<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, def: &'ast Typedef) {
+    v.visit_type(&def.ty);
+    v.visit_ident(&def.name);
+}
+
+pub fn walk_init<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, init: &'ast Init) {
+    match init {
+        Init::Expr(expr) => v.visit_expr(expr),
+        Init::List { items, .. } => {
+            for item in items {
+                v.visit_init(item);
+            }
+        }
+    }
+}
+
+pub fn walk_expr<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::Unary(_, operand) | ExprKind::SizeofExpr(operand) => v.visit_expr(operand),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::Assign(_, lhs, rhs)
+        | ExprKind::Index(lhs, rhs)
+        | ExprKind::Comma(lhs, rhs) => {
+            v.visit_expr(lhs);
+            v.visit_expr(rhs);
+        }
+        ExprKind::Member(value, field) | ExprKind::Arrow(value, field) => {
+            v.visit_expr(value);
+            v.visit_ident(field);
+        }
+        ExprKind::Cast(ty, operand) => {
+            v.visit_type(ty);
+            v.visit_expr(operand);
+        }
+        ExprKind::Sizeof(ty) => v.visit_type(ty),
+        ExprKind::Cond(cond, then, els) => {
+            v.visit_expr(cond);
+            v.visit_expr(then);
+            v.visit_expr(els);
+        }
+        ExprKind::Call(callee, args) => {
+            v.visit_expr(callee);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        ExprKind::Int(_) | ExprKind::Char(_) | ExprKind::Str(_) | ExprKind::Var(_) => {}
+    }
+}
+
+/// Visits the types a type is built from, such as the element type of an array.
+pub fn walk_type<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, ty: &'ast Type) {
+    match ty {
+        Type::Pointer(to) | Type::Qualified(_, to) => v.visit_type(to),
+        Type::Array { elem, .. } => v.visit_type(elem),
+        Type::Function { ret, params } => {
+            v.visit_type(ret);
+            for param in params {
+                v.visit_type(param);
+            }
+        }
+        Type::Void
+        | Type::Bool
+        | Type::Char
+        | Type::UChar
+        | Type::Int
+        | Type::UInt
+        | Type::Long
+        | Type::ULong
+        | Type::Struct(_)
+        | Type::Union(_)
+        | Type::Enum(_) => {}
+    }
+}
+
+/// Visits a tree by mutable reference, to rewrite it in place. The methods and their order
+/// are those of `Visit`.
+pub trait VisitMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_decl_mut(&mut self, decl: &mut Decl) {
+        walk_decl_mut(self, decl)
+    }
+
+    fn visit_var_decl_mut(&mut self, var: &mut VarDecl) {
+        walk_var_decl_mut(self, var)
+    }
+
+    fn visit_function_mut(&mut self, func: &mut Function) {
+        walk_function_mut(self, func)
+    }
+
+    fn visit_param_mut(&mut self, param: &mut Param) {
+        walk_param_mut(self, param)
+    }
+
+    fn visit_struct_def_mut(&mut self, def: &mut StructDef) {
+        walk_struct_def_mut(self, def)
+    }
+
+    fn visit_field_mut(&mut self, field: &mut Field) {
+        walk_field_mut(self, field)
+    }
+
+    fn visit_enum_def_mut(&mut self, def: &mut EnumDef) {
+        walk_enum_def_mut(self, def)
+    }
+
+    fn visit_enumerator_mut(&mut self, enumerator: &mut Enumerator) {
+        walk_enumerator_mut(self, enumerator)
+    }
+
+    fn visit_typedef_mut(&mut self, def: &mut Typedef) {
+        walk_typedef_mut(self, def)
+    }
+
+    fn visit_init_mut(&mut self, init: &mut Init) {
+        walk_init_mut(self, init)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        walk_type_mut(self, ty)
+    }
+
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_program_mut<V: VisitMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for stmt in &mut program.stmts {
+        v.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitMut + ?Sized>(v: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Decl(decls) => {
+            for decl in decls {
+                v.visit_decl_mut(decl);
+            }
+        }
+        StmtKind::Expr(expr) => v.visit_expr_mut(expr),
+        StmtKind::Block(stmts) => {
+            for stmt in stmts {
+                v.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::If { cond, then, els } => {
+            v.visit_expr_mut(cond);
+            v.visit_stmt_mut(then);
+            if let Some(els) = els {
+                v.visit_stmt_mut(els);
+            }
+        }
+        StmtKind::While { cond, body } | StmtKind::Switch { cond, body } => {
+            v.visit_expr_mut(cond);
+            v.visit_stmt_mut(body);
+        }
+        StmtKind::DoWhile { body, cond } => {
+            v.visit_stmt_mut(body);
+            v.visit_expr_mut(cond);
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            if let Some(init) = init {
+                v.visit_stmt_mut(init);
+            }
+            for expr in cond.iter_mut().chain(step) {
+                v.visit_expr_mut(expr);
+            }
+            v.visit_stmt_mut(body);
+        }
+        StmtKind::Case { value, stmt } => {
+            v.visit_expr_mut(value);
+            v.visit_stmt_mut(stmt);
+        }
+        StmtKind::Default(stmt) => v.visit_stmt_mut(stmt),
+        StmtKind::Label { label, stmt } => {
+            v.visit_ident_mut(label);
+            v.visit_stmt_mut(stmt);
+        }
+        StmtKind::Goto(label) => v.visit_ident_mut(label),
+        StmtKind::Return(value) => {
+            if let Some(value) = value {
+                v.visit_expr_mut(value);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue | StmtKind::Empty => {}
+    }
+}
+
+pub fn walk_decl_mut<V: VisitMut + ?Sized>(v: &mut V, decl: &mut Decl) {
+    match decl {
+        Decl::Var(var) => v.visit_var_decl_mut(var),
+        Decl::Func(func) => v.visit_function_mut(func),
+        Decl::Struct(def) => v.visit_struct_def_mut(def),
+        Decl::Enum(def) => v.visit_enum_def_mut(def),
+        Decl::Typedef(def) => v.visit_typedef_mut(def),
+    }
+}
+
+pub fn walk_var_decl_mut<V: VisitMut + ?Sized>(v: &mut V, var: &mut VarDecl) {
+    v.visit_type_mut(&mut var.ty);
+    v.visit_ident_mut(&mut var.name);
+    if let Some(init) = &mut var.init {
+        v.visit_init_mut(init);
+    }
+}
+
+pub fn walk_function_mut<V: VisitMut + ?Sized>(v: &mut V, func: &mut Function) {
+    v.visit_type_mut(&mut func.ret);
+    v.visit_ident_mut(&mut func.name);
+    for param in &mut func.params {
+        v.visit_param_mut(param);
+    }
+    if let Some(body) = &mut func.body {
+        v.visit_stmt_mut(body);
+    }
+}
+
+pub fn walk_param_mut<V: VisitMut + ?Sized>(v: &mut V, param: &mut Param) {
+    v.visit_type_mut(&mut param.ty);
+    if let Some(name) = &mut param.name {
+        v.visit_ident_mut(name);
+    }
+}
+
+pub fn walk_struct_def_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut StructDef) {
+    v.visit_ident_mut(&mut def.tag);
+    for field in &mut def.fields {
+        v.visit_field_mut(field);
+    }
+}
+
+pub fn walk_field_mut<V: VisitMut + ?Sized>(v: &mut V, field: &mut Field) {
+    v.visit_type_mut(&mut field.ty);
+    v.visit_ident_mut(&mut field.name);
+}
+
+pub fn walk_enum_def_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut EnumDef) {
+    if let Some(tag) = &mut def.tag {
+        v.visit_ident_mut(tag);
+    }
+    for enumerator in &mut def.enumerators {
+        v.visit_enumerator_mut(enumerator);
+    }
+}
+
+pub fn walk_enumerator_mut<V: VisitMut + ?Sized>(v: &mut V, enumerator: &mut Enumerator) {
+    v.visit_ident_mut(&mut enumerator.name);
+}
+
+pub fn walk_typedef_mut<V: VisitMut + ?Sized>(v: &mut V, def: &mut Typedef) {
+    v.visit_type_mut(&mut def.ty);
+    v.visit_ident_mut(&mut def.name);
+}
+
+pub fn walk_init_mut<V: VisitMut + ?Sized>(v: &mut V, init: &mut Init) {
+    match init {
+        Init::Expr(expr) => v.visit_expr_mut(expr),
+        Init::List { items, .. } => {
+            for item in items {
+                v.visit_init_mut(item);
+            }
+        }
+    }
+}
+
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(v: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Unary(_, operand) | ExprKind::SizeofExpr(operand) => v.visit_expr_mut(operand),
+        ExprKind::Binary(_, lhs, rhs)
+        | ExprKind::Assign(_, lhs, rhs)
+        | ExprKind::Index(lhs, rhs)
+        | ExprKind::Comma(lhs, rhs) => {
+            v.visit_expr_mut(lhs);
+            v.visit_expr_mut(rhs);
+        }
+        ExprKind::Member(value, field) | ExprKind::Arrow(value, field) => {
+            v.visit_expr_mut(value);
+            v.visit_ident_mut(field);
+        }
+        ExprKind::Cast(ty, operand) => {
+            v.visit_type_mut(ty);
+            v.visit_expr_mut(operand);
+        }
+        ExprKind::Sizeof(ty) => v.visit_type_mut(ty),
+        ExprKind::Cond(cond, then, els) => {
+            v.visit_expr_mut(cond);
+            v.visit_expr_mut(then);
+            v.visit_expr_mut(els);
+        }
+        ExprKind::Call(callee, args) => {
+            v.visit_expr_mut(callee);
+            for arg in args {
+                v.visit_expr_mut(arg);
+            }
+        }
+        ExprKind::Int(_) | ExprKind::Char(_) | ExprKind::Str(_) | ExprKind::Var(_) => {}
+    }
+}
+
+pub fn walk_type_mut<V: VisitMut + ?Sized>(v: &mut V, ty: &mut Type) {
+    match ty {
+        Type::Pointer(to) | Type::Qualified(_, to) => v.visit_type_mut(to),
+        Type::Array { elem, .. } => v.visit_type_mut(elem),
+        Type::Function { ret, params } => {
+            v.visit_type_mut(ret);
+            for param in params {
+                v.visit_type_mut(param);
+            }
+        }
+        Type::Void
+        | Type::Bool
+        | Type::Char
+        | Type::UChar
+        | Type::Int
+        | Type::UInt
+        | Type::Long
+        | Type::ULong
+        | Type::Struct(_)
+        | Type::Union(_)
+        | Type::Enum(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn visit_test() {
+        let src = "struct p { int x; }; int f(struct p *q) { lbl: return q->x + 1 + g(2); }";
+        let mut program = parse(src).unwrap();
+
+        struct Names<'ast>(Vec<&'ast str>, usize);
+        impl<'ast> Visit<'ast> for Names<'ast> {
+            fn visit_ident(&mut self, ident: &'ast Ident) {
+                self.0.push(&ident.name);
+            }
+
+            fn visit_expr(&mut self, expr: &'ast Expr) {
+                self.1 += 1;
+                walk_expr(self, expr);
+            }
+        }
+        let mut names = Names(Vec::new(), 0);
+        names.visit_program(&program);
+        assert_eq!(names.0, ["p", "x", "f", "q", "lbl", "x"]);
+        assert_eq!(names.1, 8);
+
+        // folds `n + 1` into a literal, after folding inside `n`
+        struct Fold;
+        impl VisitMut for Fold {
+            fn visit_expr_mut(&mut self, expr: &mut Expr) {
+                walk_expr_mut(self, expr);
+                if let ExprKind::Binary(BinOp::Add, lhs, rhs) = &expr.kind {
+                    if let (ExprKind::Int(a), ExprKind::Int(b)) = (&lhs.kind, &rhs.kind) {
+                        expr.kind = ExprKind::Int(a + b);
+                    }
+                }
+            }
+        }
+        let mut program2 = parse("int x = 1 + 2 + 3 + y;").unwrap();
+        Fold.visit_program_mut(&mut program2);
+        assert_eq!(crate::pretty::ast_to_source(&program2), "int x = 6 + y;\n");
+        Fold.visit_program_mut(&mut program);
+        assert_eq!(program, parse(src).unwrap());
+    }
+}